@@ -1,5 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use milvuso::*;
+use milvuso::algorithms::RecommendationAlgorithm;
+use milvuso::algorithms::retriever::VectorRetriever;
 use uuid::Uuid;
 use chrono::Utc;
 
@@ -23,7 +25,8 @@ fn benchmark_collaborative_filtering(c: &mut Criterion) {
                 timestamp: Utc::now(),
             };
             
-            black_box(cf.train(&[example]).await.unwrap());
+            cf.train(&[example]).await.unwrap();
+            black_box(&cf);
         });
     });
     
@@ -57,6 +60,41 @@ fn benchmark_vector_retrieval(c: &mut Criterion) {
         });
     });
     
+    // Search only, against a prebuilt index, with and without precomputed norms
+    for cached_norms in [false, true] {
+        let retriever = rt.block_on(async {
+            let mut retriever = algorithms::retriever::InMemoryRetriever::new(128).with_cached_norms(cached_norms);
+            for i in 0..10_000 {
+                let vector: Vec<f32> = (0..128).map(|j| ((i + j) % 101) as f32 / 101.0).collect();
+                retriever.add_vector(Uuid::new_v4(), vector).await.unwrap();
+            }
+            retriever
+        });
+        let query = vec![0.5; 128];
+        let name = if cached_norms { "in_memory_search_10000_cached_norms" } else { "in_memory_search_10000" };
+        c.bench_function(name, |b| {
+            b.to_async(&rt).iter(|| async {
+                black_box(retriever.search_similar(&query, 10).await.unwrap());
+            });
+        });
+    }
+
+    // Pre-normalized vectors score on a plain dot product
+    let retriever = rt.block_on(async {
+        let mut retriever = algorithms::retriever::InMemoryRetriever::new(128).with_normalized_vectors(true);
+        for i in 0..10_000 {
+            let vector: Vec<f32> = (0..128).map(|j| ((i + j) % 101) as f32 / 101.0).collect();
+            retriever.add_vector(Uuid::new_v4(), utils::normalize_vector_copy(&vector)).await.unwrap();
+        }
+        retriever
+    });
+    let query = utils::normalize_vector_copy(&[0.5; 128]);
+    c.bench_function("in_memory_search_10000_normalized", |b| {
+        b.to_async(&rt).iter(|| async {
+            black_box(retriever.search_similar(&query, 10).await.unwrap());
+        });
+    });
+    
     c.bench_function("hnsw_retriever_search", |b| {
         b.to_async(&rt).iter(|| async {
             let mut retriever = algorithms::retriever::HNSWRetriever::new(128, 16, 200);
@@ -84,7 +122,8 @@ fn benchmark_optimizers(c: &mut Criterion) {
             let mut params = DVector::from_vec(vec![1.0; 1000]);
             let gradients = DVector::from_vec(vec![0.01; 1000]);
             
-            black_box(optimizer.update(&mut params, &gradients));
+            optimizer.update(&mut params, &gradients);
+            black_box(&params);
         });
     });
     
@@ -94,7 +133,8 @@ fn benchmark_optimizers(c: &mut Criterion) {
             let mut params = DVector::from_vec(vec![1.0; 1000]);
             let gradients = DVector::from_vec(vec![0.01; 1000]);
             
-            black_box(optimizer.update(&mut params, &gradients));
+            optimizer.update(&mut params, &gradients);
+            black_box(&params);
         });
     });
     
@@ -104,7 +144,8 @@ fn benchmark_optimizers(c: &mut Criterion) {
             let mut params = DVector::from_vec(vec![1.0; 1000]);
             let gradients = DVector::from_vec(vec![0.01; 1000]);
             
-            black_box(optimizer.update(&mut params, &gradients));
+            optimizer.update(&mut params, &gradients);
+            black_box(&params);
         });
     });
 }
@@ -152,7 +193,8 @@ fn benchmark_utils(c: &mut Criterion) {
     c.bench_function("normalize_vector", |b| {
         b.iter(|| {
             let mut vec = vec_a.clone();
-            black_box(normalize_vector(&mut vec));
+            normalize_vector(&mut vec);
+            black_box(&vec);
         });
     });
     
@@ -207,6 +249,104 @@ fn benchmark_metrics(c: &mut Criterion) {
     });
 }
 
+fn benchmark_recommendation_pipeline(c: &mut Criterion) {
+    use milvuso::services::cache::InMemoryCache;
+    use milvuso::services::recommendation::RecommendationService;
+    use milvuso::services::vector_db::VectorDbService;
+    use std::sync::Arc;
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let config = Arc::new(Config::default());
+    let dimension = config.recommendation.embedding_dim;
+
+    let (service, user_id) = rt.block_on(async {
+        let vector_db = Arc::new(VectorDbService::new(&config).await.unwrap());
+        let service = RecommendationService::with_cache(vector_db, Arc::new(InMemoryCache::new()), config.clone())
+            .await
+            .unwrap();
+
+        let mut item_ids = Vec::new();
+        for i in 0..5000 {
+            let item_id = Uuid::new_v4();
+            let embedding: Vec<f32> = (0..dimension).map(|j| ((i + j) % 97) as f32 / 97.0).collect();
+            service.add_item_feature(ItemFeature::new(item_id, embedding, format!("category_{}", i % 10))).await.unwrap();
+            item_ids.push(item_id);
+        }
+
+        let user_id = Uuid::new_v4();
+        for item_id in item_ids.iter().take(5) {
+            service.process_user_action(&UserAction::new(user_id, *item_id, ActionType::Purchase)).await.unwrap();
+        }
+        (service, user_id)
+    });
+
+    let request = RecommendationRequest::new(user_id, 50);
+    c.bench_function("get_recommendations_5000_items", |b| {
+        b.to_async(&rt).iter(|| async {
+            black_box(service.get_recommendations(&request).await.unwrap());
+        });
+    });
+}
+
+fn benchmark_cache_codecs(c: &mut Criterion) {
+    use milvuso::config::CacheCodec;
+    use milvuso::utils::cache_codec::{decode_payload, encode_payload};
+
+    let mut profile = UserProfile::new(Uuid::new_v4(), 128);
+    profile.embedding = algorithms::initializer::xavier_uniform(128);
+    profile.preferences = vec!["electronics".to_string(), "books".to_string()];
+
+    for (name, codec) in [
+        ("json", CacheCodec::Json),
+        ("bincode", CacheCodec::Bincode),
+        ("bincode_zstd", CacheCodec::BincodeZstd),
+    ] {
+        let payload = encode_payload(&profile, codec).unwrap();
+        println!("cache codec {}: {} bytes per 128-dim profile", name, payload.len());
+
+        c.bench_function(&format!("cache_codec_{}_round_trip", name), |b| {
+            b.iter(|| {
+                let bytes = encode_payload(black_box(&profile), codec).unwrap();
+                black_box(decode_payload::<UserProfile>(&bytes).unwrap());
+            });
+        });
+    }
+}
+
+fn benchmark_batch_insert(c: &mut Criterion) {
+    use milvuso::services::vector_db::VectorDbService;
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let config = Config::default();
+    let dimension = config.recommendation.embedding_dim;
+    let features: Vec<ItemFeature> = (0..10_000)
+        .map(|i| {
+            let embedding: Vec<f32> = (0..dimension).map(|j| ((i + j) % 97) as f32 / 97.0).collect();
+            ItemFeature::new(Uuid::new_v4(), embedding, format!("category_{}", i % 10))
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("insert_10000_items");
+    group.sample_size(10);
+    group.bench_function("sequential", |b| {
+        b.to_async(&rt).iter(|| async {
+            let vector_db = VectorDbService::new(&config).await.unwrap();
+            for feature in &features {
+                vector_db.insert_item_feature(feature).await.unwrap();
+            }
+            black_box(vector_db);
+        });
+    });
+    group.bench_function("batched", |b| {
+        b.to_async(&rt).iter(|| async {
+            let vector_db = VectorDbService::new(&config).await.unwrap();
+            vector_db.batch_insert_features(&features).await.unwrap();
+            black_box(vector_db);
+        });
+    });
+    group.finish();
+}
+
 criterion_group!(
     benches,
     benchmark_collaborative_filtering,
@@ -214,6 +354,9 @@ criterion_group!(
     benchmark_optimizers,
     benchmark_initializers,
     benchmark_utils,
-    benchmark_metrics
+    benchmark_metrics,
+    benchmark_recommendation_pipeline,
+    benchmark_cache_codecs,
+    benchmark_batch_insert
 );
 criterion_main!(benches);
@@ -1,8 +1,6 @@
 use milvuso::*;
+use milvuso::algorithms::RecommendationAlgorithm;
 use uuid::Uuid;
-use chrono::Utc;
-use std::time::Duration;
-use tokio::time::sleep;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -33,7 +31,7 @@ async fn main() -> anyhow::Result<()> {
     
     // 5. 创建物品特征
     let mut item_features = Vec::new();
-    let categories = vec!["电子产品", "服装", "图书", "食品", "运动"];
+    let categories = ["电子产品", "服装", "图书", "食品", "运动"];
     
     for (i, &item_id) in item_ids.iter().enumerate() {
         let category = categories[i % categories.len()];
@@ -86,6 +84,9 @@ async fn main() -> anyhow::Result<()> {
             ActionType::Purchase => 1.0,
             ActionType::Share => 0.8,
             ActionType::Convert => 1.0,
+            ActionType::Hide => -0.5,
+            ActionType::NotInterested => -0.7,
+            ActionType::Dislike => -1.0,
         };
         
         let example = TrainingExample {
@@ -203,7 +204,7 @@ async fn main() -> anyhow::Result<()> {
     use algorithms::optimizer::*;
     use nalgebra::DVector;
     
-    let mut params = DVector::from_vec(vec![1.0, 2.0, 3.0]);
+    let params = DVector::from_vec(vec![1.0, 2.0, 3.0]);
     let gradients = DVector::from_vec(vec![0.1, 0.2, 0.3]);
     
     let optimizers: Vec<(&str, Box<dyn Optimizer>)> = vec![
@@ -0,0 +1,200 @@
+use super::{initializer, RecommendationAlgorithm};
+use crate::models::*;
+use crate::utils::sigmoid;
+use anyhow::Result;
+use nalgebra::DVector;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+
+/// Labels above this count as positive feedback.
+const POSITIVE_LABEL: f32 = 0.5;
+/// Draws before giving up on finding a negative, e.g. for a user who interacted with every item.
+const NEGATIVE_SAMPLE_ATTEMPTS: usize = 10;
+
+/// Bayesian Personalized Ranking: learns embeddings from implicit feedback by ranking each observed
+/// positive above a sampled item the user has not interacted with, instead of regressing labels.
+#[derive(Debug)]
+pub struct BPR {
+    pub user_embeddings: HashMap<uuid::Uuid, DVector<f32>>,
+    pub item_embeddings: HashMap<uuid::Uuid, DVector<f32>>,
+    pub embedding_dim: usize,
+    pub learning_rate: f64,
+    pub regularization: f64,
+    /// Items each user gave positive feedback to, never sampled as their negatives.
+    positives: HashMap<uuid::Uuid, HashSet<uuid::Uuid>>,
+    /// Every item seen in training, in first-seen order, to sample negatives from.
+    item_ids: Vec<uuid::Uuid>,
+    rng: StdRng,
+}
+
+impl BPR {
+    pub fn new(embedding_dim: usize, learning_rate: f64, regularization: f64) -> Self {
+        Self::with_rng(embedding_dim, learning_rate, regularization, StdRng::from_entropy())
+    }
+
+    /// Samples negatives from `seed`, so the same examples draw the same negatives.
+    pub fn with_seed(embedding_dim: usize, learning_rate: f64, regularization: f64, seed: u64) -> Self {
+        Self::with_rng(embedding_dim, learning_rate, regularization, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(embedding_dim: usize, learning_rate: f64, regularization: f64, rng: StdRng) -> Self {
+        Self {
+            user_embeddings: HashMap::new(),
+            item_embeddings: HashMap::new(),
+            embedding_dim,
+            learning_rate,
+            regularization,
+            positives: HashMap::new(),
+            item_ids: Vec::new(),
+            rng,
+        }
+    }
+
+    pub fn initialize_user_embedding(&mut self, user_id: uuid::Uuid) {
+        if !self.user_embeddings.contains_key(&user_id) {
+            let embedding = initializer::xavier_uniform(self.embedding_dim);
+            self.user_embeddings.insert(user_id, DVector::from_vec(embedding));
+        }
+    }
+
+    pub fn initialize_item_embedding(&mut self, item_id: uuid::Uuid) {
+        if !self.item_embeddings.contains_key(&item_id) {
+            let embedding = initializer::xavier_uniform(self.embedding_dim);
+            self.item_embeddings.insert(item_id, DVector::from_vec(embedding));
+            self.item_ids.push(item_id);
+        }
+    }
+
+    /// A random known item `user_id` has no positive feedback for, if one turns up.
+    fn sample_negative(&mut self, user_id: uuid::Uuid) -> Option<uuid::Uuid> {
+        if self.item_ids.is_empty() {
+            return None;
+        }
+        let positives = self.positives.get(&user_id);
+        (0..NEGATIVE_SAMPLE_ATTEMPTS)
+            .map(|_| self.item_ids[self.rng.gen_range(0..self.item_ids.len())])
+            .find(|item_id| positives.is_none_or(|positives| !positives.contains(item_id)))
+    }
+
+    /// Mean BPR loss, `-ln sigmoid(x_ui - x_uj)`, over `(user, positive, negative)` triples the model
+    /// has embeddings for.
+    pub fn compute_loss(&self, triples: &[(uuid::Uuid, uuid::Uuid, uuid::Uuid)]) -> f64 {
+        let losses: Vec<f64> = triples
+            .iter()
+            .filter_map(|(user_id, positive_id, negative_id)| {
+                let user_emb = self.user_embeddings.get(user_id)?;
+                let difference = self.item_embeddings.get(positive_id)? - self.item_embeddings.get(negative_id)?;
+                Some(-(sigmoid(user_emb.dot(&difference)) as f64).ln())
+            })
+            .collect();
+
+        if losses.is_empty() {
+            0.0
+        } else {
+            losses.iter().sum::<f64>() / losses.len() as f64
+        }
+    }
+
+    /// One gradient ascent step on `ln sigmoid(x_ui - x_uj)`, with L2 regularization.
+    pub fn train_triple(&mut self, user_id: uuid::Uuid, positive_id: uuid::Uuid, negative_id: uuid::Uuid) {
+        self.initialize_user_embedding(user_id);
+        self.initialize_item_embedding(positive_id);
+        self.initialize_item_embedding(negative_id);
+
+        let user_emb = self.user_embeddings[&user_id].clone();
+        let positive_emb = self.item_embeddings[&positive_id].clone();
+        let negative_emb = self.item_embeddings[&negative_id].clone();
+
+        let difference = &positive_emb - &negative_emb;
+        // d/dx ln sigmoid(x) = sigmoid(-x)
+        let weight = sigmoid(-user_emb.dot(&difference));
+        let learning_rate = self.learning_rate as f32;
+        let regularization = self.regularization as f32;
+
+        let user_step = (&difference * weight - &user_emb * regularization) * learning_rate;
+        let positive_step = (&user_emb * weight - &positive_emb * regularization) * learning_rate;
+        let negative_step = (-&user_emb * weight - &negative_emb * regularization) * learning_rate;
+
+        self.user_embeddings.insert(user_id, user_emb + user_step);
+        self.item_embeddings.insert(positive_id, positive_emb + positive_step);
+        self.item_embeddings.insert(negative_id, negative_emb + negative_step);
+    }
+}
+
+#[async_trait::async_trait]
+impl RecommendationAlgorithm for BPR {
+    /// Every example's items join the negative pool first; then each positive example
+    /// (label > 0.5) trains against one sampled negative. Other examples only add items.
+    async fn train(&mut self, examples: &[TrainingExample]) -> Result<()> {
+        for example in examples {
+            self.initialize_item_embedding(example.item_id);
+            if example.label > POSITIVE_LABEL {
+                self.positives.entry(example.user_id).or_default().insert(example.item_id);
+            }
+        }
+
+        for example in examples.iter().filter(|example| example.label > POSITIVE_LABEL) {
+            if let Some(negative_id) = self.sample_negative(example.user_id) {
+                self.train_triple(example.user_id, example.item_id, negative_id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn predict(&self, user_features: &[f32], item_features: &[f32]) -> Result<f32> {
+        if user_features.len() != item_features.len() {
+            return Err(anyhow::anyhow!(
+                "Feature dimension mismatch: user {} vs item {}",
+                user_features.len(),
+                item_features.len()
+            ));
+        }
+
+        let user_vec = DVector::from_vec(user_features.to_vec());
+        let item_vec = DVector::from_vec(item_features.to_vec());
+        Ok(user_vec.dot(&item_vec))
+    }
+
+    async fn get_user_embedding(&self, user_id: uuid::Uuid) -> Result<Vec<f32>> {
+        if let Some(embedding) = self.user_embeddings.get(&user_id) {
+            Ok(embedding.as_slice().to_vec())
+        } else {
+            Ok(initializer::xavier_uniform(self.embedding_dim))
+        }
+    }
+
+    async fn get_item_embedding(&self, item_id: uuid::Uuid) -> Result<Vec<f32>> {
+        if let Some(embedding) = self.item_embeddings.get(&item_id) {
+            Ok(embedding.as_slice().to_vec())
+        } else {
+            Ok(initializer::xavier_uniform(self.embedding_dim))
+        }
+    }
+
+    /// Replaces the learned embeddings with the snapshot's, and samples negatives from its items.
+    /// Nothing changes if any embedding has the wrong dimension.
+    async fn update_parameters(&mut self, parameters: &ModelParameters) -> Result<()> {
+        let embeddings = parameters.user_embeddings.values().chain(parameters.item_embeddings.values());
+        if let Some(embedding) = embeddings.into_iter().find(|embedding| embedding.len() != self.embedding_dim) {
+            return Err(anyhow::anyhow!(
+                "Model parameters {} hold {}-dimensional embeddings, model expects {}",
+                parameters.version,
+                embedding.len(),
+                self.embedding_dim
+            ));
+        }
+
+        let to_vectors = |embeddings: &HashMap<uuid::Uuid, Vec<f32>>| {
+            embeddings
+                .iter()
+                .map(|(id, embedding)| (*id, DVector::from_vec(embedding.clone())))
+                .collect()
+        };
+        self.user_embeddings = to_vectors(&parameters.user_embeddings);
+        self.item_embeddings = to_vectors(&parameters.item_embeddings);
+        self.item_ids = parameters.item_embeddings.keys().copied().collect();
+        self.item_ids.sort();
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use crate::utils::euclidean_distance;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Result of `k_means`: the centroid index of each point, and the centroids.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KMeans {
+    pub assignments: Vec<usize>,
+    pub centroids: Vec<Vec<f32>>,
+}
+
+/// Lloyd's k-means over `points`, seeded with k-means++ from `seed` so the same points always
+/// cluster the same way. Stops once no assignment changes or after `max_iterations`. `k` is capped
+/// at the number of points; no points or `k` of zero yields no clusters.
+pub fn k_means(points: &[&[f32]], k: usize, max_iterations: usize, seed: u64) -> KMeans {
+    let k = k.min(points.len());
+    if k == 0 {
+        return KMeans { assignments: vec![0; points.len()], centroids: Vec::new() };
+    }
+
+    let mut centroids = k_means_plus_plus(points, k, &mut StdRng::seed_from_u64(seed));
+    let mut assignments = vec![usize::MAX; points.len()];
+    for _ in 0..max_iterations.max(1) {
+        let mut changed = false;
+        for (assignment, point) in assignments.iter_mut().zip(points) {
+            let nearest = nearest_centroid(point, &centroids);
+            if *assignment != nearest {
+                *assignment = nearest;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        let dimension = points[0].len();
+        let mut sums = vec![vec![0.0f32; dimension]; k];
+        let mut counts = vec![0usize; k];
+        for (&assignment, point) in assignments.iter().zip(points) {
+            for (sum, x) in sums[assignment].iter_mut().zip(point.iter()) {
+                *sum += x;
+            }
+            counts[assignment] += 1;
+        }
+        // An emptied cluster keeps its previous centroid
+        for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+            if count > 0 {
+                *centroid = sum.into_iter().map(|x| x / count as f32).collect();
+            }
+        }
+    }
+
+    KMeans { assignments, centroids }
+}
+
+/// Index of the centroid closest to `point`, the lowest index on ties.
+pub fn nearest_centroid(point: &[f32], centroids: &[Vec<f32>]) -> usize {
+    centroids
+        .iter()
+        .map(|centroid| euclidean_distance(point, centroid))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)))
+        .map_or(0, |(index, _)| index)
+}
+
+/// Picks the first centroid uniformly, then each next one with probability proportional to the
+/// squared distance from the nearest centroid picked so far.
+fn k_means_plus_plus(points: &[&[f32]], k: usize, rng: &mut StdRng) -> Vec<Vec<f32>> {
+    let mut centroids = vec![points[rng.gen_range(0..points.len())].to_vec()];
+    let mut distances: Vec<f32> = points.iter().map(|point| euclidean_distance(point, &centroids[0]).powi(2)).collect();
+    while centroids.len() < k {
+        let total: f32 = distances.iter().sum();
+        let next = if total > 0.0 {
+            let mut target = rng.gen_range(0.0..total);
+            distances
+                .iter()
+                .position(|&distance| {
+                    target -= distance;
+                    target < 0.0
+                })
+                .unwrap_or(points.len() - 1)
+        } else {
+            // Every point coincides with a centroid already
+            rng.gen_range(0..points.len())
+        };
+        centroids.push(points[next].to_vec());
+        for (distance, point) in distances.iter_mut().zip(points) {
+            *distance = distance.min(euclidean_distance(point, points[next]).powi(2));
+        }
+    }
+    centroids
+}
@@ -0,0 +1,105 @@
+use crate::models::{TrainingExample, CONTEXT_FEATURE_NAMES};
+use nalgebra::DVector;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+/// Passes over the examples when fitting the context weights.
+const CONTEXT_FIT_EPOCHS: usize = 50;
+const CONTEXT_FIT_LEARNING_RATE: f32 = 0.05;
+
+/// How much one context feature contributes to the model's predictions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureImportance {
+    /// Position of the feature in `TrainingExample::context_features`.
+    pub index: usize,
+    pub name: Option<String>,
+    /// Increase in mean squared error when the feature is shuffled across examples. Near zero, or
+    /// below, for a feature the predictions do not depend on.
+    pub importance: f64,
+}
+
+/// Linear context tower on top of a base score: predicts `base + bias + weights · context`.
+#[derive(Debug, Clone)]
+pub struct ContextModel {
+    pub weights: DVector<f32>,
+    pub bias: f32,
+}
+
+impl ContextModel {
+    /// Fits the weights by SGD on what `base_score` leaves unexplained of each example's label.
+    /// Examples with fewer context features than the widest one read the missing ones as zero.
+    pub fn fit(examples: &[TrainingExample], base_score: impl Fn(&TrainingExample) -> f32) -> Self {
+        let dimension = examples.iter().map(|example| example.context_features.len()).max().unwrap_or(0);
+        let mut model = Self { weights: DVector::zeros(dimension), bias: 0.0 };
+        let residuals: Vec<f32> = examples.iter().map(|example| example.label - base_score(example)).collect();
+
+        for _ in 0..CONTEXT_FIT_EPOCHS {
+            for (example, residual) in examples.iter().zip(&residuals) {
+                let context = context_vector(&example.context_features, dimension);
+                let error = residual - model.context_score(&context);
+                model.weights += &context * (CONTEXT_FIT_LEARNING_RATE * error);
+                model.bias += CONTEXT_FIT_LEARNING_RATE * error;
+            }
+        }
+        model
+    }
+
+    fn context_score(&self, context: &DVector<f32>) -> f32 {
+        self.bias + self.weights.dot(context)
+    }
+}
+
+/// Permutation importance of every context feature: fits a `ContextModel` over `base_score`, then
+/// measures how much worse it predicts the labels once one feature's values are shuffled across
+/// the examples. Shuffles are drawn from `seed`. Sorted most important first, ties by index.
+pub fn context_importance(
+    examples: &[TrainingExample],
+    base_score: impl Fn(&TrainingExample) -> f32,
+    seed: u64,
+) -> Vec<FeatureImportance> {
+    let model = ContextModel::fit(examples, &base_score);
+    let dimension = model.weights.len();
+    let base_scores: Vec<f32> = examples.iter().map(&base_score).collect();
+    let contexts: Vec<DVector<f32>> =
+        examples.iter().map(|example| context_vector(&example.context_features, dimension)).collect();
+
+    let mean_squared_error = |contexts: &[DVector<f32>]| {
+        let total: f64 = examples
+            .iter()
+            .zip(&base_scores)
+            .zip(contexts)
+            .map(|((example, base), context)| {
+                let error = example.label - (base + model.context_score(context));
+                (error * error) as f64
+            })
+            .sum();
+        total / examples.len().max(1) as f64
+    };
+    let baseline = mean_squared_error(&contexts);
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut importances: Vec<FeatureImportance> = (0..dimension)
+        .map(|index| {
+            let mut column: Vec<f32> = contexts.iter().map(|context| context[index]).collect();
+            column.shuffle(&mut rng);
+            let mut permuted = contexts.clone();
+            for (context, value) in permuted.iter_mut().zip(column) {
+                context[index] = value;
+            }
+            FeatureImportance {
+                index,
+                name: CONTEXT_FEATURE_NAMES.get(index).map(|name| name.to_string()),
+                importance: mean_squared_error(&permuted) - baseline,
+            }
+        })
+        .collect();
+
+    importances.sort_by(|a, b| b.importance.total_cmp(&a.importance).then_with(|| a.index.cmp(&b.index)));
+    importances
+}
+
+fn context_vector(features: &[f32], dimension: usize) -> DVector<f32> {
+    DVector::from_iterator(dimension, (0..dimension).map(|i| features.get(i).copied().unwrap_or(0.0)))
+}
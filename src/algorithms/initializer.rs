@@ -106,16 +106,18 @@ pub fn orthogonal(rows: usize, cols: usize) -> Vec<Vec<f32>> {
         // Normalize current vector
         let norm: f32 = matrix[i].iter().map(|x| x * x).sum::<f32>().sqrt();
         if norm > 1e-8 {
-            for j in 0..cols {
-                matrix[i][j] /= norm;
+            for x in matrix[i].iter_mut() {
+                *x /= norm;
             }
         }
         
         // Orthogonalize remaining vectors
-        for k in (i + 1)..rows {
-            let dot_product: f32 = (0..cols).map(|j| matrix[i][j] * matrix[k][j]).sum();
-            for j in 0..cols {
-                matrix[k][j] -= dot_product * matrix[i][j];
+        let (done, rest) = matrix.split_at_mut(i + 1);
+        let current = &done[i];
+        for row in rest.iter_mut() {
+            let dot_product: f32 = current.iter().zip(row.iter()).map(|(a, b)| a * b).sum();
+            for (x, c) in row.iter_mut().zip(current.iter()) {
+                *x -= dot_product * c;
             }
         }
     }
@@ -171,9 +173,7 @@ impl InitializationMethod {
     }
     
     pub fn initialize_matrix(&self, rows: usize, cols: usize) -> Vec<Vec<f32>> {
-        match self {
-            _ => (0..rows).map(|_| self.initialize(cols)).collect(),
-        }
+        (0..rows).map(|_| self.initialize(cols)).collect()
     }
 }
 
@@ -1,10 +1,16 @@
+pub mod bpr;
+pub mod clustering;
+pub mod feature_importance;
 pub mod optimizer;
 pub mod retriever;
 pub mod initializer;
+pub mod projection;
+pub mod two_tower;
 
 use crate::models::*;
 use anyhow::Result;
 use nalgebra::DVector;
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 #[async_trait::async_trait]
@@ -16,23 +22,29 @@ pub trait RecommendationAlgorithm: Send + Sync {
     async fn update_parameters(&mut self, parameters: &ModelParameters) -> Result<()>;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct CollaborativeFiltering {
     pub user_embeddings: HashMap<uuid::Uuid, DVector<f32>>,
     pub item_embeddings: HashMap<uuid::Uuid, DVector<f32>>,
     pub embedding_dim: usize,
-    pub learning_rate: f64,
     pub regularization: f64,
+    /// Steps every embedding, keeping its state under `user:<id>` or `item:<id>`.
+    optimizer: Box<dyn optimizer::Optimizer>,
 }
 
 impl CollaborativeFiltering {
+    /// Trains with plain SGD at `learning_rate`.
     pub fn new(embedding_dim: usize, learning_rate: f64, regularization: f64) -> Self {
+        Self::with_optimizer(embedding_dim, regularization, Box::new(optimizer::SGD::new(learning_rate)))
+    }
+
+    pub fn with_optimizer(embedding_dim: usize, regularization: f64, optimizer: Box<dyn optimizer::Optimizer>) -> Self {
         Self {
             user_embeddings: HashMap::new(),
             item_embeddings: HashMap::new(),
             embedding_dim,
-            learning_rate,
             regularization,
+            optimizer,
         }
     }
     
@@ -50,6 +62,23 @@ impl CollaborativeFiltering {
         }
     }
     
+    /// `content` blended with the item's learned embedding, weighted `collaborative_weight` towards
+    /// the latter. Cold items the model has no embedding of, or one of another length, keep their
+    /// content embedding.
+    pub fn blended_item_embedding<'a>(&self, item_id: uuid::Uuid, content: &'a [f32], collaborative_weight: f32) -> Cow<'a, [f32]> {
+        let weight = collaborative_weight.clamp(0.0, 1.0);
+        match self.item_embeddings.get(&item_id) {
+            Some(collaborative) if weight > 0.0 && collaborative.len() == content.len() => Cow::Owned(
+                content
+                    .iter()
+                    .zip(collaborative.iter())
+                    .map(|(c, l)| (1.0 - weight) * c + weight * l)
+                    .collect(),
+            ),
+            _ => Cow::Borrowed(content),
+        }
+    }
+
     pub fn compute_loss(&self, examples: &[TrainingExample]) -> f64 {
         let mut total_loss = 0.0f64;
         let mut count = 0;
@@ -73,24 +102,25 @@ impl CollaborativeFiltering {
         }
     }
     
-    pub fn sgd_update(&mut self, example: &TrainingExample) -> Result<()> {
+    /// One optimizer step on the squared error of `example`, with L2 regularization.
+    pub fn train_example(&mut self, example: &TrainingExample) -> Result<()> {
         self.initialize_user_embedding(example.user_id);
         self.initialize_item_embedding(example.item_id);
         
-        let user_emb = self.user_embeddings.get(&example.user_id).unwrap().clone();
-        let item_emb = self.item_embeddings.get(&example.item_id).unwrap().clone();
+        let mut user_emb = self.user_embeddings.get(&example.user_id).unwrap().clone();
+        let mut item_emb = self.item_embeddings.get(&example.item_id).unwrap().clone();
         
         let prediction = user_emb.dot(&item_emb);
         let error = example.label - prediction;
         
-        let user_gradient = &item_emb * error - &user_emb * (self.regularization as f32);
-        let item_gradient = &user_emb * error - &item_emb * (self.regularization as f32);
+        let user_gradient = &user_emb * (self.regularization as f32) - &item_emb * error;
+        let item_gradient = &item_emb * (self.regularization as f32) - &user_emb * error;
         
-        let new_user_emb = &user_emb + &user_gradient * (self.learning_rate as f32);
-        let new_item_emb = &item_emb + &item_gradient * (self.learning_rate as f32);
+        self.optimizer.update_with_key(&format!("user:{}", example.user_id), &mut user_emb, &user_gradient);
+        self.optimizer.update_with_key(&format!("item:{}", example.item_id), &mut item_emb, &item_gradient);
         
-        self.user_embeddings.insert(example.user_id, new_user_emb);
-        self.item_embeddings.insert(example.item_id, new_item_emb);
+        self.user_embeddings.insert(example.user_id, user_emb);
+        self.item_embeddings.insert(example.item_id, item_emb);
         
         Ok(())
     }
@@ -100,12 +130,20 @@ impl CollaborativeFiltering {
 impl RecommendationAlgorithm for CollaborativeFiltering {
     async fn train(&mut self, examples: &[TrainingExample]) -> Result<()> {
         for example in examples {
-            self.sgd_update(example)?;
+            self.train_example(example)?;
         }
         Ok(())
     }
     
     async fn predict(&self, user_features: &[f32], item_features: &[f32]) -> Result<f32> {
+        if user_features.len() != item_features.len() {
+            return Err(anyhow::anyhow!(
+                "Feature dimension mismatch: user {} vs item {}",
+                user_features.len(),
+                item_features.len()
+            ));
+        }
+
         let user_vec = DVector::from_vec(user_features.to_vec());
         let item_vec = DVector::from_vec(item_features.to_vec());
         Ok(user_vec.dot(&item_vec))
@@ -127,9 +165,27 @@ impl RecommendationAlgorithm for CollaborativeFiltering {
         }
     }
     
-    async fn update_parameters(&mut self, _parameters: &ModelParameters) -> Result<()> {
-        // Update embeddings from model parameters
-        // This would be implemented based on the specific parameter format
+    /// Replaces the learned embeddings with the snapshot's. Nothing changes if any embedding has
+    /// the wrong dimension.
+    async fn update_parameters(&mut self, parameters: &ModelParameters) -> Result<()> {
+        let embeddings = parameters.user_embeddings.values().chain(parameters.item_embeddings.values());
+        if let Some(embedding) = embeddings.into_iter().find(|embedding| embedding.len() != self.embedding_dim) {
+            return Err(anyhow::anyhow!(
+                "Model parameters {} hold {}-dimensional embeddings, model expects {}",
+                parameters.version,
+                embedding.len(),
+                self.embedding_dim
+            ));
+        }
+
+        let to_vectors = |embeddings: &HashMap<uuid::Uuid, Vec<f32>>| {
+            embeddings
+                .iter()
+                .map(|(id, embedding)| (*id, DVector::from_vec(embedding.clone())))
+                .collect()
+        };
+        self.user_embeddings = to_vectors(&parameters.user_embeddings);
+        self.item_embeddings = to_vectors(&parameters.item_embeddings);
         Ok(())
     }
 }
@@ -1,8 +1,15 @@
 use nalgebra::DVector;
 use std::collections::HashMap;
 
-pub trait Optimizer: Send + Sync {
-    fn update(&mut self, params: &mut DVector<f32>, gradients: &DVector<f32>);
+pub trait Optimizer: Send + Sync + std::fmt::Debug {
+    /// Steps `params` against `gradients`, keeping any per-parameter state (e.g. moment estimates)
+    /// under `key`, so one optimizer can drive many parameter vectors.
+    fn update_with_key(&mut self, key: &str, params: &mut DVector<f32>, gradients: &DVector<f32>);
+
+    fn update(&mut self, params: &mut DVector<f32>, gradients: &DVector<f32>) {
+        self.update_with_key("default", params, gradients);
+    }
+
     fn reset(&mut self);
 }
 
@@ -18,7 +25,7 @@ impl SGD {
 }
 
 impl Optimizer for SGD {
-    fn update(&mut self, params: &mut DVector<f32>, gradients: &DVector<f32>) {
+    fn update_with_key(&mut self, _key: &str, params: &mut DVector<f32>, gradients: &DVector<f32>) {
         *params -= gradients * self.learning_rate as f32;
     }
     
@@ -50,12 +57,16 @@ impl Adam {
             v: HashMap::new(),
         }
     }
-    
-    pub fn default() -> Self {
+}
+
+impl Default for Adam {
+    fn default() -> Self {
         Self::new(0.001, 0.9, 0.999, 1e-8)
     }
-    
-    pub fn update_with_key(&mut self, key: &str, params: &mut DVector<f32>, gradients: &DVector<f32>) {
+}
+
+impl Optimizer for Adam {
+    fn update_with_key(&mut self, key: &str, params: &mut DVector<f32>, gradients: &DVector<f32>) {
         self.t += 1;
         
         let m = self.m.entry(key.to_string())
@@ -81,12 +92,6 @@ impl Adam {
         
         *params -= update;
     }
-}
-
-impl Optimizer for Adam {
-    fn update(&mut self, params: &mut DVector<f32>, gradients: &DVector<f32>) {
-        self.update_with_key("default", params, gradients);
-    }
     
     fn reset(&mut self) {
         self.t = 0;
@@ -95,6 +100,43 @@ impl Optimizer for Adam {
     }
 }
 
+/// Adam with decoupled weight decay: parameters shrink by `learning_rate * weight_decay` each
+/// step independently of the gradient moments, rather than through an L2 term in the gradient.
+#[derive(Debug, Clone)]
+pub struct AdamW {
+    adam: Adam,
+    weight_decay: f64,
+}
+
+impl AdamW {
+    pub fn new(learning_rate: f64, beta1: f64, beta2: f64, epsilon: f64, weight_decay: f64) -> Self {
+        Self {
+            adam: Adam::new(learning_rate, beta1, beta2, epsilon),
+            weight_decay,
+        }
+    }
+}
+
+impl Default for AdamW {
+    fn default() -> Self {
+        Self::new(0.001, 0.9, 0.999, 1e-8, 0.01)
+    }
+}
+
+impl Optimizer for AdamW {
+    fn update_with_key(&mut self, key: &str, params: &mut DVector<f32>, gradients: &DVector<f32>) {
+        // Decay the parameters directly, so it is not rescaled by the second moment estimate
+        let decay = (self.adam.learning_rate * self.weight_decay) as f32;
+        *params -= params.scale(decay);
+        
+        self.adam.update_with_key(key, params, gradients);
+    }
+    
+    fn reset(&mut self) {
+        self.adam.reset();
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AdaGrad {
     learning_rate: f64,
@@ -110,12 +152,16 @@ impl AdaGrad {
             sum_squared_gradients: HashMap::new(),
         }
     }
-    
-    pub fn default() -> Self {
+}
+
+impl Default for AdaGrad {
+    fn default() -> Self {
         Self::new(0.01, 1e-8)
     }
-    
-    pub fn update_with_key(&mut self, key: &str, params: &mut DVector<f32>, gradients: &DVector<f32>) {
+}
+
+impl Optimizer for AdaGrad {
+    fn update_with_key(&mut self, key: &str, params: &mut DVector<f32>, gradients: &DVector<f32>) {
         let sum_sq_grad = self.sum_squared_gradients.entry(key.to_string())
             .or_insert_with(|| DVector::zeros(params.len()));
         
@@ -128,12 +174,6 @@ impl AdaGrad {
         // Update parameters
         *params -= gradients.component_mul(&adaptive_lr);
     }
-}
-
-impl Optimizer for AdaGrad {
-    fn update(&mut self, params: &mut DVector<f32>, gradients: &DVector<f32>) {
-        self.update_with_key("default", params, gradients);
-    }
     
     fn reset(&mut self) {
         self.sum_squared_gradients.clear();
@@ -157,12 +197,16 @@ impl RMSprop {
             cache: HashMap::new(),
         }
     }
-    
-    pub fn default() -> Self {
+}
+
+impl Default for RMSprop {
+    fn default() -> Self {
         Self::new(0.001, 0.9, 1e-8)
     }
-    
-    pub fn update_with_key(&mut self, key: &str, params: &mut DVector<f32>, gradients: &DVector<f32>) {
+}
+
+impl Optimizer for RMSprop {
+    fn update_with_key(&mut self, key: &str, params: &mut DVector<f32>, gradients: &DVector<f32>) {
         let cache = self.cache.entry(key.to_string())
             .or_insert_with(|| DVector::zeros(params.len()));
         
@@ -176,12 +220,6 @@ impl RMSprop {
         
         *params -= update;
     }
-}
-
-impl Optimizer for RMSprop {
-    fn update(&mut self, params: &mut DVector<f32>, gradients: &DVector<f32>) {
-        self.update_with_key("default", params, gradients);
-    }
     
     fn reset(&mut self) {
         self.cache.clear();
@@ -0,0 +1,91 @@
+use crate::config::ProjectionConfig;
+use anyhow::{anyhow, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// Linear map from `input_dim` to `output_dim`, used to bring embeddings from a larger model
+/// (e.g. a 768-dim language model) into the index dimension.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectionMatrix {
+    input_dim: usize,
+    output_dim: usize,
+    /// Row-major, `output_dim` rows of `input_dim` weights.
+    weights: Vec<f32>,
+}
+
+impl ProjectionMatrix {
+    /// Gaussian random projection with entries drawn from N(0, 1/output_dim), which preserves
+    /// pairwise distances up to a small relative error (Johnson-Lindenstrauss). The same seed
+    /// always yields the same matrix, so every instance projects identically.
+    pub fn random(input_dim: usize, output_dim: usize, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let std_dev = (1.0 / output_dim as f32).sqrt();
+        let weights = (0..input_dim * output_dim)
+            .map(|_| {
+                // 1 - u keeps the logarithm finite
+                let u1: f32 = 1.0 - rng.gen::<f32>();
+                let u2: f32 = rng.gen();
+                (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos() * std_dev
+            })
+            .collect();
+
+        Self { input_dim, output_dim, weights }
+    }
+
+    /// A learned projection given as `output_dim` rows of `input_dim` weights.
+    pub fn from_rows(rows: &[Vec<f32>]) -> Result<Self> {
+        let input_dim = rows.first().map_or(0, |row| row.len());
+        if input_dim == 0 || rows.iter().any(|row| row.len() != input_dim) {
+            return Err(anyhow!("Projection rows must be non-empty and of equal length"));
+        }
+
+        Ok(Self {
+            input_dim,
+            output_dim: rows.len(),
+            weights: rows.concat(),
+        })
+    }
+
+    /// Builds the configured projection into `output_dim` dimensions.
+    pub fn from_config(config: &ProjectionConfig, output_dim: usize) -> Result<Self> {
+        let projection = match &config.weights {
+            Some(rows) => Self::from_rows(rows)?,
+            None => Self::random(config.input_dim, output_dim, config.seed),
+        };
+        if projection.input_dim != config.input_dim || projection.output_dim != output_dim {
+            return Err(anyhow!(
+                "Projection maps {} to {} dimensions, expected {} to {}",
+                projection.input_dim,
+                projection.output_dim,
+                config.input_dim,
+                output_dim
+            ));
+        }
+        Ok(projection)
+    }
+
+    pub fn input_dim(&self) -> usize {
+        self.input_dim
+    }
+
+    pub fn output_dim(&self) -> usize {
+        self.output_dim
+    }
+
+    pub fn project(&self, embedding: &[f32]) -> Result<Vec<f32>> {
+        if embedding.len() != self.input_dim {
+            return Err(anyhow!(
+                "Cannot project a {}-dim embedding with a {}-dim projection",
+                embedding.len(),
+                self.input_dim
+            ));
+        }
+
+        Ok(self.weights
+            .chunks_exact(self.input_dim)
+            .map(|row| row.iter().zip(embedding).map(|(w, x)| w * x).sum())
+            .collect())
+    }
+}
@@ -1,31 +1,197 @@
 use anyhow::Result;
 use nalgebra::DVector;
-use std::collections::HashMap;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::cmp::Ordering;
+use std::mem::size_of;
 
 #[async_trait::async_trait]
 pub trait VectorRetriever: Send + Sync {
     async fn search_similar(&self, query_vector: &[f32], top_k: usize) -> Result<Vec<(uuid::Uuid, f32)>>;
+    /// Like `search_similar`, but drops results below `min_similarity`, so a query with nothing
+    /// similar returns fewer than `top_k` results, possibly none.
+    async fn search_similar_filtered(
+        &self,
+        query_vector: &[f32],
+        top_k: usize,
+        min_similarity: f32,
+    ) -> Result<Vec<(uuid::Uuid, f32)>> {
+        let mut results = self.search_similar(query_vector, top_k).await?;
+        results.retain(|(_, similarity)| *similarity >= min_similarity);
+        Ok(results)
+    }
+    /// Runs `search_similar` for every query, under whatever lock the caller holds.
+    async fn search_similar_batch(&self, queries: &[&[f32]], top_k: usize) -> Result<Vec<Vec<(uuid::Uuid, f32)>>> {
+        let mut results = Vec::with_capacity(queries.len());
+        for query in queries {
+            results.push(self.search_similar(query, top_k).await?);
+        }
+        Ok(results)
+    }
     async fn add_vector(&mut self, id: uuid::Uuid, vector: Vec<f32>) -> Result<()>;
     async fn remove_vector(&mut self, id: uuid::Uuid) -> Result<()>;
     async fn update_vector(&mut self, id: uuid::Uuid, vector: Vec<f32>) -> Result<()>;
+    /// Adds `delta` to each listed dimension of the stored vector in place.
+    async fn update_vector_sparse(&mut self, id: uuid::Uuid, deltas: &[(usize, f32)]) -> Result<()>;
+    /// Whether the index lives outside this process and is shared with other instances.
+    fn is_shared(&self) -> bool {
+        false
+    }
+    /// Size and shape of the index, for capacity planning.
+    fn stats(&self) -> RetrieverStats;
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RetrieverStats {
+    pub kind: &'static str,
+    pub vector_count: usize,
+    pub dimension: usize,
+    /// Rough heap footprint of the stored vectors and graph, ignoring allocator and hash table slack.
+    pub estimated_bytes: usize,
+    /// HNSW only: number of nodes on each layer, bottom layer first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub layer_node_counts: Vec<usize>,
+    /// Hybrid only: the stats of each underlying retriever.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<RetrieverStats>,
+}
+
+impl RetrieverStats {
+    pub(crate) fn flat(kind: &'static str, vector_count: usize, dimension: usize) -> Self {
+        Self {
+            kind,
+            vector_count,
+            dimension,
+            estimated_bytes: estimate_vector_bytes(vector_count, dimension),
+            layer_node_counts: Vec::new(),
+            components: Vec::new(),
+        }
+    }
+}
+
+fn estimate_vector_bytes(count: usize, dimension: usize) -> usize {
+    count * (size_of::<uuid::Uuid>() + size_of::<DVector<f32>>() + dimension * size_of::<f32>())
+}
+
+/// Cosine similarity of two unit-length vectors, which is just their dot product.
+fn dot_product_similarity(a: &DVector<f32>, b: &DVector<f32>) -> f32 {
+    a.dot(b)
+}
+
+fn apply_sparse_deltas(vector: &mut DVector<f32>, deltas: &[(usize, f32)]) -> Result<()> {
+    // Check every index first so a bad update leaves the vector untouched
+    if let Some((index, _)) = deltas.iter().find(|(index, _)| *index >= vector.len()) {
+        return Err(anyhow::anyhow!(
+            "Sparse update index {} out of range for dimension {}",
+            index,
+            vector.len()
+        ));
+    }
+
+    for &(index, delta) in deltas {
+        vector[index] += delta;
+    }
+    Ok(())
+}
+
+/// How `InMemoryRetriever` scores stored vectors against a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceMetric {
+    #[default]
+    Cosine,
+    Euclidean,
+    InnerProduct,
+    Manhattan,
+}
+
+impl DistanceMetric {
+    /// Parses a `milvus.metric_type`: `COSINE`, `L2`, `IP` or `MANHATTAN`, in any case.
+    pub fn from_metric_type(metric_type: &str) -> Result<Self> {
+        match metric_type.to_ascii_uppercase().as_str() {
+            "COSINE" => Ok(Self::Cosine),
+            "L2" => Ok(Self::Euclidean),
+            "IP" => Ok(Self::InnerProduct),
+            "MANHATTAN" => Ok(Self::Manhattan),
+            other => Err(anyhow::anyhow!("Unsupported metric type {}; use COSINE, L2, IP or MANHATTAN", other)),
+        }
+    }
+
+    /// Whether scores are distances, where lower is closer.
+    pub fn is_distance(self) -> bool {
+        matches!(self, Self::Euclidean | Self::Manhattan)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct InMemoryRetriever {
     vectors: HashMap<uuid::Uuid, DVector<f32>>,
+    /// Norm of every stored vector, kept in step with `vectors`.
+    norms: HashMap<uuid::Uuid, f32>,
     dimension: usize,
+    f64_accumulation: bool,
+    cached_norms: bool,
+    normalized_vectors: bool,
+    metric: DistanceMetric,
 }
 
 impl InMemoryRetriever {
     pub fn new(dimension: usize) -> Self {
         Self {
             vectors: HashMap::new(),
+            norms: HashMap::new(),
             dimension,
+            f64_accumulation: false,
+            cached_norms: false,
+            normalized_vectors: false,
+            metric: DistanceMetric::Cosine,
         }
     }
+
+    /// Rank by `metric` instead of cosine similarity. Scores are the metric's own values, so under
+    /// a distance metric results come nearest first with ascending scores. The cosine-only options
+    /// (cached norms, normalized vectors, f64 accumulation) are ignored for other metrics.
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Declare that every stored vector has unit length (e.g. normalized at ingest), so cosine
+    /// similarity is the dot product with the normalized query and no norms are computed per vector.
+    /// Vectors are not checked; unnormalized ones get scaled scores. Ignored with f64 accumulation.
+    pub fn with_normalized_vectors(mut self, enabled: bool) -> Self {
+        self.normalized_vectors = enabled;
+        self
+    }
+
+    /// Score with the stored vectors' precomputed norms, so cosine similarity costs one dot product
+    /// per vector. Ignored with f64 accumulation, which recomputes everything in f64.
+    pub fn with_cached_norms(mut self, enabled: bool) -> Self {
+        self.cached_norms = enabled;
+        self
+    }
+
+    fn store(&mut self, id: uuid::Uuid, vector: DVector<f32>) {
+        self.norms.insert(id, vector.norm());
+        self.vectors.insert(id, vector);
+    }
+
+    fn unstore(&mut self, id: uuid::Uuid) -> Option<DVector<f32>> {
+        self.norms.remove(&id);
+        self.vectors.remove(&id)
+    }
+
+    /// Accumulate similarity scores in f64 (returned as f32) for stable rankings.
+    pub fn with_f64_accumulation(mut self, enabled: bool) -> Self {
+        self.f64_accumulation = enabled;
+        self
+    }
     
     fn cosine_similarity(&self, a: &DVector<f32>, b: &DVector<f32>) -> f32 {
+        if self.f64_accumulation {
+            return crate::utils::cosine_similarity_f64(a.as_slice(), b.as_slice());
+        }
+
         let dot_product = a.dot(b);
         let norm_a = a.norm();
         let norm_b = b.norm();
@@ -37,49 +203,135 @@ impl InMemoryRetriever {
         }
     }
     
-    fn euclidean_distance(&self, a: &DVector<f32>, b: &DVector<f32>) -> f32 {
-        (a - b).norm()
+    /// Ids of every stored vector, sorted.
+    pub fn ids(&self) -> Vec<uuid::Uuid> {
+        let mut ids: Vec<uuid::Uuid> = self.vectors.keys().copied().collect();
+        ids.sort();
+        ids
     }
-    
-    fn manhattan_distance(&self, a: &DVector<f32>, b: &DVector<f32>) -> f32 {
-        (a - b).iter().map(|x| x.abs()).sum()
+
+    pub fn get_vector(&self, id: uuid::Uuid) -> Option<&[f32]> {
+        self.vectors.get(&id).map(|vector| vector.as_slice())
     }
-}
 
-#[async_trait::async_trait]
-impl VectorRetriever for InMemoryRetriever {
-    async fn search_similar(&self, query_vector: &[f32], top_k: usize) -> Result<Vec<(uuid::Uuid, f32)>> {
+    fn rank(&self, query_vector: &[f32], top_k: usize, min_similarity: Option<f32>) -> Result<Vec<(uuid::Uuid, f32)>> {
         if query_vector.len() != self.dimension {
             return Err(anyhow::anyhow!("Query vector dimension mismatch"));
         }
+        if self.metric != DistanceMetric::Cosine {
+            return Ok(self.rank_by_metric(query_vector, top_k, min_similarity));
+        }
         
-        let query = DVector::from_vec(query_vector.to_vec());
+        let mut query = DVector::from_vec(query_vector.to_vec());
+        let query_norm = query.norm();
+        let use_dot_product = self.normalized_vectors && !self.f64_accumulation;
+        let use_cached_norms = self.cached_norms && !self.f64_accumulation;
+        if use_dot_product && query_norm > 0.0 {
+            query /= query_norm;
+        }
         let mut similarities = Vec::new();
         
         for (id, vector) in &self.vectors {
-            let similarity = self.cosine_similarity(&query, vector);
-            similarities.push((*id, similarity));
+            let similarity = if use_dot_product {
+                if query_norm == 0.0 {
+                    0.0
+                } else {
+                    dot_product_similarity(&query, vector)
+                }
+            } else if use_cached_norms {
+                let norm = self.norms[id];
+                if query_norm == 0.0 || norm == 0.0 {
+                    0.0
+                } else {
+                    query.dot(vector) / (query_norm * norm)
+                }
+            } else {
+                self.cosine_similarity(&query, vector)
+            };
+            if min_similarity.is_none_or(|floor| similarity >= floor) {
+                similarities.push((*id, similarity));
+            }
         }
         
-        // Sort by similarity in descending order
-        similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        // Sort by similarity in descending order, breaking ties by id so results don't depend on HashMap order
+        similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then_with(|| a.0.cmp(&b.0)));
         
         // Return top k results
         similarities.truncate(top_k);
         Ok(similarities)
     }
+
+    /// `rank` under a metric other than cosine. For distances `bound` is a maximum, not a minimum.
+    fn rank_by_metric(&self, query_vector: &[f32], top_k: usize, bound: Option<f32>) -> Vec<(uuid::Uuid, f32)> {
+        let query = DVector::from_vec(query_vector.to_vec());
+        let is_distance = self.metric.is_distance();
+        let mut scores: Vec<(uuid::Uuid, f32)> = self.vectors
+            .iter()
+            .map(|(id, vector)| {
+                let score = match self.metric {
+                    DistanceMetric::Euclidean => self.euclidean_distance(&query, vector),
+                    DistanceMetric::Manhattan => self.manhattan_distance(&query, vector),
+                    DistanceMetric::InnerProduct => query.dot(vector),
+                    DistanceMetric::Cosine => self.cosine_similarity(&query, vector),
+                };
+                (*id, score)
+            })
+            .filter(|(_, score)| bound.is_none_or(|bound| if is_distance { *score <= bound } else { *score >= bound }))
+            .collect();
+
+        scores.sort_by(|a, b| {
+            let order = if is_distance { a.1.partial_cmp(&b.1) } else { b.1.partial_cmp(&a.1) };
+            order.unwrap_or(Ordering::Equal).then_with(|| a.0.cmp(&b.0))
+        });
+        scores.truncate(top_k);
+        scores
+    }
+    
+    fn euclidean_distance(&self, a: &DVector<f32>, b: &DVector<f32>) -> f32 {
+        (a - b).norm()
+    }
+    
+    fn manhattan_distance(&self, a: &DVector<f32>, b: &DVector<f32>) -> f32 {
+        (a - b).iter().map(|x| x.abs()).sum()
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorRetriever for InMemoryRetriever {
+    async fn search_similar(&self, query_vector: &[f32], top_k: usize) -> Result<Vec<(uuid::Uuid, f32)>> {
+        self.rank(query_vector, top_k, None)
+    }
+
+    /// Drops results below `min_similarity` before ranking rather than after; under a distance
+    /// metric, results further than it.
+    async fn search_similar_filtered(
+        &self,
+        query_vector: &[f32],
+        top_k: usize,
+        min_similarity: f32,
+    ) -> Result<Vec<(uuid::Uuid, f32)>> {
+        self.rank(query_vector, top_k, Some(min_similarity))
+    }
+
+    /// Ranks the queries in parallel.
+    async fn search_similar_batch(&self, queries: &[&[f32]], top_k: usize) -> Result<Vec<Vec<(uuid::Uuid, f32)>>> {
+        queries
+            .par_iter()
+            .map(|query| self.rank(query, top_k, None))
+            .collect()
+    }
     
     async fn add_vector(&mut self, id: uuid::Uuid, vector: Vec<f32>) -> Result<()> {
         if vector.len() != self.dimension {
             return Err(anyhow::anyhow!("Vector dimension mismatch"));
         }
         
-        self.vectors.insert(id, DVector::from_vec(vector));
+        self.store(id, DVector::from_vec(vector));
         Ok(())
     }
     
     async fn remove_vector(&mut self, id: uuid::Uuid) -> Result<()> {
-        self.vectors.remove(&id);
+        self.unstore(id);
         Ok(())
     }
     
@@ -88,16 +340,489 @@ impl VectorRetriever for InMemoryRetriever {
             return Err(anyhow::anyhow!("Vector dimension mismatch"));
         }
         
-        self.vectors.insert(id, DVector::from_vec(vector));
+        self.store(id, DVector::from_vec(vector));
+        Ok(())
+    }
+
+    async fn update_vector_sparse(&mut self, id: uuid::Uuid, deltas: &[(usize, f32)]) -> Result<()> {
+        let vector = self.vectors
+            .get_mut(&id)
+            .ok_or_else(|| anyhow::anyhow!("Vector not found: {}", id))?;
+        apply_sparse_deltas(vector, deltas)?;
+        self.norms.insert(id, vector.norm());
+        Ok(())
+    }
+
+    fn stats(&self) -> RetrieverStats {
+        let mut stats = RetrieverStats::flat("in_memory", self.vectors.len(), self.dimension);
+        stats.estimated_bytes += self.norms.len() * (size_of::<uuid::Uuid>() + size_of::<f32>());
+        stats
+    }
+}
+
+/// Lloyd iterations when an `IVFRetriever` reclusters.
+const IVF_KMEANS_ITERATIONS: usize = 10;
+/// Seeds the reclustering, so rebuilding the same vectors reproduces the same buckets.
+const IVF_KMEANS_SEED: u64 = 0x697666;
+/// Centroids are trained on at most this many vectors per list, evenly spread over the ids.
+const IVF_TRAINING_POINTS_PER_LIST: usize = 256;
+/// Fewer vectors per list than this leave buckets too sparse to be worth clustering.
+const IVF_MIN_POINTS_PER_LIST: usize = 39;
+
+/// Inverted-file index: vectors are bucketed by their nearest k-means centroid, and a query scans
+/// only the buckets of its `nprobe` nearest centroids. Each bucket scores like the template
+/// `InMemoryRetriever`. Until the first rebuild every vector sits in one bucket and search is
+/// exact; the index reclusters itself whenever it has doubled in size since the last build, once
+/// it holds `IVF_MIN_POINTS_PER_LIST` vectors per list. That recluster trains on a copy of the
+/// vectors on the blocking pool, and the next write after it finishes swaps the new buckets in;
+/// until then, new vectors join the bucket of their nearest existing centroid.
+#[derive(Debug)]
+pub struct IVFRetriever {
+    template: InMemoryRetriever,
+    nlist: usize,
+    nprobe: usize,
+    centroids: Vec<Vec<f32>>,
+    buckets: Vec<InMemoryRetriever>,
+    bucket_of: HashMap<uuid::Uuid, usize>,
+    /// Vector count at the last rebuild, 0 before the first.
+    built_size: usize,
+    /// Background recluster in flight, if any.
+    pending: Option<tokio::task::JoinHandle<IvfLayout>>,
+    /// Ids written since the in-flight recluster copied the vectors; their trained bucket is stale.
+    written_since_snapshot: HashSet<uuid::Uuid>,
+}
+
+/// Centroids trained on a copy of an `IVFRetriever`'s vectors, and the bucket each copied id falls in.
+#[derive(Debug)]
+struct IvfLayout {
+    centroids: Vec<Vec<f32>>,
+    bucket_of: HashMap<uuid::Uuid, usize>,
+}
+
+impl IvfLayout {
+    /// Clusters `vectors` into up to `nlist` lists with k-means.
+    fn train(mut vectors: Vec<(uuid::Uuid, DVector<f32>)>, nlist: usize) -> Self {
+        // Sorted so the seeded clustering sees the same order every run
+        vectors.sort_by_key(|(id, _)| *id);
+
+        let step = vectors.len().div_ceil(nlist * IVF_TRAINING_POINTS_PER_LIST).max(1);
+        let training: Vec<&[f32]> = vectors.iter().step_by(step).map(|(_, vector)| vector.as_slice()).collect();
+        let centroids = crate::algorithms::clustering::k_means(&training, nlist, IVF_KMEANS_ITERATIONS, IVF_KMEANS_SEED).centroids;
+        let bucket_of = vectors
+            .iter()
+            .map(|(id, vector)| (*id, nearest_bucket(vector.as_slice(), &centroids)))
+            .collect();
+        Self { centroids, bucket_of }
+    }
+}
+
+fn nearest_bucket(vector: &[f32], centroids: &[Vec<f32>]) -> usize {
+    if centroids.is_empty() {
+        0
+    } else {
+        crate::algorithms::clustering::nearest_centroid(vector, centroids)
+    }
+}
+
+impl IVFRetriever {
+    pub fn new(dimension: usize, nlist: usize, nprobe: usize) -> Self {
+        Self::from_template(InMemoryRetriever::new(dimension), nlist, nprobe)
+    }
+
+    /// An index whose buckets are empty copies of `template`, so they score by its metric and options.
+    pub fn from_template(mut template: InMemoryRetriever, nlist: usize, nprobe: usize) -> Self {
+        template.vectors.clear();
+        template.norms.clear();
+        Self {
+            buckets: vec![template.clone()],
+            template,
+            nlist: nlist.max(1),
+            nprobe: nprobe.max(1),
+            centroids: Vec::new(),
+            bucket_of: HashMap::new(),
+            built_size: 0,
+            pending: None,
+            written_since_snapshot: HashSet::new(),
+        }
+    }
+
+    /// Reclusters every stored vector into up to `nlist` buckets with k-means, in place, superseding
+    /// any background recluster.
+    pub fn rebuild(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            pending.abort();
+        }
+        let layout = IvfLayout::train(self.snapshot(), self.nlist);
+        self.adopt(layout);
+    }
+
+    /// Waits for the background recluster in flight, if any, and swaps its buckets in.
+    pub async fn finish_rebuild(&mut self) -> Result<()> {
+        if let Some(pending) = self.pending.take() {
+            let layout = pending.await?;
+            self.adopt(layout);
+        }
+        Ok(())
+    }
+
+    /// Number of buckets, 1 until the first rebuild.
+    pub fn list_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    fn snapshot(&self) -> Vec<(uuid::Uuid, DVector<f32>)> {
+        self.buckets
+            .iter()
+            .flat_map(|bucket| bucket.vectors.iter().map(|(id, vector)| (*id, vector.clone())))
+            .collect()
+    }
+
+    /// Rebuckets every stored vector under `layout`'s centroids. Vectors written after its snapshot
+    /// are placed by their nearest new centroid instead.
+    fn adopt(&mut self, layout: IvfLayout) {
+        let vectors: Vec<(uuid::Uuid, DVector<f32>)> = self.buckets
+            .iter_mut()
+            .flat_map(|bucket| {
+                bucket.norms.clear();
+                bucket.vectors.drain()
+            })
+            .collect();
+        self.buckets = vec![self.template.clone(); layout.centroids.len().max(1)];
+        self.bucket_of.clear();
+        self.built_size = layout.bucket_of.len();
+        self.centroids = layout.centroids;
+        for (id, vector) in vectors {
+            let bucket = match layout.bucket_of.get(&id) {
+                Some(bucket) if !self.written_since_snapshot.contains(&id) => *bucket,
+                _ => nearest_bucket(vector.as_slice(), &self.centroids),
+            };
+            self.buckets[bucket].store(id, vector);
+            self.bucket_of.insert(id, bucket);
+        }
+        self.written_since_snapshot.clear();
+    }
+
+    fn place(&mut self, id: uuid::Uuid, vector: DVector<f32>) {
+        let bucket = nearest_bucket(vector.as_slice(), &self.centroids);
+        self.buckets[bucket].store(id, vector);
+        self.bucket_of.insert(id, bucket);
+        if self.pending.is_some() {
+            self.written_since_snapshot.insert(id);
+        }
+    }
+
+    fn unplace(&mut self, id: uuid::Uuid) -> Option<DVector<f32>> {
+        let bucket = self.bucket_of.remove(&id)?;
+        self.buckets[bucket].unstore(id)
+    }
+
+    /// Swaps in a finished background recluster, or starts one once the index has grown enough.
+    /// Only the copy of the vectors happens here; k-means runs on the blocking pool.
+    async fn rebuild_if_grown(&mut self) -> Result<()> {
+        if self.pending.as_ref().is_some_and(|pending| pending.is_finished()) {
+            self.finish_rebuild().await?;
+        }
+        let count = self.bucket_of.len();
+        if self.pending.is_none() && count >= self.nlist * IVF_MIN_POINTS_PER_LIST && count >= 2 * self.built_size {
+            let (snapshot, nlist) = (self.snapshot(), self.nlist);
+            self.pending = Some(tokio::task::spawn_blocking(move || IvfLayout::train(snapshot, nlist)));
+        }
+        Ok(())
+    }
+
+    /// Buckets of the `nprobe` centroids nearest to `query_vector`.
+    fn probed_buckets(&self, query_vector: &[f32]) -> Vec<usize> {
+        if self.centroids.is_empty() {
+            return vec![0];
+        }
+        let mut distances: Vec<(usize, f32)> = self.centroids
+            .iter()
+            .map(|centroid| crate::utils::euclidean_distance(query_vector, centroid))
+            .enumerate()
+            .collect();
+        distances.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        distances.into_iter().take(self.nprobe).map(|(bucket, _)| bucket).collect()
+    }
+
+    fn rank(&self, query_vector: &[f32], top_k: usize, bound: Option<f32>) -> Result<Vec<(uuid::Uuid, f32)>> {
+        if query_vector.len() != self.template.dimension {
+            return Err(anyhow::anyhow!("Query vector dimension mismatch"));
+        }
+
+        let mut results = Vec::new();
+        for bucket in self.probed_buckets(query_vector) {
+            results.extend(self.buckets[bucket].rank(query_vector, top_k, bound)?);
+        }
+        let is_distance = self.template.metric.is_distance();
+        results.sort_by(|a, b| {
+            let order = if is_distance { a.1.partial_cmp(&b.1) } else { b.1.partial_cmp(&a.1) };
+            order.unwrap_or(Ordering::Equal).then_with(|| a.0.cmp(&b.0))
+        });
+        results.truncate(top_k);
+        Ok(results)
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorRetriever for IVFRetriever {
+    async fn search_similar(&self, query_vector: &[f32], top_k: usize) -> Result<Vec<(uuid::Uuid, f32)>> {
+        self.rank(query_vector, top_k, None)
+    }
+
+    async fn search_similar_filtered(
+        &self,
+        query_vector: &[f32],
+        top_k: usize,
+        min_similarity: f32,
+    ) -> Result<Vec<(uuid::Uuid, f32)>> {
+        self.rank(query_vector, top_k, Some(min_similarity))
+    }
+
+    async fn search_similar_batch(&self, queries: &[&[f32]], top_k: usize) -> Result<Vec<Vec<(uuid::Uuid, f32)>>> {
+        queries
+            .par_iter()
+            .map(|query| self.rank(query, top_k, None))
+            .collect()
+    }
+
+    async fn add_vector(&mut self, id: uuid::Uuid, vector: Vec<f32>) -> Result<()> {
+        self.update_vector(id, vector).await
+    }
+
+    async fn remove_vector(&mut self, id: uuid::Uuid) -> Result<()> {
+        self.unplace(id);
         Ok(())
     }
+
+    async fn update_vector(&mut self, id: uuid::Uuid, vector: Vec<f32>) -> Result<()> {
+        if vector.len() != self.template.dimension {
+            return Err(anyhow::anyhow!("Vector dimension mismatch"));
+        }
+
+        self.unplace(id);
+        self.place(id, DVector::from_vec(vector));
+        self.rebuild_if_grown().await
+    }
+
+    /// The updated vector moves to the bucket of its now nearest centroid.
+    async fn update_vector_sparse(&mut self, id: uuid::Uuid, deltas: &[(usize, f32)]) -> Result<()> {
+        let bucket = *self.bucket_of
+            .get(&id)
+            .ok_or_else(|| anyhow::anyhow!("Vector not found: {}", id))?;
+        let mut vector = self.buckets[bucket].vectors[&id].clone();
+        apply_sparse_deltas(&mut vector, deltas)?;
+        self.unplace(id);
+        self.place(id, vector);
+        Ok(())
+    }
+
+    fn stats(&self) -> RetrieverStats {
+        let mut stats = RetrieverStats::flat("ivf_flat", self.bucket_of.len(), self.template.dimension);
+        stats.estimated_bytes += self.bucket_of.len() * (2 * size_of::<uuid::Uuid>() + size_of::<f32>() + size_of::<usize>())
+            + self.centroids.len() * self.template.dimension * size_of::<f32>();
+        stats
+    }
+}
+
+/// Picks which sub-retriever of a `HybridRetriever` stores a vector (an index into its retrievers).
+pub type WritePolicy = Box<dyn Fn(uuid::Uuid, &[f32]) -> usize + Send + Sync>;
+
+/// Fans queries out to several retrievers (e.g. an exact one for hot items and an approximate
+/// one for the cold bulk) and merges their weighted results into a single top-k.
+pub struct HybridRetriever {
+    retrievers: Vec<(Box<dyn VectorRetriever>, f32)>,
+    write_policy: WritePolicy,
+}
+
+impl HybridRetriever {
+    pub fn new(write_policy: WritePolicy) -> Self {
+        Self {
+            retrievers: Vec::new(),
+            write_policy,
+        }
+    }
+
+    /// Adds a sub-retriever whose scores are multiplied by `weight` when merging.
+    pub fn with_retriever(mut self, retriever: Box<dyn VectorRetriever>, weight: f32) -> Self {
+        self.retrievers.push((retriever, weight));
+        self
+    }
+
+    fn route(&self, id: uuid::Uuid, vector: &[f32]) -> Result<usize> {
+        let index = (self.write_policy)(id, vector);
+        if index >= self.retrievers.len() {
+            return Err(anyhow::anyhow!(
+                "Write policy routed to retriever {} but only {} are configured",
+                index,
+                self.retrievers.len()
+            ));
+        }
+        Ok(index)
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorRetriever for HybridRetriever {
+    async fn search_similar(&self, query_vector: &[f32], top_k: usize) -> Result<Vec<(uuid::Uuid, f32)>> {
+        let searches = self.retrievers
+            .iter()
+            .map(|(retriever, _)| retriever.search_similar(query_vector, top_k));
+        let results = futures::future::try_join_all(searches).await?;
+
+        // Dedup by id, keeping the best weighted score
+        let mut merged: HashMap<uuid::Uuid, f32> = HashMap::new();
+        for ((_, weight), retriever_results) in self.retrievers.iter().zip(results) {
+            for (id, score) in retriever_results {
+                let weighted = score * weight;
+                merged
+                    .entry(id)
+                    .and_modify(|best| *best = best.max(weighted))
+                    .or_insert(weighted);
+            }
+        }
+
+        let mut merged: Vec<(uuid::Uuid, f32)> = merged.into_iter().collect();
+        merged.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then_with(|| a.0.cmp(&b.0)));
+        merged.truncate(top_k);
+        Ok(merged)
+    }
+
+    async fn add_vector(&mut self, id: uuid::Uuid, vector: Vec<f32>) -> Result<()> {
+        let index = self.route(id, &vector)?;
+        self.retrievers[index].0.add_vector(id, vector).await
+    }
+
+    async fn remove_vector(&mut self, id: uuid::Uuid) -> Result<()> {
+        for (retriever, _) in self.retrievers.iter_mut() {
+            retriever.remove_vector(id).await?;
+        }
+        Ok(())
+    }
+
+    async fn update_vector(&mut self, id: uuid::Uuid, vector: Vec<f32>) -> Result<()> {
+        // The policy may now route the vector elsewhere, so drop any stale copy first
+        let index = self.route(id, &vector)?;
+        self.remove_vector(id).await?;
+        self.retrievers[index].0.add_vector(id, vector).await
+    }
+
+    async fn update_vector_sparse(&mut self, id: uuid::Uuid, deltas: &[(usize, f32)]) -> Result<()> {
+        let mut last_error = None;
+        for (retriever, _) in self.retrievers.iter_mut() {
+            match retriever.update_vector_sparse(id, deltas).await {
+                Ok(()) => return Ok(()),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("Vector not found: {}", id)))
+    }
+
+    fn stats(&self) -> RetrieverStats {
+        let components: Vec<RetrieverStats> = self.retrievers
+            .iter()
+            .map(|(retriever, _)| retriever.stats())
+            .collect();
+        RetrieverStats {
+            kind: "hybrid",
+            vector_count: components.iter().map(|stats| stats.vector_count).sum(),
+            dimension: components.first().map_or(0, |stats| stats.dimension),
+            estimated_bytes: components.iter().map(|stats| stats.estimated_bytes).sum(),
+            layer_node_counts: Vec::new(),
+            components,
+        }
+    }
+}
+
+/// Immutable snapshot of an index, for serving replicas: searches go to the snapshot and every
+/// write is refused. Replicas pick up new data by loading a fresh snapshot, not by writing.
+pub struct ReadOnlyRetriever {
+    snapshot: Box<dyn VectorRetriever>,
+}
+
+impl ReadOnlyRetriever {
+    pub fn from_snapshot(snapshot: Box<dyn VectorRetriever>) -> Self {
+        Self { snapshot }
+    }
+}
+
+fn read_only_error(id: uuid::Uuid) -> anyhow::Error {
+    anyhow::anyhow!("Cannot write vector {}: the index is a read-only snapshot", id)
+}
+
+#[async_trait::async_trait]
+impl VectorRetriever for ReadOnlyRetriever {
+    async fn search_similar(&self, query_vector: &[f32], top_k: usize) -> Result<Vec<(uuid::Uuid, f32)>> {
+        self.snapshot.search_similar(query_vector, top_k).await
+    }
+
+    async fn search_similar_filtered(
+        &self,
+        query_vector: &[f32],
+        top_k: usize,
+        min_similarity: f32,
+    ) -> Result<Vec<(uuid::Uuid, f32)>> {
+        self.snapshot.search_similar_filtered(query_vector, top_k, min_similarity).await
+    }
+
+    async fn search_similar_batch(&self, queries: &[&[f32]], top_k: usize) -> Result<Vec<Vec<(uuid::Uuid, f32)>>> {
+        self.snapshot.search_similar_batch(queries, top_k).await
+    }
+
+    async fn add_vector(&mut self, id: uuid::Uuid, _vector: Vec<f32>) -> Result<()> {
+        Err(read_only_error(id))
+    }
+
+    async fn remove_vector(&mut self, id: uuid::Uuid) -> Result<()> {
+        Err(read_only_error(id))
+    }
+
+    async fn update_vector(&mut self, id: uuid::Uuid, _vector: Vec<f32>) -> Result<()> {
+        Err(read_only_error(id))
+    }
+
+    async fn update_vector_sparse(&mut self, id: uuid::Uuid, _deltas: &[(usize, f32)]) -> Result<()> {
+        Err(read_only_error(id))
+    }
+
+    fn is_shared(&self) -> bool {
+        self.snapshot.is_shared()
+    }
+
+    fn stats(&self) -> RetrieverStats {
+        self.snapshot.stats()
+    }
+}
+
+/// Node reached during a layer search, ordered by distance to the query, ties by id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LayerCandidate {
+    distance: f32,
+    id: uuid::Uuid,
+}
+
+impl Eq for LayerCandidate {}
+
+impl PartialOrd for LayerCandidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for LayerCandidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then_with(|| self.id.cmp(&other.id))
+    }
 }
 
+/// Hierarchical Navigable Small World graph over squared L2 distance. Search scores are those
+/// distances, closest first.
 #[derive(Debug, Clone)]
 pub struct HNSWRetriever {
     // Hierarchical Navigable Small World implementation
     layers: Vec<HashMap<uuid::Uuid, Vec<uuid::Uuid>>>,
     vectors: HashMap<uuid::Uuid, DVector<f32>>,
+    /// Node every search starts from, with its top layer.
+    entry_point: Option<(uuid::Uuid, usize)>,
     dimension: usize,
     max_connections: usize,
     ef_construction: usize,
@@ -109,6 +834,7 @@ impl HNSWRetriever {
         Self {
             layers: vec![HashMap::new()],
             vectors: HashMap::new(),
+            entry_point: None,
             dimension,
             max_connections,
             ef_construction,
@@ -117,72 +843,135 @@ impl HNSWRetriever {
     }
     
     fn get_random_level(&self) -> usize {
-        let mut level = 0;
-        while rand::random::<f64>() < 0.5 && level < 16 {
-            level += 1;
-        }
-        level
+        let uniform = 1.0 - rand::random::<f64>();
+        ((-uniform.ln() * self.ml) as usize).min(16)
     }
     
-    fn distance(&self, a: &DVector<f32>, b: &DVector<f32>) -> f32 {
-        (a - b).norm_squared()
+    fn distance(a: &DVector<f32>, b: &DVector<f32>) -> f32 {
+        a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
     }
     
-    async fn search_layer(&self, query: &DVector<f32>, entry_points: Vec<uuid::Uuid>, 
-                         num_closest: usize, layer: usize) -> Result<Vec<(uuid::Uuid, f32)>> {
+    /// The `num_closest` nodes of `layer` nearest to `query` reachable from `entry_points`,
+    /// closest first.
+    fn search_layer(&self, query: &DVector<f32>, entry_points: &[uuid::Uuid],
+                    num_closest: usize, layer: usize) -> Vec<LayerCandidate> {
         let mut visited = std::collections::HashSet::new();
         let mut candidates = std::collections::BinaryHeap::new();
         let mut w = std::collections::BinaryHeap::new();
         
-        for ep in entry_points {
+        for &ep in entry_points {
             if let Some(vector) = self.vectors.get(&ep) {
-                let dist = self.distance(query, vector);
-                candidates.push(std::cmp::Reverse((dist as i32, ep)));
-                w.push((dist as i32, ep));
-                visited.insert(ep);
+                if visited.insert(ep) {
+                    let candidate = LayerCandidate { distance: Self::distance(query, vector), id: ep };
+                    candidates.push(std::cmp::Reverse(candidate));
+                    w.push(candidate);
+                }
             }
         }
+        while w.len() > num_closest {
+            w.pop();
+        }
         
-        while let Some(std::cmp::Reverse((current_dist, current))) = candidates.pop() {
-            if w.len() >= num_closest {
-                if let Some((furthest_dist, _)) = w.peek() {
-                    if current_dist > *furthest_dist {
-                        break;
-                    }
-                }
+        while let Some(std::cmp::Reverse(current)) = candidates.pop() {
+            if w.len() >= num_closest && w.peek().is_some_and(|furthest| current > *furthest) {
+                break;
             }
             
-            if let Some(connections) = self.layers.get(layer).and_then(|l| l.get(&current)) {
-                for &neighbor in connections {
-                    if !visited.contains(&neighbor) {
-                        visited.insert(neighbor);
-                        
-                        if let Some(neighbor_vector) = self.vectors.get(&neighbor) {
-                            let dist = self.distance(query, neighbor_vector);
-                            
-                            if w.len() < num_closest {
-                                candidates.push(std::cmp::Reverse((dist as i32, neighbor)));
-                                w.push((dist as i32, neighbor));
-                            } else if let Some((furthest_dist, _furthest_id)) = w.peek() {
-                                if (dist as i32) < *furthest_dist {
-                                    candidates.push(std::cmp::Reverse((dist as i32, neighbor)));
-                                    w.pop();
-                                    w.push((dist as i32, neighbor));
-                                }
-                            }
-                        }
+            let Some(connections) = self.layers.get(layer).and_then(|l| l.get(&current.id)) else {
+                continue;
+            };
+            for &neighbor in connections {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let Some(neighbor_vector) = self.vectors.get(&neighbor) else {
+                    continue;
+                };
+                let candidate = LayerCandidate { distance: Self::distance(query, neighbor_vector), id: neighbor };
+                if w.len() < num_closest || w.peek().is_some_and(|furthest| candidate < *furthest) {
+                    candidates.push(std::cmp::Reverse(candidate));
+                    w.push(candidate);
+                    if w.len() > num_closest {
+                        w.pop();
                     }
                 }
             }
         }
         
-        let mut result = Vec::new();
-        while let Some((dist, id)) = w.pop() {
-            result.push((id, dist as f32));
+        w.into_sorted_vec()
+    }
+
+    /// Picks up to `max_connections` of `candidates` (closest first) with the neighbor-selection
+    /// heuristic: a candidate closer to an already selected neighbor than to the base node is
+    /// skipped, so edges spread in different directions. Skipped candidates fill any slots left.
+    fn select_neighbors(&self, candidates: &[LayerCandidate]) -> Vec<uuid::Uuid> {
+        let mut selected: Vec<uuid::Uuid> = Vec::with_capacity(self.max_connections);
+        let mut skipped = Vec::new();
+        for candidate in candidates {
+            if selected.len() >= self.max_connections {
+                break;
+            }
+            let vector = &self.vectors[&candidate.id];
+            let diverse = selected
+                .iter()
+                .all(|chosen| Self::distance(vector, &self.vectors[chosen]) > candidate.distance);
+            if diverse {
+                selected.push(candidate.id);
+            } else {
+                skipped.push(candidate.id);
+            }
         }
-        result.reverse();
+        let free = self.max_connections - selected.len();
+        selected.extend(skipped.into_iter().take(free));
+        selected
+    }
+
+    /// Links a node already present in `layer` to `neighbors` in both directions. A neighbor
+    /// pushed over `max_connections` edges drops its furthest one, which is much cheaper than
+    /// re-running the heuristic on every insert.
+    fn connect(&mut self, id: uuid::Uuid, neighbors: Vec<uuid::Uuid>, layer: usize) {
+        for &neighbor in &neighbors {
+            let base = &self.vectors[&neighbor];
+            let Some(connections) = self.layers[layer].get_mut(&neighbor) else {
+                continue;
+            };
+            connections.push(id);
+            if connections.len() <= self.max_connections {
+                continue;
+            }
+
+            let furthest = connections
+                .iter()
+                .enumerate()
+                .map(|(i, &other)| (i, LayerCandidate { distance: Self::distance(base, &self.vectors[&other]), id: other }))
+                .max_by(|a, b| a.1.cmp(&b.1))
+                .map(|(i, _)| i);
+            if let Some(i) = furthest {
+                connections.swap_remove(i);
+            }
+        }
+        self.layers[layer].insert(id, neighbors);
+    }
+
+    fn remove_node(&mut self, id: uuid::Uuid) {
+        self.vectors.remove(&id);
         
-        Ok(result)
+        for layer in &mut self.layers {
+            layer.remove(&id);
+            // Also remove from other nodes' connection lists
+            for connections in layer.values_mut() {
+                connections.retain(|&x| x != id);
+            }
+        }
+
+        while self.layers.len() > 1 && self.layers.last().is_some_and(|layer| layer.is_empty()) {
+            self.layers.pop();
+        }
+        if self.entry_point.is_some_and(|(entry, _)| entry == id) {
+            let top = self.layers.len() - 1;
+            // Lowest id rather than map order, so the choice is deterministic
+            self.entry_point = self.layers[top].keys().min().map(|&node| (node, top));
+        }
     }
 }
 
@@ -192,38 +981,34 @@ impl VectorRetriever for HNSWRetriever {
         if query_vector.len() != self.dimension {
             return Err(anyhow::anyhow!("Query vector dimension mismatch"));
         }
-        
-        let query = DVector::from_vec(query_vector.to_vec());
-        
-        // Start from the top layer and work down
-        let mut entry_points = vec![];
-        
-        // Find entry point from top layer
-        if let Some(top_layer) = self.layers.last() {
-            if let Some(first_node) = top_layer.keys().next() {
-                entry_points.push(*first_node);
-            }
-        }
-        
-        if entry_points.is_empty() {
+        let Some((entry, top_layer)) = self.entry_point else {
+            return Ok(Vec::new());
+        };
+        if top_k == 0 {
             return Ok(Vec::new());
         }
         
-        // Search through layers
-        for layer in (1..self.layers.len()).rev() {
-            let results = self.search_layer(&query, entry_points.clone(), 1, layer).await?;
-            entry_points = results.into_iter().map(|(id, _)| id).collect();
+        let query = DVector::from_vec(query_vector.to_vec());
+        
+        // Greedy descent from the top layer to the bottom one
+        let mut entry_points = vec![entry];
+        for layer in (1..=top_layer).rev() {
+            entry_points = self.search_layer(&query, &entry_points, 1, layer).iter().map(|c| c.id).collect();
         }
         
-        // Search the bottom layer
-        let results = self.search_layer(&query, entry_points, top_k, 0).await?;
-        Ok(results)
+        // Search the bottom layer with at least the construction beam width, for recall
+        let mut results = self.search_layer(&query, &entry_points, top_k.max(self.ef_construction), 0);
+        results.truncate(top_k);
+        Ok(results.into_iter().map(|candidate| (candidate.id, candidate.distance)).collect())
     }
     
     async fn add_vector(&mut self, id: uuid::Uuid, vector: Vec<f32>) -> Result<()> {
         if vector.len() != self.dimension {
             return Err(anyhow::anyhow!("Vector dimension mismatch"));
         }
+        if self.vectors.contains_key(&id) {
+            self.remove_node(id);
+        }
         
         let level = self.get_random_level();
         
@@ -232,36 +1017,70 @@ impl VectorRetriever for HNSWRetriever {
             self.layers.push(HashMap::new());
         }
         
-        self.vectors.insert(id, DVector::from_vec(vector));
-        
-        // Add to all layers up to the determined level
+        let query = DVector::from_vec(vector);
+        self.vectors.insert(id, query.clone());
         for l in 0..=level {
             self.layers[l].insert(id, Vec::new());
         }
+
+        let Some((entry, top_layer)) = self.entry_point else {
+            self.entry_point = Some((id, level));
+            return Ok(());
+        };
+
+        // Descend greedily through the layers above the new node's level
+        let mut entry_points = vec![entry];
+        for l in (level + 1..=top_layer).rev() {
+            entry_points = self.search_layer(&query, &entry_points, 1, l).iter().map(|c| c.id).collect();
+        }
+
+        // Link the node to its nearest neighbors on each of its layers
+        for l in (0..=level.min(top_layer)).rev() {
+            let nearest = self.search_layer(&query, &entry_points, self.ef_construction, l);
+            let nearest: Vec<LayerCandidate> = nearest.into_iter().filter(|c| c.id != id).collect();
+            let neighbors = self.select_neighbors(&nearest);
+            self.connect(id, neighbors, l);
+            entry_points = nearest.iter().map(|c| c.id).collect();
+            if entry_points.is_empty() {
+                entry_points.push(entry);
+            }
+        }
+
+        if level > top_layer {
+            self.entry_point = Some((id, level));
+        }
         
         Ok(())
     }
     
     async fn remove_vector(&mut self, id: uuid::Uuid) -> Result<()> {
-        self.vectors.remove(&id);
-        
-        for layer in &mut self.layers {
-            layer.remove(&id);
-            // Also remove from other nodes' connection lists
-            for connections in layer.values_mut() {
-                connections.retain(|&x| x != id);
-            }
-        }
-        
+        self.remove_node(id);
         Ok(())
     }
     
+    /// Re-inserts the node, so its edges match the new vector.
     async fn update_vector(&mut self, id: uuid::Uuid, vector: Vec<f32>) -> Result<()> {
-        if vector.len() != self.dimension {
-            return Err(anyhow::anyhow!("Vector dimension mismatch"));
-        }
-        
-        self.vectors.insert(id, DVector::from_vec(vector));
-        Ok(())
+        self.add_vector(id, vector).await
+    }
+
+    async fn update_vector_sparse(&mut self, id: uuid::Uuid, deltas: &[(usize, f32)]) -> Result<()> {
+        let vector = self.vectors
+            .get_mut(&id)
+            .ok_or_else(|| anyhow::anyhow!("Vector not found: {}", id))?;
+        apply_sparse_deltas(vector, deltas)
+    }
+
+    fn stats(&self) -> RetrieverStats {
+        let graph_bytes: usize = self.layers
+            .iter()
+            .flat_map(|layer| layer.values())
+            .map(|connections| {
+                size_of::<uuid::Uuid>() + size_of::<Vec<uuid::Uuid>>() + connections.len() * size_of::<uuid::Uuid>()
+            })
+            .sum();
+        let mut stats = RetrieverStats::flat("hnsw", self.vectors.len(), self.dimension);
+        stats.estimated_bytes += graph_bytes;
+        stats.layer_node_counts = self.layers.iter().map(|layer| layer.len()).collect();
+        stats
     }
 }
@@ -0,0 +1,258 @@
+use super::optimizer::{Optimizer, SGD};
+use super::{initializer, RecommendationAlgorithm};
+use crate::models::*;
+use crate::utils::relu;
+use anyhow::Result;
+use nalgebra::DVector;
+use std::collections::HashMap;
+
+/// A one-hidden-layer MLP, `output · relu(hidden · x + hidden_bias) + output_bias`, with one
+/// weight vector per unit so each can be stepped by an `Optimizer` under its own key.
+#[derive(Debug, Clone)]
+pub struct Tower {
+    pub hidden: Vec<DVector<f32>>,
+    pub hidden_bias: DVector<f32>,
+    pub output: Vec<DVector<f32>>,
+    pub output_bias: DVector<f32>,
+}
+
+/// What a forward pass leaves behind for the backward pass.
+struct Activations {
+    pre_activation: DVector<f32>,
+    hidden: DVector<f32>,
+    output: DVector<f32>,
+}
+
+impl Tower {
+    /// He-initialized hidden units for the relu, Xavier-initialized output units, zero biases.
+    pub fn new(input_dim: usize, hidden_dim: usize, output_dim: usize) -> Self {
+        Self {
+            hidden: (0..hidden_dim).map(|_| DVector::from_vec(initializer::he_uniform(input_dim))).collect(),
+            hidden_bias: DVector::zeros(hidden_dim),
+            output: (0..output_dim).map(|_| DVector::from_vec(initializer::xavier_uniform(hidden_dim))).collect(),
+            output_bias: DVector::zeros(output_dim),
+        }
+    }
+
+    pub fn forward(&self, input: &DVector<f32>) -> DVector<f32> {
+        self.activations(input).output
+    }
+
+    fn activations(&self, input: &DVector<f32>) -> Activations {
+        let pre_activation = DVector::from_iterator(
+            self.hidden.len(),
+            self.hidden.iter().zip(self.hidden_bias.iter()).map(|(unit, bias)| unit.dot(input) + bias),
+        );
+        let hidden = pre_activation.map(relu);
+        let output = DVector::from_iterator(
+            self.output.len(),
+            self.output.iter().zip(self.output_bias.iter()).map(|(unit, bias)| unit.dot(&hidden) + bias),
+        );
+        Activations { pre_activation, hidden, output }
+    }
+
+    /// Steps every weight against `output_gradient`, the loss gradient at this tower's output, and
+    /// returns the gradient at its input (taken before the step). Optimizer state is kept under
+    /// `name`.
+    fn backward(
+        &mut self,
+        name: &str,
+        optimizer: &mut dyn Optimizer,
+        input: &DVector<f32>,
+        activations: &Activations,
+        output_gradient: &DVector<f32>,
+        regularization: f32,
+    ) -> DVector<f32> {
+        let mut hidden_gradient = DVector::zeros(self.hidden.len());
+        for (unit, gradient) in self.output.iter().zip(output_gradient.iter()) {
+            hidden_gradient += unit * *gradient;
+        }
+        let pre_activation_gradient = hidden_gradient.zip_map(&activations.pre_activation, |gradient, x| {
+            if x > 0.0 { gradient } else { 0.0 }
+        });
+        let mut input_gradient = DVector::zeros(input.len());
+        for (unit, gradient) in self.hidden.iter().zip(pre_activation_gradient.iter()) {
+            input_gradient += unit * *gradient;
+        }
+
+        for (k, (unit, gradient)) in self.output.iter_mut().zip(output_gradient.iter()).enumerate() {
+            let unit_gradient = &activations.hidden * *gradient + &*unit * regularization;
+            optimizer.update_with_key(&format!("{}:output:{}", name, k), unit, &unit_gradient);
+        }
+        optimizer.update_with_key(&format!("{}:output_bias", name), &mut self.output_bias, output_gradient);
+        for (j, (unit, gradient)) in self.hidden.iter_mut().zip(pre_activation_gradient.iter()).enumerate() {
+            let unit_gradient = input * *gradient + &*unit * regularization;
+            optimizer.update_with_key(&format!("{}:hidden:{}", name, j), unit, &unit_gradient);
+        }
+        optimizer.update_with_key(&format!("{}:hidden_bias", name), &mut self.hidden_bias, &pre_activation_gradient);
+
+        input_gradient
+    }
+}
+
+/// Scores the dot product of a user tower and an item tower, each a `Tower` over the raw
+/// embedding. The per-id embeddings, like `CollaborativeFiltering`'s, are learned along with the
+/// towers; `predict` runs the towers on whatever features it is given.
+#[derive(Debug)]
+pub struct TwoTower {
+    pub user_embeddings: HashMap<uuid::Uuid, DVector<f32>>,
+    pub item_embeddings: HashMap<uuid::Uuid, DVector<f32>>,
+    pub user_tower: Tower,
+    pub item_tower: Tower,
+    pub embedding_dim: usize,
+    pub regularization: f64,
+    /// Steps the towers under `user_tower:*` and `item_tower:*`, and embeddings under `user:<id>`
+    /// or `item:<id>`.
+    optimizer: Box<dyn Optimizer>,
+}
+
+impl TwoTower {
+    /// Trains with plain SGD at `learning_rate`. Both towers map `embedding_dim` to
+    /// `embedding_dim` through `hidden_dim` units.
+    pub fn new(embedding_dim: usize, hidden_dim: usize, learning_rate: f64, regularization: f64) -> Self {
+        Self::with_optimizer(embedding_dim, hidden_dim, regularization, Box::new(SGD::new(learning_rate)))
+    }
+
+    pub fn with_optimizer(
+        embedding_dim: usize,
+        hidden_dim: usize,
+        regularization: f64,
+        optimizer: Box<dyn Optimizer>,
+    ) -> Self {
+        Self {
+            user_embeddings: HashMap::new(),
+            item_embeddings: HashMap::new(),
+            user_tower: Tower::new(embedding_dim, hidden_dim, embedding_dim),
+            item_tower: Tower::new(embedding_dim, hidden_dim, embedding_dim),
+            embedding_dim,
+            regularization,
+            optimizer,
+        }
+    }
+
+    pub fn initialize_user_embedding(&mut self, user_id: uuid::Uuid) {
+        if !self.user_embeddings.contains_key(&user_id) {
+            let embedding = initializer::xavier_uniform(self.embedding_dim);
+            self.user_embeddings.insert(user_id, DVector::from_vec(embedding));
+        }
+    }
+
+    pub fn initialize_item_embedding(&mut self, item_id: uuid::Uuid) {
+        if !self.item_embeddings.contains_key(&item_id) {
+            let embedding = initializer::xavier_uniform(self.embedding_dim);
+            self.item_embeddings.insert(item_id, DVector::from_vec(embedding));
+        }
+    }
+
+    /// One optimizer step on the squared error of `example`, backpropagated through both towers
+    /// into the user and item embeddings, with L2 regularization.
+    pub fn train_example(&mut self, example: &TrainingExample) -> Result<()> {
+        self.initialize_user_embedding(example.user_id);
+        self.initialize_item_embedding(example.item_id);
+
+        let mut user_emb = self.user_embeddings[&example.user_id].clone();
+        let mut item_emb = self.item_embeddings[&example.item_id].clone();
+        let user_activations = self.user_tower.activations(&user_emb);
+        let item_activations = self.item_tower.activations(&item_emb);
+
+        let prediction = user_activations.output.dot(&item_activations.output);
+        let error = example.label - prediction;
+        let regularization = self.regularization as f32;
+
+        let user_output_gradient = &item_activations.output * -error;
+        let item_output_gradient = &user_activations.output * -error;
+        let user_input_gradient = self.user_tower.backward(
+            "user_tower",
+            self.optimizer.as_mut(),
+            &user_emb,
+            &user_activations,
+            &user_output_gradient,
+            regularization,
+        );
+        let item_input_gradient = self.item_tower.backward(
+            "item_tower",
+            self.optimizer.as_mut(),
+            &item_emb,
+            &item_activations,
+            &item_output_gradient,
+            regularization,
+        );
+
+        let user_gradient = user_input_gradient + &user_emb * regularization;
+        let item_gradient = item_input_gradient + &item_emb * regularization;
+        self.optimizer.update_with_key(&format!("user:{}", example.user_id), &mut user_emb, &user_gradient);
+        self.optimizer.update_with_key(&format!("item:{}", example.item_id), &mut item_emb, &item_gradient);
+
+        self.user_embeddings.insert(example.user_id, user_emb);
+        self.item_embeddings.insert(example.item_id, item_emb);
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl RecommendationAlgorithm for TwoTower {
+    async fn train(&mut self, examples: &[TrainingExample]) -> Result<()> {
+        for example in examples {
+            self.train_example(example)?;
+        }
+        Ok(())
+    }
+
+    async fn predict(&self, user_features: &[f32], item_features: &[f32]) -> Result<f32> {
+        for (tower, features) in [("user", user_features), ("item", item_features)] {
+            if features.len() != self.embedding_dim {
+                return Err(anyhow::anyhow!(
+                    "Feature dimension mismatch: {} features {} vs tower input {}",
+                    tower,
+                    features.len(),
+                    self.embedding_dim
+                ));
+            }
+        }
+
+        let user_output = self.user_tower.forward(&DVector::from_vec(user_features.to_vec()));
+        let item_output = self.item_tower.forward(&DVector::from_vec(item_features.to_vec()));
+        Ok(user_output.dot(&item_output))
+    }
+
+    async fn get_user_embedding(&self, user_id: uuid::Uuid) -> Result<Vec<f32>> {
+        if let Some(embedding) = self.user_embeddings.get(&user_id) {
+            Ok(embedding.as_slice().to_vec())
+        } else {
+            Ok(initializer::xavier_uniform(self.embedding_dim))
+        }
+    }
+
+    async fn get_item_embedding(&self, item_id: uuid::Uuid) -> Result<Vec<f32>> {
+        if let Some(embedding) = self.item_embeddings.get(&item_id) {
+            Ok(embedding.as_slice().to_vec())
+        } else {
+            Ok(initializer::xavier_uniform(self.embedding_dim))
+        }
+    }
+
+    /// Replaces the learned embeddings with the snapshot's; the snapshot carries no tower weights,
+    /// so the towers keep theirs. Nothing changes if any embedding has the wrong dimension.
+    async fn update_parameters(&mut self, parameters: &ModelParameters) -> Result<()> {
+        let embeddings = parameters.user_embeddings.values().chain(parameters.item_embeddings.values());
+        if let Some(embedding) = embeddings.into_iter().find(|embedding| embedding.len() != self.embedding_dim) {
+            return Err(anyhow::anyhow!(
+                "Model parameters {} hold {}-dimensional embeddings, model expects {}",
+                parameters.version,
+                embedding.len(),
+                self.embedding_dim
+            ));
+        }
+
+        let to_vectors = |embeddings: &HashMap<uuid::Uuid, Vec<f32>>| {
+            embeddings
+                .iter()
+                .map(|(id, embedding)| (*id, DVector::from_vec(embedding.clone())))
+                .collect()
+        };
+        self.user_embeddings = to_vectors(&parameters.user_embeddings);
+        self.item_embeddings = to_vectors(&parameters.item_embeddings);
+        Ok(())
+    }
+}
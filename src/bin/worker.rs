@@ -2,7 +2,9 @@ use milvuso::{init_tracing, AppState, Config};
 use anyhow::Result;
 use clap::Parser;
 use tokio::sync::mpsc;
-use tracing::{info, error};
+use tracing::{info, warn, error};
+use milvuso::utils::shutdown::shutdown_signal;
+use milvuso::utils::validation::validate_feature_vector_with_sanitization;
 use chrono::{Timelike, Datelike};
 
 #[derive(Parser, Debug)]
@@ -38,21 +40,28 @@ async fn main() -> Result<()> {
 
     // Initialize application state
     let state = AppState::new(config).await?;
+    let kafka_producer = state.kafka_producer.clone();
+    let drain_timeout = std::time::Duration::from_secs(state.config.server.shutdown_timeout_seconds);
 
-    match args.worker_type.as_str() {
-        "feature" => {
-            start_feature_worker(state).await?;
-        }
-        "action" => {
-            start_action_worker(state).await?;
-        }
-        "joiner" => {
-            start_joiner_worker(state).await?;
-        }
-        _ => {
-            error!("Unknown worker type: {}", args.worker_type);
-            return Err(anyhow::anyhow!("Invalid worker type"));
+    let worker = async {
+        match args.worker_type.as_str() {
+            "feature" => start_feature_worker(state).await,
+            "action" => start_action_worker(state).await,
+            "joiner" => start_joiner_worker(state).await,
+            _ => {
+                error!("Unknown worker type: {}", args.worker_type);
+                Err(anyhow::anyhow!("Invalid worker type"))
+            }
         }
+    };
+
+    // Stops consuming on shutdown, then delivers what the worker already produced
+    tokio::select! {
+        result = worker => result?,
+        _ = shutdown_signal() => info!("Worker {} shutting down", args.worker_type),
+    }
+    if let Err(e) = tokio::task::spawn_blocking(move || kafka_producer.flush(drain_timeout)).await? {
+        warn!("Failed to flush Kafka producer on shutdown: {}", e);
     }
 
     Ok(())
@@ -143,7 +152,12 @@ async fn start_joiner_worker(state: AppState) -> Result<()> {
                 }
             }
             feature = feature_rx.recv() => {
-                if let Some(feature) = feature {
+                if let Some(mut feature) = feature {
+                    let sanitize = state.config.recommendation.sanitize_non_finite_embeddings;
+                    if let Err(e) = validate_feature_vector_with_sanitization(&mut feature, sanitize) {
+                        warn!("Dropping invalid feature vector {}: {}", feature.id, e);
+                        continue;
+                    }
                     feature_buffer.push(feature);
                     if feature_buffer.len() >= 100 {
                         feature_buffer.clear(); // Keep buffer size manageable
@@ -193,6 +207,7 @@ async fn generate_feature_vector_from_action(action: &milvuso::UserAction) -> Re
         milvuso::ActionType::Share => features[3] = 1.0,
         milvuso::ActionType::Purchase => features[4] = 1.0,
         milvuso::ActionType::Convert => features[5] = 1.0,
+        milvuso::ActionType::Dislike | milvuso::ActionType::Hide | milvuso::ActionType::NotInterested => features[8] = 1.0,
     }
     
     // Time-based features
@@ -204,8 +219,8 @@ async fn generate_feature_vector_from_action(action: &milvuso::UserAction) -> Re
     // Add some random features for demonstration
     use rand::Rng;
     let mut rng = rand::thread_rng();
-    for i in 8..128 {
-        features[i] = rng.gen_range(-1.0..1.0);
+    for feature in features.iter_mut().skip(9) {
+        *feature = rng.gen_range(-1.0..1.0);
     }
     
     Ok(features)
@@ -216,7 +231,13 @@ async fn process_joined_data(
     actions: &[milvuso::UserAction],
     _features: &[milvuso::FeatureVector],
 ) -> Result<()> {
+    let min_action_weight = state.config.recommendation.min_action_weight;
+    let action_weights = &state.config.action_weights;
     for action in actions {
+        if !action.action_type.is_negative() && action_weights.weight(&action.action_type) < min_action_weight {
+            continue;
+        }
+
         // Create training example from joined data
         let user_profile = state.vector_db.get_user_profile(action.user_id).await?
             .unwrap_or_else(|| milvuso::UserProfile::new(action.user_id, 128));
@@ -227,10 +248,10 @@ async fn process_joined_data(
             let training_example = milvuso::TrainingExample {
                 user_id: action.user_id,
                 item_id: action.item_id,
-                label: get_label_from_action(&action.action_type),
+                label: action_weights.weight(&action.action_type),
                 user_features: user_profile.embedding,
                 item_features: item_feature.embedding,
-                context_features: generate_context_features(action).await?,
+                context_features: generate_context_features(action, action_weights).await?,
                 timestamp: action.timestamp,
             };
 
@@ -243,18 +264,10 @@ async fn process_joined_data(
     Ok(())
 }
 
-fn get_label_from_action(action_type: &milvuso::ActionType) -> f32 {
-    match action_type {
-        milvuso::ActionType::View => 0.1,
-        milvuso::ActionType::Click => 0.3,
-        milvuso::ActionType::Like => 0.7,
-        milvuso::ActionType::Share => 0.8,
-        milvuso::ActionType::Purchase => 1.0,
-        milvuso::ActionType::Convert => 1.0,
-    }
-}
-
-async fn generate_context_features(action: &milvuso::UserAction) -> Result<Vec<f32>> {
+async fn generate_context_features(
+    action: &milvuso::UserAction,
+    action_weights: &milvuso::config::ActionWeightsConfig,
+) -> Result<Vec<f32>> {
     let mut features = vec![0.0; 10];
     
     // Time-based context
@@ -264,7 +277,7 @@ async fn generate_context_features(action: &milvuso::UserAction) -> Result<Vec<f
     features[1] = day_of_week;
     
     // Action strength
-    features[2] = get_label_from_action(&action.action_type);
+    features[2] = action_weights.weight(&action.action_type);
     
     Ok(features)
 }
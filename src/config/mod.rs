@@ -1,4 +1,6 @@
+use crate::models::ActionType;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::net::SocketAddr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,6 +12,18 @@ pub struct Config {
     pub postgres: PostgresConfig,
     pub recommendation: RecommendationConfig,
     pub training: TrainingConfig,
+    #[serde(default)]
+    pub outlier_detection: OutlierDetectionConfig,
+    #[serde(default)]
+    pub action_weights: ActionWeightsConfig,
+    #[serde(default)]
+    pub metadata_store: MetadataStoreConfig,
+    /// Parent category -> child categories. A category filter also matches every descendant.
+    #[serde(default)]
+    pub category_taxonomy: HashMap<String, Vec<String>>,
+    /// Per-tenant overrides of `recommendation`, keyed by tenant id.
+    #[serde(default)]
+    pub tenants: HashMap<String, RecommendationConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +31,52 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub workers: usize,
+    /// Log 1 in this many successful requests; failures are always logged. 0 disables success logs.
+    #[serde(default = "default_request_log_sample_rate")]
+    pub request_log_sample_rate: u64,
+    /// Header a caller can set to `verbose` to force detailed logging of a single request.
+    #[serde(default = "default_log_verbosity_header")]
+    pub log_verbosity_header: String,
+    /// Token that admin operations (e.g. profile rebuilds) require in the `x-admin-token` header.
+    /// Unset disables those operations.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Most requests each route (by its path pattern, e.g. `/recommendations/group`) serves at once;
+    /// requests past it get `503`. Routes not listed, or listed with 0, are unbounded.
+    #[serde(default = "default_concurrency_limits")]
+    pub concurrency_limits: HashMap<String, usize>,
+    /// How long a shutting-down server lets in-flight requests finish before exiting.
+    #[serde(default = "default_shutdown_timeout_seconds")]
+    pub shutdown_timeout_seconds: u64,
+    /// Compress responses with gzip or brotli when the request's `Accept-Encoding` allows it.
+    #[serde(default = "default_compress_responses")]
+    pub compress_responses: bool,
+}
+
+fn default_request_log_sample_rate() -> u64 {
+    1
+}
+
+fn default_shutdown_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_compress_responses() -> bool {
+    true
+}
+
+fn default_log_verbosity_header() -> String {
+    "x-log-verbosity".to_string()
+}
+
+/// Bounds on the endpoints whose cost scales with the index or the request rather than a single lookup.
+fn default_concurrency_limits() -> HashMap<String, usize> {
+    HashMap::from([
+        ("/recommendations/group".to_string(), 32),
+        ("/admin/evaluation/threshold-sweep".to_string(), 4),
+        ("/admin/knn-graph".to_string(), 2),
+        ("/admin/renormalize".to_string(), 1),
+    ])
 }
 
 impl ServerConfig {
@@ -27,12 +87,63 @@ impl ServerConfig {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MilvusConfig {
+    /// Milvus instance holding the user and item indexes. Empty keeps them in memory.
     pub host: String,
     pub port: u16,
+    /// Prefix of the `_users` and `_items` collections.
     pub collection_name: String,
     pub dimension: usize,
     pub index_type: String,
+    /// Layout of the in-memory indexes: `flat` scans every vector exactly, `ivf` buckets them into
+    /// `nlist` k-means clusters and scans only the nearest `nprobe`, trading recall for speed.
+    #[serde(default = "default_in_memory_index")]
+    pub in_memory_index: String,
+    /// Clusters of an `ivf` in-memory index.
+    #[serde(default = "default_nlist")]
+    pub nlist: usize,
+    /// Clusters an `ivf` in-memory search scans.
+    #[serde(default = "default_nprobe")]
+    pub nprobe: usize,
+    /// `COSINE`, `IP` or `L2`, or `MANHATTAN` in memory. Milvus search scores are returned as cosine
+    /// similarity either way; the in-memory index returns the metric's own value, so under `L2` or
+    /// `MANHATTAN` scores are distances and rank ascending.
     pub metric_type: String,
+    /// Milvus auth token, as `user:password` or an API key.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Accumulate similarity scores in f64 instead of f32.
+    #[serde(default)]
+    pub f64_accumulation: bool,
+    /// Precompute stored vectors' norms so cosine scoring is one dot product per vector.
+    #[serde(default)]
+    pub cache_vector_norms: bool,
+    /// Batch inserts take the index locks once per chunk of this many records, so readers are not
+    /// starved for the whole duration of a large catalog load.
+    #[serde(default = "default_batch_insert_chunk_size")]
+    pub batch_insert_chunk_size: usize,
+    /// Serve a snapshot of the metadata store and refuse every write, for horizontally scaled
+    /// serving replicas behind a single writer.
+    #[serde(default)]
+    pub read_only: bool,
+    /// How often a read-only replica reloads its snapshot from the metadata store (0 disables it).
+    #[serde(default)]
+    pub snapshot_reload_interval_seconds: u64,
+}
+
+fn default_batch_insert_chunk_size() -> usize {
+    1024
+}
+
+fn default_in_memory_index() -> String {
+    "flat".to_string()
+}
+
+fn default_nlist() -> usize {
+    128
+}
+
+fn default_nprobe() -> usize {
+    8
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,15 +152,59 @@ pub struct KafkaConfig {
     pub log_topic: String,
     pub feature_topic: String,
     pub training_topic: String,
+    /// Topic receiving an audit record for every write and admin request.
+    #[serde(default = "default_audit_topic")]
+    pub audit_topic: String,
+    /// Topic for consumed messages that fail to deserialize, with the error in their headers.
+    /// Unset drops them with a warning.
+    #[serde(default)]
+    pub dead_letter_topic: Option<String>,
+    /// Whether the training consumer commits offsets on its own. When false, an example's offset
+    /// is committed only once the batch holding it has been trained.
+    #[serde(default = "default_enable_auto_commit")]
+    pub enable_auto_commit: bool,
     pub group_id: String,
     pub auto_offset_reset: String,
 }
 
+fn default_audit_topic() -> String {
+    "audit_log".to_string()
+}
+
+fn default_enable_auto_commit() -> bool {
+    true
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RedisConfig {
     pub url: String,
     pub pool_size: u32,
     pub ttl_seconds: u64,
+    /// Encoding of cached profiles and features. Reads detect the format, so it can be changed live.
+    #[serde(default)]
+    pub codec: CacheCodec,
+    /// How long a full recommendation response is reused for an identical request while the user's
+    /// profile is unchanged. 0 disables response caching.
+    #[serde(default)]
+    pub response_cache_ttl_seconds: u64,
+    /// Delete cache entries that fail to decode, so the next write replaces them instead of every
+    /// read missing on them until they expire.
+    #[serde(default = "default_repair_corrupt_entries")]
+    pub repair_corrupt_entries: bool,
+}
+
+fn default_repair_corrupt_entries() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheCodec {
+    #[default]
+    Json,
+    Bincode,
+    /// Bincode compressed with zstd.
+    BincodeZstd,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,16 +218,485 @@ pub struct RecommendationConfig {
     pub embedding_dim: usize,
     pub top_k: usize,
     pub similarity_threshold: f32,
+    /// When > 0, scores near `similarity_threshold` are scaled by a sigmoid of this width instead of
+    /// being cut off, and only scores more than four widths below the threshold are dropped.
+    #[serde(default)]
+    pub soft_threshold_width: f32,
+    /// Weight of diversity against relevance when re-ranking with maximal marginal relevance, from
+    /// 0.0 (rank by score alone) to 1.0; redundancy is cosine similarity to higher-ranked items.
+    #[serde(default)]
+    pub diversity_lambda: f32,
+    /// How long a session's scored candidates serve its later requests. 0 scores every request.
+    #[serde(default = "default_session_cache_ttl_seconds")]
+    pub session_cache_ttl_seconds: u64,
+    /// Users with fewer interactions than this get the most popular items instead of a similarity
+    /// ranking, since their embedding says nothing about them yet. 0 turns the fallback off.
+    #[serde(default = "default_cold_start_threshold")]
+    pub cold_start_threshold: u64,
+    /// How strongly a first page sticks to the user's previous one, from 0 (off) to 1. An item
+    /// served before moves at most `(1 - stability_weight) * num_recommendations` positions.
+    #[serde(default)]
+    pub stability_weight: f32,
+    /// Candidates retrieved with a similarity below this are never scored. Unset keeps every top-k hit.
+    #[serde(default)]
+    pub retrieval_min_similarity: Option<f32>,
+    /// Serve similarity-only scores when the loaded model parameters are older than this, or when
+    /// none were ever loaded. Unset disables the check.
+    #[serde(default)]
+    pub max_model_age_seconds: Option<u64>,
+    /// Per-hour decay applied to older actions when a profile is rebuilt from history.
+    #[serde(default = "default_profile_rebuild_decay_rate")]
+    pub profile_rebuild_decay_rate: f64,
+    /// Per-hour decay of how far a live action moves the profile, by the age of its timestamp.
+    /// 0 lets every action count the same however old it is.
+    #[serde(default = "default_interaction_decay_rate")]
+    pub interaction_decay_rate: f64,
+    /// Actions kept per user for profile rebuilds, in memory or in the metadata store.
+    #[serde(default = "default_max_action_history")]
+    pub max_action_history: usize,
+    /// Most recently interacted items remembered per user and left out of their recommendations
+    /// unless a request sets `include_seen`. Views and negative feedback do not count. 0 keeps
+    /// serving items the user already interacted with.
+    #[serde(default)]
+    pub seen_items_cap: usize,
+    /// Redis key holding a JSON array of item ids that are never served.
+    #[serde(default)]
+    pub deny_list_key: Option<String>,
+    /// Redis key holding a JSON array of item ids; when the key exists only these are served.
+    /// The deny list wins over it.
+    #[serde(default)]
+    pub allow_list_key: Option<String>,
+    /// Fixed scores for editorially curated items, which bypass the model and the similarity
+    /// threshold. Entries under `manual_scores_key` take precedence.
+    #[serde(default)]
+    pub manual_scores: HashMap<uuid::Uuid, f32>,
+    /// Redis key holding a JSON object of item id to manual score.
+    #[serde(default)]
+    pub manual_scores_key: Option<String>,
+    /// How long the lists and manual scores are reused before they are read from Redis again.
+    #[serde(default = "default_item_list_cache_ttl_ms")]
+    pub item_list_cache_ttl_ms: u64,
+    /// How long a tenant's config from Redis is reused before it is read again. Read from the
+    /// global settings only; 0 reads it on every request.
+    #[serde(default = "default_tenant_config_cache_ttl_ms")]
+    pub tenant_config_cache_ttl_ms: u64,
+    /// Half-life of an interaction's weight in the recent velocity behind personalized trending.
+    #[serde(default = "default_trending_half_life_seconds")]
+    pub trending_half_life_seconds: u64,
     pub user_profile_update_interval: u64,
+    /// Identical (user, item, action type) events inside this many seconds count once. Claims a
+    /// Redis key per action, so actions fail while Redis is down; 0 turns it off.
+    #[serde(default = "default_duplicate_action_window_seconds")]
+    pub duplicate_action_window_seconds: u64,
+    /// Repair NaN/Infinity embedding values (to 0.0) at ingest instead of rejecting the vector.
+    #[serde(default)]
+    pub sanitize_non_finite_embeddings: bool,
+    /// Serve similarity-only scores until the model has trained on this many examples (0 disables the gate).
+    #[serde(default)]
+    pub min_training_examples: u64,
+    /// How long scoring waits for the model lock (e.g. held by training) before falling back to similarity-only.
+    #[serde(default = "default_predict_lock_timeout_ms")]
+    pub predict_lock_timeout_ms: u64,
+    /// How often the background reaper drops expired items from the index (0 disables it).
+    #[serde(default)]
+    pub expired_item_reap_interval_seconds: u64,
+    /// How long an expired item stays indexed (but unserved) before it is reaped.
+    #[serde(default)]
+    pub expired_item_grace_seconds: u64,
+    /// How often stored user and item embeddings are swept back to unit length (0 disables it).
+    #[serde(default)]
+    pub renormalize_interval_seconds: u64,
+    /// Clusters for cluster-based recommendations. 0 leaves items unclustered unless clustered on demand.
+    #[serde(default)]
+    pub item_cluster_count: usize,
+    /// How often items are reclustered into `item_cluster_count` clusters (0 disables it).
+    #[serde(default)]
+    pub item_cluster_interval_seconds: u64,
+    /// Actions weighted below this are still logged but skip training and profile updates.
+    #[serde(default)]
+    pub min_action_weight: f32,
+    /// How to score a candidate when the model cannot produce a prediction for it.
+    #[serde(default)]
+    pub prediction_fallback: PredictionFallback,
+    /// z-value of the Wilson lower bound used to rank trending items; higher trusts low volume less.
+    #[serde(default = "default_popularity_confidence_z")]
+    pub popularity_confidence_z: f64,
+    /// Trials a time-of-day or region slice needs before it is ranked on its own instead of falling
+    /// back to global trending.
+    #[serde(default = "default_context_trending_min_interactions")]
+    pub context_trending_min_interactions: u64,
+    /// What to do with an inserted embedding whose length differs from `embedding_dim`.
+    #[serde(default)]
+    pub dimension_adapt: DimensionAdapt,
+    /// Scale item embeddings to unit length at ingest, so item searches score a dot product
+    /// instead of a full cosine similarity.
+    #[serde(default)]
+    pub auto_normalize_embeddings: bool,
+    /// Share of an item's learned collaborative embedding in the representation it is scored by,
+    /// the rest being its content embedding. 0 scores content alone; items the model has not
+    /// learned yet always do.
+    #[serde(default)]
+    pub collaborative_blend_weight: f32,
+    /// Share of a user's previous embedding step carried into the next live update, from 0 (each
+    /// action moves the profile on its own) to below 1. Higher values smooth out users whose
+    /// actions alternate between unrelated items.
+    #[serde(default)]
+    pub embedding_momentum: f32,
+    /// Added to an item's score, times the share of the user's interaction weight that went to the
+    /// item's category. 0 ignores category affinity.
+    #[serde(default)]
+    pub category_affinity_weight: f32,
+    /// Projects embeddings of `projection.input_dim` (inserts and queries alike) down to
+    /// `embedding_dim` before `dimension_adapt` is considered.
+    #[serde(default)]
+    pub projection: Option<ProjectionConfig>,
+    /// Per-user token bucket on incoming actions. Unset leaves actions unlimited.
+    #[serde(default)]
+    pub action_rate_limit: Option<ActionRateLimitConfig>,
+    /// Slots of each response reserved for randomly sampled cold items. Unset serves the ranking only.
+    #[serde(default)]
+    pub cold_item_exploration: Option<ColdItemExplorationConfig>,
+    /// Train on items shown in a recommendation that got feedback, but that were not themselves
+    /// interacted with, as negatives. Unset trains on interactions only.
+    #[serde(default)]
+    pub hard_negatives: Option<HardNegativeConfig>,
+    /// Shrink the embeddings of users with few interactions toward the centroid of their
+    /// top-affinity category before ranking. Unset ranks by the stored embedding alone.
+    #[serde(default)]
+    pub embedding_smoothing: Option<EmbeddingSmoothingConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectionConfig {
+    pub input_dim: usize,
+    /// Seed of the random projection; every instance must use the same one.
+    #[serde(default = "default_projection_seed")]
+    pub seed: u64,
+    /// Learned projection, `embedding_dim` rows of `input_dim` weights, used instead of a random one.
+    #[serde(default)]
+    pub weights: Option<Vec<Vec<f32>>>,
+}
+
+fn default_projection_seed() -> u64 {
+    42
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionRateLimitConfig {
+    /// Sustained actions per second each user may send.
+    pub actions_per_second: f64,
+    /// Bucket size: how many actions a user may send in a burst.
+    pub burst: u32,
+    #[serde(default)]
+    pub policy: RateLimitPolicy,
+    /// Weight multiplier for excess actions under `RateLimitPolicy::DownWeight`.
+    #[serde(default = "default_rate_limit_down_weight")]
+    pub down_weight: f32,
+    /// Excess actions after which a user is flagged as abusive (0 never flags).
+    #[serde(default)]
+    pub abuse_threshold: u64,
+}
+
+fn default_rate_limit_down_weight() -> f32 {
+    0.1
+}
+
+/// Items without interactions have no collaborative signal and rarely rank, so a share of every
+/// response goes to a random sample of them until they gather enough interactions to rank normally.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColdItemExplorationConfig {
+    /// Fraction of the requested slots reserved for cold items, e.g. 0.1 for one in ten.
+    pub slot_share: f32,
+    /// Items with fewer recorded interactions than this count as cold.
+    #[serde(default = "default_cold_item_max_interactions")]
+    pub max_interactions: u64,
+    /// Fixes the sample, for tests. Unset samples afresh for every request.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+fn default_cold_item_max_interactions() -> u64 {
+    5
+}
+
+/// Hard negatives are drawn from the impressions feedback reveals: the items a recommendation
+/// showed next to the ones the user acted on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HardNegativeConfig {
+    /// Negatives drawn from each impression, higher-ranked ignored items more likely.
+    pub per_impression: usize,
+    /// How long after its first feedback an impression is left for further feedback before its
+    /// ignored items are taken as negatives.
+    #[serde(default = "default_hard_negative_delay_seconds")]
+    pub delay_seconds: u64,
+    /// How often ripe impressions are turned into negatives.
+    #[serde(default = "default_hard_negative_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+fn default_hard_negative_delay_seconds() -> u64 {
+    300
+}
+
+fn default_hard_negative_interval_seconds() -> u64 {
+    60
+}
+
+/// James-Stein style shrinkage: a user with `n` interactions is ranked by
+/// `(n * embedding + prior_strength * centroid) / (n + prior_strength)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingSmoothingConfig {
+    /// Interactions the category centroid counts as; a user with this many is ranked half by it.
+    pub prior_strength: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitPolicy {
+    /// Discard excess actions entirely.
+    #[default]
+    Drop,
+    /// Keep excess actions but scale their weight by `down_weight`.
+    DownWeight,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DimensionAdapt {
+    /// Leave the embedding as is, so the index rejects it.
+    #[default]
+    Reject,
+    /// Zero-pad shorter embeddings; longer ones are still rejected.
+    PadZero,
+    /// Drop trailing values of longer embeddings; shorter ones are still rejected.
+    Truncate,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PredictionFallback {
+    /// Score on cosine similarity alone.
+    #[default]
+    SimilarityOnly,
+    /// Blend with a prediction of 0.0, which halves the similarity score.
+    Zero,
+}
+
+fn default_predict_lock_timeout_ms() -> u64 {
+    50
+}
+
+fn default_session_cache_ttl_seconds() -> u64 {
+    60
+}
+
+fn default_cold_start_threshold() -> u64 {
+    1
+}
+
+fn default_context_trending_min_interactions() -> u64 {
+    20
+}
+
+fn default_popularity_confidence_z() -> f64 {
+    1.96
+}
+
+fn default_profile_rebuild_decay_rate() -> f64 {
+    0.01
+}
+
+fn default_interaction_decay_rate() -> f64 {
+    0.01
+}
+
+fn default_max_action_history() -> usize {
+    1000
+}
+
+fn default_item_list_cache_ttl_ms() -> u64 {
+    5000
+}
+
+fn default_tenant_config_cache_ttl_ms() -> u64 {
+    5000
+}
+
+fn default_trending_half_life_seconds() -> u64 {
+    3600
+}
+
+fn default_duplicate_action_window_seconds() -> u64 {
+    0
+}
+
+/// Where user profiles and item features are persisted between restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataStoreConfig {
+    #[serde(default)]
+    pub backend: MetadataBackend,
+    /// Directory of the sled database.
+    #[serde(default = "default_metadata_store_path")]
+    pub path: String,
+}
+
+impl Default for MetadataStoreConfig {
+    fn default() -> Self {
+        Self {
+            backend: MetadataBackend::None,
+            path: default_metadata_store_path(),
+        }
+    }
+}
+
+fn default_metadata_store_path() -> String {
+    "data/metadata".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataBackend {
+    /// Keep metadata in memory only; it is lost on restart.
+    #[default]
+    None,
+    Sled,
+}
+
+/// Ingest-time check that rejects item embeddings far from the rest of the catalog.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlierDetectionConfig {
+    pub enabled: bool,
+    /// Maximum z-score of the embedding norm against the catalog's running norm statistics.
+    pub norm_z_threshold: f32,
+    /// Maximum z-score of the distance to the catalog mean embedding.
+    pub distance_z_threshold: f32,
+    /// Number of accepted embeddings required before outliers are checked.
+    pub min_samples: u64,
+    /// Keep rejected embeddings aside for inspection instead of discarding them.
+    pub quarantine: bool,
+}
+
+impl Default for OutlierDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            norm_z_threshold: 6.0,
+            distance_z_threshold: 6.0,
+            min_samples: 100,
+            quarantine: false,
+        }
+    }
+}
+
+/// How much each action type counts, both for profile updates and as its training label.
+/// Negative feedback weighs below 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ActionWeightsConfig {
+    pub view: f32,
+    pub click: f32,
+    pub like: f32,
+    pub share: f32,
+    pub purchase: f32,
+    pub convert: f32,
+    pub hide: f32,
+    pub not_interested: f32,
+    pub dislike: f32,
+}
+
+impl ActionWeightsConfig {
+    pub fn weight(&self, action_type: &ActionType) -> f32 {
+        match action_type {
+            ActionType::View => self.view,
+            ActionType::Click => self.click,
+            ActionType::Like => self.like,
+            ActionType::Share => self.share,
+            ActionType::Purchase => self.purchase,
+            ActionType::Convert => self.convert,
+            ActionType::Hide => self.hide,
+            ActionType::NotInterested => self.not_interested,
+            ActionType::Dislike => self.dislike,
+        }
+    }
+}
+
+impl Default for ActionWeightsConfig {
+    fn default() -> Self {
+        Self {
+            view: 0.1,
+            click: 0.3,
+            like: 0.7,
+            share: 0.8,
+            purchase: 1.0,
+            convert: 1.0,
+            hide: -0.5,
+            not_interested: -0.7,
+            dislike: -1.0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrainingConfig {
+    /// Lowest training label accepted. Negative feedback actions train with labels below 0.
+    #[serde(default = "default_min_training_label")]
+    pub min_training_label: f32,
     pub batch_size: usize,
     pub learning_rate: f64,
     pub epochs: usize,
     pub model_save_interval: u64,
     pub negative_sampling_ratio: f32,
+    /// Upper bound on a batch after negative sampling; larger batches are split before training.
+    #[serde(default = "default_max_augmented_batch_size")]
+    pub max_augmented_batch_size: usize,
+    /// Number of training batches that may be processed concurrently.
+    #[serde(default = "default_max_in_flight_batches")]
+    pub max_in_flight_batches: usize,
+    #[serde(default)]
+    pub checkpoint_retention: CheckpointRetentionConfig,
+    /// Directory model checkpoints are written to, one file per version. Unset keeps them in memory.
+    #[serde(default)]
+    pub model_dir: Option<String>,
+}
+
+/// Which saved model checkpoints survive rotation. The version currently being served is always
+/// kept on top of these.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointRetentionConfig {
+    /// Number of most recent checkpoints kept.
+    pub keep_latest: usize,
+    /// Length of a retention period, e.g. 86400 to also keep one checkpoint per day.
+    #[serde(default)]
+    pub period_seconds: Option<u64>,
+    /// Number of most recent periods that keep their newest checkpoint.
+    #[serde(default = "default_keep_periods")]
+    pub keep_periods: usize,
+}
+
+fn default_keep_periods() -> usize {
+    7
+}
+
+impl Default for CheckpointRetentionConfig {
+    fn default() -> Self {
+        Self {
+            keep_latest: 5,
+            period_seconds: None,
+            keep_periods: default_keep_periods(),
+        }
+    }
+}
+
+fn default_min_training_label() -> f32 {
+    -1.0
+}
+
+fn default_max_augmented_batch_size() -> usize {
+    4096
+}
+
+fn default_max_in_flight_batches() -> usize {
+    1
 }
 
 impl Default for Config {
@@ -82,20 +706,38 @@ impl Default for Config {
                 host: "0.0.0.0".to_string(),
                 port: 8080,
                 workers: num_cpus::get(),
+                request_log_sample_rate: default_request_log_sample_rate(),
+                log_verbosity_header: default_log_verbosity_header(),
+                admin_token: None,
+                concurrency_limits: default_concurrency_limits(),
+                shutdown_timeout_seconds: default_shutdown_timeout_seconds(),
+                compress_responses: default_compress_responses(),
             },
             milvus: MilvusConfig {
-                host: "localhost".to_string(),
+                host: String::new(),
                 port: 19530,
                 collection_name: "recommendation_vectors".to_string(),
                 dimension: 128,
                 index_type: "IVF_FLAT".to_string(),
-                metric_type: "L2".to_string(),
+                in_memory_index: default_in_memory_index(),
+                nlist: default_nlist(),
+                nprobe: default_nprobe(),
+                metric_type: "COSINE".to_string(),
+                f64_accumulation: false,
+                cache_vector_norms: false,
+                batch_insert_chunk_size: default_batch_insert_chunk_size(),
+                read_only: false,
+                snapshot_reload_interval_seconds: 0,
+                token: None,
             },
             kafka: KafkaConfig {
                 brokers: "localhost:9092".to_string(),
                 log_topic: "user_actions".to_string(),
                 feature_topic: "features".to_string(),
                 training_topic: "training_examples".to_string(),
+                audit_topic: default_audit_topic(),
+                dead_letter_topic: None,
+                enable_auto_commit: default_enable_auto_commit(),
                 group_id: "milvuso_group".to_string(),
                 auto_offset_reset: "earliest".to_string(),
             },
@@ -103,6 +745,9 @@ impl Default for Config {
                 url: "redis://localhost:6379".to_string(),
                 pool_size: 10,
                 ttl_seconds: 3600,
+                codec: CacheCodec::Json,
+                response_cache_ttl_seconds: 0,
+                repair_corrupt_entries: default_repair_corrupt_entries(),
             },
             postgres: PostgresConfig {
                 url: "postgresql://localhost:5432/milvuso".to_string(),
@@ -112,15 +757,66 @@ impl Default for Config {
                 embedding_dim: 128,
                 top_k: 50,
                 similarity_threshold: 0.7,
+                soft_threshold_width: 0.0,
+                diversity_lambda: 0.0,
+                session_cache_ttl_seconds: default_session_cache_ttl_seconds(),
+                cold_start_threshold: default_cold_start_threshold(),
+                stability_weight: 0.0,
+                retrieval_min_similarity: None,
+                max_model_age_seconds: None,
+                profile_rebuild_decay_rate: default_profile_rebuild_decay_rate(),
+                interaction_decay_rate: default_interaction_decay_rate(),
+                max_action_history: default_max_action_history(),
+                seen_items_cap: 0,
+                deny_list_key: None,
+                allow_list_key: None,
+                manual_scores: HashMap::new(),
+                manual_scores_key: None,
+                item_list_cache_ttl_ms: default_item_list_cache_ttl_ms(),
+                tenant_config_cache_ttl_ms: default_tenant_config_cache_ttl_ms(),
+                trending_half_life_seconds: default_trending_half_life_seconds(),
                 user_profile_update_interval: 300,
+                duplicate_action_window_seconds: default_duplicate_action_window_seconds(),
+                sanitize_non_finite_embeddings: false,
+                min_training_examples: 0,
+                predict_lock_timeout_ms: default_predict_lock_timeout_ms(),
+                expired_item_reap_interval_seconds: 300,
+                expired_item_grace_seconds: 3600,
+                renormalize_interval_seconds: 0,
+                item_cluster_count: 0,
+                item_cluster_interval_seconds: 0,
+                min_action_weight: 0.0,
+                prediction_fallback: PredictionFallback::SimilarityOnly,
+                popularity_confidence_z: default_popularity_confidence_z(),
+                context_trending_min_interactions: default_context_trending_min_interactions(),
+                dimension_adapt: DimensionAdapt::Reject,
+                auto_normalize_embeddings: false,
+                collaborative_blend_weight: 0.0,
+                embedding_momentum: 0.0,
+                category_affinity_weight: 0.0,
+                projection: None,
+                action_rate_limit: None,
+                cold_item_exploration: None,
+                hard_negatives: None,
+                embedding_smoothing: None,
             },
             training: TrainingConfig {
+                min_training_label: default_min_training_label(),
                 batch_size: 1024,
                 learning_rate: 0.001,
                 epochs: 10,
                 model_save_interval: 3600,
                 negative_sampling_ratio: 4.0,
+                max_augmented_batch_size: default_max_augmented_batch_size(),
+                max_in_flight_batches: default_max_in_flight_batches(),
+                checkpoint_retention: CheckpointRetentionConfig::default(),
+                model_dir: None,
             },
+            outlier_detection: OutlierDetectionConfig::default(),
+            action_weights: ActionWeightsConfig::default(),
+            metadata_store: MetadataStoreConfig::default(),
+            category_taxonomy: HashMap::new(),
+            tenants: HashMap::new(),
         }
     }
 }
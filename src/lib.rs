@@ -17,8 +17,11 @@ pub struct AppState {
     pub kafka_producer: Arc<services::kafka::KafkaProducer>,
     pub kafka_consumer: Arc<services::kafka::KafkaConsumer>,
     pub recommendation_service: Arc<services::recommendation::RecommendationService>,
+    pub serving_service: Arc<services::serving::ServingService>,
     pub training_service: Arc<services::training::TrainingService>,
     pub redis_client: Arc<redis::Client>,
+    pub request_log_sampler: Arc<utils::request_log::RequestLogSampler>,
+    pub audit_log: services::audit::AuditLog,
 }
 
 impl AppState {
@@ -29,12 +32,39 @@ impl AppState {
             services::vector_db::VectorDbService::new(&config).await?
         );
         
+        if config.recommendation.expired_item_reap_interval_seconds > 0 {
+            vector_db.clone().spawn_expired_item_reaper(
+                std::time::Duration::from_secs(config.recommendation.expired_item_reap_interval_seconds),
+                chrono::Duration::seconds(config.recommendation.expired_item_grace_seconds as i64),
+            );
+        }
+
+        if config.recommendation.renormalize_interval_seconds > 0 && !config.milvus.read_only {
+            vector_db.clone().spawn_renormalizer(
+                std::time::Duration::from_secs(config.recommendation.renormalize_interval_seconds),
+            );
+        }
+
+        if config.recommendation.item_cluster_count > 0 && config.recommendation.item_cluster_interval_seconds > 0 {
+            vector_db.clone().spawn_item_clusterer(
+                std::time::Duration::from_secs(config.recommendation.item_cluster_interval_seconds),
+                config.recommendation.item_cluster_count,
+            );
+        }
+
+        if config.milvus.read_only && config.milvus.snapshot_reload_interval_seconds > 0 {
+            vector_db.clone().spawn_snapshot_reloader(
+                std::time::Duration::from_secs(config.milvus.snapshot_reload_interval_seconds),
+            );
+        }
+        
         let kafka_producer = Arc::new(
             services::kafka::KafkaProducer::new(&config)?
         );
         
         let kafka_consumer = Arc::new(
             services::kafka::KafkaConsumer::new(&config)?
+                .with_dead_letter_sink(kafka_producer.clone())
         );
         
         let redis_client = Arc::new(
@@ -49,12 +79,36 @@ impl AppState {
             ).await?
         );
         
+        if let Some(hard_negatives) = &config.recommendation.hard_negatives {
+            recommendation_service.clone().spawn_hard_negative_trainer(
+                std::time::Duration::from_secs(hard_negatives.interval_seconds.max(1)),
+            );
+        }
+        
+        let serving_service = Arc::new(
+            services::serving::ServingService::new(
+                vector_db.clone(),
+                recommendation_service.clone(),
+                config.clone(),
+            ).await?
+        );
+        
         let training_service = Arc::new(
             services::training::TrainingService::new(
                 vector_db.clone(),
                 kafka_producer.clone(),
                 config.clone(),
             ).await?
+            .with_trained_examples_counter(recommendation_service.trained_examples_counter())
+        );
+        
+        let request_log_sampler = Arc::new(
+            utils::request_log::RequestLogSampler::new(config.server.request_log_sample_rate)
+        );
+        
+        let audit_log = services::audit::AuditLog::new(
+            Arc::new(services::audit::KafkaAuditSink::new(kafka_producer.clone())),
+            config.server.admin_token.clone(),
         );
         
         Ok(Self {
@@ -63,8 +117,11 @@ impl AppState {
             kafka_producer,
             kafka_consumer,
             recommendation_service,
+            serving_service,
             training_service,
             redis_client,
+            request_log_sampler,
+            audit_log,
         })
     }
 }
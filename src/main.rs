@@ -1,14 +1,30 @@
 use milvuso::{init_tracing, AppState, Config};
+use milvuso::services::audit::audit_middleware;
+use milvuso::services::recommendation::UnknownModelVersion;
+use milvuso::services::vector_db::{InsertConflict, OutlierRejected, ReadOnlyReplica};
+use milvuso::utils::concurrency::limit_concurrency;
+use milvuso::utils::request_log::LogVerbosity;
+use milvuso::utils::shutdown::{serve_with_graceful_shutdown, shutdown_signal};
+use milvuso::utils::validation::{
+    validate_anonymous_recommendation_request, validate_group_recommendation_request,
+    validate_item_feature_with_sanitization, validate_recommendation_request, validate_user_action,
+};
 use axum::{
+    body::Body,
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::sse::{Event, KeepAlive, Sse},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post, MethodRouter},
     Router,
 };
+use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::convert::Infallible;
 use tower::ServiceBuilder;
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 use tracing::info;
@@ -19,6 +35,16 @@ struct RecommendationQuery {
     num_recommendations: Option<usize>,
     filter_categories: Option<String>,
     exclude_items: Option<String>,
+    embedding_version: Option<String>,
+    min_distinct_categories: Option<usize>,
+    filter_tags: Option<String>,
+    relax_filters: Option<bool>,
+    max_item_age_seconds: Option<u64>,
+    offset: Option<usize>,
+    session_id: Option<String>,
+    include_seen: Option<bool>,
+    /// `false` serves every item without a `reason`, for callers that never show one.
+    include_reasons: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,6 +72,46 @@ impl<T> ApiResponse<T> {
     }
 }
 
+/// 400 with the validation error as the message, for input the validators reject.
+fn bad_request(e: anyhow::Error) -> Response {
+    (StatusCode::BAD_REQUEST, Json(ApiResponse::<String>::error(format!("{:#}", e)))).into_response()
+}
+
+/// 503 with the message for writes a read-only replica refuses; anything else is logged as `what`
+/// failing and answered with a bare 500.
+fn write_failure(e: anyhow::Error, what: &str) -> Response {
+    if e.is::<ReadOnlyReplica>() {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(ApiResponse::<String>::error(e.to_string()))).into_response();
+    }
+    tracing::error!("{}: {}", what, e);
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+fn request_verbosity(state: &AppState, headers: &HeaderMap) -> LogVerbosity {
+    LogVerbosity::from_header_value(
+        headers
+            .get(state.config.server.log_verbosity_header.as_str())
+            .and_then(|value| value.to_str().ok()),
+    )
+}
+
+/// Logs a successful request when it is sampled, adding `details` if the caller asked for verbose logging.
+fn log_success(
+    state: &AppState,
+    verbosity: LogVerbosity,
+    summary: impl FnOnce() -> String,
+    details: &dyn std::fmt::Debug,
+) {
+    if !state.request_log_sampler.should_log(false, verbosity) {
+        return;
+    }
+
+    match verbosity {
+        LogVerbosity::Verbose => info!("{}: {:?}", summary(), details),
+        LogVerbosity::Sampled => info!("{}", summary()),
+    }
+}
+
 async fn health_check() -> Json<ApiResponse<HashMap<String, String>>> {
     let mut status = HashMap::new();
     status.insert("status".to_string(), "healthy".to_string());
@@ -55,63 +121,338 @@ async fn health_check() -> Json<ApiResponse<HashMap<String, String>>> {
     Json(ApiResponse::success(status))
 }
 
-async fn get_recommendations(
-    State(state): State<AppState>,
-    Path(user_id): Path<Uuid>,
-    Query(params): Query<RecommendationQuery>,
-) -> Result<Json<ApiResponse<milvuso::RecommendationResponse>>, StatusCode> {
+/// Serving stats and score histograms for Prometheus to scrape.
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.serving_service.render_metrics().await,
+    )
+}
+
+fn recommendation_request(
+    user_id: Uuid,
+    params: RecommendationQuery,
+    headers: &HeaderMap,
+) -> milvuso::RecommendationRequest {
     let filter_categories = params.filter_categories
         .map(|s| s.split(',').map(|s| s.trim().to_string()).collect());
     
+    let filter_tags = params.filter_tags
+        .map(|s| s.split(',').map(|s| s.trim().to_string()).collect());
+
     let exclude_items = params.exclude_items
         .map(|s| s.split(',')
             .filter_map(|s| Uuid::parse_str(s.trim()).ok())
             .collect());
 
-    let request = milvuso::RecommendationRequest {
-        user_id,
-        num_recommendations: params.num_recommendations.unwrap_or(10),
+    let tenant_id = headers
+        .get("x-tenant-id")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+
+    let model_version = headers
+        .get("x-model-version")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+
+    milvuso::RecommendationRequest {
         filter_categories,
         exclude_items,
-    };
+        tenant_id,
+        embedding_version: params.embedding_version,
+        min_distinct_categories: params.min_distinct_categories,
+        filter_tags,
+        relax_filters: params.relax_filters.unwrap_or(false),
+        max_item_age_seconds: params.max_item_age_seconds,
+        offset: params.offset.unwrap_or(0),
+        session_id: params.session_id,
+        model_version,
+        include_seen: params.include_seen.unwrap_or(false),
+        include_reasons: params.include_reasons.unwrap_or(true),
+        ..milvuso::RecommendationRequest::new(user_id, params.num_recommendations.unwrap_or(10))
+    }
+}
 
-    match state.recommendation_service.get_recommendations(&request).await {
-        Ok(response) => Ok(Json(ApiResponse::success(response))),
+async fn get_recommendations(
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    Query(params): Query<RecommendationQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<milvuso::RecommendationResponse>>, Response> {
+    let request = recommendation_request(user_id, params, &headers);
+    validate_recommendation_request(&request).map_err(bad_request)?;
+
+    let verbosity = request_verbosity(&state, &headers);
+    match state.serving_service.serve_recommendations(&request).await {
+        Ok(response) => {
+            log_success(
+                &state,
+                verbosity,
+                || format!("Served recommendation {} for user {} with {} items", response.recommendation_id, user_id, response.recommendations.len()),
+                &(&request, &response),
+            );
+            Ok(Json(ApiResponse::success(response)))
+        }
+        Err(e) if e.is::<UnknownModelVersion>() => {
+            Err((StatusCode::NOT_FOUND, Json(ApiResponse::<String>::error(e.to_string()))).into_response())
+        }
         Err(e) => {
             tracing::error!("Failed to get recommendations: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ClusterQuery {
+    num_clusters: Option<usize>,
+}
+
+async fn get_cluster_recommendations(
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    Query(params): Query<RecommendationQuery>,
+    Query(clusters): Query<ClusterQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<milvuso::RecommendationResponse>>, Response> {
+    let request = recommendation_request(user_id, params, &headers);
+    validate_recommendation_request(&request).map_err(bad_request)?;
+    let num_clusters = clusters.num_clusters.unwrap_or(3);
+
+    match state.recommendation_service.get_cluster_recommendations(&request, num_clusters).await {
+        Ok(response) => {
+            log_success(
+                &state,
+                request_verbosity(&state, &headers),
+                || format!("Served cluster recommendation {} for user {} from {} clusters", response.recommendation_id, user_id, num_clusters),
+                &(&request, &response),
+            );
+            Ok(Json(ApiResponse::success(response)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get cluster recommendations: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// Server-sent events variant of `get_recommendations` that reports scoring progress before the items.
+async fn stream_recommendations(
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    Query(params): Query<RecommendationQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Response> {
+    let request = recommendation_request(user_id, params, &headers);
+    validate_recommendation_request(&request).map_err(bad_request)?;
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let recommendation_service = state.recommendation_service.clone();
+    tokio::spawn(async move {
+        if let Err(e) = recommendation_service.get_recommendations_with_progress(&request, &tx).await {
+            tracing::error!("Failed to stream recommendations: {}", e);
+            let _ = tx.send(milvuso::RecommendationStreamEvent::Error { message: e.to_string() });
+        }
+    });
+
+    let events = futures::stream::unfold(rx, |mut rx| async move {
+        let event = rx.recv().await?;
+        let name = match &event {
+            milvuso::RecommendationStreamEvent::Progress { .. } => "progress",
+            milvuso::RecommendationStreamEvent::Item { .. } => "item",
+            milvuso::RecommendationStreamEvent::Done { .. } => "done",
+            milvuso::RecommendationStreamEvent::Error { .. } => "error",
+        };
+        let sse_event = Event::default()
+            .event(name)
+            .json_data(&event)
+            .unwrap_or_else(|_| Event::default().event("error"));
+        Some((Ok(sse_event), rx))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+async fn get_anonymous_recommendations(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut request): Json<milvuso::AnonymousRecommendationRequest>,
+) -> Result<Json<ApiResponse<milvuso::RecommendationResponse>>, Response> {
+    validate_anonymous_recommendation_request(&request).map_err(bad_request)?;
+    if request.tenant_id.is_none() {
+        request.tenant_id = headers
+            .get("x-tenant-id")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+    }
+
+    let verbosity = request_verbosity(&state, &headers);
+    match state.recommendation_service.get_anonymous_recommendations(&request).await {
+        Ok(response) => {
+            log_success(
+                &state,
+                verbosity,
+                || format!("Served anonymous recommendation {} with {} items", response.recommendation_id, response.recommendations.len()),
+                &(&request, &response),
+            );
+            Ok(Json(ApiResponse::success(response)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get anonymous recommendations: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// One request of a batch: its response or why it failed, with the serving diagnostics.
+#[derive(Debug, Serialize)]
+struct BatchRecommendationResult {
+    user_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<milvuso::RecommendationResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    latency_ms: u64,
+    candidate_count: usize,
+    recommendation_count: usize,
+}
+
+/// Serves each request in turn; a failing one reports its error without failing the batch.
+async fn batch_recommendations(
+    State(state): State<AppState>,
+    Json(requests): Json<Vec<milvuso::RecommendationRequest>>,
+) -> Result<Json<ApiResponse<Vec<BatchRecommendationResult>>>, Response> {
+    for (index, request) in requests.iter().enumerate() {
+        validate_recommendation_request(request).map_err(|e| bad_request(e.context(format!("Request {}", index))))?;
+    }
+
+    let results = state.serving_service
+        .batch_serve_recommendations(&requests)
+        .await
+        .into_iter()
+        .map(|served| {
+            let (response, error) = match served.result {
+                Ok(response) => (Some(response), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            BatchRecommendationResult {
+                user_id: served.request.user_id,
+                response,
+                error,
+                latency_ms: served.latency_ms,
+                candidate_count: served.candidate_count,
+                recommendation_count: served.recommendation_count,
+            }
+        })
+        .collect();
+    Ok(Json(ApiResponse::success(results)))
+}
+
+async fn get_group_recommendations(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut request): Json<milvuso::GroupRecommendationRequest>,
+) -> Result<Json<ApiResponse<milvuso::RecommendationResponse>>, Response> {
+    validate_group_recommendation_request(&request).map_err(bad_request)?;
+    if request.tenant_id.is_none() {
+        request.tenant_id = headers
+            .get("x-tenant-id")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+    }
+
+    let verbosity = request_verbosity(&state, &headers);
+    match state.recommendation_service.get_group_recommendations(&request).await {
+        Ok(response) => {
+            log_success(
+                &state,
+                verbosity,
+                || format!("Served group recommendation {} for {} users with {} items", response.recommendation_id, request.user_ids.len(), response.recommendations.len()),
+                &(&request, &response),
+            );
+            Ok(Json(ApiResponse::success(response)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get group recommendations: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
         }
     }
 }
 
 async fn record_user_action(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(action): Json<milvuso::UserAction>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
+) -> Result<Json<ApiResponse<String>>, Response> {
+    validate_user_action(&action).map_err(bad_request)?;
+    // Refused before the event is produced, so a replica never publishes an action it cannot apply
+    state.vector_db.ensure_writable().map_err(|e| write_failure(e, "Failed to record user action"))?;
+
     // Send to Kafka
     if let Err(e) = state.kafka_producer.send_user_action(&action).await {
         tracing::error!("Failed to send user action to Kafka: {}", e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
     }
 
     // Process immediately for real-time updates
-    if let Err(e) = state.recommendation_service.process_user_action(&action).await {
-        tracing::error!("Failed to process user action: {}", e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-    }
+    state.recommendation_service
+        .process_user_action(&action)
+        .await
+        .map_err(|e| write_failure(e, "Failed to process user action"))?;
+
+    log_success(
+        &state,
+        request_verbosity(&state, &headers),
+        || format!("Recorded {:?} for user {} on item {}", action.action_type, action.user_id, action.item_id),
+        &action,
+    );
 
     Ok(Json(ApiResponse::success("Action recorded successfully".to_string())))
 }
 
+#[derive(Debug, Deserialize)]
+struct AddItemQuery {
+    mode: Option<milvuso::InsertMode>,
+}
+
 async fn add_item(
     State(state): State<AppState>,
-    Json(item_feature): Json<milvuso::ItemFeature>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
-    match state.recommendation_service.add_item_feature(item_feature).await {
-        Ok(_) => Ok(Json(ApiResponse::success("Item added successfully".to_string()))),
+    Query(params): Query<AddItemQuery>,
+    headers: HeaderMap,
+    Json(mut item_feature): Json<milvuso::ItemFeature>,
+) -> Result<Json<ApiResponse<String>>, Response> {
+    let sanitize = state.config.recommendation.sanitize_non_finite_embeddings;
+    validate_item_feature_with_sanitization(&mut item_feature, sanitize).map_err(bad_request)?;
+    if let Err(e) = state.vector_db.validate_item_dimensions(&item_feature) {
+        let message = format!("Item {} rejected: {:#}", item_feature.item_id, e);
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(ApiResponse::<String>::error(message))).into_response());
+    }
+
+    let item_id = item_feature.item_id;
+    let category = item_feature.category.clone();
+    let mode = params.mode.unwrap_or_default();
+    match state.recommendation_service.add_item_feature_with_mode(item_feature, mode).await {
+        Ok(_) => {
+            log_success(
+                &state,
+                request_verbosity(&state, &headers),
+                || format!("Added item {}", item_id),
+                &category,
+            );
+            Ok(Json(ApiResponse::success("Item added successfully".to_string())))
+        }
         Err(e) => {
-            tracing::error!("Failed to add item: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            let status = if let Some(conflict) = e.downcast_ref::<InsertConflict>() {
+                match conflict {
+                    InsertConflict::Exists(_) => StatusCode::CONFLICT,
+                    InsertConflict::Missing(_) => StatusCode::NOT_FOUND,
+                }
+            } else if e.is::<OutlierRejected>() {
+                StatusCode::UNPROCESSABLE_ENTITY
+            } else {
+                return Err(write_failure(e, "Failed to add item"));
+            };
+            Err((status, Json(ApiResponse::<String>::error(e.to_string()))).into_response())
         }
     }
 }
@@ -130,6 +471,103 @@ async fn get_user_profile(
     }
 }
 
+/// Profiles of the requested users that exist; missing ids are left out.
+async fn get_user_profiles(
+    State(state): State<AppState>,
+    Json(user_ids): Json<Vec<Uuid>>,
+) -> Json<ApiResponse<HashMap<Uuid, milvuso::UserProfile>>> {
+    Json(ApiResponse::success(state.vector_db.get_user_profiles(&user_ids).await))
+}
+
+/// Features of the requested items that exist; missing ids are left out.
+async fn get_item_features(
+    State(state): State<AppState>,
+    Json(item_ids): Json<Vec<Uuid>>,
+) -> Json<ApiResponse<HashMap<Uuid, milvuso::ItemFeature>>> {
+    Json(ApiResponse::success(state.vector_db.get_item_features(&item_ids).await))
+}
+
+#[derive(Debug, Deserialize)]
+struct SimilarQuery {
+    top_k: Option<usize>,
+}
+
+async fn get_similar_users(
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    Query(params): Query<SimilarQuery>,
+) -> Result<Json<ApiResponse<Vec<(Uuid, f32)>>>, StatusCode> {
+    match state.serving_service.get_similar_users(user_id, params.top_k.unwrap_or(10)).await {
+        Ok(users) => Ok(Json(ApiResponse::success(users))),
+        Err(e) => {
+            tracing::error!("Failed to get similar users: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_similar_items(
+    State(state): State<AppState>,
+    Path(item_id): Path<Uuid>,
+    Query(params): Query<SimilarQuery>,
+) -> Result<Json<ApiResponse<Vec<(Uuid, f32)>>>, StatusCode> {
+    match state.serving_service.get_similar_items(item_id, params.top_k.unwrap_or(10)).await {
+        Ok(items) => Ok(Json(ApiResponse::success(items))),
+        Err(e) => {
+            tracing::error!("Failed to get similar items: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TrendingQuery {
+    category: Option<String>,
+    top_k: Option<usize>,
+}
+
+async fn get_trending_items(
+    State(state): State<AppState>,
+    Query(params): Query<TrendingQuery>,
+) -> Result<Json<ApiResponse<Vec<milvuso::RecommendationItem>>>, StatusCode> {
+    match state.serving_service.get_trending_items(params.category, params.top_k.unwrap_or(10)).await {
+        Ok(items) => Ok(Json(ApiResponse::success(items))),
+        Err(e) => {
+            tracing::error!("Failed to get trending items: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_serving_stats(State(state): State<AppState>) -> Json<ApiResponse<HashMap<String, u64>>> {
+    Json(ApiResponse::success(state.serving_service.get_serving_stats().await))
+}
+
+async fn get_catalog_coverage(
+    State(state): State<AppState>,
+) -> Json<ApiResponse<milvuso::services::serving::CatalogCoverage>> {
+    Json(ApiResponse::success(state.serving_service.get_catalog_coverage().await))
+}
+
+fn is_admin(state: &AppState, headers: &HeaderMap) -> bool {
+    state.audit_log.is_admin(headers)
+}
+
+async fn rebuild_user_profile(
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<milvuso::UserProfile>>, Response> {
+    if !is_admin(&state, &headers) {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+
+    match state.recommendation_service.rebuild_user_profile(user_id).await {
+        Ok(profile) => Ok(Json(ApiResponse::success(profile))),
+        Err(e) => Err(write_failure(e, &format!("Failed to rebuild profile of user {}", user_id))),
+    }
+}
+
 async fn get_item_feature(
     State(state): State<AppState>,
     Path(item_id): Path<Uuid>,
@@ -144,20 +582,189 @@ async fn get_item_feature(
     }
 }
 
+async fn delete_item(
+    State(state): State<AppState>,
+    Path(item_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<String>>, Response> {
+    if !is_admin(&state, &headers) {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+
+    match state.recommendation_service.delete_item_feature(item_id).await {
+        Ok(true) => Ok(Json(ApiResponse::success("Item deleted successfully".to_string()))),
+        Ok(false) => Err(StatusCode::NOT_FOUND.into_response()),
+        Err(e) => Err(write_failure(e, &format!("Failed to delete item {}", item_id))),
+    }
+}
+
+/// Which context features the model's predictions depend on, most important first.
+async fn get_context_importance(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<milvuso::algorithms::feature_importance::FeatureImportance>>>, StatusCode> {
+    if !is_admin(&state, &headers) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse::success(state.training_service.context_feature_importance().await)))
+}
+
+async fn get_index_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<milvuso::services::vector_db::IndexStats>>, StatusCode> {
+    if !is_admin(&state, &headers) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse::success(state.vector_db.index_stats().await)))
+}
+
+/// Rescales every stored embedding to unit length and reports how many changed.
+async fn renormalize_embeddings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<milvuso::services::vector_db::RenormalizationReport>>, Response> {
+    if !is_admin(&state, &headers) {
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+
+    state.vector_db
+        .renormalize_all()
+        .await
+        .map(|report| Json(ApiResponse::success(report)))
+        .map_err(|e| write_failure(e, "Failed to renormalize embeddings"))
+}
+
+#[derive(Debug, Deserialize)]
+struct KnnGraphQuery {
+    k: Option<usize>,
+    chunk_size: Option<usize>,
+}
+
+/// Streams the item-item k-NN graph as NDJSON, one item per line.
+async fn export_knn_graph(
+    State(state): State<AppState>,
+    Query(params): Query<KnnGraphQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if !is_admin(&state, &headers) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let rows = state.vector_db.clone().stream_knn_graph(params.k.unwrap_or(10), params.chunk_size.unwrap_or(1000));
+    let lines = rows.map(|chunk| {
+        let mut lines = String::new();
+        for row in chunk? {
+            lines.push_str(&serde_json::to_string(&row)?);
+            lines.push('\n');
+        }
+        Ok::<_, anyhow::Error>(lines)
+    });
+
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(lines)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct ThresholdSweepRequest {
+    k: usize,
+    thresholds: Vec<f32>,
+    candidates: Vec<milvuso::utils::metrics::ScoredCandidate>,
+}
+
+#[derive(Debug, Serialize)]
+struct ThresholdSweepResult {
+    threshold: f32,
+    metrics: milvuso::utils::metrics::RecommendationMetrics,
+}
+
+async fn sweep_thresholds(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ThresholdSweepRequest>,
+) -> Result<Json<ApiResponse<Vec<ThresholdSweepResult>>>, StatusCode> {
+    if !is_admin(&state, &headers) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let evaluator = milvuso::utils::metrics::Evaluator::new(request.k, request.candidates);
+    let results = evaluator
+        .sweep_thresholds(&request.thresholds)
+        .into_iter()
+        .map(|(threshold, metrics)| ThresholdSweepResult { threshold, metrics })
+        .collect();
+
+    Ok(Json(ApiResponse::success(results)))
+}
+
 fn create_router(state: AppState) -> Router {
-    Router::new()
+    let limits = state.config.server.concurrency_limits.clone();
+    let limited = |path: &str, route: MethodRouter<AppState>| match limits.get(path) {
+        Some(&max_in_flight) if max_in_flight > 0 => limit_concurrency(route, max_in_flight),
+        _ => route,
+    };
+
+    // Write and admin routes leave an audit trail; reads among them are skipped by the middleware
+    let audited = Router::new()
+        .route("/actions", limited("/actions", post(record_user_action)))
+        .route("/items", limited("/items", post(add_item)))
+        .route("/items/:item_id", limited("/items/:item_id", get(get_item_feature).delete(delete_item)))
+        .route("/users/:user_id/rebuild", limited("/users/:user_id/rebuild", post(rebuild_user_profile)))
+        .route(
+            "/admin/evaluation/threshold-sweep",
+            limited("/admin/evaluation/threshold-sweep", post(sweep_thresholds)),
+        )
+        .route(
+            "/admin/context-importance",
+            limited("/admin/context-importance", get(get_context_importance)),
+        )
+        .route("/admin/index-stats", limited("/admin/index-stats", get(get_index_stats)))
+        .route("/admin/knn-graph", limited("/admin/knn-graph", get(export_knn_graph)))
+        .route("/admin/renormalize", limited("/admin/renormalize", post(renormalize_embeddings)))
+        .layer(middleware::from_fn_with_state(state.audit_log.clone(), audit_middleware));
+
+    let compress_responses = state.config.server.compress_responses;
+    let router = Router::new()
         .route("/health", get(health_check))
-        .route("/recommendations/:user_id", get(get_recommendations))
-        .route("/actions", post(record_user_action))
-        .route("/items", post(add_item))
-        .route("/users/:user_id", get(get_user_profile))
-        .route("/items/:item_id", get(get_item_feature))
+        .route("/metrics", get(metrics))
+        .route(
+            "/recommendations/anonymous",
+            limited("/recommendations/anonymous", post(get_anonymous_recommendations)),
+        )
+        .route("/recommendations/group", limited("/recommendations/group", post(get_group_recommendations)))
+        .route("/recommendations/batch", limited("/recommendations/batch", post(batch_recommendations)))
+        .route("/recommendations/:user_id", limited("/recommendations/:user_id", get(get_recommendations)))
+        .route(
+            "/recommendations/:user_id/clusters",
+            limited("/recommendations/:user_id/clusters", get(get_cluster_recommendations)),
+        )
+        .route(
+            "/recommendations/:user_id/stream",
+            limited("/recommendations/:user_id/stream", get(stream_recommendations)),
+        )
+        .route("/users/:user_id", limited("/users/:user_id", get(get_user_profile)))
+        .route("/users/batch", limited("/users/batch", post(get_user_profiles)))
+        .route("/items/batch", limited("/items/batch", post(get_item_features)))
+        .route("/similar/users/:user_id", limited("/similar/users/:user_id", get(get_similar_users)))
+        .route("/similar/items/:item_id", limited("/similar/items/:item_id", get(get_similar_items)))
+        .route("/trending", limited("/trending", get(get_trending_items)))
+        .route("/stats/serving", get(get_serving_stats))
+        .route("/stats/coverage", get(get_catalog_coverage))
+        .merge(audited)
         .layer(
             ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
                 .layer(CorsLayer::permissive())
         )
-        .with_state(state)
+        .with_state(state);
+
+    // Negotiated by `Accept-Encoding`; large recommendation lists shrink several times over
+    if compress_responses {
+        router.layer(CompressionLayer::new())
+    } else {
+        router
+    }
 }
 
 #[tokio::main]
@@ -168,12 +775,280 @@ async fn main() -> anyhow::Result<()> {
     info!("Starting MilRustRec Recommendation Server with config: {:?}", config.server);
 
     let state = AppState::new(config.clone()).await?;
+    let kafka_producer = state.kafka_producer.clone();
+    let vector_db = state.vector_db.clone();
     let app = create_router(state);
 
     let listener = tokio::net::TcpListener::bind(config.server.socket_addr()).await?;
     info!("Server listening on {}", config.server.socket_addr());
 
-    axum::serve(listener, app).await?;
+    let drain_timeout = std::time::Duration::from_secs(config.server.shutdown_timeout_seconds);
+    serve_with_graceful_shutdown(listener, app, shutdown_signal(), drain_timeout).await?;
+
+    // Audit records queued by the drained requests still go out
+    if let Err(e) = tokio::task::spawn_blocking(move || kafka_producer.flush(drain_timeout)).await? {
+        tracing::warn!("Failed to flush Kafka producer on shutdown: {}", e);
+    }
+    if let Err(e) = vector_db.flush_metadata().await {
+        tracing::warn!("Failed to flush metadata store on shutdown: {}", e);
+    }
+    info!("Server stopped");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{Method, Request};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    /// `AppState` whose recommendation and serving services cache in memory instead of in Redis.
+    async fn in_memory_state(config: Config) -> AppState {
+        use milvuso::services::cache::InMemoryCache;
+        use milvuso::services::recommendation::RecommendationService;
+        use milvuso::services::serving::ServingService;
+
+        let mut state = AppState::new(config).await.unwrap();
+        let recommendation_service = Arc::new(
+            RecommendationService::with_cache(state.vector_db.clone(), Arc::new(InMemoryCache::new()), state.config.clone())
+                .await
+                .unwrap(),
+        );
+        state.serving_service = Arc::new(
+            ServingService::new(state.vector_db.clone(), recommendation_service.clone(), state.config.clone())
+                .await
+                .unwrap(),
+        );
+        state.recommendation_service = recommendation_service;
+        state
+    }
+
+    async fn error_response(app: Router, request: Request<Body>) -> (StatusCode, String) {
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: ApiResponse<String> = serde_json::from_slice(&body).unwrap();
+        assert!(!body.success);
+        (status, body.message)
+    }
+
+    #[tokio::test]
+    async fn test_invalid_input_is_rejected_with_bad_request() {
+        let app = create_router(AppState::new(Config::default()).await.unwrap());
+
+        let action = milvuso::UserAction::new(Uuid::nil(), Uuid::new_v4(), milvuso::ActionType::Click);
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/actions")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&action).unwrap()))
+            .unwrap();
+        let (status, message) = error_response(app.clone(), request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "User ID cannot be nil");
+
+        let request = Request::builder()
+            .uri(format!("/recommendations/{}?num_recommendations=0", Uuid::new_v4()))
+            .body(Body::empty())
+            .unwrap();
+        let (status, message) = error_response(app, request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(message, "Number of recommendations must be greater than 0");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_model_version_is_not_found() {
+        let app = create_router(in_memory_state(Config::default()).await);
+
+        let request = Request::builder()
+            .uri(format!("/recommendations/{}", Uuid::new_v4()))
+            .header("x-model-version", "missing")
+            .body(Body::empty())
+            .unwrap();
+        let (status, message) = error_response(app, request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(message, "Unknown model version missing");
+    }
+
+    #[tokio::test]
+    async fn test_batch_recommendations_report_each_request() {
+        let mut config = Config::default();
+        config.recommendation.embedding_dim = 8;
+        config.milvus.dimension = 8;
+        config.recommendation.similarity_threshold = 0.0;
+        let state = in_memory_state(config).await;
+        for i in 0..5 {
+            let embedding: Vec<f32> = (0..8).map(|j| if j == i { 1.0 } else { 0.1 }).collect();
+            let feature = milvuso::ItemFeature::new(Uuid::from_u128(i as u128 + 1), embedding, "books".to_string());
+            state.recommendation_service.add_item_feature(feature).await.unwrap();
+        }
+        let user_id = Uuid::new_v4();
+        let purchase = milvuso::UserAction::new(user_id, Uuid::from_u128(1), milvuso::ActionType::Purchase);
+        state.recommendation_service.process_user_action(&purchase).await.unwrap();
+        let app = create_router(state);
+
+        let served = milvuso::RecommendationRequest::new(user_id, 2);
+        let failing = milvuso::RecommendationRequest {
+            model_version: Some("missing".to_string()),
+            ..milvuso::RecommendationRequest::new(user_id, 2)
+        };
+        let post = |requests: &[milvuso::RecommendationRequest]| {
+            Request::builder()
+                .method(Method::POST)
+                .uri("/recommendations/batch")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(requests).unwrap()))
+                .unwrap()
+        };
+        let response = app.clone().oneshot(post(&[served.clone(), failing])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let results = body["data"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["recommendation_count"], 2);
+        assert_eq!(results[0]["response"]["recommendations"].as_array().unwrap().len(), 2);
+        assert!(results[0]["candidate_count"].as_u64().unwrap() >= 2);
+        assert_eq!(results[1]["error"], "Unknown model version missing");
+        assert_eq!(results[1]["candidate_count"], 0);
+
+        // Invalid requests fail the whole batch up front
+        let invalid = milvuso::RecommendationRequest::new(user_id, 0);
+        let (status, message) = error_response(app, post(&[served, invalid])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(message.starts_with("Request 1: "));
+    }
+
+    #[tokio::test]
+    async fn test_refused_writes_and_invalid_group_requests_are_not_server_errors() {
+        let post_json = |uri: &str, body: Vec<u8>| {
+            Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let mut config = Config::default();
+        config.recommendation.embedding_dim = 8;
+        config.milvus.dimension = 8;
+        config.outlier_detection.enabled = true;
+        config.outlier_detection.min_samples = 20;
+        let state = in_memory_state(config.clone()).await;
+        for i in 0..50 {
+            let embedding: Vec<f32> = (0..8).map(|j| 0.5 + ((i * 3 + j) % 7) as f32 * 0.01).collect();
+            let feature = milvuso::ItemFeature::new(Uuid::new_v4(), embedding, "books".to_string());
+            state.recommendation_service.add_item_feature(feature).await.unwrap();
+        }
+        let app = create_router(state);
+
+        let outlier = milvuso::ItemFeature::new(Uuid::new_v4(), vec![50.0; 8], "books".to_string());
+        let request = post_json("/items", serde_json::to_vec(&outlier).unwrap());
+        let (status, message) = error_response(app.clone(), request).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(message.contains("outlier"));
+
+        let group = milvuso::GroupRecommendationRequest {
+            user_ids: Vec::new(),
+            aggregation: Default::default(),
+            num_recommendations: 10,
+            filter_categories: None,
+            exclude_items: None,
+            tenant_id: None,
+        };
+        let request = post_json("/recommendations/group", serde_json::to_vec(&group).unwrap());
+        let (status, _) = error_response(app, request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        config.milvus.read_only = true;
+        let app = create_router(in_memory_state(config).await);
+        let item = milvuso::ItemFeature::new(Uuid::new_v4(), vec![0.5; 8], "books".to_string());
+        let (status, _) = error_response(app.clone(), post_json("/items", serde_json::to_vec(&item).unwrap())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        // Refused before the Kafka send, so this never reaches the (absent) broker
+        let action = milvuso::UserAction::new(Uuid::new_v4(), item.item_id, milvuso::ActionType::Click);
+        let (status, message) = error_response(app, post_json("/actions", serde_json::to_vec(&action).unwrap())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(message.contains("read-only"));
+    }
+
+    #[tokio::test]
+    async fn test_admin_reads_require_the_admin_token() {
+        let mut config = Config::default();
+        config.server.admin_token = Some("secret".to_string());
+        let app = create_router(in_memory_state(config).await);
+
+        let sweep = serde_json::json!({ "k": 5, "thresholds": [0.5], "candidates": [] });
+        let request = |method: Method, uri: &str, token: Option<&str>| {
+            let mut request = Request::builder().method(method.clone()).uri(uri);
+            if let Some(token) = token {
+                request = request.header("x-admin-token", token);
+            }
+            if method == Method::POST {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                return request.body(Body::from(sweep.to_string())).unwrap();
+            }
+            request.body(Body::empty()).unwrap()
+        };
+        for (method, uri) in [(Method::GET, "/admin/index-stats"), (Method::POST, "/admin/evaluation/threshold-sweep")] {
+            let status = |token| {
+                let app = app.clone();
+                let request = request(method.clone(), uri, token);
+                async move { app.oneshot(request).await.unwrap().status() }
+            };
+            assert_eq!(status(None).await, StatusCode::FORBIDDEN, "{}", uri);
+            assert_eq!(status(Some("wrong")).await, StatusCode::FORBIDDEN, "{}", uri);
+            assert_eq!(status(Some("secret")).await, StatusCode::OK, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compressed_response_decompresses_to_the_same_body() {
+        use std::io::Read;
+
+        let mut config = Config::default();
+        config.recommendation.cold_start_threshold = 0;
+        config.recommendation.similarity_threshold = -1.0;
+        let state = in_memory_state(config).await;
+        let dimension = state.config.recommendation.embedding_dim;
+        let user_id = Uuid::new_v4();
+        for i in 0..200usize {
+            let mut embedding = vec![0.0; dimension];
+            embedding[i % dimension] = 1.0;
+            let feature = milvuso::ItemFeature::new(Uuid::from_u128(i as u128 + 1), embedding, "books".to_string());
+            state.recommendation_service.add_item_feature(feature).await.unwrap();
+        }
+        let purchase = milvuso::UserAction::new(user_id, Uuid::from_u128(1), milvuso::ActionType::Purchase);
+        state.recommendation_service.process_user_action(&purchase).await.unwrap();
+        let app = create_router(state);
+
+        let fetch = |accept_encoding: Option<&'static str>| {
+            let mut request = Request::builder().uri(format!("/recommendations/{}?num_recommendations=100", user_id));
+            if let Some(encoding) = accept_encoding {
+                request = request.header(header::ACCEPT_ENCODING, encoding);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let plain = fetch(None).await.unwrap();
+        assert!(plain.headers().get(header::CONTENT_ENCODING).is_none());
+        let plain = axum::body::to_bytes(plain.into_body(), usize::MAX).await.unwrap();
+
+        let compressed = fetch(Some("gzip")).await.unwrap();
+        assert_eq!(compressed.headers()[header::CONTENT_ENCODING], "gzip");
+        let compressed = axum::body::to_bytes(compressed.into_body(), usize::MAX).await.unwrap();
+        assert!(compressed.len() < plain.len() / 2);
+
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_end(&mut decompressed).unwrap();
+        // Each serving gets its own id and timestamp, so compare the ranked items
+        let plain: serde_json::Value = serde_json::from_slice(&plain).unwrap();
+        let decompressed: serde_json::Value = serde_json::from_slice(&decompressed).unwrap();
+        assert_eq!(decompressed["data"]["recommendations"], plain["data"]["recommendations"]);
+        assert_eq!(decompressed["data"]["recommendations"].as_array().unwrap().len(), 100);
+    }
+}
@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Timelike, Utc};
+
+/// Name of the embedding version stored in `ItemFeature::embedding`.
+pub const CURRENT_EMBEDDING_VERSION: &str = "current";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserAction {
@@ -9,6 +13,9 @@ pub struct UserAction {
     pub action_type: ActionType,
     pub timestamp: DateTime<Utc>,
     pub context: Option<serde_json::Value>,
+    /// The `recommendation_id` of the response this action is feedback on, if any.
+    #[serde(default)]
+    pub recommendation_id: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +26,17 @@ pub enum ActionType {
     Purchase,
     View,
     Convert,
+    /// Explicit negative feedback: trains below 0, pushes the profile away from the item and stops
+    /// it from being recommended to the user again.
+    Dislike,
+    Hide,
+    NotInterested,
+}
+
+impl ActionType {
+    pub fn is_negative(&self) -> bool {
+        matches!(self, ActionType::Dislike | ActionType::Hide | ActionType::NotInterested)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +46,9 @@ pub struct UserProfile {
     pub preferences: Vec<String>,
     pub last_updated: DateTime<Utc>,
     pub interaction_count: u64,
+    /// Bumped on every embedding or interaction change, so caches keyed on it go stale on their own.
+    #[serde(default)]
+    pub version: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,8 +59,33 @@ pub struct ItemFeature {
     pub tags: Vec<String>,
     pub popularity_score: f32,
     pub created_at: DateTime<Utc>,
+    /// Alternative named embeddings (e.g. from a retrained model) served side by side with `embedding`.
+    #[serde(default)]
+    pub embedding_versions: HashMap<String, Vec<f32>>,
+    /// Time-limited items (events, flash sales) stop being recommended after this instant.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When the item was last inserted or replaced. `created_at` survives replacement.
+    #[serde(default = "Utc::now")]
+    pub last_updated: DateTime<Utc>,
+}
+
+/// How inserting an item treats an existing item with the same id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InsertMode {
+    /// Insert, or replace an existing item.
+    #[default]
+    Upsert,
+    /// Insert, failing if the item exists.
+    InsertOnly,
+    /// Replace, failing if the item does not exist.
+    UpdateOnly,
 }
 
+/// Names of the leading `TrainingExample::context_features`, by position. Later positions are unused.
+pub const CONTEXT_FEATURE_NAMES: [&str; 3] = ["hour_of_day", "day_of_week", "action_weight"];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrainingExample {
     pub user_id: Uuid,
@@ -57,28 +103,233 @@ pub struct RecommendationRequest {
     pub num_recommendations: usize,
     pub filter_categories: Option<Vec<String>>,
     pub exclude_items: Option<Vec<Uuid>>,
+    pub tenant_id: Option<String>,
+    /// Item embedding version to retrieve and score against; `None` means the current one.
+    #[serde(default)]
+    pub embedding_version: Option<String>,
+    /// Guarantee at least this many distinct categories in the response, at some cost in relevance.
+    #[serde(default)]
+    pub min_distinct_categories: Option<usize>,
+    /// Only serve items carrying at least one of these tags.
+    #[serde(default)]
+    pub filter_tags: Option<Vec<String>>,
+    /// When the filters leave fewer than `num_recommendations` items, fill the rest by dropping the
+    /// tag filter, then the category filter. Exclusions are never relaxed.
+    #[serde(default)]
+    pub relax_filters: bool,
+    /// Only serve items created at most this many seconds ago. A hard filter, never relaxed.
+    #[serde(default)]
+    pub max_item_age_seconds: Option<u64>,
+    /// Number of top-ranked items to skip, for paging.
+    #[serde(default)]
+    pub offset: usize,
+    /// Requests of one session reuse its scored candidates while the profile is unchanged, so
+    /// later pages and filter changes are not scored again.
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Named serving model to score with, e.g. `challenger`, instead of the default one. Unknown
+    /// names are an error.
+    #[serde(default)]
+    pub model_version: Option<String>,
+    /// Also serve items the user already interacted with, which `seen_items_cap` otherwise
+    /// excludes.
+    #[serde(default)]
+    pub include_seen: bool,
+    /// Explain each recommendation in its `reason`. Off skips building the explanations entirely.
+    #[serde(default = "default_include_reasons")]
+    pub include_reasons: bool,
+}
+
+fn default_include_reasons() -> bool {
+    true
+}
+
+/// Recommendation request for logged-out traffic, built only from context.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnonymousRecommendationRequest {
+    #[serde(default)]
+    pub recent_items: Vec<Uuid>,
+    pub preferred_categories: Option<Vec<String>>,
+    pub device: Option<String>,
+    pub num_recommendations: usize,
+    pub tenant_id: Option<String>,
+}
+
+/// How a group's individual preferences are combined into one ranking.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GroupAggregation {
+    /// Rank against the average of the members' embeddings.
+    #[default]
+    AverageEmbedding,
+    /// Score each item by its least satisfied member, so nobody gets something they dislike.
+    LeastMisery,
+    /// Score each item by its most satisfied member.
+    MostPleasure,
+}
+
+/// Recommendation request for several users consuming items together (family accounts, group watch).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupRecommendationRequest {
+    pub user_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub aggregation: GroupAggregation,
+    pub num_recommendations: usize,
+    pub filter_categories: Option<Vec<String>>,
+    pub exclude_items: Option<Vec<Uuid>>,
+    pub tenant_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecommendationResponse {
+    pub recommendation_id: Uuid,
     pub user_id: Uuid,
     pub recommendations: Vec<RecommendationItem>,
     pub generated_at: DateTime<Utc>,
+    /// Set while the model is below its training threshold and scores are similarity-only.
+    #[serde(default)]
+    pub model_warming_up: bool,
+}
+
+/// Event of a streamed recommendation: progress while scoring, then the ranked items, then `Done`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RecommendationStreamEvent {
+    /// `scored` of `total` candidates have been processed.
+    Progress { scored: usize, total: usize },
+    Item { rank: usize, item: RecommendationItem },
+    Done { recommendation_id: Uuid, model_warming_up: bool },
+    Error { message: String },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecommendationItem {
     pub item_id: Uuid,
     pub score: f32,
-    pub reason: String,
+    /// Why the item was picked, left out when the request turns reasons off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
     pub category: String,
+    /// Set on items that only made it in because this filter was relaxed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relaxed_filter: Option<RelaxedFilter>,
+}
+
+/// Request filter dropped to fill a short response, in the order filters are relaxed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelaxedFilter {
+    Tags,
+    Category,
+}
+
+/// Interaction volume for an item: how often it was viewed and how often it drew a stronger action.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ItemInteractionCounts {
+    pub views: u64,
+    pub positives: u64,
+    /// Recency-weighted count of non-negative interactions, for trending.
+    #[serde(default)]
+    pub recent: DecayingCount,
+}
+
+impl ItemInteractionCounts {
+    /// Number of trials for popularity scoring; an item can be engaged with without a recorded view.
+    pub fn trials(&self) -> u64 {
+        self.views.max(self.positives)
+    }
+}
+
+/// Slice of traffic a trending list is computed over. Unset dimensions are not sliced on, so the
+/// default context is global trending.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TrendingContext {
+    /// UTC hour of day, 0-23.
+    #[serde(default)]
+    pub hour_of_day: Option<u32>,
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+impl TrendingContext {
+    /// The context an action happened in: its UTC hour, and the string `region` of its context if any.
+    pub fn of_action(action: &UserAction) -> Self {
+        let region = action
+            .context
+            .as_ref()
+            .and_then(|context| context.get("region"))
+            .and_then(|region| region.as_str())
+            .map(str::to_string);
+        Self { hour_of_day: Some(action.timestamp.hour()), region }
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.hour_of_day.is_none() && self.region.is_none()
+    }
+
+    /// Every non-global context an interaction in this context counts toward: each set dimension on
+    /// its own and all of them together.
+    pub fn buckets(&self) -> Vec<TrendingContext> {
+        let mut buckets = Vec::new();
+        for hour_of_day in [None, self.hour_of_day] {
+            for region in [None, self.region.clone()] {
+                let bucket = TrendingContext { hour_of_day, region };
+                if !bucket.is_global() && !buckets.contains(&bucket) {
+                    buckets.push(bucket);
+                }
+            }
+        }
+        buckets
+    }
+}
+
+/// Count in which every event loses half its weight per `half_life`, i.e. a measure of how fast
+/// events are arriving lately.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct DecayingCount {
+    value: f64,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl DecayingCount {
+    fn decay(elapsed: chrono::Duration, half_life_seconds: f64) -> f64 {
+        let elapsed = elapsed.num_milliseconds() as f64 / 1000.0;
+        if half_life_seconds <= 0.0 {
+            return if elapsed > 0.0 { 0.0 } else { 1.0 };
+        }
+        0.5f64.powf(elapsed / half_life_seconds)
+    }
+
+    /// Adds one event that happened at `at`, which may be older than earlier events.
+    pub fn add(&mut self, at: DateTime<Utc>, half_life_seconds: f64) {
+        match self.updated_at {
+            Some(updated_at) if at < updated_at => {
+                self.value += Self::decay(updated_at - at, half_life_seconds);
+            }
+            Some(updated_at) => {
+                self.value = self.value * Self::decay(at - updated_at, half_life_seconds) + 1.0;
+                self.updated_at = Some(at);
+            }
+            None => {
+                self.value = 1.0;
+                self.updated_at = Some(at);
+            }
+        }
+    }
+
+    pub fn value_at(&self, now: DateTime<Utc>, half_life_seconds: f64) -> f64 {
+        match self.updated_at {
+            Some(updated_at) if now > updated_at => self.value * Self::decay(now - updated_at, half_life_seconds),
+            _ => self.value,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelParameters {
     pub version: String,
-    pub user_embedding_weights: Vec<Vec<f32>>,
-    pub item_embedding_weights: Vec<Vec<f32>>,
+    /// Learned embedding of every user and item the model has trained on.
+    pub user_embeddings: HashMap<Uuid, Vec<f32>>,
+    pub item_embeddings: HashMap<Uuid, Vec<f32>>,
     pub bias_weights: Vec<f32>,
     pub updated_at: DateTime<Utc>,
 }
@@ -105,6 +356,7 @@ impl UserAction {
             action_type,
             timestamp: Utc::now(),
             context: None,
+            recommendation_id: None,
         }
     }
     
@@ -112,6 +364,83 @@ impl UserAction {
         self.context = Some(context);
         self
     }
+
+    pub fn with_recommendation_id(mut self, recommendation_id: Uuid) -> Self {
+        self.recommendation_id = Some(recommendation_id);
+        self
+    }
+}
+
+impl RecommendationRequest {
+    pub fn new(user_id: Uuid, num_recommendations: usize) -> Self {
+        Self {
+            user_id,
+            num_recommendations,
+            filter_categories: None,
+            exclude_items: None,
+            tenant_id: None,
+            embedding_version: None,
+            min_distinct_categories: None,
+            filter_tags: None,
+            relax_filters: false,
+            max_item_age_seconds: None,
+            offset: 0,
+            session_id: None,
+            model_version: None,
+            include_seen: false,
+            include_reasons: true,
+        }
+    }
+    
+    pub fn with_filter_categories(mut self, categories: Vec<String>) -> Self {
+        self.filter_categories = Some(categories);
+        self
+    }
+    
+    pub fn with_exclude_items(mut self, items: Vec<Uuid>) -> Self {
+        self.exclude_items = Some(items);
+        self
+    }
+    
+    pub fn with_tenant(mut self, tenant_id: String) -> Self {
+        self.tenant_id = Some(tenant_id);
+        self
+    }
+
+    pub fn with_embedding_version(mut self, version: String) -> Self {
+        self.embedding_version = Some(version);
+        self
+    }
+
+    pub fn with_min_distinct_categories(mut self, min_distinct_categories: usize) -> Self {
+        self.min_distinct_categories = Some(min_distinct_categories);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_session(mut self, session_id: String) -> Self {
+        self.session_id = Some(session_id);
+        self
+    }
+
+    pub fn with_filter_tags(mut self, tags: Vec<String>) -> Self {
+        self.filter_tags = Some(tags);
+        self
+    }
+
+    pub fn with_relaxed_filters(mut self) -> Self {
+        self.relax_filters = true;
+        self
+    }
+
+    pub fn with_max_item_age(mut self, max_age: std::time::Duration) -> Self {
+        self.max_item_age_seconds = Some(max_age.as_secs());
+        self
+    }
 }
 
 impl UserProfile {
@@ -122,29 +451,40 @@ impl UserProfile {
             preferences: Vec::new(),
             last_updated: Utc::now(),
             interaction_count: 0,
+            version: 0,
         }
     }
     
     pub fn update_embedding(&mut self, new_embedding: Vec<f32>) {
         self.embedding = new_embedding;
-        self.last_updated = Utc::now();
+        self.touch();
     }
     
     pub fn increment_interactions(&mut self) {
         self.interaction_count += 1;
+        self.touch();
+    }
+
+    /// Marks the profile as changed: refreshes `last_updated` and bumps `version`.
+    pub fn touch(&mut self) {
         self.last_updated = Utc::now();
+        self.version += 1;
     }
 }
 
 impl ItemFeature {
     pub fn new(item_id: Uuid, embedding: Vec<f32>, category: String) -> Self {
+        let now = Utc::now();
         Self {
             item_id,
             embedding,
             category,
             tags: Vec::new(),
             popularity_score: 0.0,
-            created_at: Utc::now(),
+            created_at: now,
+            embedding_versions: HashMap::new(),
+            expires_at: None,
+            last_updated: now,
         }
     }
     
@@ -157,4 +497,27 @@ impl ItemFeature {
         self.popularity_score = score;
         self
     }
+
+    pub fn with_embedding_version(mut self, version: String, embedding: Vec<f32>) -> Self {
+        self.embedding_versions.insert(version, embedding);
+        self
+    }
+
+    pub fn with_expiry(mut self, expires_at: DateTime<Utc>) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// The embedding for a named version, where `CURRENT_EMBEDDING_VERSION` is `embedding` itself.
+    pub fn embedding_for(&self, version: &str) -> Option<&[f32]> {
+        if version == CURRENT_EMBEDDING_VERSION {
+            Some(&self.embedding)
+        } else {
+            self.embedding_versions.get(version).map(|embedding| embedding.as_slice())
+        }
+    }
 }
@@ -0,0 +1,158 @@
+use crate::services::kafka::KafkaProducer;
+use anyhow::Result;
+use axum::body::{to_bytes, Body};
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{HeaderMap, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+use uuid::Uuid;
+
+/// Largest request body the audit middleware buffers to find target ids, matching axum's default
+/// body limit for JSON extractors.
+const MAX_AUDITED_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Who did what to which ids, and when. One is written for every audited request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Method and route, e.g. `DELETE /items/:item_id`.
+    pub operation: String,
+    /// `admin` when the request carried the admin token, otherwise `anonymous`.
+    pub principal: String,
+    /// Ids in the path and in top-level `*_id` fields of a JSON body.
+    pub target_ids: Vec<Uuid>,
+    pub status: u16,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[async_trait::async_trait]
+pub trait AuditSink: Send + Sync {
+    async fn record(&self, record: &AuditRecord) -> Result<()>;
+}
+
+/// Publishes audit records to `kafka.audit_topic`.
+pub struct KafkaAuditSink {
+    producer: Arc<KafkaProducer>,
+}
+
+impl KafkaAuditSink {
+    pub fn new(producer: Arc<KafkaProducer>) -> Self {
+        Self { producer }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditSink for KafkaAuditSink {
+    async fn record(&self, record: &AuditRecord) -> Result<()> {
+        self.producer.send_audit_record(record).await
+    }
+}
+
+/// Keeps audit records in memory, for tests and single-node setups.
+#[derive(Default)]
+pub struct InMemoryAuditSink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl InMemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditSink for InMemoryAuditSink {
+    async fn record(&self, record: &AuditRecord) -> Result<()> {
+        self.records.lock().unwrap().push(record.clone());
+        Ok(())
+    }
+}
+
+/// State of `audit_middleware`: where records go and how principals are recognised.
+#[derive(Clone)]
+pub struct AuditLog {
+    sink: Arc<dyn AuditSink>,
+    admin_token: Option<String>,
+}
+
+impl AuditLog {
+    pub fn new(sink: Arc<dyn AuditSink>, admin_token: Option<String>) -> Self {
+        Self { sink, admin_token }
+    }
+
+    /// Whether the request carries the configured admin token in `x-admin-token`.
+    pub fn is_admin(&self, headers: &HeaderMap) -> bool {
+        self.admin_token.as_deref().is_some_and(|token| {
+            headers.get("x-admin-token").and_then(|value| value.to_str().ok()) == Some(token)
+        })
+    }
+
+    fn principal(&self, headers: &HeaderMap) -> &'static str {
+        if self.is_admin(headers) {
+            "admin"
+        } else {
+            "anonymous"
+        }
+    }
+}
+
+/// Writes an `AuditRecord` for every request it wraps except `GET`/`HEAD`/`OPTIONS` ones, after
+/// the handler ran so the outcome is recorded too. A failing sink is logged but never fails the
+/// request, which has already taken effect.
+pub async fn audit_middleware(State(audit): State<AuditLog>, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let operation = format!("{} {}", request.method(), route);
+    let principal = audit.principal(request.headers()).to_string();
+    let mut target_ids: Vec<Uuid> = request
+        .uri()
+        .path()
+        .split('/')
+        .filter_map(|segment| Uuid::parse_str(segment).ok())
+        .collect();
+
+    // The body has to be buffered to read its ids, then handed on to the handler unchanged
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, MAX_AUDITED_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+    if let Ok(serde_json::Value::Object(fields)) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+        for (name, value) in &fields {
+            if let Some(id) = value.as_str().filter(|_| name.ends_with("_id")).and_then(|id| Uuid::parse_str(id).ok()) {
+                if !target_ids.contains(&id) {
+                    target_ids.push(id);
+                }
+            }
+        }
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(bytes))).await;
+
+    let record = AuditRecord {
+        operation,
+        principal,
+        target_ids,
+        status: response.status().as_u16(),
+        timestamp: Utc::now(),
+    };
+    if let Err(e) = audit.sink.record(&record).await {
+        warn!("Failed to write audit record for {}: {}", record.operation, e);
+    }
+
+    response
+}
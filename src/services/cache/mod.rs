@@ -0,0 +1,278 @@
+use anyhow::Result;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+#[async_trait::async_trait]
+pub trait CacheBackend: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+    async fn set_ex(&self, key: &str, value: String, ttl_seconds: u64) -> Result<()>;
+    /// Binary-safe variants of `get`/`set_ex`, for payloads that are not UTF-8.
+    async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    async fn set_ex_bytes(&self, key: &str, value: Vec<u8>, ttl_seconds: u64) -> Result<()>;
+    /// Sets the key only if it does not exist yet. Returns `true` when the value was written.
+    async fn set_nx_ex(&self, key: &str, value: String, ttl_seconds: u64) -> Result<bool>;
+    async fn delete(&self, key: &str) -> Result<()>;
+    /// Takes one token from the bucket at `key`, which holds up to `capacity` tokens and refills at
+    /// `refill_per_second`. Returns `false` when the bucket is empty.
+    async fn take_token(&self, key: &str, capacity: f64, refill_per_second: f64) -> Result<bool>;
+}
+
+/// Refills the bucket for the time since it was last touched, then takes a token if one is left.
+/// Evaluated atomically so concurrent instances share one bucket per key.
+const TAKE_TOKEN_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local now_ms = tonumber(ARGV[3])
+local ttl = tonumber(ARGV[4])
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_ms')
+local tokens = tonumber(bucket[1]) or capacity
+local updated_ms = tonumber(bucket[2]) or now_ms
+tokens = math.min(capacity, tokens + math.max(0, now_ms - updated_ms) / 1000 * rate)
+local taken = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    taken = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_ms', tostring(now_ms))
+redis.call('EXPIRE', KEYS[1], ttl)
+return taken
+"#;
+
+/// In-memory token buckets are swept for idle ones once there are this many.
+const BUCKET_SWEEP_THRESHOLD: usize = 10_000;
+
+/// Seconds until an untouched bucket is full again, after which its key can expire.
+fn bucket_ttl_seconds(capacity: f64, refill_per_second: f64) -> u64 {
+    if refill_per_second > 0.0 {
+        (capacity / refill_per_second).ceil() as u64 + 1
+    } else {
+        // A bucket that never refills has to outlive any realistic session
+        86_400
+    }
+}
+
+/// Redis-backed cache over a fixed pool of multiplexed connections, handed out round-robin and
+/// cloned per call. Each slot connects on first use and reconnects after its connection fails.
+pub struct RedisCache {
+    client: Arc<redis::Client>,
+    connections: Vec<RwLock<Option<MultiplexedConnection>>>,
+    next_slot: AtomicUsize,
+    connections_opened: AtomicU64,
+}
+
+impl RedisCache {
+    pub fn new(client: Arc<redis::Client>) -> Self {
+        Self::with_pool_size(client, 1)
+    }
+
+    /// Spreads calls over `pool_size` connections, at least one.
+    pub fn with_pool_size(client: Arc<redis::Client>, pool_size: usize) -> Self {
+        Self {
+            client,
+            connections: (0..pool_size.max(1)).map(|_| RwLock::new(None)).collect(),
+            next_slot: AtomicUsize::new(0),
+            connections_opened: AtomicU64::new(0),
+        }
+    }
+
+    /// Connections established since the cache was created, reconnects included.
+    pub fn connections_opened(&self) -> u64 {
+        self.connections_opened.load(Ordering::Relaxed)
+    }
+
+    /// Runs `query` on the next pooled connection, opening it first if needed. A failed query
+    /// drops the slot's connection so the next call through it reconnects.
+    async fn with_connection<T, F, Fut>(&self, query: F) -> Result<T>
+    where
+        F: FnOnce(MultiplexedConnection) -> Fut,
+        Fut: std::future::Future<Output = redis::RedisResult<T>>,
+    {
+        let slot = &self.connections[self.next_slot.fetch_add(1, Ordering::Relaxed) % self.connections.len()];
+        let existing = slot.read().await.clone();
+        let conn = match existing {
+            Some(conn) => conn,
+            None => {
+                let mut guard = slot.write().await;
+                match guard.as_ref() {
+                    Some(conn) => conn.clone(),
+                    None => {
+                        let conn = self.client.get_multiplexed_tokio_connection().await?;
+                        self.connections_opened.fetch_add(1, Ordering::Relaxed);
+                        *guard = Some(conn.clone());
+                        conn
+                    }
+                }
+            }
+        };
+
+        match query(conn).await {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                if e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() || e.is_timeout() {
+                    *slot.write().await = None;
+                }
+                Err(e.into())
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheBackend for RedisCache {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        self.with_connection(|mut conn| async move { conn.get(key).await }).await
+    }
+
+    async fn set_ex(&self, key: &str, value: String, ttl_seconds: u64) -> Result<()> {
+        self.with_connection(|mut conn| async move { conn.set_ex(key, value, ttl_seconds).await })
+            .await
+    }
+
+    async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.with_connection(|mut conn| async move { conn.get(key).await }).await
+    }
+
+    async fn set_ex_bytes(&self, key: &str, value: Vec<u8>, ttl_seconds: u64) -> Result<()> {
+        self.with_connection(|mut conn| async move { conn.set_ex(key, value, ttl_seconds).await })
+            .await
+    }
+
+    async fn set_nx_ex(&self, key: &str, value: String, ttl_seconds: u64) -> Result<bool> {
+        let reply: Option<String> = self
+            .with_connection(|mut conn| async move {
+                redis::cmd("SET")
+                    .arg(key)
+                    .arg(value)
+                    .arg("NX")
+                    .arg("EX")
+                    .arg(ttl_seconds)
+                    .query_async(&mut conn)
+                    .await
+            })
+            .await?;
+        Ok(reply.is_some())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.with_connection(|mut conn| async move { conn.del(key).await }).await
+    }
+
+    async fn take_token(&self, key: &str, capacity: f64, refill_per_second: f64) -> Result<bool> {
+        let taken: i64 = self
+            .with_connection(|mut conn| async move {
+                redis::Script::new(TAKE_TOKEN_SCRIPT)
+                    .key(key)
+                    .arg(capacity)
+                    .arg(refill_per_second)
+                    .arg(chrono::Utc::now().timestamp_millis())
+                    .arg(bucket_ttl_seconds(capacity, refill_per_second))
+                    .invoke_async(&mut conn)
+                    .await
+            })
+            .await?;
+        Ok(taken == 1)
+    }
+}
+
+/// Process-local cache with the same semantics as `RedisCache`, used for tests and single-node setups.
+#[derive(Default)]
+pub struct InMemoryCache {
+    entries: DashMap<String, (Vec<u8>, Instant)>,
+    /// Token buckets as (tokens left, last refill, expiry). Like the Redis keys, a bucket left idle
+    /// until it would be full again expires and starts over full.
+    buckets: DashMap<String, (f64, Instant, Instant)>,
+}
+
+impl InMemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn live_value(&self, key: &str) -> Option<Vec<u8>> {
+        if let Some(entry) = self.entries.get(key) {
+            if entry.1 > Instant::now() {
+                return Some(entry.0.clone());
+            }
+        }
+
+        self.entries.remove_if(key, |_, (_, expires_at)| *expires_at <= Instant::now());
+        None
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheBackend for InMemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        match self.live_value(key) {
+            Some(bytes) => Ok(Some(String::from_utf8(bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn set_ex(&self, key: &str, value: String, ttl_seconds: u64) -> Result<()> {
+        self.set_ex_bytes(key, value.into_bytes(), ttl_seconds).await
+    }
+
+    async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.live_value(key))
+    }
+
+    async fn set_ex_bytes(&self, key: &str, value: Vec<u8>, ttl_seconds: u64) -> Result<()> {
+        let expires_at = Instant::now() + Duration::from_secs(ttl_seconds);
+        self.entries.insert(key.to_string(), (value, expires_at));
+        Ok(())
+    }
+
+    async fn set_nx_ex(&self, key: &str, value: String, ttl_seconds: u64) -> Result<bool> {
+        let now = Instant::now();
+        let expires_at = now + Duration::from_secs(ttl_seconds);
+
+        match self.entries.entry(key.to_string()) {
+            Entry::Occupied(entry) if entry.get().1 > now => Ok(false),
+            Entry::Occupied(mut entry) => {
+                entry.insert((value.into_bytes(), expires_at));
+                Ok(true)
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((value.into_bytes(), expires_at));
+                Ok(true)
+            }
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.entries.remove(key);
+        Ok(())
+    }
+
+    async fn take_token(&self, key: &str, capacity: f64, refill_per_second: f64) -> Result<bool> {
+        let now = Instant::now();
+        if self.buckets.len() >= BUCKET_SWEEP_THRESHOLD {
+            self.buckets.retain(|_, (_, _, expires_at)| *expires_at > now);
+        }
+
+        let mut bucket = self.buckets.entry(key.to_string()).or_insert((capacity, now, now));
+        let (tokens, updated_at, expires_at) = &mut *bucket;
+        if *expires_at <= now {
+            *tokens = capacity;
+        } else {
+            let elapsed = now.duration_since(*updated_at).as_secs_f64();
+            *tokens = (*tokens + elapsed * refill_per_second).min(capacity);
+        }
+        *updated_at = now;
+        *expires_at = now + Duration::from_secs(bucket_ttl_seconds(capacity, refill_per_second));
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
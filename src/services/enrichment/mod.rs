@@ -0,0 +1,42 @@
+use crate::models::ItemFeature;
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Rewrites an item before it is validated and indexed, e.g. to derive tags from its text or look
+/// its category up in another service.
+#[async_trait::async_trait]
+pub trait FeatureEnricher: Send + Sync {
+    async fn enrich(&self, feature: ItemFeature) -> Result<ItemFeature>;
+}
+
+/// Runs enrichers in the order they were added, each seeing the previous one's output. Empty, it
+/// returns features unchanged. The first failure aborts the chain.
+#[derive(Clone, Default)]
+pub struct EnricherChain {
+    enrichers: Vec<Arc<dyn FeatureEnricher>>,
+}
+
+impl EnricherChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then(mut self, enricher: Arc<dyn FeatureEnricher>) -> Self {
+        self.enrichers.push(enricher);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.enrichers.is_empty()
+    }
+}
+
+#[async_trait::async_trait]
+impl FeatureEnricher for EnricherChain {
+    async fn enrich(&self, mut feature: ItemFeature) -> Result<ItemFeature> {
+        for enricher in &self.enrichers {
+            feature = enricher.enrich(feature).await?;
+        }
+        Ok(feature)
+    }
+}
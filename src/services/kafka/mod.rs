@@ -1,15 +1,65 @@
 use crate::config::Config;
 use crate::models::*;
+use crate::services::audit::AuditRecord;
 use anyhow::Result;
 use rdkafka::config::ClientConfig;
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::Message;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::{Message, Offset, TopicPartitionList};
+use serde::de::DeserializeOwned;
 use serde_json;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tracing::{info, error, warn};
 
+/// Where consumed messages go when they cannot be deserialized.
+#[async_trait::async_trait]
+pub trait DeadLetterSink: Send + Sync {
+    /// Publishes `payload`, exactly as consumed from `source_topic`, to `topic` along with `error`.
+    async fn send_dead_letter(&self, topic: &str, source_topic: &str, payload: &[u8], error: &str) -> Result<()>;
+}
+
+/// A message `InMemoryDeadLetterSink` received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    pub topic: String,
+    pub source_topic: String,
+    pub payload: Vec<u8>,
+    pub error: String,
+}
+
+/// Keeps dead letters in memory, for tests and single-node setups.
+#[derive(Default)]
+pub struct InMemoryDeadLetterSink {
+    letters: Mutex<Vec<DeadLetter>>,
+}
+
+impl InMemoryDeadLetterSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn letters(&self) -> Vec<DeadLetter> {
+        self.letters.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl DeadLetterSink for InMemoryDeadLetterSink {
+    async fn send_dead_letter(&self, topic: &str, source_topic: &str, payload: &[u8], error: &str) -> Result<()> {
+        self.letters.lock().unwrap().push(DeadLetter {
+            topic: topic.to_string(),
+            source_topic: source_topic.to_string(),
+            payload: payload.to_vec(),
+            error: error.to_string(),
+        });
+        Ok(())
+    }
+}
+
 pub struct KafkaProducer {
     producer: FutureProducer,
     config: std::sync::Arc<Config>,
@@ -31,6 +81,12 @@ impl KafkaProducer {
         })
     }
 
+    /// Waits up to `timeout` for queued messages to be delivered, e.g. before exiting.
+    pub fn flush(&self, timeout: Duration) -> Result<()> {
+        self.producer.flush(timeout)?;
+        Ok(())
+    }
+
     pub async fn send_user_action(&self, action: &UserAction) -> Result<()> {
         let payload = serde_json::to_string(action)?;
         let key = action.user_id.to_string();
@@ -87,30 +143,101 @@ impl KafkaProducer {
             }
         }
     }
+
+    pub async fn send_audit_record(&self, record: &AuditRecord) -> Result<()> {
+        let payload = serde_json::to_string(record)?;
+        let key = record.principal.clone();
+        let audit_record = FutureRecord::to(&self.config.kafka.audit_topic)
+            .payload(&payload)
+            .key(&key);
+
+        match self.producer.send(audit_record, Duration::from_secs(5)).await {
+            Ok(_) => Ok(()),
+            Err((e, _)) => {
+                error!("Failed to send audit record to Kafka: {}", e);
+                Err(anyhow::anyhow!("Kafka send error: {}", e))
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl DeadLetterSink for KafkaProducer {
+    /// Sends the raw payload as is, with the error and source topic in `error` and `source_topic`
+    /// headers.
+    async fn send_dead_letter(&self, topic: &str, source_topic: &str, payload: &[u8], error: &str) -> Result<()> {
+        let headers = OwnedHeaders::new()
+            .insert(Header { key: "error", value: Some(error) })
+            .insert(Header { key: "source_topic", value: Some(source_topic) });
+        let record = FutureRecord::<(), [u8]>::to(topic).payload(payload).headers(headers);
+
+        match self.producer.send(record, Duration::from_secs(5)).await {
+            Ok(_) => Ok(()),
+            Err((e, _)) => {
+                error!("Failed to send dead letter to Kafka: {}", e);
+                Err(anyhow::anyhow!("Kafka send error: {}", e))
+            }
+        }
+    }
 }
 
 pub struct KafkaConsumer {
     consumer: StreamConsumer,
     config: std::sync::Arc<Config>,
+    dead_letters: Option<Arc<dyn DeadLetterSink>>,
 }
 
 impl KafkaConsumer {
     pub fn new(config: &Config) -> Result<Self> {
+        Self::create(config, true)
+    }
+
+    /// A consumer that never commits on its own, for `consume_training_examples_manual`.
+    pub fn new_manual_commit(config: &Config) -> Result<Self> {
+        Self::create(config, false)
+    }
+
+    fn create(config: &Config, auto_commit: bool) -> Result<Self> {
         let consumer: StreamConsumer = ClientConfig::new()
             .set("group.id", &config.kafka.group_id)
             .set("bootstrap.servers", &config.kafka.brokers)
             .set("enable.partition.eof", "false")
             .set("session.timeout.ms", "6000")
-            .set("enable.auto.commit", "true")
+            .set("enable.auto.commit", auto_commit.to_string())
             .set("auto.offset.reset", &config.kafka.auto_offset_reset)
             .create()?;
 
         Ok(Self {
             consumer,
             config: std::sync::Arc::new(config.clone()),
+            dead_letters: None,
         })
     }
 
+    /// Routes undeserializable messages to `sink` when `kafka.dead_letter_topic` is set.
+    pub fn with_dead_letter_sink(mut self, sink: Arc<dyn DeadLetterSink>) -> Self {
+        self.dead_letters = Some(sink);
+        self
+    }
+
+    /// Deserializes a `kind` message consumed from `source_topic`. One that fails is sent to the
+    /// dead-letter topic when there is one, and dropped with a warning either way.
+    pub async fn decode_message<T: DeserializeOwned>(&self, source_topic: &str, payload: &[u8], kind: &str) -> Option<T> {
+        let e = match serde_json::from_slice::<T>(payload) {
+            Ok(value) => return Some(value),
+            Err(e) => e,
+        };
+        warn!("Failed to deserialize {}: {}", kind, e);
+
+        if let (Some(topic), Some(sink)) = (&self.config.kafka.dead_letter_topic, &self.dead_letters) {
+            let error = format!("Failed to deserialize {}: {}", kind, e);
+            if let Err(e) = sink.send_dead_letter(topic, source_topic, payload, &error).await {
+                error!("Failed to dead-letter {} from {}: {}", kind, source_topic, e);
+            }
+        }
+        None
+    }
+
     pub async fn subscribe_user_actions(&self) -> Result<()> {
         self.consumer.subscribe(&[&self.config.kafka.log_topic])?;
         Ok(())
@@ -133,15 +260,13 @@ impl KafkaConsumer {
             match self.consumer.recv().await {
                 Ok(message) => {
                     if let Some(payload) = message.payload() {
-                        match serde_json::from_slice::<UserAction>(payload) {
-                            Ok(action) => {
-                                if let Err(e) = tx.send(action).await {
-                                    error!("Failed to send user action to channel: {}", e);
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                warn!("Failed to deserialize user action: {}", e);
+                        let decoded = self
+                            .decode_message::<UserAction>(&self.config.kafka.log_topic, payload, "user action")
+                            .await;
+                        if let Some(action) = decoded {
+                            if let Err(e) = tx.send(action).await {
+                                error!("Failed to send user action to channel: {}", e);
+                                break;
                             }
                         }
                     }
@@ -163,15 +288,13 @@ impl KafkaConsumer {
             match self.consumer.recv().await {
                 Ok(message) => {
                     if let Some(payload) = message.payload() {
-                        match serde_json::from_slice::<FeatureVector>(payload) {
-                            Ok(feature) => {
-                                if let Err(e) = tx.send(feature).await {
-                                    error!("Failed to send feature vector to channel: {}", e);
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                warn!("Failed to deserialize feature vector: {}", e);
+                        let decoded = self
+                            .decode_message::<FeatureVector>(&self.config.kafka.feature_topic, payload, "feature vector")
+                            .await;
+                        if let Some(feature) = decoded {
+                            if let Err(e) = tx.send(feature).await {
+                                error!("Failed to send feature vector to channel: {}", e);
+                                break;
                             }
                         }
                     }
@@ -193,15 +316,13 @@ impl KafkaConsumer {
             match self.consumer.recv().await {
                 Ok(message) => {
                     if let Some(payload) = message.payload() {
-                        match serde_json::from_slice::<TrainingExample>(payload) {
-                            Ok(example) => {
-                                if let Err(e) = tx.send(example).await {
-                                    error!("Failed to send training example to channel: {}", e);
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                warn!("Failed to deserialize training example: {}", e);
+                        let decoded = self
+                            .decode_message::<TrainingExample>(&self.config.kafka.training_topic, payload, "training example")
+                            .await;
+                        if let Some(example) = decoded {
+                            if let Err(e) = tx.send(example).await {
+                                error!("Failed to send training example to channel: {}", e);
+                                break;
                             }
                         }
                     }
@@ -215,4 +336,131 @@ impl KafkaConsumer {
         
         Ok(())
     }
+
+    /// At-least-once variant of `consume_training_examples` for a `new_manual_commit` consumer.
+    /// Each example goes out with its offset, and offsets come back on `acks` once trained; an
+    /// offset is committed only when it and every earlier one on its partition have come back, so
+    /// a batch that fails to train is consumed again after a restart.
+    pub async fn consume_training_examples_manual(
+        &self,
+        tx: mpsc::Sender<(TrainingExample, MessageOffset)>,
+        mut acks: mpsc::UnboundedReceiver<Vec<MessageOffset>>,
+    ) -> Result<()> {
+        self.subscribe_training_examples().await?;
+        let topic = &self.config.kafka.training_topic;
+        let mut tracker = OffsetTracker::new();
+
+        loop {
+            tokio::select! {
+                received = self.consumer.recv() => match received {
+                    Ok(message) => {
+                        let offset = MessageOffset { partition: message.partition(), offset: message.offset() };
+                        tracker.track(offset);
+                        let decoded = match message.payload() {
+                            Some(payload) => self.decode_message::<TrainingExample>(topic, payload, "training example").await,
+                            None => None,
+                        };
+                        match decoded {
+                            Some(example) => {
+                                if let Err(e) = tx.send((example, offset)).await {
+                                    error!("Failed to send training example to channel: {}", e);
+                                    break;
+                                }
+                            }
+                            // Nothing will be trained, so nothing holds the offset back
+                            None => tracker.complete(offset),
+                        }
+                    }
+                    Err(e) => {
+                        error!("Kafka consumer error: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                },
+                Some(offsets) = acks.recv() => {
+                    for offset in offsets {
+                        tracker.complete(offset);
+                    }
+                }
+            }
+            self.commit_offsets(&mut tracker);
+        }
+
+        Ok(())
+    }
+
+    fn commit_offsets(&self, tracker: &mut OffsetTracker) {
+        let positions = tracker.take_commit_positions();
+        if positions.is_empty() {
+            return;
+        }
+
+        let mut partitions = TopicPartitionList::new();
+        for (partition, offset) in positions {
+            if let Err(e) = partitions.add_partition_offset(&self.config.kafka.training_topic, partition, Offset::Offset(offset)) {
+                error!("Failed to stage offset {} of partition {}: {}", offset, partition, e);
+            }
+        }
+        if let Err(e) = self.consumer.commit(&partitions, CommitMode::Async) {
+            error!("Failed to commit training offsets: {}", e);
+        }
+    }
+}
+
+/// Where a consumed message sits in its topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MessageOffset {
+    pub partition: i32,
+    pub offset: i64,
+}
+
+/// Follows consumed offsets until they are processed, which may happen out of order. Per
+/// partition, the position safe to commit is the oldest offset still pending, or one past the
+/// newest processed offset once none are.
+#[derive(Debug, Default)]
+pub struct OffsetTracker {
+    pending: HashMap<i32, BTreeSet<i64>>,
+    processed_through: HashMap<i32, i64>,
+    committed: HashMap<i32, i64>,
+}
+
+impl OffsetTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track(&mut self, offset: MessageOffset) {
+        self.pending.entry(offset.partition).or_default().insert(offset.offset);
+    }
+
+    pub fn complete(&mut self, offset: MessageOffset) {
+        if let Some(pending) = self.pending.get_mut(&offset.partition) {
+            pending.remove(&offset.offset);
+        }
+        let through = self.processed_through.entry(offset.partition).or_insert(offset.offset);
+        *through = (*through).max(offset.offset);
+    }
+
+    /// The commit position of every partition, i.e. the next offset to consume after a restart.
+    pub fn commit_positions(&self) -> HashMap<i32, i64> {
+        self.processed_through
+            .iter()
+            .map(|(partition, through)| {
+                let oldest_pending = self.pending.get(partition).and_then(|pending| pending.first().copied());
+                (*partition, oldest_pending.unwrap_or(through + 1).min(through + 1))
+            })
+            .collect()
+    }
+
+    /// Commit positions that moved since the last call, remembered as committed.
+    pub fn take_commit_positions(&mut self) -> Vec<(i32, i64)> {
+        let mut moved: Vec<(i32, i64)> = self.commit_positions()
+            .into_iter()
+            .filter(|(partition, position)| self.committed.get(partition).is_none_or(|committed| position > committed))
+            .collect();
+        moved.sort();
+        for (partition, position) in &moved {
+            self.committed.insert(*partition, *position);
+        }
+        moved
+    }
 }
@@ -0,0 +1,158 @@
+use crate::config::{Config, MetadataBackend};
+use crate::models::{ItemFeature, UserAction, UserProfile};
+use anyhow::Result;
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Durable home of user profiles and item features. `VectorDbService` writes through to it and
+/// reloads from it on startup; its in-memory maps act as the hot cache.
+#[async_trait::async_trait]
+pub trait MetadataStore: Send + Sync {
+    async fn get_user_profile(&self, user_id: Uuid) -> Result<Option<UserProfile>>;
+    async fn put_user_profile(&self, profile: &UserProfile) -> Result<()>;
+    async fn delete_user_profile(&self, user_id: Uuid) -> Result<()>;
+    async fn user_profiles(&self) -> Result<Vec<UserProfile>>;
+
+    async fn get_item_feature(&self, item_id: Uuid) -> Result<Option<ItemFeature>>;
+    async fn put_item_feature(&self, feature: &ItemFeature) -> Result<()>;
+    async fn delete_item_feature(&self, item_id: Uuid) -> Result<()>;
+    async fn item_features(&self) -> Result<Vec<ItemFeature>>;
+
+    /// Records `action`, then drops the user's oldest actions beyond `max_history`.
+    async fn append_user_action(&self, action: &UserAction, max_history: usize) -> Result<()>;
+    /// The user's recorded actions, oldest first.
+    async fn user_actions(&self, user_id: Uuid) -> Result<Vec<UserAction>>;
+
+    /// Makes every write so far durable.
+    async fn flush(&self) -> Result<()>;
+}
+
+/// Opens the store selected by `metadata_store.backend`, or `None` to keep metadata in memory only.
+pub fn open_metadata_store(config: &Config) -> Result<Option<Arc<dyn MetadataStore>>> {
+    match config.metadata_store.backend {
+        MetadataBackend::None => Ok(None),
+        MetadataBackend::Sled => Ok(Some(Arc::new(SledMetadataStore::open(&config.metadata_store.path)?))),
+    }
+}
+
+/// Embedded store backed by sled, one tree per record type, keyed by id with JSON values so
+/// records written before a field was added still load through its serde default. Writes are not
+/// flushed one by one: `flush` runs on shutdown and on drop. There is no background flusher, so
+/// dropping the store releases the database lock at once.
+pub struct SledMetadataStore {
+    db: sled::Db,
+    user_profiles: sled::Tree,
+    item_features: sled::Tree,
+    /// Keyed by user id, then timestamp, then a unique suffix, so a prefix scan yields one user's
+    /// history in time order.
+    user_actions: sled::Tree,
+}
+
+impl SledMetadataStore {
+    pub fn open(path: &str) -> Result<Self> {
+        let db = sled::Config::new().path(path).flush_every_ms(None).open()?;
+        let user_profiles = db.open_tree("user_profiles")?;
+        let item_features = db.open_tree("item_features")?;
+        let user_actions = db.open_tree("user_actions")?;
+        Ok(Self { db, user_profiles, item_features, user_actions })
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(tree: &sled::Tree, id: Uuid) -> Result<Option<T>> {
+        match tree.get(id.as_bytes())? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put<T: serde::Serialize>(tree: &sled::Tree, id: Uuid, value: &T) -> Result<()> {
+        tree.insert(id.as_bytes(), serde_json::to_vec(value)?)?;
+        Ok(())
+    }
+
+    fn delete(tree: &sled::Tree, id: Uuid) -> Result<()> {
+        tree.remove(id.as_bytes())?;
+        Ok(())
+    }
+
+    fn all<T: serde::de::DeserializeOwned>(tree: &sled::Tree) -> Result<Vec<T>> {
+        tree.iter()
+            .values()
+            .map(|bytes| Ok(serde_json::from_slice(&bytes?)?))
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl MetadataStore for SledMetadataStore {
+    async fn get_user_profile(&self, user_id: Uuid) -> Result<Option<UserProfile>> {
+        Self::get(&self.user_profiles, user_id)
+    }
+
+    async fn put_user_profile(&self, profile: &UserProfile) -> Result<()> {
+        Self::put(&self.user_profiles, profile.user_id, profile)
+    }
+
+    async fn delete_user_profile(&self, user_id: Uuid) -> Result<()> {
+        Self::delete(&self.user_profiles, user_id)
+    }
+
+    async fn user_profiles(&self) -> Result<Vec<UserProfile>> {
+        Self::all(&self.user_profiles)
+    }
+
+    async fn get_item_feature(&self, item_id: Uuid) -> Result<Option<ItemFeature>> {
+        Self::get(&self.item_features, item_id)
+    }
+
+    async fn put_item_feature(&self, feature: &ItemFeature) -> Result<()> {
+        Self::put(&self.item_features, feature.item_id, feature)
+    }
+
+    async fn delete_item_feature(&self, item_id: Uuid) -> Result<()> {
+        Self::delete(&self.item_features, item_id)
+    }
+
+    async fn item_features(&self) -> Result<Vec<ItemFeature>> {
+        Self::all(&self.item_features)
+    }
+
+    async fn append_user_action(&self, action: &UserAction, max_history: usize) -> Result<()> {
+        // Flipping the sign bit makes the big-endian bytes sort in timestamp order
+        let timestamp = (action.timestamp.timestamp_micros() as u64) ^ (1 << 63);
+        let mut key = Vec::with_capacity(32);
+        key.extend_from_slice(action.user_id.as_bytes());
+        key.extend_from_slice(&timestamp.to_be_bytes());
+        key.extend_from_slice(&self.db.generate_id()?.to_be_bytes());
+
+        self.user_actions.insert(key, serde_json::to_vec(action)?)?;
+
+        let prefix = action.user_id.as_bytes();
+        let excess = self.user_actions.scan_prefix(prefix).count().saturating_sub(max_history);
+        for entry in self.user_actions.scan_prefix(prefix).keys().take(excess) {
+            self.user_actions.remove(entry?)?;
+        }
+        Ok(())
+    }
+
+    async fn user_actions(&self, user_id: Uuid) -> Result<Vec<UserAction>> {
+        self.user_actions
+            .scan_prefix(user_id.as_bytes())
+            .values()
+            .map(|bytes| Ok(serde_json::from_slice(&bytes?)?))
+            .collect()
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+}
+
+impl Drop for SledMetadataStore {
+    fn drop(&mut self) {
+        if let Err(e) = self.db.flush() {
+            warn!("Failed to flush metadata store on drop: {}", e);
+        }
+    }
+}
@@ -1,4 +1,9 @@
+pub mod audit;
+pub mod cache;
+pub mod enrichment;
 pub mod kafka;
+pub mod metadata;
+pub mod model_store;
 pub mod vector_db;
 pub mod recommendation;
 pub mod training;
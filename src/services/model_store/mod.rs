@@ -0,0 +1,177 @@
+use crate::config::CheckpointRetentionConfig;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// A saved model checkpoint, without its snapshot bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointInfo {
+    pub version: String,
+    pub saved_at: DateTime<Utc>,
+}
+
+/// Where `TrainingService` keeps encoded model snapshots, one per version.
+#[async_trait::async_trait]
+pub trait ModelStore: Send + Sync {
+    async fn save(&self, version: &str, saved_at: DateTime<Utc>, snapshot: Vec<u8>) -> Result<()>;
+    async fn load(&self, version: &str) -> Result<Option<Vec<u8>>>;
+    async fn delete(&self, version: &str) -> Result<()>;
+    /// Every stored checkpoint, in no particular order.
+    async fn list(&self) -> Result<Vec<CheckpointInfo>>;
+}
+
+/// Keeps snapshots in memory, for tests and deployments that only need the latest model in-process.
+#[derive(Default)]
+pub struct InMemoryModelStore {
+    checkpoints: DashMap<String, (DateTime<Utc>, Vec<u8>)>,
+}
+
+impl InMemoryModelStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl ModelStore for InMemoryModelStore {
+    async fn save(&self, version: &str, saved_at: DateTime<Utc>, snapshot: Vec<u8>) -> Result<()> {
+        self.checkpoints.insert(version.to_string(), (saved_at, snapshot));
+        Ok(())
+    }
+
+    async fn load(&self, version: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.checkpoints.get(version).map(|entry| entry.1.clone()))
+    }
+
+    async fn delete(&self, version: &str) -> Result<()> {
+        self.checkpoints.remove(version);
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<Vec<CheckpointInfo>> {
+        Ok(self
+            .checkpoints
+            .iter()
+            .map(|entry| CheckpointInfo { version: entry.key().clone(), saved_at: entry.value().0 })
+            .collect())
+    }
+}
+
+const CHECKPOINT_EXTENSION: &str = "snapshot";
+
+/// Keeps one `<version>.snapshot` file per checkpoint in a directory, so models survive restarts.
+/// A checkpoint's save time is its file's modification time.
+pub struct FileModelStore {
+    dir: PathBuf,
+}
+
+impl FileModelStore {
+    /// Uses `dir`, creating it if needed.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create model directory {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    /// Versions become file names, so anything that could leave the directory is refused.
+    fn path(&self, version: &str) -> Result<PathBuf> {
+        let valid = !version.is_empty()
+            && !version.starts_with('.')
+            && version.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(anyhow!("Invalid model checkpoint version {:?}", version));
+        }
+        Ok(self.dir.join(format!("{}.{}", version, CHECKPOINT_EXTENSION)))
+    }
+}
+
+#[async_trait::async_trait]
+impl ModelStore for FileModelStore {
+    /// Written to a temporary file and renamed into place, so a crash never leaves a partial checkpoint.
+    async fn save(&self, version: &str, saved_at: DateTime<Utc>, snapshot: Vec<u8>) -> Result<()> {
+        let path = self.path(version)?;
+        let partial = path.with_extension(format!("{}.partial", CHECKPOINT_EXTENSION));
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let file = std::fs::File::create(&partial)?;
+            std::io::Write::write_all(&mut &file, &snapshot)?;
+            file.set_modified(saved_at.into())?;
+            file.sync_all()?;
+            std::fs::rename(&partial, &path)?;
+            Ok(())
+        })
+        .await?
+        .with_context(|| format!("Failed to write model checkpoint {}", version))
+    }
+
+    async fn load(&self, version: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(version)?).await {
+            Ok(snapshot) => Ok(Some(snapshot)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow!(e).context(format!("Failed to read model checkpoint {}", version))),
+        }
+    }
+
+    async fn delete(&self, version: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(version)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<CheckpointInfo>> {
+        let mut checkpoints = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some(CHECKPOINT_EXTENSION) {
+                continue;
+            }
+            let Some(version) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let saved_at = entry.metadata().await?.modified()?;
+            checkpoints.push(CheckpointInfo { version: version.to_string(), saved_at: saved_at.into() });
+        }
+        Ok(checkpoints)
+    }
+}
+
+/// Versions that rotation removes: everything outside the `keep_latest` newest checkpoints and the
+/// newest checkpoint of each of the last `keep_periods` periods, except `protected`.
+pub fn expired_checkpoints(
+    checkpoints: &[CheckpointInfo],
+    retention: &CheckpointRetentionConfig,
+    protected: Option<&str>,
+) -> Vec<String> {
+    let mut newest_first: Vec<&CheckpointInfo> = checkpoints.iter().collect();
+    newest_first.sort_by(|a, b| b.saved_at.cmp(&a.saved_at).then_with(|| b.version.cmp(&a.version)));
+
+    let mut kept: HashSet<&str> = newest_first
+        .iter()
+        .take(retention.keep_latest)
+        .map(|checkpoint| checkpoint.version.as_str())
+        .collect();
+    if let Some(period) = retention.period_seconds.filter(|&period| period > 0) {
+        let mut periods_seen = HashSet::new();
+        for checkpoint in &newest_first {
+            let period_index = checkpoint.saved_at.timestamp().div_euclid(period as i64);
+            if periods_seen.len() >= retention.keep_periods && !periods_seen.contains(&period_index) {
+                break;
+            }
+            if periods_seen.insert(period_index) {
+                kept.insert(checkpoint.version.as_str());
+            }
+        }
+    }
+    kept.extend(protected);
+
+    newest_first
+        .into_iter()
+        .filter(|checkpoint| !kept.contains(checkpoint.version.as_str()))
+        .map(|checkpoint| checkpoint.version.clone())
+        .collect()
+}
@@ -1,23 +1,132 @@
-use crate::config::Config;
+use crate::config::{ColdItemExplorationConfig, Config, PredictionFallback, RateLimitPolicy, RecommendationConfig};
 use crate::models::*;
+use crate::services::cache::{CacheBackend, RedisCache};
+use crate::services::enrichment::{EnricherChain, FeatureEnricher};
 use crate::services::vector_db::VectorDbService;
+use crate::utils::cache_codec::{decode_payload, encode_payload};
+use crate::utils::{cosine_similarity, cosine_similarity_f64, exponential_decay_weight, fnv1a_64, sigmoid, weighted_average};
+use crate::utils::validation::validate_item_feature_with_sanitization;
 use crate::algorithms::{CollaborativeFiltering, RecommendationAlgorithm};
-use anyhow::Result;
-use redis::AsyncCommands;
+use anyhow::{anyhow, Result};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, RwLock};
 use uuid::Uuid;
-use chrono::{Utc, Timelike, Datelike};
-use tracing::info;
+use chrono::{DateTime, Utc, Timelike, Datelike};
+use tracing::{debug, info, warn};
 use dashmap::DashMap;
+use futures::future::try_join_all;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
 
 pub struct RecommendationService {
     vector_db: Arc<VectorDbService>,
-    redis_client: Arc<redis::Client>,
+    cache: Arc<dyn CacheBackend>,
     algorithm: Arc<RwLock<CollaborativeFiltering>>,
     config: Arc<Config>,
     user_profiles_cache: Arc<DashMap<Uuid, UserProfile>>,
     item_features_cache: Arc<DashMap<Uuid, ItemFeature>>,
+    recommendation_stats: Arc<DashMap<String, u64>>,
+    trained_examples: Arc<AtomicU64>,
+    /// `updated_at` of the last loaded model parameters, checked against `max_model_age_seconds`.
+    model_updated_at: Arc<RwLock<Option<DateTime<Utc>>>>,
+    /// Whether the last staleness check found the model stale, so only the change is logged.
+    model_stale: Arc<AtomicBool>,
+    /// Deny/allow lists by Redis key, with the instant they were read.
+    item_lists_cache: Arc<DashMap<String, (Instant, ItemList)>>,
+    /// Manual scores by Redis key, with the instant they were read.
+    manual_scores_cache: Arc<DashMap<String, (Instant, Option<ManualScores>)>>,
+    /// Tenant configs read from Redis by tenant id.
+    tenant_configs_cache: Arc<DashMap<String, CachedTenantConfig>>,
+    /// Actions each user sent beyond the rate limit, for flagging abusive users.
+    rate_limit_excess: Arc<DashMap<Uuid, u64>>,
+    /// Applied to every item `add_item_feature` indexes.
+    enrichers: EnricherChain,
+    /// Scored candidates by user and session, reused across a session's requests.
+    session_pools: Arc<DashMap<(Uuid, String), Arc<SessionPool>>>,
+    /// Models loaded under a name, which a request's `model_version` scores with in place of `algorithm`.
+    named_models: Arc<DashMap<String, Arc<RwLock<CollaborativeFiltering>>>>,
+    /// Served recommendations that got feedback, by recommendation id, until they yield hard negatives.
+    impressions: Arc<DashMap<Uuid, Impression>>,
+    /// Each user's last live embedding step, carried into the next under `embedding_momentum`.
+    user_velocities: Arc<DashMap<Uuid, Vec<f32>>>,
+}
+
+/// A request named a `model_version` no model is loaded under.
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown model version {0}")]
+pub struct UnknownModelVersion(pub String);
+
+/// Items one recommendation showed, best first, and those the user acted on.
+struct Impression {
+    user_id: Uuid,
+    served: Vec<Uuid>,
+    interacted: HashSet<Uuid>,
+    first_feedback_at: Instant,
+}
+
+/// Redis key holding a token that is part of every response cache key; replacing it drops them all.
+const RESPONSE_CACHE_GENERATION_KEY: &str = "recommendation_response_generation";
+
+/// Session pools are swept for expired entries once there are this many.
+const SESSION_POOL_SWEEP_THRESHOLD: usize = 10_000;
+
+/// Candidates scored for one session, best first, before any request's filters or paging.
+struct SessionPool {
+    profile_version: u64,
+    tenant_id: Option<String>,
+    embedding_version: Option<String>,
+    model_version: Option<String>,
+    scored_at: Instant,
+    /// Number of candidates scoring asked for; fewer means there were no more.
+    depth: usize,
+    items: Vec<RecommendationItem>,
+    model_warming_up: bool,
+    candidate_count: usize,
+}
+
+/// Ranked items of a cached response; every hit is served under a fresh recommendation id.
+#[derive(Serialize, Deserialize)]
+struct CachedRanking {
+    items: Vec<RecommendationItem>,
+    model_warming_up: bool,
+    #[serde(default)]
+    candidate_count: usize,
+}
+
+/// How a request was served, for monitoring batch jobs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecommendationDiagnostics {
+    /// Candidates that reached scoring, after retrieval and eligibility filtering. Responses served
+    /// from a session pool or the response cache report the count they were scored from.
+    pub candidate_count: usize,
+}
+
+/// Item ids read from one Redis key, or `None` when the key is absent.
+type ItemList = Option<Arc<HashSet<Uuid>>>;
+
+/// Editorial scores by item id.
+type ManualScores = Arc<HashMap<Uuid, f32>>;
+
+/// A tenant's config as read from Redis, `None` when it has none, with the instant it was read.
+type CachedTenantConfig = (Instant, Option<Arc<RecommendationConfig>>);
+
+/// Merchandising deny/allow lists applied at serving time.
+struct ItemLists {
+    deny: ItemList,
+    allow: ItemList,
+}
+
+impl ItemLists {
+    fn permits(&self, item_id: &Uuid) -> bool {
+        self.deny.as_ref().is_none_or(|deny| !deny.contains(item_id))
+            && self.allow.as_ref().is_none_or(|allow| allow.contains(item_id))
+    }
 }
 
 impl RecommendationService {
@@ -25,6 +134,15 @@ impl RecommendationService {
         vector_db: Arc<VectorDbService>,
         redis_client: Arc<redis::Client>,
         config: Arc<Config>,
+    ) -> Result<Self> {
+        let cache = RedisCache::with_pool_size(redis_client, config.redis.pool_size as usize);
+        Self::with_cache(vector_db, Arc::new(cache), config).await
+    }
+
+    pub async fn with_cache(
+        vector_db: Arc<VectorDbService>,
+        cache: Arc<dyn CacheBackend>,
+        config: Arc<Config>,
     ) -> Result<Self> {
         let algorithm = Arc::new(RwLock::new(
             CollaborativeFiltering::new(
@@ -36,86 +154,997 @@ impl RecommendationService {
 
         Ok(Self {
             vector_db,
-            redis_client,
+            cache,
             algorithm,
             config,
             user_profiles_cache: Arc::new(DashMap::new()),
             item_features_cache: Arc::new(DashMap::new()),
+            recommendation_stats: Arc::new(DashMap::new()),
+            trained_examples: Arc::new(AtomicU64::new(0)),
+            model_updated_at: Arc::new(RwLock::new(None)),
+            model_stale: Arc::new(AtomicBool::new(false)),
+            item_lists_cache: Arc::new(DashMap::new()),
+            manual_scores_cache: Arc::new(DashMap::new()),
+            tenant_configs_cache: Arc::new(DashMap::new()),
+            rate_limit_excess: Arc::new(DashMap::new()),
+            enrichers: EnricherChain::new(),
+            session_pools: Arc::new(DashMap::new()),
+            named_models: Arc::new(DashMap::new()),
+            impressions: Arc::new(DashMap::new()),
+            user_velocities: Arc::new(DashMap::new()),
         })
     }
 
+    /// Runs `enricher` on items before they are indexed, after any enrichers added before it.
+    pub fn with_enricher(mut self, enricher: Arc<dyn FeatureEnricher>) -> Self {
+        self.enrichers = self.enrichers.then(enricher);
+        self
+    }
+
     pub async fn get_recommendations(&self, request: &RecommendationRequest) -> Result<RecommendationResponse> {
+        self.get_recommendations_with_diagnostics(request).await.map(|(response, _)| response)
+    }
+
+    /// Same as `get_recommendations`, also reporting how the request was served.
+    pub async fn get_recommendations_with_diagnostics(
+        &self,
+        request: &RecommendationRequest,
+    ) -> Result<(RecommendationResponse, RecommendationDiagnostics)> {
+        let mut diagnostics = RecommendationDiagnostics::default();
+        // Fails an unknown model version even for the cold-start and cached paths that never score
+        self.model_for(request)?;
+        let rec_config = self.resolve_recommendation_config(request.tenant_id.as_deref()).await?;
         let user_profile = self.get_or_create_user_profile(request.user_id).await?;
-        
-        // Get similar items based on user embedding
-        let similar_items = self.vector_db
-            .search_similar_items(&user_profile.embedding, request.num_recommendations * 2)
+
+        let session_id = request.session_id
+            .as_deref()
+            .filter(|_| self.config.recommendation.session_cache_ttl_seconds > 0 && !is_cold_start(&user_profile, &rec_config));
+        if let Some(session_id) = session_id {
+            let response = self.recommend_from_session(session_id, request, &user_profile, &rec_config, &mut diagnostics).await?;
+            return Ok((response, diagnostics));
+        }
+
+        let ttl_seconds = self.config.redis.response_cache_ttl_seconds;
+        if ttl_seconds == 0 {
+            let response = self.recommend_stably(request, &user_profile, &rec_config, &mut diagnostics).await?;
+            return Ok((response, diagnostics));
+        }
+
+        let generation = self.cache.get(RESPONSE_CACHE_GENERATION_KEY).await?.unwrap_or_default();
+        let cache_key = response_cache_key(request, &user_profile, &generation)?;
+        if let Some(mut ranking) = self.get_cached_payload::<CachedRanking>(&cache_key).await? {
+            self.increment_stat("response_cache_hits").await;
+            // The lists may have changed since the ranking was cached
+            let item_lists = self.load_item_lists(&rec_config).await?;
+            ranking.items.retain(|item| item_lists.permits(&item.item_id));
+            diagnostics.candidate_count = ranking.candidate_count;
+            let response = self.record_served(request.user_id, ranking.items, ranking.model_warming_up).await?;
+            return Ok((response, diagnostics));
+        }
+
+        self.increment_stat("response_cache_misses").await;
+        let response = self.recommend_stably(request, &user_profile, &rec_config, &mut diagnostics).await?;
+        let ranking = CachedRanking {
+            items: response.recommendations.clone(),
+            model_warming_up: response.model_warming_up,
+            candidate_count: diagnostics.candidate_count,
+        };
+        let payload = encode_payload(&ranking, self.config.redis.codec)?;
+        self.cache.set_ex_bytes(&cache_key, payload, ttl_seconds).await?;
+        Ok((response, diagnostics))
+    }
+
+    /// `recommend_for_profile`, with a first page held close to the user's previous one when
+    /// `stability_weight` is set, so model and profile updates do not reshuffle it all at once.
+    async fn recommend_stably(
+        &self,
+        request: &RecommendationRequest,
+        profile: &UserProfile,
+        rec_config: &RecommendationConfig,
+        diagnostics: &mut RecommendationDiagnostics,
+    ) -> Result<RecommendationResponse> {
+        let mut response = self.recommend_for_profile(request, profile, rec_config, None, diagnostics).await?;
+        if rec_config.stability_weight <= 0.0 || request.offset > 0 {
+            return Ok(response);
+        }
+
+        let cache_key = format!(
+            "previous_response:{}:{}",
+            request.tenant_id.as_deref().unwrap_or_default(),
+            request.user_id
+        );
+        if let Some(previous) = self.get_cached_payload::<Vec<Uuid>>(&cache_key).await? {
+            let weight = rec_config.stability_weight.min(1.0);
+            let max_shift = ((1.0 - weight) * request.num_recommendations as f32).round() as usize;
+            response.recommendations = stabilize_ranking(std::mem::take(&mut response.recommendations), &previous, max_shift);
+            self.increment_stat("stabilized_responses").await;
+        }
+        let served: Vec<Uuid> = response.recommendations.iter().map(|item| item.item_id).collect();
+        self.set_cached_payload(&cache_key, &served).await?;
+        Ok(response)
+    }
+
+    /// Same as `get_recommendations`, but reports scoring progress on `events` while candidates are
+    /// scored, then the ranked items, then `Done`. The returned response holds the same items.
+    pub async fn get_recommendations_with_progress(
+        &self,
+        request: &RecommendationRequest,
+        events: &mpsc::UnboundedSender<RecommendationStreamEvent>,
+    ) -> Result<RecommendationResponse> {
+        let rec_config = self.resolve_recommendation_config(request.tenant_id.as_deref()).await?;
+        let user_profile = self.get_or_create_user_profile(request.user_id).await?;
+
+        let response = self
+            .recommend_for_profile(request, &user_profile, &rec_config, Some(events), &mut RecommendationDiagnostics::default())
             .await?;
 
+        // A closed channel only means the client went away; the response is still returned
+        for (rank, item) in response.recommendations.iter().enumerate() {
+            let _ = events.send(RecommendationStreamEvent::Item { rank, item: item.clone() });
+        }
+        let _ = events.send(RecommendationStreamEvent::Done {
+            recommendation_id: response.recommendation_id,
+            model_warming_up: response.model_warming_up,
+        });
+        Ok(response)
+    }
+
+    /// Recommends for logged-out traffic from a transient embedding (the centroid of the context
+    /// items) without creating or persisting a `UserProfile`.
+    pub async fn get_anonymous_recommendations(&self, request: &AnonymousRecommendationRequest) -> Result<RecommendationResponse> {
+        let rec_config = self.resolve_recommendation_config(request.tenant_id.as_deref()).await?;
+
+        let mut centroid = vec![0.0; self.config.recommendation.embedding_dim];
+        let mut context_count = 0;
+        for item_id in &request.recent_items {
+            if let Some(feature) = self.get_item_feature(*item_id).await? {
+                if feature.embedding.len() == centroid.len() {
+                    for (c, x) in centroid.iter_mut().zip(feature.embedding.iter()) {
+                        *c += x;
+                    }
+                    context_count += 1;
+                }
+            }
+        }
+        if context_count > 0 {
+            for c in centroid.iter_mut() {
+                *c /= context_count as f32;
+            }
+        }
+
+        info!(
+            "Anonymous recommendation from {} context items (device: {})",
+            context_count,
+            request.device.as_deref().unwrap_or("unknown")
+        );
+
+        let scoring_request = RecommendationRequest {
+            filter_categories: request.preferred_categories.clone(),
+            exclude_items: Some(request.recent_items.clone()),
+            tenant_id: request.tenant_id.clone(),
+            ..RecommendationRequest::new(Uuid::nil(), request.num_recommendations)
+        };
+
+        self.recommend_from_embedding(&scoring_request, &centroid, &rec_config, None, &mut RecommendationDiagnostics::default()).await
+    }
+
+    /// Recommends across the `num_clusters` item clusters nearest the user, taking each cluster's
+    /// most similar item in turn so every nearby cluster is represented. Uses the cached clusters,
+    /// clustering now into `item_cluster_count` clusters when there are none. Honors the request's
+    /// category filter, exclusions and maximum item age; paging and tag filters do not apply.
+    pub async fn get_cluster_recommendations(
+        &self,
+        request: &RecommendationRequest,
+        num_clusters: usize,
+    ) -> Result<RecommendationResponse> {
+        let rec_config = self.resolve_recommendation_config(request.tenant_id.as_deref()).await?;
+        let user_profile = self.get_or_create_user_profile(request.user_id).await?;
+        let clusters = match self.vector_db.item_clusters().await {
+            Some(clusters) => clusters,
+            None if rec_config.item_cluster_count > 0 => self.vector_db.cluster_items(rec_config.item_cluster_count).await?,
+            None => return Err(anyhow!("Items have not been clustered and item_cluster_count is 0")),
+        };
+
+        let user_exclusions = self.excluded_items(request).await;
+        let item_lists = self.load_item_lists(&rec_config).await?;
+        let filter_categories = request.filter_categories
+            .as_ref()
+            .map(|categories| self.expand_categories(categories));
+        let now = Utc::now();
+        let created_after = request.max_item_age_seconds
+            .map(|max_age| now - chrono::Duration::seconds(max_age.min(i64::MAX as u64) as i64));
+
+        // Each nearby cluster's eligible members, most similar last so they pop first
+        let mut per_cluster = Vec::new();
+        for cluster in clusters.nearest_clusters(&user_profile.embedding).into_iter().take(num_clusters) {
+            let mut members = Vec::new();
+            for item_id in clusters.members(cluster) {
+                let excluded = user_exclusions.contains(&item_id)
+                    || !item_lists.permits(&item_id)
+                    || request.exclude_items.as_ref().is_some_and(|excluded| excluded.contains(&item_id));
+                let Some(feature) = self.get_item_feature(item_id).await?.filter(|feature| {
+                    !excluded
+                        && is_servable(feature, now, created_after)
+                        && filter_categories.as_ref().is_none_or(|categories| categories.contains(&feature.category))
+                }) else {
+                    continue;
+                };
+                let score = self.similarity(&user_profile.embedding, &feature.embedding);
+                members.push(RecommendationItem {
+                    item_id,
+                    score,
+                    reason: request.include_reasons.then(|| format!("From item cluster {} near your interests", cluster)),
+                    category: feature.category,
+                    relaxed_filter: None,
+                });
+            }
+            members.sort_by(|a, b| compare_by_score(b, a));
+            per_cluster.push(members);
+        }
+
+        let mut recommendations = Vec::with_capacity(request.num_recommendations);
+        while recommendations.len() < request.num_recommendations {
+            let before = recommendations.len();
+            for members in per_cluster.iter_mut() {
+                if recommendations.len() == request.num_recommendations {
+                    break;
+                }
+                recommendations.extend(members.pop());
+            }
+            if recommendations.len() == before {
+                break;
+            }
+        }
+
+        self.increment_stat("cluster_recommendations").await;
+        self.record_served(request.user_id, recommendations, !self.is_model_ready(&rec_config)).await
+    }
+
+    /// Recommends for a group of users jointly. `AverageEmbedding` ranks against the members' mean
+    /// embedding; the other strategies score pooled candidates per member and aggregate the scores.
+    pub async fn get_group_recommendations(&self, request: &GroupRecommendationRequest) -> Result<RecommendationResponse> {
+        let mut user_ids = request.user_ids.clone();
+        user_ids.sort();
+        user_ids.dedup();
+        if user_ids.is_empty() {
+            return Err(anyhow!("Group recommendations need at least one user"));
+        }
+
+        let rec_config = self.resolve_recommendation_config(request.tenant_id.as_deref()).await?;
+        let profiles = try_join_all(
+            user_ids.iter().map(|user_id| self.get_or_create_user_profile(*user_id))
+        ).await?;
+
+        let scoring_request = RecommendationRequest {
+            filter_categories: request.filter_categories.clone(),
+            exclude_items: request.exclude_items.clone(),
+            tenant_id: request.tenant_id.clone(),
+            ..RecommendationRequest::new(Uuid::nil(), request.num_recommendations)
+        };
+
+        match request.aggregation {
+            GroupAggregation::AverageEmbedding => {
+                let weighted: Vec<(Vec<f32>, f32)> = profiles
+                    .into_iter()
+                    .map(|profile| (profile.embedding, 1.0))
+                    .collect();
+                let group_embedding = weighted_average(&weighted);
+                self.recommend_from_embedding(&scoring_request, &group_embedding, &rec_config, None, &mut RecommendationDiagnostics::default()).await
+            }
+            GroupAggregation::LeastMisery | GroupAggregation::MostPleasure => {
+                let embeddings: Vec<Vec<f32>> = profiles.into_iter().map(|profile| profile.embedding).collect();
+                self.recommend_by_member_scores(&scoring_request, &embeddings, request.aggregation, &rec_config).await
+            }
+        }
+    }
+
+    /// Pools every member's nearest items, then scores each by the minimum (least misery) or maximum
+    /// (most pleasure) of the members' similarities to it.
+    async fn recommend_by_member_scores(
+        &self,
+        request: &RecommendationRequest,
+        embeddings: &[Vec<f32>],
+        aggregation: GroupAggregation,
+        rec_config: &RecommendationConfig,
+    ) -> Result<RecommendationResponse> {
+        let per_member = request.num_recommendations * 2;
+        let mut candidate_ids = HashSet::new();
+        for embedding in embeddings {
+            for (item_id, _) in self.vector_db.search_similar_items(embedding, per_member).await? {
+                candidate_ids.insert(item_id);
+            }
+        }
+        if let Some(ref excluded) = request.exclude_items {
+            for item_id in excluded {
+                candidate_ids.remove(item_id);
+            }
+        }
+        let candidate_ids: Vec<Uuid> = candidate_ids.into_iter().collect();
+
+        let item_features = try_join_all(
+            candidate_ids.iter().map(|item_id| self.get_item_feature(*item_id))
+        ).await?;
+
+        let filter_categories = request.filter_categories
+            .as_ref()
+            .map(|categories| self.expand_categories(categories));
+
+        let label = match aggregation {
+            GroupAggregation::LeastMisery => "least misery",
+            _ => "most pleasure",
+        };
         let mut recommendations = Vec::new();
-        
-        for (item_id, similarity_score) in similar_items {
-            // Skip excluded items
-            if let Some(ref excluded) = request.exclude_items {
-                if excluded.contains(&item_id) {
+        let now = Utc::now();
+        for (item_id, item_feature) in candidate_ids.into_iter().zip(item_features) {
+            let Some(item_feature) = item_feature else {
+                continue;
+            };
+            if item_feature.is_expired_at(now) {
+                continue;
+            }
+            if let Some(ref filter_categories) = filter_categories {
+                if !filter_categories.contains(&item_feature.category) {
                     continue;
                 }
             }
 
-            // Get item feature
-            if let Some(item_feature) = self.get_item_feature(item_id).await? {
-                // Filter by category if specified
-                if let Some(ref filter_categories) = request.filter_categories {
-                    if !filter_categories.contains(&item_feature.category) {
-                        continue;
+            let member_scores = embeddings
+                .iter()
+                .map(|embedding| self.similarity(embedding, &item_feature.embedding));
+            let group_score = match aggregation {
+                GroupAggregation::LeastMisery => member_scores.fold(f32::INFINITY, f32::min),
+                _ => member_scores.fold(f32::NEG_INFINITY, f32::max),
+            };
+
+            if let Some(group_score) = apply_similarity_threshold(group_score, rec_config) {
+                recommendations.push(RecommendationItem {
+                    item_id,
+                    score: group_score,
+                    reason: Some(format!("Group pick by {} (score: {:.3})", label, group_score)),
+                    category: item_feature.category,
+                    relaxed_filter: None,
+                });
+            }
+        }
+
+        recommendations.sort_by(compare_by_score);
+        recommendations.truncate(request.num_recommendations);
+
+        self.record_served(request.user_id, recommendations, !self.is_model_ready(rec_config)).await
+    }
+
+    fn similarity(&self, a: &[f32], b: &[f32]) -> f32 {
+        if self.config.milvus.f64_accumulation {
+            cosine_similarity_f64(a, b)
+        } else {
+            cosine_similarity(a, b)
+        }
+    }
+
+    async fn recommend_from_embedding(
+        &self,
+        request: &RecommendationRequest,
+        embedding: &[f32],
+        rec_config: &RecommendationConfig,
+        progress: Option<&mpsc::UnboundedSender<RecommendationStreamEvent>>,
+        diagnostics: &mut RecommendationDiagnostics,
+    ) -> Result<RecommendationResponse> {
+        // Earlier pages are ranked too, then dropped
+        let (mut recommendations, model_warming_up) = self
+            .rank_candidates(&paged_window(request), embedding, rec_config, progress, diagnostics)
+            .await?;
+        recommendations.drain(..request.offset.min(recommendations.len()));
+        self.record_served(request.user_id, recommendations, model_warming_up).await
+    }
+
+    /// The top `num_recommendations` items for `request` (ignoring its offset), and whether the
+    /// model was still warming up.
+    async fn rank_candidates(
+        &self,
+        request: &RecommendationRequest,
+        embedding: &[f32],
+        rec_config: &RecommendationConfig,
+        progress: Option<&mpsc::UnboundedSender<RecommendationStreamEvent>>,
+        diagnostics: &mut RecommendationDiagnostics,
+    ) -> Result<(Vec<RecommendationItem>, bool)> {
+        let embedding_version = request.embedding_version.as_deref().unwrap_or(CURRENT_EMBEDDING_VERSION);
+        check_category_guarantee(request)?;
+        let model = self.model_for(request)?;
+
+        // Backfilling categories and diversifying need a deeper candidate pool than the natural ranking
+        let diversity_lambda = rec_config.diversity_lambda.clamp(0.0, 1.0);
+        let exhaustive = request.min_distinct_categories.is_some() || diversity_lambda > 0.0;
+        let candidate_multiplier = if exhaustive { 4 } else { 2 };
+
+        // Stage 1: retrieve candidate ids based on the query embedding
+        let user_exclusions = self.excluded_items(request).await;
+        let item_lists = self.load_item_lists(rec_config).await?;
+        let manual_scores = self.load_manual_scores(rec_config).await?;
+        let category_affinity = if rec_config.category_affinity_weight > 0.0 {
+            self.vector_db.get_category_affinity(request.user_id).await
+        } else {
+            HashMap::new()
+        };
+        let mut retrieved = self.vector_db
+            .search_similar_items_above(
+                embedding,
+                request.num_recommendations * candidate_multiplier,
+                embedding_version,
+                rec_config.retrieval_min_similarity,
+            )
+            .await?;
+        // Curated items are candidates even when retrieval missed them, and go first so the
+        // early exit below cannot skip them
+        for item_id in manual_scores.keys() {
+            if !retrieved.iter().any(|(retrieved_id, _)| retrieved_id == item_id) {
+                retrieved.push((*item_id, 0.0));
+            }
+        }
+        retrieved.sort_by_key(|(item_id, _)| !manual_scores.contains_key(item_id));
+        let eligible = |item_id: &Uuid| {
+            !user_exclusions.contains(item_id)
+                && item_lists.permits(item_id)
+                && request.exclude_items
+                    .as_ref()
+                    .is_none_or(|excluded| !excluded.contains(item_id))
+        };
+        let candidates: Vec<(Uuid, f32)> = retrieved
+            .into_iter()
+            .filter(|(item_id, _)| eligible(item_id))
+            .collect();
+
+        // Stage 2: prefetch all candidate features concurrently so cache/DB latency overlaps
+        let item_features = try_join_all(
+            candidates.iter().map(|(item_id, _)| self.get_item_feature(*item_id))
+        ).await?;
+
+        let filter_categories = request.filter_categories
+            .as_ref()
+            .map(|categories| self.expand_categories(categories));
+
+        // Stage 3: score every candidate in one pass under a single algorithm read lock.
+        // Until the model has seen enough examples its predictions are noise, so score on similarity alone.
+        // A long training step holding the write lock must not stall serving, so the wait is bounded.
+        // A named model was loaded on purpose, so it is scored with regardless.
+        let model_warming_up = request.model_version.is_none() && !self.is_model_ready(rec_config);
+        let algorithm = if model_warming_up || (request.model_version.is_none() && self.is_model_stale(rec_config).await) {
+            None
+        } else {
+            let lock_timeout = Duration::from_millis(rec_config.predict_lock_timeout_ms);
+            match tokio::time::timeout(lock_timeout, model.read()).await {
+                Ok(algorithm) => Some(algorithm),
+                Err(_) => {
+                    self.increment_stat("predict_lock_timeouts").await;
+                    warn!("Model lock not acquired within {:?}, scoring user {} on similarity only", lock_timeout, request.user_id);
+                    None
+                }
+            }
+        };
+        let mut recommendations = Vec::new();
+        let mut relaxed = Vec::new();
+        // Embeddings of the scored items, for the diversity re-ranking
+        let mut scored_embeddings = HashMap::new();
+
+        let total = candidates.len();
+        diagnostics.candidate_count = total;
+        // Report roughly every 5% so large catalogs do not flood the stream
+        let progress_step = (total / 20).max(1);
+        let report_progress = |scored: usize| {
+            if let Some(progress) = progress {
+                let _ = progress.send(RecommendationStreamEvent::Progress { scored, total });
+            }
+        };
+
+        let now = Utc::now();
+        let created_after = request.max_item_age_seconds
+            .map(|max_age| now - chrono::Duration::seconds(max_age.min(i64::MAX as u64) as i64));
+        for (scored, ((item_id, similarity_score), item_feature)) in candidates.into_iter().zip(item_features).enumerate() {
+            if scored.is_multiple_of(progress_step) {
+                report_progress(scored);
+            }
+
+            let Some(item_feature) = item_feature.filter(|feature| is_servable(feature, now, created_after)) else {
+                continue;
+            };
+            let Some(item_embedding) = item_feature.embedding_for(embedding_version) else {
+                continue;
+            };
+
+            // Items failing a soft filter are kept aside as fill when the request allows relaxing it
+            let relaxed_filter = failed_soft_filter(request, filter_categories.as_ref(), &item_feature);
+            if relaxed_filter.is_some() && !request.relax_filters {
+                continue;
+            }
+
+            let final_score = match algorithm {
+                Some(ref algorithm) => {
+                    // Warm items are scored by their blend with the learned embedding, both for
+                    // similarity and for the prediction
+                    let item_embedding = algorithm.blended_item_embedding(item_id, item_embedding, rec_config.collaborative_blend_weight);
+                    let similarity_score = match item_embedding {
+                        Cow::Owned(ref blended) => self.similarity(embedding, blended),
+                        Cow::Borrowed(_) => similarity_score,
+                    };
+                    // Calculate prediction score using the algorithm
+                    match algorithm.predict(embedding, &item_embedding).await {
+                        // Combine similarity and prediction scores
+                        Ok(prediction_score) => (similarity_score + prediction_score) / 2.0,
+                        Err(e) => {
+                            self.increment_stat("prediction_failures").await;
+                            warn!("Prediction failed for item {}: {}", item_id, e);
+                            match rec_config.prediction_fallback {
+                                PredictionFallback::SimilarityOnly => similarity_score,
+                                PredictionFallback::Zero => similarity_score / 2.0,
+                            }
+                        }
                     }
                 }
+                None => similarity_score,
+            };
 
-                // Calculate prediction score using the algorithm
-                let prediction_score = self.algorithm
-                    .read()
-                    .await
-                    .predict(&user_profile.embedding, &item_feature.embedding)
-                    .await
-                    .unwrap_or(0.0);
-
-                // Combine similarity and prediction scores
-                let final_score = (similarity_score + prediction_score) / 2.0;
-
-                if final_score >= self.config.recommendation.similarity_threshold {
-                    recommendations.push(RecommendationItem {
-                        item_id,
-                        score: final_score,
-                        reason: format!("Similar to your preferences (score: {:.3})", final_score),
-                        category: item_feature.category.clone(),
-                    });
+            let scored = match manual_scores.get(&item_id) {
+                Some(&manual_score) => Some((
+                    manual_score,
+                    request.include_reasons.then(|| format!("Editorially curated (score: {:.3})", manual_score)),
+                )),
+                None => apply_similarity_threshold(final_score, rec_config).map(|score| {
+                    let affinity = category_affinity.get(&item_feature.category).copied().unwrap_or(0.0);
+                    let score = score + rec_config.category_affinity_weight * affinity;
+                    (score, request.include_reasons.then(|| format!("Similar to your preferences (score: {:.3})", score)))
+                }),
+            };
+            if let Some((final_score, reason)) = scored {
+                if diversity_lambda > 0.0 && relaxed_filter.is_none() {
+                    scored_embeddings.insert(item_id, item_embedding.to_vec());
                 }
+                let item = RecommendationItem {
+                    item_id,
+                    score: final_score,
+                    reason,
+                    category: item_feature.category,
+                    relaxed_filter,
+                };
+                if relaxed_filter.is_some() {
+                    relaxed.push(item);
+                } else {
+                    recommendations.push(item);
+                }
+            }
 
-                if recommendations.len() >= request.num_recommendations {
-                    break;
+            if !exhaustive && recommendations.len() >= request.num_recommendations {
+                break;
+            }
+        }
+        drop(algorithm);
+        report_progress(total);
+
+        let mut recommendations = self
+            .merge_ranked(request, diversity_lambda, recommendations, relaxed, &scored_embeddings)
+            .await;
+
+        // A category guarantee picks from the ranking alone, so it takes precedence over exploration
+        if let Some(exploration) = rec_config.cold_item_exploration.as_ref().filter(|_| request.min_distinct_categories.is_none()) {
+            let explored = self
+                .sample_cold_items(request, embedding, exploration, filter_categories.as_ref(), |item_id| {
+                    eligible(item_id) && !recommendations.iter().any(|r| r.item_id == *item_id)
+                })
+                .await?;
+            if !explored.is_empty() {
+                recommendations.truncate(request.num_recommendations.saturating_sub(explored.len()));
+                recommendations.extend(explored);
+                self.increment_stat("cold_item_explorations").await;
+            }
+        }
+
+        let recommendations = self.apply_category_guarantee(request, recommendations).await;
+        Ok((recommendations, model_warming_up))
+    }
+
+    /// Sorts the scored items, re-ranks them for diversity when `diversity_lambda` is set, and fills
+    /// a short list with the relaxed items.
+    async fn merge_ranked(
+        &self,
+        request: &RecommendationRequest,
+        diversity_lambda: f32,
+        mut recommendations: Vec<RecommendationItem>,
+        mut relaxed: Vec<RecommendationItem>,
+        embeddings: &HashMap<Uuid, Vec<f32>>,
+    ) -> Vec<RecommendationItem> {
+        // Sort by score descending, ties by item id for deterministic output
+        recommendations.sort_by(compare_by_score);
+        if diversity_lambda > 0.0 {
+            recommendations = rerank_by_marginal_relevance(recommendations, embeddings, diversity_lambda, |a, b| self.similarity(a, b));
+            if request.min_distinct_categories.is_none() {
+                recommendations.truncate(request.num_recommendations);
+            }
+        }
+
+        // Relaxed items rank below every item that passed the filters, those missing only the
+        // tag filter first
+        if recommendations.len() < request.num_recommendations && !relaxed.is_empty() {
+            relaxed.sort_by(|a, b| a.relaxed_filter.cmp(&b.relaxed_filter).then_with(|| compare_by_score(a, b)));
+            relaxed.truncate(request.num_recommendations - recommendations.len());
+            self.increment_stat("relaxed_filter_responses").await;
+            debug!("Relaxed filters to add {} items for user {}", relaxed.len(), request.user_id);
+            recommendations.extend(relaxed);
+        }
+        recommendations
+    }
+
+    async fn apply_category_guarantee(
+        &self,
+        request: &RecommendationRequest,
+        recommendations: Vec<RecommendationItem>,
+    ) -> Vec<RecommendationItem> {
+        let Some(min_categories) = request.min_distinct_categories else {
+            return recommendations;
+        };
+        let (recommendations, met) = enforce_min_distinct_categories(recommendations, request.num_recommendations, min_categories);
+        if !met {
+            self.increment_stat("category_guarantee_unmet").await;
+            warn!("Candidates for user {1} span fewer than {0} distinct categories", min_categories, request.user_id);
+        }
+        recommendations
+    }
+
+    /// Ranks by the profile's embedding, or by popularity for a cold start.
+    async fn recommend_for_profile(
+        &self,
+        request: &RecommendationRequest,
+        profile: &UserProfile,
+        rec_config: &RecommendationConfig,
+        progress: Option<&mpsc::UnboundedSender<RecommendationStreamEvent>>,
+        diagnostics: &mut RecommendationDiagnostics,
+    ) -> Result<RecommendationResponse> {
+        if is_cold_start(profile, rec_config) {
+            self.recommend_cold_start(request, rec_config, diagnostics).await
+        } else {
+            let embedding = self.effective_user_embedding(profile).await;
+            self.recommend_from_embedding(request, &embedding, rec_config, progress, diagnostics).await
+        }
+    }
+
+    /// The embedding `profile` is ranked by. With `embedding_smoothing` it is shrunk toward the
+    /// centroid of the user's top-affinity category, the more so the fewer interactions they have.
+    pub async fn effective_user_embedding(&self, profile: &UserProfile) -> Vec<f32> {
+        let Some(smoothing) = &self.config.recommendation.embedding_smoothing else {
+            return profile.embedding.clone();
+        };
+        let affinity = self.vector_db.get_category_affinity(profile.user_id).await;
+        let top_category = affinity
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(category, _)| category);
+        let centroid = match top_category {
+            Some(category) => self.vector_db.category_centroid(category).await,
+            None => None,
+        };
+        let Some(centroid) = centroid.filter(|centroid| centroid.len() == profile.embedding.len()) else {
+            return profile.embedding.clone();
+        };
+
+        let prior = smoothing.prior_strength.max(0.0);
+        let interactions = profile.interaction_count as f32;
+        if prior + interactions <= 0.0 {
+            return profile.embedding.clone();
+        }
+        let shrinkage = prior / (prior + interactions);
+        profile.embedding
+            .iter()
+            .zip(&centroid)
+            .map(|(x, c)| (1.0 - shrinkage) * x + shrinkage * c)
+            .collect()
+    }
+
+    /// Ranks by `popularity_score` for users with too few interactions to rank by similarity. The
+    /// request's category filter and exclusions still apply; tag filters and category guarantees do not.
+    async fn recommend_cold_start(
+        &self,
+        request: &RecommendationRequest,
+        rec_config: &RecommendationConfig,
+        diagnostics: &mut RecommendationDiagnostics,
+    ) -> Result<RecommendationResponse> {
+        check_category_guarantee(request)?;
+        let user_exclusions = self.excluded_items(request).await;
+        let item_lists = self.load_item_lists(rec_config).await?;
+        let filter_categories = request.filter_categories
+            .as_ref()
+            .map(|categories| self.expand_categories(categories));
+        let now = Utc::now();
+        let created_after = request.max_item_age_seconds
+            .map(|max_age| now - chrono::Duration::seconds(max_age.min(i64::MAX as u64) as i64));
+
+        let popular = self.vector_db
+            .search_items_by_popularity_score(request.offset.saturating_add(request.num_recommendations), |feature| {
+                is_servable(feature, now, created_after)
+                    && filter_categories.as_ref().is_none_or(|categories| categories.contains(&feature.category))
+                    && !user_exclusions.contains(&feature.item_id)
+                    && item_lists.permits(&feature.item_id)
+                    && !request.exclude_items.as_ref().is_some_and(|excluded| excluded.contains(&feature.item_id))
+            })
+            .await;
+        diagnostics.candidate_count = popular.len();
+        let recommendations = popular
+            .into_iter()
+            .skip(request.offset)
+            .map(|feature| RecommendationItem {
+                item_id: feature.item_id,
+                score: feature.popularity_score,
+                reason: request.include_reasons.then(|| "popular item (cold start)".to_string()),
+                category: feature.category,
+                relaxed_filter: None,
+            })
+            .collect();
+
+        self.increment_stat("cold_start_responses").await;
+        debug!("Served cold start recommendations to user {}", request.user_id);
+        self.record_served(request.user_id, recommendations, !self.is_model_ready(rec_config)).await
+    }
+
+    /// Serves `request` from the candidates scored for its session, scoring them first when the
+    /// session has none for the current profile. Pages and filters only re-filter the pool. Cold
+    /// item exploration is skipped, so a session's pages never overlap.
+    async fn recommend_from_session(
+        &self,
+        session_id: &str,
+        request: &RecommendationRequest,
+        profile: &UserProfile,
+        rec_config: &RecommendationConfig,
+        diagnostics: &mut RecommendationDiagnostics,
+    ) -> Result<RecommendationResponse> {
+        let window = paged_window(request);
+        check_category_guarantee(&window)?;
+        let pool = self.session_pool(session_id, &window, profile, rec_config).await?;
+        diagnostics.candidate_count = pool.candidate_count;
+
+        let embedding_version = request.embedding_version.as_deref().unwrap_or(CURRENT_EMBEDDING_VERSION);
+        let diversity_lambda = rec_config.diversity_lambda.clamp(0.0, 1.0);
+        // Exclusions and lists may have changed since the pool was scored
+        let user_exclusions = self.excluded_items(request).await;
+        let item_lists = self.load_item_lists(rec_config).await?;
+        let filter_categories = request.filter_categories
+            .as_ref()
+            .map(|categories| self.expand_categories(categories));
+        let now = Utc::now();
+        let created_after = request.max_item_age_seconds
+            .map(|max_age| now - chrono::Duration::seconds(max_age.min(i64::MAX as u64) as i64));
+
+        let item_features = try_join_all(pool.items.iter().map(|item| self.get_item_feature(item.item_id))).await?;
+        let mut recommendations = Vec::new();
+        let mut relaxed = Vec::new();
+        let mut embeddings = HashMap::new();
+        for (item, item_feature) in pool.items.iter().zip(item_features) {
+            let excluded = user_exclusions.contains(&item.item_id)
+                || !item_lists.permits(&item.item_id)
+                || request.exclude_items.as_ref().is_some_and(|excluded| excluded.contains(&item.item_id));
+            let Some(item_feature) = item_feature.filter(|feature| !excluded && is_servable(feature, now, created_after)) else {
+                continue;
+            };
+            let relaxed_filter = failed_soft_filter(request, filter_categories.as_ref(), &item_feature);
+            if relaxed_filter.is_some() && !request.relax_filters {
+                continue;
+            }
+
+            // The pool is scored with reasons, since they can differ between pages
+            let item = RecommendationItem {
+                relaxed_filter,
+                reason: item.reason.clone().filter(|_| request.include_reasons),
+                ..item.clone()
+            };
+            if relaxed_filter.is_some() {
+                relaxed.push(item);
+            } else {
+                if let Some(embedding) = item_feature.embedding_for(embedding_version).filter(|_| diversity_lambda > 0.0) {
+                    embeddings.insert(item.item_id, embedding.to_vec());
                 }
+                recommendations.push(item);
+            }
+        }
+
+        let recommendations = self.merge_ranked(&window, diversity_lambda, recommendations, relaxed, &embeddings).await;
+        let mut recommendations = self.apply_category_guarantee(&window, recommendations).await;
+        recommendations.truncate(window.num_recommendations);
+        recommendations.drain(..request.offset.min(recommendations.len()));
+        self.record_served(request.user_id, recommendations, pool.model_warming_up).await
+    }
+
+    /// The session's scored pool, when it was scored for this profile version, tenant, embedding
+    /// version and model version within `session_cache_ttl_seconds` and deep enough for `window`; otherwise a fresh one.
+    async fn session_pool(
+        &self,
+        session_id: &str,
+        window: &RecommendationRequest,
+        profile: &UserProfile,
+        rec_config: &RecommendationConfig,
+    ) -> Result<Arc<SessionPool>> {
+        let ttl = Duration::from_secs(self.config.recommendation.session_cache_ttl_seconds);
+        // Filtered pages draw on a deeper pool than they show
+        let depth = rec_config.top_k.max(window.num_recommendations * 4);
+        let key = (window.user_id, session_id.to_string());
+        let cached = self.session_pools.get(&key).map(|pool| pool.clone());
+        if let Some(pool) = cached {
+            let current = pool.profile_version == profile.version
+                && pool.tenant_id == window.tenant_id
+                && pool.embedding_version == window.embedding_version
+                && pool.model_version == window.model_version
+                && pool.scored_at.elapsed() < ttl;
+            // A pool that came up short already holds every candidate
+            let deep_enough = pool.depth >= depth || pool.items.len() < pool.depth;
+            if current && deep_enough {
+                self.increment_stat("session_pool_hits").await;
+                return Ok(pool);
             }
         }
 
-        // Sort by score descending
-        recommendations.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        self.increment_stat("session_pool_misses").await;
+        let pool_request = RecommendationRequest {
+            tenant_id: window.tenant_id.clone(),
+            embedding_version: window.embedding_version.clone(),
+            model_version: window.model_version.clone(),
+            // Seen items are filtered per page, since `include_seen` can differ between pages
+            include_seen: true,
+            ..RecommendationRequest::new(window.user_id, depth)
+        };
+        let pool_config = RecommendationConfig {
+            diversity_lambda: 0.0,
+            cold_item_exploration: None,
+            ..rec_config.clone()
+        };
+        let embedding = self.effective_user_embedding(profile).await;
+        let mut diagnostics = RecommendationDiagnostics::default();
+        let (items, model_warming_up) = self
+            .rank_candidates(&pool_request, &embedding, &pool_config, None, &mut diagnostics)
+            .await?;
+        let pool = Arc::new(SessionPool {
+            profile_version: profile.version,
+            tenant_id: window.tenant_id.clone(),
+            embedding_version: window.embedding_version.clone(),
+            model_version: window.model_version.clone(),
+            scored_at: Instant::now(),
+            depth,
+            items,
+            model_warming_up,
+            candidate_count: diagnostics.candidate_count,
+        });
+        if self.session_pools.len() >= SESSION_POOL_SWEEP_THRESHOLD {
+            self.session_pools.retain(|_, pool| pool.scored_at.elapsed() < ttl);
+        }
+        self.session_pools.insert(key, pool.clone());
+        Ok(pool)
+    }
+
+    /// Randomly picks up to `slot_share` of the requested slots from cold items that pass the
+    /// request's filters, scored by their similarity to the query.
+    async fn sample_cold_items(
+        &self,
+        request: &RecommendationRequest,
+        embedding: &[f32],
+        exploration: &ColdItemExplorationConfig,
+        filter_categories: Option<&HashSet<String>>,
+        eligible: impl Fn(&Uuid) -> bool,
+    ) -> Result<Vec<RecommendationItem>> {
+        let slots = ((request.num_recommendations as f32 * exploration.slot_share).round() as usize)
+            .min(request.num_recommendations);
+        if slots == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut pool = self.vector_db.cold_item_ids(exploration.max_interactions).await;
+        pool.retain(|item_id| eligible(item_id));
+        let mut rng = match exploration.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        pool.shuffle(&mut rng);
+
+        let embedding_version = request.embedding_version.as_deref().unwrap_or(CURRENT_EMBEDDING_VERSION);
+        let now = Utc::now();
+        let created_after = request.max_item_age_seconds
+            .map(|max_age| now - chrono::Duration::seconds(max_age.min(i64::MAX as u64) as i64));
+        let mut explored = Vec::with_capacity(slots);
+        for item_id in pool {
+            if explored.len() >= slots {
+                break;
+            }
+            let Some(item_feature) = self.get_item_feature(item_id).await? else {
+                continue;
+            };
+            if item_feature.is_expired_at(now)
+                || created_after.is_some_and(|created_after| item_feature.created_at < created_after)
+                || filter_categories.is_some_and(|categories| !categories.contains(&item_feature.category))
+                || request.filter_tags.as_ref().is_some_and(|tags| !tags.iter().any(|tag| item_feature.tags.contains(tag)))
+            {
+                continue;
+            }
+            let Some(item_embedding) = item_feature.embedding_for(embedding_version) else {
+                continue;
+            };
+
+            explored.push(RecommendationItem {
+                item_id,
+                score: self.similarity(embedding, item_embedding),
+                reason: request.include_reasons.then(|| "New item exploration".to_string()),
+                category: item_feature.category,
+                relaxed_filter: None,
+            });
+        }
+        Ok(explored)
+    }
+
+    /// Remembers what was served so feedback carrying the returned id can be attributed to it.
+    async fn record_served(
+        &self,
+        user_id: Uuid,
+        recommendations: Vec<RecommendationItem>,
+        model_warming_up: bool,
+    ) -> Result<RecommendationResponse> {
+        let recommendation_id = Uuid::new_v4();
+        let served_items: Vec<Uuid> = recommendations.iter().map(|r| r.item_id).collect();
+        let cache_key = format!("served_recommendation:{}", recommendation_id);
+        self.cache.set_ex(&cache_key, serde_json::to_string(&served_items)?, self.config.redis.ttl_seconds).await?;
+
+        debug!("Generated recommendation {} for user {} with {} items", recommendation_id, user_id, served_items.len());
 
         Ok(RecommendationResponse {
-            user_id: request.user_id,
+            recommendation_id,
+            user_id,
             recommendations,
             generated_at: Utc::now(),
+            model_warming_up,
         })
     }
 
     pub async fn process_user_action(&self, action: &UserAction) -> Result<()> {
+        if self.is_duplicate_action(action).await? {
+            self.increment_stat("suppressed_duplicate_actions").await;
+            info!("Suppressed duplicate action {:?} for user {} on item {}", action.action_type, action.user_id, action.item_id);
+            return Ok(());
+        }
+
+        let Some(rate_weight) = self.rate_limit_weight(action.user_id).await? else {
+            debug!("Dropped rate-limited action {:?} for user {}", action.action_type, action.user_id);
+            return Ok(());
+        };
+
+        if let Some(recommendation_id) = action.recommendation_id {
+            self.attribute_feedback(recommendation_id, action).await?;
+        }
+
+        self.vector_db.record_item_interaction_at(action.item_id, &action.action_type, action.timestamp).await;
+        self.vector_db
+            .record_contextual_interaction(
+                action.item_id,
+                &action.action_type,
+                &TrendingContext::of_action(action),
+                action.timestamp,
+            )
+            .await;
+        self.vector_db.record_user_action(action).await?;
+
+        if action.action_type.is_negative() {
+            self.vector_db.exclude_item_for_user(action.user_id, action.item_id).await;
+        } else if !matches!(action.action_type, ActionType::View) {
+            self.vector_db.record_seen_item(action.user_id, action.item_id).await;
+        }
+
+        // Low-weight actions (e.g. incidental views) are noise for training and profiles.
+        // Negative feedback is explicit, so it is never dropped as noise.
+        let weight = self.get_action_weight(&action.action_type) * rate_weight;
+        if !action.action_type.is_negative() && weight < self.config.recommendation.min_action_weight {
+            self.increment_stat("low_weight_actions_skipped").await;
+            return Ok(());
+        }
+
         // Update user profile based on action
         let mut user_profile = self.get_or_create_user_profile(action.user_id).await?;
         
         // Get item feature
         if let Some(item_feature) = self.get_item_feature(action.item_id).await? {
-            // Update user embedding based on interaction
-            let weight = self.get_action_weight(&action.action_type);
-            self.update_user_embedding(&mut user_profile, &item_feature, weight).await?;
+            self.vector_db.record_category_affinity(action.user_id, &item_feature.category, weight).await;
+
+            // Update user embedding based on interaction, less the older the action is
+            let recency = exponential_decay_weight(action.timestamp, self.config.recommendation.interaction_decay_rate).min(1.0);
+            self.update_user_embedding(&mut user_profile, &item_feature, weight, recency).await?;
             
             // Cache updated profile
             self.user_profiles_cache.insert(action.user_id, user_profile.clone());
@@ -136,13 +1165,257 @@ impl RecommendationService {
 
             // Train algorithm incrementally
             self.algorithm.write().await.train(&[training_example]).await?;
+            self.trained_examples.fetch_add(1, Ordering::Relaxed);
             
-            info!("Processed user action: {:?} for user {}", action.action_type, action.user_id);
+            debug!("Processed user action: {:?} for user {}", action.action_type, action.user_id);
         }
 
         Ok(())
     }
 
+    /// Trains on negatives drawn from impressions whose first feedback is at least `delay_seconds`
+    /// old: up to `per_impression` of the items shown but not acted on, sampled with weight
+    /// `1 / rank` since users more surely saw the top of a list. Returns the examples trained on.
+    pub async fn train_hard_negatives(&self) -> Result<Vec<TrainingExample>> {
+        let Some(hard_negatives) = self.config.recommendation.hard_negatives.as_ref() else {
+            return Ok(Vec::new());
+        };
+        let delay = Duration::from_secs(hard_negatives.delay_seconds);
+        let ripe: Vec<Uuid> = self.impressions
+            .iter()
+            .filter(|impression| impression.first_feedback_at.elapsed() >= delay)
+            .map(|impression| *impression.key())
+            .collect();
+
+        let mut rng = StdRng::from_entropy();
+        let mut examples = Vec::new();
+        for recommendation_id in ripe {
+            let Some((_, impression)) = self.impressions.remove(&recommendation_id) else {
+                continue;
+            };
+            let ignored: Vec<(usize, Uuid)> = impression.served
+                .iter()
+                .copied()
+                .enumerate()
+                .filter(|(_, item_id)| !impression.interacted.contains(item_id))
+                .collect();
+            let sampled: Vec<Uuid> = match ignored.choose_multiple_weighted(&mut rng, hard_negatives.per_impression, |(rank, _)| 1.0 / (*rank + 1) as f64) {
+                Ok(sampled) => sampled.map(|(_, item_id)| *item_id).collect(),
+                Err(e) => {
+                    warn!("Failed to sample hard negatives of recommendation {}: {}", recommendation_id, e);
+                    continue;
+                }
+            };
+
+            let profile = self.get_or_create_user_profile(impression.user_id).await?;
+            for item_id in sampled {
+                let Some(item_feature) = self.get_item_feature(item_id).await? else {
+                    continue;
+                };
+                examples.push(TrainingExample {
+                    user_id: impression.user_id,
+                    item_id,
+                    label: 0.0,
+                    user_features: profile.embedding.clone(),
+                    item_features: item_feature.embedding,
+                    context_features: vec![],
+                    timestamp: Utc::now(),
+                });
+            }
+        }
+
+        if !examples.is_empty() {
+            self.algorithm.write().await.train(&examples).await?;
+            self.trained_examples.fetch_add(examples.len() as u64, Ordering::Relaxed);
+            self.increment_stat_by("hard_negatives_trained", examples.len() as u64).await;
+            debug!("Trained on {} hard negatives", examples.len());
+        }
+        Ok(examples)
+    }
+
+    /// Periodically runs `train_hard_negatives`.
+    pub fn spawn_hard_negative_trainer(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.train_hard_negatives().await {
+                    warn!("Failed to train hard negatives: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Re-derives a user's embedding from their action history, as the average of the interacted
+    /// items' embeddings weighted by action weight and decayed by age, and writes it back. Used to
+    /// repair a profile damaged by a bad update.
+    pub async fn rebuild_user_profile(&self, user_id: Uuid) -> Result<UserProfile> {
+        let actions = self.vector_db.get_user_actions(user_id).await?;
+        let decay_rate = self.config.recommendation.profile_rebuild_decay_rate;
+
+        let mut weighted_embeddings = Vec::new();
+        for action in &actions {
+            // A weighted average has no room for negative weights, so negative feedback is left out
+            let weight = self.get_action_weight(&action.action_type);
+            if action.action_type.is_negative() || weight < self.config.recommendation.min_action_weight {
+                continue;
+            }
+            if let Some(feature) = self.get_item_feature(action.item_id).await? {
+                let decay = exponential_decay_weight(action.timestamp, decay_rate);
+                weighted_embeddings.push((feature.embedding, weight * decay));
+            }
+        }
+        if weighted_embeddings.is_empty() {
+            return Err(anyhow!("User {} has no usable interaction history", user_id));
+        }
+
+        let mut profile = self.get_or_create_user_profile(user_id).await?;
+        profile.interaction_count = weighted_embeddings.len() as u64;
+        profile.update_embedding(weighted_average(&weighted_embeddings));
+        // Steps taken towards the old embedding say nothing about the rebuilt one
+        self.user_velocities.remove(&user_id);
+
+        self.vector_db.insert_user_profile(&profile).await?;
+        self.set_cached_payload(&format!("user_profile:{}", user_id), &profile).await?;
+        self.user_profiles_cache.insert(user_id, profile.clone());
+
+        info!("Rebuilt profile of user {} from {} of {} recorded actions", user_id, weighted_embeddings.len(), actions.len());
+        Ok(profile)
+    }
+
+    /// Resolves the recommendation settings for a tenant: static overrides from the config file first,
+    /// then a `tenant_config:<id>` entry in Redis, falling back to the global settings. The Redis
+    /// entry is reused for `tenant_config_cache_ttl_ms`.
+    pub async fn resolve_recommendation_config(&self, tenant_id: Option<&str>) -> Result<RecommendationConfig> {
+        let Some(tenant_id) = tenant_id else {
+            return Ok(self.config.recommendation.clone());
+        };
+
+        if let Some(tenant_config) = self.config.tenants.get(tenant_id) {
+            return Ok(tenant_config.clone());
+        }
+
+        let ttl = Duration::from_millis(self.config.recommendation.tenant_config_cache_ttl_ms);
+        let cached = self.tenant_configs_cache
+            .get(tenant_id)
+            .filter(|entry| entry.0.elapsed() < ttl)
+            .map(|entry| entry.1.clone());
+        let tenant_config = match cached {
+            Some(tenant_config) => tenant_config,
+            None => {
+                let tenant_config = self.load_tenant_config(tenant_id).await?;
+                self.tenant_configs_cache.insert(tenant_id.to_string(), (Instant::now(), tenant_config.clone()));
+                tenant_config
+            }
+        };
+
+        Ok(tenant_config.map_or_else(|| self.config.recommendation.clone(), |config| (*config).clone()))
+    }
+
+    /// The tenant's `tenant_config:<id>` entry, or `None` when it is absent or unreadable.
+    async fn load_tenant_config(&self, tenant_id: &str) -> Result<Option<Arc<RecommendationConfig>>> {
+        let cache_key = format!("tenant_config:{}", tenant_id);
+        let Some(cached_data) = self.cache.get(&cache_key).await? else {
+            return Ok(None);
+        };
+        match serde_json::from_str::<RecommendationConfig>(&cached_data) {
+            Ok(tenant_config) => Ok(Some(Arc::new(tenant_config))),
+            Err(e) => {
+                warn!("Invalid recommendation config for tenant {}: {}", tenant_id, e);
+                Ok(None)
+            }
+        }
+    }
+
+    async fn load_item_lists(&self, rec_config: &RecommendationConfig) -> Result<ItemLists> {
+        let ttl = Duration::from_millis(rec_config.item_list_cache_ttl_ms);
+        let deny = match &rec_config.deny_list_key {
+            Some(key) => self.load_item_list(key, ttl).await?,
+            None => None,
+        };
+        let allow = match &rec_config.allow_list_key {
+            Some(key) => self.load_item_list(key, ttl).await?,
+            None => None,
+        };
+        Ok(ItemLists { deny, allow })
+    }
+
+    /// Reads a JSON array of item ids from `key`, reusing the last read for `ttl`. An unreadable list
+    /// is treated as absent so a bad merchandising update cannot take serving down.
+    async fn load_item_list(&self, key: &str, ttl: Duration) -> Result<ItemList> {
+        if let Some(entry) = self.item_lists_cache.get(key) {
+            if entry.0.elapsed() < ttl {
+                return Ok(entry.1.clone());
+            }
+        }
+
+        let list = match self.cache.get(key).await? {
+            Some(data) => match serde_json::from_str::<HashSet<Uuid>>(&data) {
+                Ok(item_ids) => Some(Arc::new(item_ids)),
+                Err(e) => {
+                    warn!("Invalid item list at {}: {}", key, e);
+                    None
+                }
+            },
+            None => None,
+        };
+        self.item_lists_cache.insert(key.to_string(), (Instant::now(), list.clone()));
+        Ok(list)
+    }
+
+    /// Static manual scores from the config, overlaid with the ones under `manual_scores_key`.
+    /// An unreadable Redis entry is ignored like an unreadable item list.
+    async fn load_manual_scores(&self, rec_config: &RecommendationConfig) -> Result<ManualScores> {
+        let Some(key) = &rec_config.manual_scores_key else {
+            return Ok(Arc::new(rec_config.manual_scores.clone()));
+        };
+
+        let ttl = Duration::from_millis(rec_config.item_list_cache_ttl_ms);
+        let cached = self.manual_scores_cache
+            .get(key)
+            .filter(|entry| entry.0.elapsed() < ttl)
+            .map(|entry| entry.1.clone());
+        let overrides = match cached {
+            Some(overrides) => overrides,
+            None => {
+                let overrides = match self.cache.get(key).await? {
+                    Some(data) => match serde_json::from_str::<HashMap<Uuid, f32>>(&data) {
+                        Ok(scores) => Some(Arc::new(scores)),
+                        Err(e) => {
+                            warn!("Invalid manual scores at {}: {}", key, e);
+                            None
+                        }
+                    },
+                    None => None,
+                };
+                self.manual_scores_cache.insert(key.to_string(), (Instant::now(), overrides.clone()));
+                overrides
+            }
+        };
+
+        let mut scores = rec_config.manual_scores.clone();
+        if let Some(overrides) = overrides {
+            scores.extend(overrides.iter().map(|(item_id, score)| (*item_id, *score)));
+        }
+        Ok(Arc::new(scores))
+    }
+
+    /// Expands category filters with all their descendants from the configured taxonomy.
+    fn expand_categories(&self, categories: &[String]) -> HashSet<String> {
+        let mut expanded: HashSet<String> = HashSet::new();
+        let mut pending: Vec<&String> = categories.iter().collect();
+
+        while let Some(category) = pending.pop() {
+            if expanded.insert(category.clone()) {
+                if let Some(children) = self.config.category_taxonomy.get(category) {
+                    pending.extend(children.iter());
+                }
+            }
+        }
+
+        expanded
+    }
+
     async fn get_or_create_user_profile(&self, user_id: Uuid) -> Result<UserProfile> {
         // Check cache first
         if let Some(profile) = self.user_profiles_cache.get(&user_id) {
@@ -150,21 +1423,17 @@ impl RecommendationService {
         }
 
         // Check Redis cache
-        let mut redis_conn = self.redis_client.get_async_connection().await?;
         let cache_key = format!("user_profile:{}", user_id);
         
-        if let Ok(cached_data) = redis_conn.get::<_, String>(&cache_key).await {
-            if let Ok(profile) = serde_json::from_str::<UserProfile>(&cached_data) {
-                self.user_profiles_cache.insert(user_id, profile.clone());
-                return Ok(profile);
-            }
+        if let Some(profile) = self.get_cached_payload::<UserProfile>(&cache_key).await? {
+            self.user_profiles_cache.insert(user_id, profile.clone());
+            return Ok(profile);
         }
 
         // Check vector database
         if let Some(profile) = self.vector_db.get_user_profile(user_id).await? {
             // Cache in Redis and memory
-            let profile_json = serde_json::to_string(&profile)?;
-            let _: () = redis_conn.set_ex(&cache_key, profile_json, self.config.redis.ttl_seconds).await?;
+            self.set_cached_payload(&cache_key, &profile).await?;
             self.user_profiles_cache.insert(user_id, profile.clone());
             return Ok(profile);
         }
@@ -176,14 +1445,45 @@ impl RecommendationService {
         self.vector_db.insert_user_profile(&new_profile).await?;
         
         // Cache in Redis and memory
-        let profile_json = serde_json::to_string(&new_profile)?;
-        let _: () = redis_conn.set_ex(&cache_key, profile_json, self.config.redis.ttl_seconds).await?;
+        self.set_cached_payload(&cache_key, &new_profile).await?;
         self.user_profiles_cache.insert(user_id, new_profile.clone());
 
         info!("Created new user profile: {}", user_id);
         Ok(new_profile)
     }
 
+    /// Reads a cached profile or feature in whichever codec it was written with. Unreadable
+    /// payloads are treated as a miss and reported through `handle_corrupt_entry`.
+    async fn get_cached_payload<T: serde::de::DeserializeOwned>(&self, cache_key: &str) -> Result<Option<T>> {
+        let Some(bytes) = self.cache.get_bytes(cache_key).await? else {
+            return Ok(None);
+        };
+        match decode_payload(&bytes) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => {
+                self.handle_corrupt_entry(cache_key, &e).await;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Counts and logs an entry this service wrote but cannot decode, and with
+    /// `repair_corrupt_entries` deletes it so it gets rewritten fresh.
+    async fn handle_corrupt_entry(&self, cache_key: &str, error: &anyhow::Error) {
+        self.increment_stat("cache_corruptions").await;
+        warn!("Corrupt cache entry at {}: {}", cache_key, error);
+        if self.config.redis.repair_corrupt_entries {
+            if let Err(e) = self.cache.delete(cache_key).await {
+                warn!("Failed to delete corrupt cache entry at {}: {}", cache_key, e);
+            }
+        }
+    }
+
+    async fn set_cached_payload<T: serde::Serialize>(&self, cache_key: &str, value: &T) -> Result<()> {
+        let payload = encode_payload(value, self.config.redis.codec)?;
+        self.cache.set_ex_bytes(cache_key, payload, self.config.redis.ttl_seconds).await
+    }
+
     async fn get_item_feature(&self, item_id: Uuid) -> Result<Option<ItemFeature>> {
         // Check cache first
         if let Some(feature) = self.item_features_cache.get(&item_id) {
@@ -191,21 +1491,17 @@ impl RecommendationService {
         }
 
         // Check Redis cache
-        let mut redis_conn = self.redis_client.get_async_connection().await?;
         let cache_key = format!("item_feature:{}", item_id);
         
-        if let Ok(cached_data) = redis_conn.get::<_, String>(&cache_key).await {
-            if let Ok(feature) = serde_json::from_str::<ItemFeature>(&cached_data) {
-                self.item_features_cache.insert(item_id, feature.clone());
-                return Ok(Some(feature));
-            }
+        if let Some(feature) = self.get_cached_payload::<ItemFeature>(&cache_key).await? {
+            self.item_features_cache.insert(item_id, feature.clone());
+            return Ok(Some(feature));
         }
 
         // Check vector database
         if let Some(feature) = self.vector_db.get_item_feature(item_id).await? {
             // Cache in Redis and memory
-            let feature_json = serde_json::to_string(&feature)?;
-            let _: () = redis_conn.set_ex(&cache_key, feature_json, self.config.redis.ttl_seconds).await?;
+            self.set_cached_payload(&cache_key, &feature).await?;
             self.item_features_cache.insert(item_id, feature.clone());
             return Ok(Some(feature));
         }
@@ -213,32 +1509,57 @@ impl RecommendationService {
         Ok(None)
     }
 
-    async fn update_user_embedding(&self, profile: &mut UserProfile, item_feature: &ItemFeature, weight: f32) -> Result<()> {
+    /// Blends the item into the profile at a rate of 0.1 scaled by `recency`, in 0..=1. Under
+    /// `embedding_momentum` the profile moves by an exponential average of its recent steps instead.
+    async fn update_user_embedding(
+        &self,
+        profile: &mut UserProfile,
+        item_feature: &ItemFeature,
+        weight: f32,
+        recency: f32,
+    ) -> Result<()> {
         // Simple weighted average update
-        let learning_rate = 0.1;
-        
-        for i in 0..profile.embedding.len() {
-            profile.embedding[i] = profile.embedding[i] * (1.0 - learning_rate) + 
-                                  item_feature.embedding[i] * learning_rate * weight;
+        let learning_rate = 0.1 * recency;
+        let momentum = self.config.recommendation.embedding_momentum.clamp(0.0, 0.99);
+
+        if momentum > 0.0 {
+            let dimension = profile.embedding.len();
+            let mut velocity = self.user_velocities.entry(profile.user_id).or_insert_with(|| vec![0.0; dimension]);
+            if velocity.len() != dimension {
+                *velocity = vec![0.0; dimension];
+            }
+            for ((x, v), target) in profile.embedding.iter_mut().zip(velocity.iter_mut()).zip(&item_feature.embedding) {
+                let step = learning_rate * (target * weight - *x);
+                *v = momentum * *v + (1.0 - momentum) * step;
+                *x += *v;
+            }
+        } else {
+            for i in 0..profile.embedding.len() {
+                profile.embedding[i] = profile.embedding[i] * (1.0 - learning_rate) + 
+                                      item_feature.embedding[i] * learning_rate * weight;
+            }
         }
         
         profile.increment_interactions();
         Ok(())
     }
 
-    fn get_action_weight(&self, action_type: &ActionType) -> f32 {
-        match action_type {
-            ActionType::View => 0.1,
-            ActionType::Click => 0.3,
-            ActionType::Like => 0.7,
-            ActionType::Share => 0.8,
-            ActionType::Purchase => 1.0,
-            ActionType::Convert => 1.0,
+    /// Items never served to the request's user: those they gave negative feedback on, and unless
+    /// the request sets `include_seen`, those they already interacted with.
+    async fn excluded_items(&self, request: &RecommendationRequest) -> HashSet<Uuid> {
+        let mut excluded = self.vector_db.get_user_exclusions(request.user_id).await;
+        if !request.include_seen {
+            excluded.extend(self.vector_db.get_seen_items(request.user_id).await);
         }
+        excluded
+    }
+
+    fn get_action_weight(&self, action_type: &ActionType) -> f32 {
+        self.config.action_weights.weight(action_type)
     }
 
     async fn extract_context_features(&self, action: &UserAction) -> Result<Vec<f32>> {
-        // Extract context features from action
+        // Extract context features from action, in the order of CONTEXT_FEATURE_NAMES
         let mut features = vec![0.0; 10]; // Simple context features
         
         // Time-based features
@@ -255,17 +1576,428 @@ impl RecommendationService {
     }
 
     pub async fn add_item_feature(&self, feature: ItemFeature) -> Result<()> {
+        self.add_item_feature_with_mode(feature, InsertMode::Upsert).await
+    }
+
+    /// Like `add_item_feature`, refusing to replace or to create the item as `mode` says.
+    pub async fn add_item_feature_with_mode(&self, feature: ItemFeature, mode: InsertMode) -> Result<()> {
+        let mut feature = self.enrichers.enrich(feature).await?;
+        // Prepare here as well so the cached copy matches what the index stores
+        self.vector_db.prepare_item_feature(&mut feature);
+        validate_item_feature_with_sanitization(
+            &mut feature,
+            self.config.recommendation.sanitize_non_finite_embeddings,
+        )?;
+
         // Save to vector database
-        self.vector_db.insert_item_feature(&feature).await?;
+        let feature = self.vector_db.insert_item_feature_with_mode(&feature, mode).await?;
         
         // Cache in memory and Redis
-        let mut redis_conn = self.redis_client.get_async_connection().await?;
         let cache_key = format!("item_feature:{}", feature.item_id);
-        let feature_json = serde_json::to_string(&feature)?;
-        let _: () = redis_conn.set_ex(&cache_key, feature_json, self.config.redis.ttl_seconds).await?;
+        self.set_cached_payload(&cache_key, &feature).await?;
         
         self.item_features_cache.insert(feature.item_id, feature);
         
         Ok(())
     }
+
+    /// Deletes an item so it is never served again, dropping every cached copy and any cached
+    /// response or session pool that could still serve it. Returns whether it existed.
+    pub async fn delete_item_feature(&self, item_id: Uuid) -> Result<bool> {
+        let existed = self.vector_db.delete_item_feature(item_id).await?;
+        self.item_features_cache.remove(&item_id);
+        self.cache.delete(&format!("item_feature:{}", item_id)).await?;
+
+        self.session_pools.retain(|_, pool| pool.items.iter().all(|item| item.item_id != item_id));
+        let ttl_seconds = self.config.redis.response_cache_ttl_seconds;
+        if ttl_seconds > 0 {
+            // Responses cached under the old token expire no later than this key, so it may lapse
+            self.cache.set_ex(RESPONSE_CACHE_GENERATION_KEY, Uuid::new_v4().to_string(), ttl_seconds).await?;
+        }
+        Ok(existed)
+    }
+
+    pub fn algorithm(&self) -> Arc<RwLock<CollaborativeFiltering>> {
+        self.algorithm.clone()
+    }
+
+    /// Count of examples the model has been trained on, shared with `TrainingService`.
+    pub fn trained_examples_counter(&self) -> Arc<AtomicU64> {
+        self.trained_examples.clone()
+    }
+
+    fn is_model_ready(&self, rec_config: &RecommendationConfig) -> bool {
+        self.trained_examples.load(Ordering::Relaxed) >= rec_config.min_training_examples
+    }
+
+    /// A model past `max_model_age_seconds` most likely means the trainer stopped publishing; its
+    /// scores are dropped rather than served indefinitely. Every fallback is counted, but only the
+    /// switch between fresh and stale is logged.
+    async fn is_model_stale(&self, rec_config: &RecommendationConfig) -> bool {
+        let Some(max_age_seconds) = rec_config.max_model_age_seconds else {
+            return false;
+        };
+
+        let updated_at = *self.model_updated_at.read().await;
+        let max_age = chrono::Duration::seconds(max_age_seconds.min(i64::MAX as u64) as i64);
+        if let Some(updated_at) = updated_at.filter(|updated_at| Utc::now() - *updated_at <= max_age) {
+            if self.model_stale.swap(false, Ordering::Relaxed) {
+                info!("Model parameters from {} are fresh again, scoring with the model", updated_at);
+            }
+            return false;
+        }
+
+        self.increment_stat("stale_model_fallbacks").await;
+        if !self.model_stale.swap(true, Ordering::Relaxed) {
+            match updated_at {
+                Some(updated_at) => warn!("Model parameters from {} are older than {}s, scoring on similarity only", updated_at, max_age_seconds),
+                None => warn!("No model parameters loaded, scoring on similarity only"),
+            }
+        }
+        true
+    }
+
+    /// Loads `parameters` as the model named `name`, which requests select with `model_version`.
+    /// Replaces any model already loaded under that name.
+    pub async fn load_named_model(&self, name: &str, parameters: &ModelParameters) -> Result<()> {
+        let mut model = CollaborativeFiltering::new(
+            self.config.recommendation.embedding_dim,
+            self.config.training.learning_rate,
+            0.01, // regularization
+        );
+        model.update_parameters(parameters).await?;
+        self.named_models.insert(name.to_string(), Arc::new(RwLock::new(model)));
+        Ok(())
+    }
+
+    /// The model `request` scores with: its named `model_version`, or the default one.
+    fn model_for(&self, request: &RecommendationRequest) -> Result<Arc<RwLock<CollaborativeFiltering>>> {
+        match &request.model_version {
+            Some(name) => self.named_models
+                .get(name)
+                .map(|model| model.clone())
+                .ok_or_else(|| UnknownModelVersion(name.clone()).into()),
+            None => Ok(self.algorithm.clone()),
+        }
+    }
+
+    /// Loads published parameters into the serving model and records their age for the freshness check.
+    pub async fn load_model_parameters(&self, parameters: &ModelParameters) -> Result<()> {
+        self.algorithm.write().await.update_parameters(parameters).await?;
+        *self.model_updated_at.write().await = Some(parameters.updated_at);
+        Ok(())
+    }
+
+    pub async fn get_recommendation_stats(&self) -> HashMap<String, u64> {
+        self.recommendation_stats.iter().map(|entry| (entry.key().clone(), *entry.value())).collect()
+    }
+
+    async fn increment_stat(&self, key: &str) {
+        self.increment_stat_by(key, 1).await;
+    }
+
+    async fn increment_stat_by(&self, key: &str, amount: u64) {
+        let mut counter = self.recommendation_stats.entry(key.to_string()).or_insert(0);
+        *counter += amount;
+    }
+
+    async fn is_duplicate_action(&self, action: &UserAction) -> Result<bool> {
+        let window = self.config.recommendation.duplicate_action_window_seconds;
+        if window == 0 {
+            return Ok(false);
+        }
+
+        // SET NX claims the (user, item, action) slot; a second identical event inside the window fails to claim it
+        let dedup_key = format!("action_dedup:{}:{}:{:?}", action.user_id, action.item_id, action.action_type);
+        let claimed = self.cache
+            .set_nx_ex(&dedup_key, action.timestamp.to_rfc3339(), window)
+            .await?;
+
+        Ok(!claimed)
+    }
+
+    /// Multiplier for the action's weight under the user's rate limit: 1.0 within the limit, the
+    /// configured down-weight beyond it, or `None` when the action is to be dropped.
+    async fn rate_limit_weight(&self, user_id: Uuid) -> Result<Option<f32>> {
+        let Some(limit) = &self.config.recommendation.action_rate_limit else {
+            return Ok(Some(1.0));
+        };
+
+        let bucket_key = format!("action_rate:{}", user_id);
+        if self.cache.take_token(&bucket_key, limit.burst as f64, limit.actions_per_second).await? {
+            return Ok(Some(1.0));
+        }
+
+        self.increment_stat("rate_limited_actions").await;
+        let excess = {
+            let mut count = self.rate_limit_excess.entry(user_id).or_insert(0);
+            *count += 1;
+            *count
+        };
+        if limit.abuse_threshold > 0 && excess == limit.abuse_threshold {
+            self.increment_stat("flagged_abusive_users").await;
+            warn!("User {} flagged as abusive after {} rate-limited actions", user_id, excess);
+        }
+
+        Ok(match limit.policy {
+            RateLimitPolicy::Drop => None,
+            RateLimitPolicy::DownWeight => Some(limit.down_weight),
+        })
+    }
+
+    /// Whether the user has exceeded the rate limit at least `abuse_threshold` times.
+    pub fn is_user_flagged(&self, user_id: Uuid) -> bool {
+        self.config.recommendation.action_rate_limit.as_ref().is_some_and(|limit| {
+            limit.abuse_threshold > 0
+                && self.rate_limit_excess.get(&user_id).is_some_and(|count| *count >= limit.abuse_threshold)
+        })
+    }
+
+    async fn attribute_feedback(&self, recommendation_id: Uuid, action: &UserAction) -> Result<()> {
+        let cache_key = format!("served_recommendation:{}", recommendation_id);
+        let served_items = match self.cache.get_bytes(&cache_key).await? {
+            Some(cached_data) => match serde_json::from_slice::<Vec<Uuid>>(&cached_data) {
+                Ok(served_items) => served_items,
+                Err(e) => {
+                    self.handle_corrupt_entry(&cache_key, &e.into()).await;
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+
+        if served_items.contains(&action.item_id) {
+            self.increment_stat("attributed_feedback").await;
+            info!("Attributed {:?} on item {} to recommendation {}", action.action_type, action.item_id, recommendation_id);
+            if self.config.recommendation.hard_negatives.is_some() {
+                self.impressions
+                    .entry(recommendation_id)
+                    .or_insert_with(|| Impression {
+                        user_id: action.user_id,
+                        served: served_items,
+                        interacted: HashSet::new(),
+                        first_feedback_at: Instant::now(),
+                    })
+                    .interacted
+                    .insert(action.item_id);
+            }
+        } else {
+            self.increment_stat("unattributed_feedback").await;
+            warn!("Feedback references recommendation {} which did not serve item {}", recommendation_id, action.item_id);
+        }
+
+        Ok(())
+    }
+}
+
+/// Fails requests asking for more distinct categories than recommendations.
+fn check_category_guarantee(request: &RecommendationRequest) -> Result<()> {
+    match request.min_distinct_categories {
+        Some(min_categories) if min_categories > request.num_recommendations => Err(anyhow!(
+            "Cannot guarantee {} distinct categories in {} recommendations",
+            min_categories,
+            request.num_recommendations
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `profile` has too few interactions to rank by. A profile seeded with an embedding but no
+/// interactions is ranked by that embedding.
+fn is_cold_start(profile: &UserProfile, rec_config: &RecommendationConfig) -> bool {
+    let seeded = profile.interaction_count == 0 && profile.embedding.iter().any(|x| *x != 0.0);
+    profile.interaction_count < rec_config.cold_start_threshold && !seeded
+}
+
+/// `request` widened to cover every page up to and including its own, from the first item.
+fn paged_window(request: &RecommendationRequest) -> RecommendationRequest {
+    RecommendationRequest {
+        num_recommendations: request.offset.saturating_add(request.num_recommendations),
+        offset: 0,
+        ..request.clone()
+    }
+}
+
+/// Whether an item can be served at all at `now`: unexpired, and created after `created_after`.
+fn is_servable(feature: &ItemFeature, now: DateTime<Utc>, created_after: Option<DateTime<Utc>>) -> bool {
+    !feature.is_expired_at(now) && created_after.is_none_or(|created_after| feature.created_at >= created_after)
+}
+
+/// The request filter an item fails: its category (or any of its descendants) or its tags.
+fn failed_soft_filter(
+    request: &RecommendationRequest,
+    filter_categories: Option<&HashSet<String>>,
+    feature: &ItemFeature,
+) -> Option<RelaxedFilter> {
+    if filter_categories.is_some_and(|categories| !categories.contains(&feature.category)) {
+        Some(RelaxedFilter::Category)
+    } else if request.filter_tags.as_ref().is_some_and(|tags| !tags.iter().any(|tag| feature.tags.contains(tag))) {
+        Some(RelaxedFilter::Tags)
+    } else {
+        None
+    }
+}
+
+/// Response cache key for `request` against this exact profile state and cache `generation`. Any
+/// profile change bumps `version` and moves later lookups to a fresh key; item deletions replace
+/// the generation.
+fn response_cache_key(request: &RecommendationRequest, profile: &UserProfile, generation: &str) -> Result<String> {
+    // A stable hash, so every instance and every release maps a request to the same key
+    let request_hash = fnv1a_64(&serde_json::to_vec(request)?);
+    Ok(format!(
+        "recommendation_response:{}:{}:{}:{:016x}",
+        request.user_id,
+        profile.version,
+        generation,
+        request_hash
+    ))
+}
+
+/// Applies `similarity_threshold` to a score: a hard cutoff by default, or with `soft_threshold_width`
+/// set, a sigmoid penalty that fades near-threshold scores out instead of dropping them at the boundary.
+fn apply_similarity_threshold(score: f32, rec_config: &RecommendationConfig) -> Option<f32> {
+    let threshold = rec_config.similarity_threshold;
+    let width = rec_config.soft_threshold_width;
+    if width <= 0.0 {
+        return (score >= threshold).then_some(score);
+    }
+
+    if score < threshold - 4.0 * width {
+        return None;
+    }
+    Some(score * sigmoid((score - threshold) / width))
+}
+
+/// Maximal marginal relevance ordering of `ranked`: each position takes the item maximising
+/// `(1 - lambda) * score - lambda * max similarity to the items placed before it`, so near-duplicates
+/// of higher-ranked items sink. Items without an embedding count as dissimilar to everything.
+fn rerank_by_marginal_relevance(
+    ranked: Vec<RecommendationItem>,
+    embeddings: &HashMap<Uuid, Vec<f32>>,
+    lambda: f32,
+    similarity: impl Fn(&[f32], &[f32]) -> f32,
+) -> Vec<RecommendationItem> {
+    let mut remaining = ranked;
+    // Highest similarity of each remaining item to anything already placed, floored at 0
+    let mut redundancy = vec![0.0f32; remaining.len()];
+    let mut reranked = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let marginal = |index: usize| (1.0 - lambda) * remaining[index].score - lambda * redundancy[index];
+        // `remaining` stays score-sorted, so the first maximum is the best-scored one
+        let mut best = 0;
+        for index in 1..remaining.len() {
+            if marginal(index) > marginal(best) {
+                best = index;
+            }
+        }
+
+        let placed = remaining.remove(best);
+        redundancy.remove(best);
+        if let Some(placed_embedding) = embeddings.get(&placed.item_id) {
+            for (item, redundancy) in remaining.iter().zip(redundancy.iter_mut()) {
+                if let Some(embedding) = embeddings.get(&item.item_id) {
+                    *redundancy = redundancy.max(similarity(placed_embedding, embedding));
+                }
+            }
+        }
+        reranked.push(placed);
+    }
+    reranked
+}
+
+/// Reorders `ranked` so that no item also in `previous` lands more than `max_shift` positions
+/// from where it was, counting only the items the two lists share. Within that bound, each position
+/// takes the best-ranked item allowed there; items new to the list only fill around the others.
+fn stabilize_ranking(ranked: Vec<RecommendationItem>, previous: &[Uuid], max_shift: usize) -> Vec<RecommendationItem> {
+    let current: HashSet<Uuid> = ranked.iter().map(|item| item.item_id).collect();
+    let previous_position: HashMap<Uuid, usize> = previous
+        .iter()
+        .filter(|item_id| current.contains(item_id))
+        .enumerate()
+        .map(|(position, item_id)| (*item_id, position))
+        .collect();
+
+    let mut remaining: Vec<Option<RecommendationItem>> = ranked.into_iter().map(Some).collect();
+    let mut stabilized = Vec::with_capacity(remaining.len());
+    let was_at = |item: &RecommendationItem| previous_position.get(&item.item_id).copied();
+    for position in 0..remaining.len() {
+        // An item about to fall more than max_shift behind goes here; otherwise the best-ranked
+        // item that would not jump more than max_shift ahead
+        let due = remaining
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| Some((index, was_at(item.as_ref()?)?)))
+            .filter(|(_, was)| was + max_shift <= position)
+            .min_by_key(|(_, was)| *was)
+            .map(|(index, _)| index);
+        let next = due
+            .or_else(|| {
+                remaining.iter().position(|item| {
+                    item.as_ref().is_some_and(|item| was_at(item).is_none_or(|was| was <= position + max_shift))
+                })
+            })
+            .or_else(|| remaining.iter().position(Option::is_some));
+        if let Some(item) = next.and_then(|index| remaining[index].take()) {
+            stabilized.push(item);
+        }
+    }
+    stabilized
+}
+
+/// Keeps the top `num` items but swaps the weakest items of over-represented categories for the best
+/// items of missing ones until `min_categories` distinct categories appear. Returns whether the
+/// guarantee was met; when the candidates do not span enough categories the best effort is returned.
+fn enforce_min_distinct_categories(
+    ranked: Vec<RecommendationItem>,
+    num: usize,
+    min_categories: usize,
+) -> (Vec<RecommendationItem>, bool) {
+    let mut selected = ranked;
+    let mut backfill = selected.split_off(num.min(selected.len()));
+
+    loop {
+        let mut category_counts: HashMap<&str, usize> = HashMap::new();
+        for item in &selected {
+            *category_counts.entry(item.category.as_str()).or_insert(0) += 1;
+        }
+        if category_counts.len() >= min_categories {
+            return (selected, true);
+        }
+
+        // Best remaining item from a category not yet represented (backfill is score-sorted)
+        let Some(backfill_index) = backfill
+            .iter()
+            .position(|item| !category_counts.contains_key(item.category.as_str()))
+        else {
+            return (selected, false);
+        };
+
+        // Weakest item whose category would still be represented without it
+        let evict_index = selected
+            .iter()
+            .rposition(|item| category_counts[item.category.as_str()] > 1);
+
+        let incoming = backfill.remove(backfill_index);
+        match evict_index {
+            Some(index) => {
+                selected.remove(index);
+            }
+            None if selected.len() >= num => return (selected, false),
+            None => {}
+        }
+
+        let position = selected
+            .iter()
+            .position(|item| item.score < incoming.score)
+            .unwrap_or(selected.len());
+        selected.insert(position, incoming);
+    }
+}
+
+/// Score descending, ties by item id.
+fn compare_by_score(a: &RecommendationItem, b: &RecommendationItem) -> std::cmp::Ordering {
+    b.score
+        .partial_cmp(&a.score)
+        .unwrap_or(std::cmp::Ordering::Equal)
+        .then_with(|| a.item_id.cmp(&b.item_id))
 }
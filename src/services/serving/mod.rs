@@ -1,6 +1,7 @@
 use crate::config::Config;
 use crate::models::*;
-use crate::services::{vector_db::VectorDbService, recommendation::RecommendationService};
+use crate::services::{vector_db::VectorDbService, recommendation::{RecommendationDiagnostics, RecommendationService}};
+use crate::utils::metrics::{Histogram, HyperLogLog, DEFAULT_SCORE_BUCKETS};
 use anyhow::Result;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -8,14 +9,58 @@ use uuid::Uuid;
 
 use tracing::{info, error};
 use dashmap::DashMap;
+use serde::Serialize;
 use std::collections::HashMap;
 
+/// Outcome of one request of a batch, with the diagnostics batch jobs need to monitor it.
+#[derive(Debug)]
+pub struct BatchServeResult {
+    pub request: RecommendationRequest,
+    pub result: Result<RecommendationResponse>,
+    pub latency_ms: u64,
+    /// Candidates the response was ranked from, as in `RecommendationDiagnostics`. Zero when the
+    /// request failed.
+    pub candidate_count: usize,
+    /// Recommendations returned, zero on failure.
+    pub recommendation_count: usize,
+}
+
+/// Distribution of served scores, one observation per non-empty response, to spot drift such as a
+/// bad model deploy shifting every score.
+#[derive(Debug, Clone)]
+pub struct ScoreHistograms {
+    pub top_score: Histogram,
+    pub mean_score: Histogram,
+}
+
+impl Default for ScoreHistograms {
+    fn default() -> Self {
+        Self {
+            top_score: Histogram::new(&DEFAULT_SCORE_BUCKETS),
+            mean_score: Histogram::new(&DEFAULT_SCORE_BUCKETS),
+        }
+    }
+}
+
+/// How much of the catalog has been recommended at least once since the service started.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CatalogCoverage {
+    /// Approximate: counted with a `HyperLogLog`.
+    pub distinct_items_served: u64,
+    pub catalog_items: u64,
+    /// `distinct_items_served` over `catalog_items`, at most 1. 0 for an empty catalog.
+    pub coverage: f64,
+}
+
 pub struct ServingService {
     vector_db: Arc<VectorDbService>,
     recommendation_service: Arc<RecommendationService>,
     config: Arc<Config>,
     model_parameters: Arc<RwLock<Option<ModelParameters>>>,
     serving_stats: Arc<DashMap<String, u64>>,
+    score_histograms: Arc<RwLock<ScoreHistograms>>,
+    /// Every item ever recommended, across all requests.
+    served_items: Arc<RwLock<HyperLogLog>>,
 }
 
 impl ServingService {
@@ -30,46 +75,73 @@ impl ServingService {
             config,
             model_parameters: Arc::new(RwLock::new(None)),
             serving_stats: Arc::new(DashMap::new()),
+            score_histograms: Arc::new(RwLock::new(ScoreHistograms::default())),
+            served_items: Arc::new(RwLock::new(HyperLogLog::new())),
         })
     }
 
     pub async fn serve_recommendations(&self, request: &RecommendationRequest) -> Result<RecommendationResponse> {
+        self.serve_with_diagnostics(request).await.map(|(response, _)| response)
+    }
+
+    async fn serve_with_diagnostics(
+        &self,
+        request: &RecommendationRequest,
+    ) -> Result<(RecommendationResponse, RecommendationDiagnostics)> {
         self.increment_stat("total_requests").await;
         
         let start_time = std::time::Instant::now();
         
-        let response = self.recommendation_service.get_recommendations(request).await?;
+        let (response, diagnostics) = self.recommendation_service.get_recommendations_with_diagnostics(request).await?;
         
         let latency = start_time.elapsed().as_millis() as u64;
         self.update_latency_stat(latency).await;
         
         self.increment_stat("successful_requests").await;
+        self.record_score_distribution(&response).await;
+        self.record_served_items(&response).await;
         
-        info!("Served recommendations for user {} in {}ms", request.user_id, latency);
-        Ok(response)
+        info!("Served recommendation {} for user {} in {}ms", response.recommendation_id, request.user_id, latency);
+        Ok((response, diagnostics))
     }
 
-    pub async fn batch_serve_recommendations(&self, requests: &[RecommendationRequest]) -> Result<Vec<RecommendationResponse>> {
+    /// Serves every request in turn like `serve_recommendations`, keeping each one's result and
+    /// diagnostics, so one failing user does not hide the others.
+    pub async fn batch_serve_recommendations(&self, requests: &[RecommendationRequest]) -> Vec<BatchServeResult> {
         self.increment_stat("batch_requests").await;
         
         let start_time = std::time::Instant::now();
-        let mut responses = Vec::new();
+        let mut results = Vec::with_capacity(requests.len());
         
         for request in requests {
-            match self.recommendation_service.get_recommendations(request).await {
-                Ok(response) => responses.push(response),
+            let request_start = std::time::Instant::now();
+            let served = self.serve_with_diagnostics(request).await;
+            let latency_ms = request_start.elapsed().as_millis() as u64;
+
+            let (result, candidate_count, recommendation_count) = match served {
+                Ok((response, diagnostics)) => {
+                    let recommendation_count = response.recommendations.len();
+                    (Ok(response), diagnostics.candidate_count, recommendation_count)
+                }
                 Err(e) => {
                     error!("Failed to get recommendations for user {}: {}", request.user_id, e);
                     self.increment_stat("failed_requests").await;
+                    (Err(e), 0, 0)
                 }
-            }
+            };
+            results.push(BatchServeResult {
+                request: request.clone(),
+                result,
+                latency_ms,
+                candidate_count,
+                recommendation_count,
+            });
         }
         
         let total_latency = start_time.elapsed().as_millis() as u64;
-        self.update_latency_stat(total_latency).await;
-        
-        info!("Batch served {} recommendations in {}ms", responses.len(), total_latency);
-        Ok(responses)
+        let succeeded = results.iter().filter(|result| result.result.is_ok()).count();
+        info!("Batch served {} of {} recommendation requests in {}ms", succeeded, results.len(), total_latency);
+        results
     }
 
     pub async fn get_similar_users(&self, user_id: Uuid, top_k: usize) -> Result<Vec<(Uuid, f32)>> {
@@ -117,7 +189,7 @@ impl ServingService {
         match (user_profile, item_feature) {
             (Some(user), Some(item)) => {
                 // Calculate cosine similarity as prediction score
-                let score = crate::utils::cosine_similarity(&user.embedding, &item.embedding);
+                let score = self.similarity(&user.embedding, &item.embedding);
                 Ok(score)
             }
             _ => Ok(0.0),
@@ -125,18 +197,48 @@ impl ServingService {
     }
 
     pub async fn get_trending_items(&self, category: Option<String>, top_k: usize) -> Result<Vec<RecommendationItem>> {
-        // This is a simplified implementation
-        // In a real system, you would track item popularity and trends
-        let mut trending_items = Vec::new();
-        
-        // For demonstration, we'll return some mock trending items
-        for i in 0..top_k {
-            trending_items.push(RecommendationItem {
-                item_id: Uuid::new_v4(),
-                score: 0.9 - (i as f32 * 0.1),
-                reason: "Trending item".to_string(),
-                category: category.clone().unwrap_or_else(|| "general".to_string()),
-            });
+        let popular_items = self.vector_db
+            .search_popular_items(category.as_deref(), top_k, self.config.recommendation.popularity_confidence_z)
+            .await;
+        self.trending_items(popular_items, "Trending item").await
+    }
+
+    /// Trending among interactions at `context`'s hour of day and region, or global trending when
+    /// that slice has too little data.
+    pub async fn get_trending_items_in_context(
+        &self,
+        category: Option<String>,
+        context: &TrendingContext,
+        top_k: usize,
+    ) -> Result<Vec<RecommendationItem>> {
+        let popular_items = self.vector_db
+            .search_popular_items_in_context(
+                category.as_deref(),
+                context,
+                top_k,
+                self.config.recommendation.popularity_confidence_z,
+            )
+            .await;
+        match popular_items {
+            Some(popular_items) if !context.is_global() => {
+                self.trending_items(popular_items, "Trending in your context").await
+            }
+            _ => self.get_trending_items(category, top_k).await,
+        }
+    }
+
+    async fn trending_items(&self, popular_items: Vec<(Uuid, f32)>, reason: &str) -> Result<Vec<RecommendationItem>> {
+        let mut trending_items = Vec::with_capacity(popular_items.len());
+        for (item_id, score) in popular_items {
+            if let Some(item_feature) = self.vector_db.get_item_feature(item_id).await? {
+                trending_items.push(RecommendationItem {
+                    item_id,
+                    score,
+                    reason: Some(reason.to_string()),
+                    category: item_feature.category,
+                    relaxed_filter: None,
+                });
+            }
         }
         
         Ok(trending_items)
@@ -147,20 +249,29 @@ impl ServingService {
         let user_profile = self.vector_db.get_user_profile(user_id).await?;
         
         if let Some(profile) = user_profile {
-            // Find items similar to user's preferences
+            // Find items similar to user's preferences, then favour the ones seeing a surge of
+            // interactions: score = recent velocity x similarity
             let similar_items = self.vector_db
                 .search_similar_items(&profile.embedding, top_k * 2)
                 .await?;
+
+            let mut scored_items = Vec::with_capacity(similar_items.len());
+            for (item_id, similarity) in similar_items {
+                let velocity = self.vector_db.get_item_velocity(item_id).await;
+                scored_items.push((item_id, (velocity * similarity.max(0.0) as f64) as f32));
+            }
+            scored_items.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
             
             let mut personalized_trending = Vec::new();
             
-            for (item_id, score) in similar_items.into_iter().take(top_k) {
+            for (item_id, score) in scored_items.into_iter().take(top_k) {
                 if let Some(item_feature) = self.vector_db.get_item_feature(item_id).await? {
                     personalized_trending.push(RecommendationItem {
                         item_id,
                         score,
-                        reason: format!("Personalized trending (score: {:.3})", score),
+                        reason: Some(format!("Personalized trending (score: {:.3})", score)),
                         category: item_feature.category,
+                        relaxed_filter: None,
                     });
                 }
             }
@@ -173,6 +284,7 @@ impl ServingService {
     }
 
     pub async fn update_model_parameters(&self, parameters: ModelParameters) -> Result<()> {
+        self.recommendation_service.load_model_parameters(&parameters).await?;
         {
             let mut model_params = self.model_parameters.write().await;
             *model_params = Some(parameters);
@@ -192,10 +304,7 @@ impl ServingService {
         let mut health = HashMap::new();
         
         // Check vector database connection
-        let vector_db_healthy = match self.vector_db.get_user_profile(Uuid::new_v4()).await {
-            Ok(_) => true,
-            Err(_) => false,
-        };
+        let vector_db_healthy = self.vector_db.get_user_profile(Uuid::new_v4()).await.is_ok();
         
         health.insert("vector_db".to_string(), serde_json::Value::Bool(vector_db_healthy));
         health.insert("model_loaded".to_string(), 
@@ -210,6 +319,78 @@ impl ServingService {
         self.serving_stats.iter().map(|entry| (entry.key().clone(), *entry.value())).collect()
     }
 
+    async fn record_served_items(&self, response: &RecommendationResponse) {
+        if response.recommendations.is_empty() {
+            return;
+        }
+        let mut served_items = self.served_items.write().await;
+        for item in &response.recommendations {
+            served_items.insert(item.item_id);
+        }
+    }
+
+    /// Share of the indexed items recommended at least once, to spot parts of the catalog that
+    /// are never surfaced.
+    pub async fn get_catalog_coverage(&self) -> CatalogCoverage {
+        let catalog_items = self.vector_db.index_stats().await.items.vector_count as u64;
+        // The estimate can overshoot, and deleted items stay counted
+        let distinct_items_served = self.served_items.read().await.estimate().min(catalog_items);
+        let coverage = if catalog_items == 0 {
+            0.0
+        } else {
+            distinct_items_served as f64 / catalog_items as f64
+        };
+        CatalogCoverage { distinct_items_served, catalog_items, coverage }
+    }
+
+    /// Observes the top and mean score of `response`. Empty responses have neither and are skipped.
+    pub async fn record_score_distribution(&self, response: &RecommendationResponse) {
+        let Some(top_score) = response.recommendations.iter().map(|item| item.score).reduce(f32::max) else {
+            return;
+        };
+        let mean_score = response.recommendations.iter().map(|item| item.score as f64).sum::<f64>()
+            / response.recommendations.len() as f64;
+
+        let mut histograms = self.score_histograms.write().await;
+        histograms.top_score.observe(top_score as f64);
+        histograms.mean_score.observe(mean_score);
+    }
+
+    pub async fn get_score_histograms(&self) -> ScoreHistograms {
+        self.score_histograms.read().await.clone()
+    }
+
+    /// Serving stats and catalog coverage as gauges and the score histograms, in the Prometheus
+    /// text exposition format.
+    pub async fn render_metrics(&self) -> String {
+        let mut stats: Vec<(String, u64)> = self.get_serving_stats().await.into_iter().collect();
+        stats.sort();
+        let mut text = String::new();
+        for (key, value) in stats {
+            text.push_str(&format!("# TYPE milvuso_serving_{key} gauge\nmilvuso_serving_{key} {value}\n"));
+        }
+
+        let coverage = self.get_catalog_coverage().await;
+        for (key, value) in [
+            ("distinct_items_served", coverage.distinct_items_served as f64),
+            ("items", coverage.catalog_items as f64),
+            ("coverage", coverage.coverage),
+        ] {
+            text.push_str(&format!("# TYPE milvuso_catalog_{key} gauge\nmilvuso_catalog_{key} {value}\n"));
+        }
+
+        let histograms = self.get_score_histograms().await;
+        text.push_str(&histograms.top_score.render(
+            "milvuso_recommendation_top_score",
+            "Score of the top item of each served recommendation.",
+        ));
+        text.push_str(&histograms.mean_score.render(
+            "milvuso_recommendation_mean_score",
+            "Mean item score of each served recommendation.",
+        ));
+        text
+    }
+
     async fn increment_stat(&self, key: &str) {
         let mut counter = self.serving_stats.entry(key.to_string()).or_insert(0);
         *counter += 1;
@@ -236,12 +417,7 @@ impl ServingService {
     }
 
     pub async fn get_user_recommendations_with_explanation(&self, user_id: Uuid, num_recommendations: usize) -> Result<Vec<(RecommendationItem, String)>> {
-        let request = RecommendationRequest {
-            user_id,
-            num_recommendations,
-            filter_categories: None,
-            exclude_items: None,
-        };
+        let request = RecommendationRequest::new(user_id, num_recommendations);
         
         let response = self.serve_recommendations(&request).await?;
         
@@ -255,13 +431,21 @@ impl ServingService {
         Ok(explained_recommendations)
     }
 
+    fn similarity(&self, a: &[f32], b: &[f32]) -> f32 {
+        if self.config.milvus.f64_accumulation {
+            crate::utils::cosine_similarity_f64(a, b)
+        } else {
+            crate::utils::cosine_similarity(a, b)
+        }
+    }
+
     async fn generate_explanation(&self, user_id: &Uuid, item: &RecommendationItem) -> Result<String> {
         let user_profile = self.vector_db.get_user_profile(*user_id).await?;
         let item_feature = self.vector_db.get_item_feature(item.item_id).await?;
         
         match (user_profile, item_feature) {
             (Some(user), Some(item_feat)) => {
-                let similarity = crate::utils::cosine_similarity(&user.embedding, &item_feat.embedding);
+                let similarity = self.similarity(&user.embedding, &item_feat.embedding);
                 
                 let explanation = if similarity > 0.8 {
                     format!("Highly recommended based on your preferences in {} category", item_feat.category)
@@ -1,16 +1,41 @@
 use crate::config::Config;
 use crate::models::*;
-use crate::services::{vector_db::VectorDbService, kafka::KafkaProducer};
+use crate::services::{vector_db::VectorDbService, kafka::{KafkaConsumer, KafkaProducer, MessageOffset}};
+use crate::services::model_store::{expired_checkpoints, FileModelStore, InMemoryModelStore, ModelStore};
 use crate::algorithms::{CollaborativeFiltering, RecommendationAlgorithm};
-use anyhow::Result;
+use crate::algorithms::feature_importance::{context_importance, FeatureImportance};
+use crate::utils::snapshot::SnapshotCodec;
+use anyhow::{anyhow, Result};
 use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::{RwLock, Semaphore, mpsc};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 use chrono::Utc;
 use tracing::{info, error, warn};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::{Duration, Instant};
 
+/// Fixed so repeated importance reports over the same buffer agree.
+const CONTEXT_IMPORTANCE_SEED: u64 = 0;
+
+/// What the batch training worker receives: an example, and its offset when it must be acked.
+trait TrainingDelivery: Send + 'static {
+    fn into_parts(self) -> (TrainingExample, Option<MessageOffset>);
+}
+
+impl TrainingDelivery for TrainingExample {
+    fn into_parts(self) -> (TrainingExample, Option<MessageOffset>) {
+        (self, None)
+    }
+}
+
+impl TrainingDelivery for (TrainingExample, MessageOffset) {
+    fn into_parts(self) -> (TrainingExample, Option<MessageOffset>) {
+        (self.0, Some(self.1))
+    }
+}
+
 pub struct TrainingService {
     vector_db: Arc<VectorDbService>,
     kafka_producer: Arc<KafkaProducer>,
@@ -18,6 +43,12 @@ pub struct TrainingService {
     config: Arc<Config>,
     training_buffer: Arc<RwLock<Vec<TrainingExample>>>,
     last_model_save: Arc<RwLock<Instant>>,
+    trained_examples: Arc<AtomicU64>,
+    batch_permits: Arc<Semaphore>,
+    largest_augmented_batch: Arc<AtomicUsize>,
+    model_store: Arc<dyn ModelStore>,
+    /// Checkpoint currently being served, which rotation never deletes.
+    active_version: Arc<RwLock<Option<String>>>,
 }
 
 impl TrainingService {
@@ -33,6 +64,11 @@ impl TrainingService {
                 0.01, // regularization
             )
         ));
+        let batch_permits = Arc::new(Semaphore::new(config.training.max_in_flight_batches.max(1)));
+        let model_store: Arc<dyn ModelStore> = match &config.training.model_dir {
+            Some(model_dir) => Arc::new(FileModelStore::open(model_dir)?),
+            None => Arc::new(InMemoryModelStore::new()),
+        };
 
         Ok(Self {
             vector_db,
@@ -41,27 +77,62 @@ impl TrainingService {
             config,
             training_buffer: Arc::new(RwLock::new(Vec::new())),
             last_model_save: Arc::new(RwLock::new(Instant::now())),
+            trained_examples: Arc::new(AtomicU64::new(0)),
+            batch_permits,
+            largest_augmented_batch: Arc::new(AtomicUsize::new(0)),
+            model_store,
+            active_version: Arc::new(RwLock::new(None)),
         })
     }
 
+    pub fn with_model_store(mut self, model_store: Arc<dyn ModelStore>) -> Self {
+        self.model_store = model_store;
+        self
+    }
+
+    pub fn model_store(&self) -> Arc<dyn ModelStore> {
+        self.model_store.clone()
+    }
+
+    /// Marks the checkpoint the serving side runs on, protecting it from rotation.
+    pub async fn set_active_version(&self, version: Option<String>) {
+        *self.active_version.write().await = version;
+    }
+
+    pub async fn active_version(&self) -> Option<String> {
+        self.active_version.read().await.clone()
+    }
+
+    /// Shares the count of trained examples with the serving side, which uses it for its model-ready gate.
+    pub fn with_trained_examples_counter(mut self, counter: Arc<AtomicU64>) -> Self {
+        self.trained_examples = counter;
+        self
+    }
+
     pub async fn start_training_worker(&self) -> Result<()> {
-        let (tx, rx) = mpsc::channel::<TrainingExample>(1000);
-        
         // Start Kafka consumer for training examples
-        let kafka_consumer = crate::services::kafka::KafkaConsumer::new(&self.config)?;
-        let consumer_tx = tx.clone();
-        
-        tokio::spawn(async move {
-            if let Err(e) = kafka_consumer.consume_training_examples(consumer_tx).await {
-                error!("Training example consumer error: {}", e);
-            }
-        });
-
-        // Start batch training worker
-        let training_service = self.clone();
-        tokio::spawn(async move {
-            training_service.batch_training_worker(rx).await;
-        });
+        if self.config.kafka.enable_auto_commit {
+            let (tx, rx) = mpsc::channel::<TrainingExample>(1000);
+            let kafka_consumer = KafkaConsumer::new(&self.config)?
+                .with_dead_letter_sink(self.kafka_producer.clone());
+            tokio::spawn(async move {
+                if let Err(e) = kafka_consumer.consume_training_examples(tx).await {
+                    error!("Training example consumer error: {}", e);
+                }
+            });
+            self.spawn_batch_training_worker(rx);
+        } else {
+            let (tx, rx) = mpsc::channel::<(TrainingExample, MessageOffset)>(1000);
+            let (ack_tx, ack_rx) = mpsc::unbounded_channel();
+            let kafka_consumer = KafkaConsumer::new_manual_commit(&self.config)?
+                .with_dead_letter_sink(self.kafka_producer.clone());
+            tokio::spawn(async move {
+                if let Err(e) = kafka_consumer.consume_training_examples_manual(tx, ack_rx).await {
+                    error!("Training example consumer error: {}", e);
+                }
+            });
+            self.spawn_acked_batch_training_worker(rx, ack_tx);
+        }
 
         // Start model saving worker
         let saving_service = self.clone();
@@ -73,25 +144,51 @@ impl TrainingService {
         Ok(())
     }
 
-    async fn batch_training_worker(&self, mut rx: mpsc::Receiver<TrainingExample>) {
+    /// Spawns the worker that batches examples from `rx` and trains on them. The returned handle
+    /// completes once the channel is closed and every in-flight batch has finished.
+    pub fn spawn_batch_training_worker(&self, rx: mpsc::Receiver<TrainingExample>) -> JoinHandle<()> {
+        let training_service = self.clone();
+        tokio::spawn(async move {
+            training_service.batch_training_worker(rx, None).await;
+        })
+    }
+
+    /// Like `spawn_batch_training_worker`, but sends the offsets of each batch back on `acks` once
+    /// it has been trained. A batch that fails to train is never acked.
+    pub fn spawn_acked_batch_training_worker(
+        &self,
+        rx: mpsc::Receiver<(TrainingExample, MessageOffset)>,
+        acks: mpsc::UnboundedSender<Vec<MessageOffset>>,
+    ) -> JoinHandle<()> {
+        let training_service = self.clone();
+        tokio::spawn(async move {
+            training_service.batch_training_worker(rx, Some(acks)).await;
+        })
+    }
+
+    async fn batch_training_worker<D: TrainingDelivery>(
+        &self,
+        mut rx: mpsc::Receiver<D>,
+        acks: Option<mpsc::UnboundedSender<Vec<MessageOffset>>>,
+    ) {
         let mut batch = Vec::new();
+        let mut offsets = Vec::new();
         let batch_timeout = Duration::from_secs(30);
         let mut last_batch_time = Instant::now();
 
         loop {
             tokio::select! {
-                example = rx.recv() => {
-                    match example {
-                        Some(example) => {
+                delivery = rx.recv() => {
+                    match delivery {
+                        Some(delivery) => {
+                            let (example, offset) = delivery.into_parts();
                             batch.push(example);
+                            offsets.extend(offset);
                             
                             // Process batch if it's full or timeout reached
                             if batch.len() >= self.config.training.batch_size || 
                                last_batch_time.elapsed() > batch_timeout {
-                                if let Err(e) = self.process_training_batch(&batch).await {
-                                    error!("Failed to process training batch: {}", e);
-                                }
-                                batch.clear();
+                                self.dispatch_training_batch(std::mem::take(&mut batch), std::mem::take(&mut offsets), acks.clone()).await;
                                 last_batch_time = Instant::now();
                             }
                         }
@@ -103,15 +200,54 @@ impl TrainingService {
                 }
                 _ = tokio::time::sleep(batch_timeout) => {
                     if !batch.is_empty() {
-                        if let Err(e) = self.process_training_batch(&batch).await {
-                            error!("Failed to process training batch: {}", e);
-                        }
-                        batch.clear();
+                        self.dispatch_training_batch(std::mem::take(&mut batch), std::mem::take(&mut offsets), acks.clone()).await;
                         last_batch_time = Instant::now();
                     }
                 }
             }
         }
+
+        if !batch.is_empty() {
+            self.dispatch_training_batch(batch, offsets, acks).await;
+        }
+
+        // Wait for in-flight batches by taking every permit back
+        let permits = self.config.training.max_in_flight_batches.max(1) as u32;
+        if let Ok(all) = self.batch_permits.acquire_many(permits).await {
+            drop(all);
+        }
+    }
+
+    /// Hands a batch to a background task once one of the `max_in_flight_batches` slots is free.
+    /// Waiting here leaves further examples queued in the channel rather than in memory.
+    async fn dispatch_training_batch(
+        &self,
+        batch: Vec<TrainingExample>,
+        offsets: Vec<MessageOffset>,
+        acks: Option<mpsc::UnboundedSender<Vec<MessageOffset>>>,
+    ) {
+        let permit = match self.batch_permits.clone().acquire_owned().await {
+            Ok(permit) => permit,
+            Err(e) => {
+                error!("Training batch semaphore closed: {}", e);
+                return;
+            }
+        };
+
+        let training_service = self.clone();
+        tokio::spawn(async move {
+            match training_service.process_training_batch(&batch).await {
+                Ok(()) => {
+                    if let Some(acks) = acks.filter(|_| !offsets.is_empty()) {
+                        if acks.send(offsets).is_err() {
+                            warn!("Training offset acks dropped, consumer is gone");
+                        }
+                    }
+                }
+                Err(e) => error!("Failed to process training batch: {}", e),
+            }
+            drop(permit);
+        });
     }
 
     async fn process_training_batch(&self, examples: &[TrainingExample]) -> Result<()> {
@@ -121,62 +257,112 @@ impl TrainingService {
 
         info!("Processing training batch of {} examples", examples.len());
 
-        // Add negative sampling
-        let augmented_examples = self.add_negative_samples(examples).await?;
+        for chunk in self.split_for_augmentation(examples) {
+            // Add negative sampling
+            let augmented_examples = self.add_negative_samples(chunk).await?;
+            self.train_augmented_batch(&augmented_examples).await?;
+        }
+
+        info!("Completed training batch processing");
+        Ok(())
+    }
+
+    fn negatives_per_positive(&self) -> usize {
+        let cap = self.config.training.max_augmented_batch_size.max(1);
+        (self.config.training.negative_sampling_ratio as usize).min(5).min(cap - 1)
+    }
+
+    /// Splits `examples` so that none of the chunks grows past `max_augmented_batch_size` once
+    /// negatives are added.
+    fn split_for_augmentation<'a>(&self, examples: &'a [TrainingExample]) -> Vec<&'a [TrainingExample]> {
+        let cap = self.config.training.max_augmented_batch_size.max(1);
+        let negatives = self.negatives_per_positive();
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut augmented_len = 0;
+        for (i, example) in examples.iter().enumerate() {
+            let size = if example.label > 0.5 { 1 + negatives } else { 1 };
+            if augmented_len + size > cap {
+                chunks.push(&examples[start..i]);
+                start = i;
+                augmented_len = 0;
+            }
+            augmented_len += size;
+        }
+        if start < examples.len() {
+            chunks.push(&examples[start..]);
+        }
+        chunks
+    }
+
+    async fn train_augmented_batch(&self, augmented_examples: &[TrainingExample]) -> Result<()> {
+        self.largest_augmented_batch.fetch_max(augmented_examples.len(), Ordering::Relaxed);
 
         // Train the algorithm
         {
             let mut algorithm = self.algorithm.write().await;
-            algorithm.train(&augmented_examples).await?;
+            algorithm.train(augmented_examples).await?;
         }
+        self.trained_examples.fetch_add(augmented_examples.len() as u64, Ordering::Relaxed);
 
         // Update embeddings in vector database
-        self.update_embeddings_from_training(&augmented_examples).await?;
+        self.update_embeddings_from_training(augmented_examples).await?;
 
         // Store training examples for batch processing
         {
             let mut buffer = self.training_buffer.write().await;
-            buffer.extend_from_slice(&augmented_examples);
+            buffer.extend_from_slice(augmented_examples);
         }
 
-        info!("Completed training batch processing");
         Ok(())
     }
 
+    /// Pairs every positive example with up to `negatives_per_positive` real items the user has
+    /// no positive in this batch for, using their stored embeddings. With fewer such items than
+    /// that, each positive gets all of them.
     async fn add_negative_samples(&self, examples: &[TrainingExample]) -> Result<Vec<TrainingExample>> {
         let mut augmented = examples.to_vec();
-        let negative_ratio = self.config.training.negative_sampling_ratio;
-        
-        for example in examples {
-            if example.label > 0.5 { // Only add negatives for positive examples
-                let num_negatives = (negative_ratio as usize).min(5);
-                
-                for _ in 0..num_negatives {
-                    // Generate random negative item
-                    let negative_item_id = Uuid::new_v4();
-                    
-                    // Create negative example
-                    let negative_example = TrainingExample {
+        let num_negatives = self.negatives_per_positive();
+        if num_negatives == 0 {
+            return Ok(augmented);
+        }
+
+        // Positive examples grouped by user, in first-seen order
+        let mut positives_by_user: Vec<(Uuid, Vec<&TrainingExample>)> = Vec::new();
+        for example in examples.iter().filter(|example| example.label > 0.5) {
+            match positives_by_user.iter_mut().find(|(user_id, _)| *user_id == example.user_id) {
+                Some((_, positives)) => positives.push(example),
+                None => positives_by_user.push((example.user_id, vec![example])),
+            }
+        }
+
+        for (_, positives) in positives_by_user {
+            let exclude: HashSet<Uuid> = positives.iter().map(|example| example.item_id).collect();
+            let candidates = self.vector_db.sample_item_features(num_negatives * positives.len(), &exclude).await;
+            if candidates.is_empty() {
+                continue;
+            }
+
+            // Each positive takes the next slice of the sample, wrapping around a short one
+            let per_positive = num_negatives.min(candidates.len());
+            for (i, example) in positives.into_iter().enumerate() {
+                for j in 0..per_positive {
+                    let negative = &candidates[(i * per_positive + j) % candidates.len()];
+                    augmented.push(TrainingExample {
                         user_id: example.user_id,
-                        item_id: negative_item_id,
+                        item_id: negative.item_id,
                         label: 0.0,
                         user_features: example.user_features.clone(),
-                        item_features: self.generate_random_item_features().await?,
+                        item_features: negative.embedding.clone(),
                         context_features: example.context_features.clone(),
                         timestamp: example.timestamp,
-                    };
-                    
-                    augmented.push(negative_example);
+                    });
                 }
             }
         }
-        
-        Ok(augmented)
-    }
 
-    async fn generate_random_item_features(&self) -> Result<Vec<f32>> {
-        use crate::algorithms::initializer::xavier_uniform;
-        Ok(xavier_uniform(self.config.recommendation.embedding_dim))
+        Ok(augmented)
     }
 
     async fn update_embeddings_from_training(&self, examples: &[TrainingExample]) -> Result<()> {
@@ -218,31 +404,32 @@ impl TrainingService {
         }
     }
 
-    async fn save_model_parameters(&self) -> Result<()> {
+    /// Snapshots the current model as a new checkpoint and returns its version.
+    pub async fn save_model_parameters(&self) -> Result<String> {
         let algorithm = self.algorithm.read().await;
         
         // Extract model parameters
-        let mut user_embeddings = Vec::new();
-        let mut item_embeddings = Vec::new();
-        
-        for (_, embedding) in &algorithm.user_embeddings {
-            user_embeddings.push(embedding.as_slice().to_vec());
-        }
-        
-        for (_, embedding) in &algorithm.item_embeddings {
-            item_embeddings.push(embedding.as_slice().to_vec());
-        }
-
+        let user_embeddings = algorithm.user_embeddings
+            .iter()
+            .map(|(user_id, embedding)| (*user_id, embedding.as_slice().to_vec()))
+            .collect();
+        let item_embeddings = algorithm.item_embeddings
+            .iter()
+            .map(|(item_id, embedding)| (*item_id, embedding.as_slice().to_vec()))
+            .collect();
+
+        // Millisecond versions keep saves within the same second from overwriting each other
+        let now = Utc::now();
         let parameters = ModelParameters {
-            version: format!("v{}", Utc::now().timestamp()),
-            user_embedding_weights: user_embeddings,
-            item_embedding_weights: item_embeddings,
+            version: format!("v{}", now.timestamp_millis()),
+            user_embeddings,
+            item_embeddings,
             bias_weights: vec![0.0; self.config.recommendation.embedding_dim],
-            updated_at: Utc::now(),
+            updated_at: now,
         };
+        drop(algorithm);
 
-        // Save to persistent storage (could be HDFS, S3, etc.)
-        self.save_to_persistent_storage(&parameters).await?;
+        self.save_checkpoint(&parameters).await?;
         
         // Update last save time
         {
@@ -251,14 +438,46 @@ impl TrainingService {
         }
 
         info!("Model parameters saved successfully");
+        Ok(parameters.version)
+    }
+
+    /// Stores `parameters` under their version, then rotates older checkpoints out. Rotation only
+    /// runs once the save succeeded, and a failed rotation leaves extra checkpoints behind rather
+    /// than failing the save.
+    pub async fn save_checkpoint(&self, parameters: &ModelParameters) -> Result<()> {
+        self.save_to_persistent_storage(parameters).await?;
+        if let Err(e) = self.rotate_checkpoints().await {
+            warn!("Failed to rotate model checkpoints: {}", e);
+        }
+        Ok(())
+    }
+
+    async fn rotate_checkpoints(&self) -> Result<()> {
+        let checkpoints = self.model_store.list().await?;
+        let active_version = self.active_version().await;
+        let expired = expired_checkpoints(
+            &checkpoints,
+            &self.config.training.checkpoint_retention,
+            active_version.as_deref(),
+        );
+        for version in expired {
+            self.model_store.delete(&version).await?;
+            info!("Rotated out model checkpoint {}", version);
+        }
         Ok(())
     }
 
     async fn save_to_persistent_storage(&self, parameters: &ModelParameters) -> Result<()> {
-        // In a real implementation, this would save to HDFS, S3, or another persistent store
-        // For now, we'll just log the save operation
-        info!("Saving model parameters version: {}", parameters.version);
-        
+        let codec = SnapshotCodec::default();
+        let snapshot = codec.encode(parameters)?;
+        info!(
+            "Saving model parameters version: {} ({} bytes, snapshot format v{})",
+            parameters.version,
+            snapshot.len(),
+            codec.format_version()
+        );
+        self.model_store.save(&parameters.version, parameters.updated_at, snapshot).await?;
+
         // Create batch training data
         let training_buffer = self.training_buffer.read().await;
         if !training_buffer.is_empty() {
@@ -274,17 +493,43 @@ impl TrainingService {
         Ok(())
     }
 
+    /// Loads a stored checkpoint into the model and marks it as the active version.
     pub async fn load_model_parameters(&self, version: &str) -> Result<()> {
-        // In a real implementation, this would load from persistent storage
         info!("Loading model parameters version: {}", version);
-        
-        // For now, just initialize with default parameters
-        let _algorithm = self.algorithm.write().await;
-        // algorithm.load_parameters(...);
-        
+        let snapshot = self
+            .model_store
+            .load(version)
+            .await?
+            .ok_or_else(|| anyhow!("Model checkpoint {} not found", version))?;
+        let parameters = SnapshotCodec::default().decode(&snapshot)?;
+        self.algorithm.write().await.update_parameters(&parameters).await?;
+        self.set_active_version(Some(version.to_string())).await;
+
         Ok(())
     }
 
+    /// Model score of a user-item pair, or `None` unless the model has learned both embeddings.
+    pub async fn predict(&self, user_id: Uuid, item_id: Uuid) -> Option<f32> {
+        let algorithm = self.algorithm.read().await;
+        let user_embedding = algorithm.user_embeddings.get(&user_id)?;
+        let item_embedding = algorithm.item_embeddings.get(&item_id)?;
+        Some(user_embedding.dot(item_embedding))
+    }
+
+    /// Permutation importance of the context features over the buffered training examples, on top
+    /// of the current model's scores. Empty until examples have been trained on.
+    pub async fn context_feature_importance(&self) -> Vec<FeatureImportance> {
+        let algorithm = self.algorithm.read().await;
+        let buffer = self.training_buffer.read().await;
+        let base_score = |example: &TrainingExample| {
+            match (algorithm.user_embeddings.get(&example.user_id), algorithm.item_embeddings.get(&example.item_id)) {
+                (Some(user_embedding), Some(item_embedding)) => user_embedding.dot(item_embedding),
+                _ => 0.0,
+            }
+        };
+        context_importance(&buffer, base_score, CONTEXT_IMPORTANCE_SEED)
+    }
+
     pub async fn get_training_stats(&self) -> Result<HashMap<String, serde_json::Value>> {
         let algorithm = self.algorithm.read().await;
         let buffer = self.training_buffer.read().await;
@@ -297,6 +542,10 @@ impl TrainingService {
                     serde_json::Value::Number(algorithm.item_embeddings.len().into()));
         stats.insert("training_buffer_size".to_string(), 
                     serde_json::Value::Number(buffer.len().into()));
+        stats.insert("trained_examples".to_string(), 
+                    serde_json::Value::Number(self.trained_examples.load(Ordering::Relaxed).into()));
+        stats.insert("largest_augmented_batch".to_string(), 
+                    serde_json::Value::Number(self.largest_augmented_batch.load(Ordering::Relaxed).into()));
         stats.insert("last_model_save".to_string(), 
                     serde_json::Value::String(format!("{:?}", *last_save)));
         
@@ -313,6 +562,11 @@ impl Clone for TrainingService {
             config: self.config.clone(),
             training_buffer: self.training_buffer.clone(),
             last_model_save: self.last_model_save.clone(),
+            trained_examples: self.trained_examples.clone(),
+            batch_permits: self.batch_permits.clone(),
+            largest_augmented_batch: self.largest_augmented_batch.clone(),
+            model_store: self.model_store.clone(),
+            active_version: self.active_version.clone(),
         }
     }
 }
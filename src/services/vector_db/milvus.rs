@@ -0,0 +1,324 @@
+use crate::algorithms::retriever::{RetrieverStats, VectorRetriever};
+use crate::config::MilvusConfig;
+use crate::utils::normalize_vector;
+use anyhow::{anyhow, Context, Result};
+use milvus::v2::prelude::*;
+use milvus::v2::{CollectionSchema, DataType, FieldSchema};
+use std::cmp::Ordering as CmpOrdering;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::info;
+use uuid::Uuid;
+
+const ID_FIELD: &str = "id";
+const VECTOR_FIELD: &str = "embedding";
+/// Length of a hyphenated UUID, the primary key.
+const ID_MAX_LENGTH: u32 = 36;
+/// Largest `limit` Milvus accepts for a search.
+const MAX_SEARCH_LIMIT: usize = 16_384;
+
+/// How a collection's native score maps to the cosine similarity `InMemoryRetriever` returns.
+/// `IP` and `L2` collections store unit-length vectors, which makes the mapping exact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScoreMapping {
+    Cosine,
+    InnerProduct,
+    /// Milvus returns the squared distance, which is `2 - 2 cos` between unit vectors.
+    SquaredL2,
+}
+
+impl ScoreMapping {
+    fn parse(metric_type: &str) -> Result<(Self, MetricType)> {
+        match metric_type.to_ascii_uppercase().as_str() {
+            "COSINE" => Ok((Self::Cosine, MetricType::Cosine)),
+            "IP" => Ok((Self::InnerProduct, MetricType::Ip)),
+            "L2" => Ok((Self::SquaredL2, MetricType::L2)),
+            other => Err(anyhow!("Unsupported Milvus metric type {}; use COSINE, IP or L2", other)),
+        }
+    }
+
+    fn stores_unit_vectors(self) -> bool {
+        self != Self::Cosine
+    }
+
+    fn to_cosine(self, score: f32) -> f32 {
+        match self {
+            Self::Cosine | Self::InnerProduct => score,
+            Self::SquaredL2 => 1.0 - score / 2.0,
+        }
+    }
+}
+
+fn parse_index_type(index_type: &str) -> Result<IndexType> {
+    match index_type.to_ascii_uppercase().as_str() {
+        "FLAT" => Ok(IndexType::Flat),
+        "IVF_FLAT" => Ok(IndexType::IvfFlat),
+        "IVF_SQ8" => Ok(IndexType::IvfSq8),
+        "IVF_PQ" => Ok(IndexType::IvfPq),
+        "HNSW" => Ok(IndexType::Hnsw),
+        "DISKANN" => Ok(IndexType::DiskAnn),
+        "SCANN" => Ok(IndexType::Scann),
+        "AUTOINDEX" => Ok(IndexType::AutoIndex),
+        other => Err(anyhow!("Unsupported Milvus index type {}", other)),
+    }
+}
+
+/// Vector index kept in a Milvus collection, keyed by the id's string form. Searches use strong
+/// consistency so a vector is searchable as soon as its write returns, as with the in-memory index.
+pub struct MilvusRetriever {
+    client: ClientV2,
+    collection: String,
+    dimension: usize,
+    score_mapping: ScoreMapping,
+    /// Row count at connect time adjusted by this instance's writes. Approximate: an upsert of an
+    /// existing id counts as an add, and other instances' writes are not seen.
+    vector_count: AtomicUsize,
+}
+
+impl MilvusRetriever {
+    /// Connects to `config.host`, creating `collection` with the configured dimension, index and
+    /// metric if it does not exist yet, and loads it for search.
+    pub async fn connect(config: &MilvusConfig, collection: &str) -> Result<Self> {
+        let uri = format!("http://{}:{}", config.host, config.port);
+        let mut connect_config = ConnectConfig::new().uri(uri.as_str());
+        if let Some(token) = &config.token {
+            connect_config = connect_config.token(token.as_str());
+        }
+        let client = ClientV2::new(&connect_config)
+            .await
+            .with_context(|| format!("Failed to connect to Milvus at {}", uri))?;
+
+        let (score_mapping, metric_type) = ScoreMapping::parse(&config.metric_type)?;
+        let index_type = parse_index_type(&config.index_type)?;
+        let exists = client
+            .has_collection(HasCollectionRequest::builder().collection_name(collection).build()?)
+            .await?
+            .exists();
+        if !exists {
+            let schema = CollectionSchema::new()
+                .add_field(
+                    FieldSchema::new()
+                        .name(ID_FIELD)
+                        .data_type(DataType::VarChar)
+                        .max_length(ID_MAX_LENGTH)
+                        .primary_key(true),
+                )
+                .add_field(
+                    FieldSchema::new()
+                        .name(VECTOR_FIELD)
+                        .data_type(DataType::FloatVector)
+                        .dimension(config.dimension as u32),
+                );
+            client
+                .create_collection(
+                    CreateCollectionRequest::builder()
+                        .collection_name(collection)
+                        .schema(schema)
+                        .index_param(
+                            IndexParam::new()
+                                .field_name(VECTOR_FIELD)
+                                .index_type(index_type)
+                                .metric_type(metric_type),
+                        )
+                        .build()?,
+                )
+                .await?;
+            info!(
+                "Created Milvus collection {} ({} dims, {}, {})",
+                collection, config.dimension, config.index_type, config.metric_type
+            );
+        }
+        client
+            .load_collection(LoadCollectionRequest::builder().collection_name(collection).build()?)
+            .await?;
+
+        let row_count = client
+            .get_collection_stats(GetCollectionStatsRequest::builder().collection_name(collection).build()?)
+            .await?
+            .statistics()
+            .get("row_count")
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0);
+        info!("Connected to Milvus collection {} with {} vectors", collection, row_count);
+
+        Ok(Self {
+            client,
+            collection: collection.to_string(),
+            dimension: config.dimension,
+            score_mapping,
+            vector_count: AtomicUsize::new(row_count),
+        })
+    }
+
+    fn check_dimension(&self, vector: &[f32]) -> Result<()> {
+        if vector.len() != self.dimension {
+            return Err(anyhow!("Vector dimension mismatch"));
+        }
+        Ok(())
+    }
+
+    fn to_stored(&self, mut vector: Vec<f32>) -> Vec<f32> {
+        if self.score_mapping.stores_unit_vectors() {
+            normalize_vector(&mut vector);
+        }
+        vector
+    }
+
+    async fn upsert(&self, id: Uuid, vector: Vec<f32>) -> Result<()> {
+        let row = serde_json::json!({ ID_FIELD: id.to_string(), VECTOR_FIELD: vector });
+        let insert = InsertRequest::builder().collection_name(self.collection.as_str()).rows(vec![row]).build()?;
+        self.client.upsert(UpsertRequest::builder().insert(insert).build()?).await?;
+        Ok(())
+    }
+
+    async fn get_stored_vector(&self, id: Uuid) -> Result<Option<Vec<f32>>> {
+        let response = self.client
+            .get(
+                GetRequest::builder()
+                    .collection_name(self.collection.as_str())
+                    .ids(Ids::VarChar(vec![id.to_string()]))
+                    .output_fields([VECTOR_FIELD])
+                    .build()?,
+            )
+            .await?;
+        let mut rows = response.results().rows()?;
+        match rows.next() {
+            Some(row) => Ok(Some(row.get_float_vector(VECTOR_FIELD)?.to_vec())),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorRetriever for MilvusRetriever {
+    async fn search_similar(&self, query_vector: &[f32], top_k: usize) -> Result<Vec<(Uuid, f32)>> {
+        let mut results = self.search_similar_batch(&[query_vector], top_k).await?;
+        Ok(results.pop().unwrap_or_default())
+    }
+
+    /// Sends every query in one search request. A zero query matches nothing, since it has no
+    /// cosine similarity to anything.
+    async fn search_similar_batch(&self, queries: &[&[f32]], top_k: usize) -> Result<Vec<Vec<(Uuid, f32)>>> {
+        for query in queries {
+            if query.len() != self.dimension {
+                return Err(anyhow!("Query vector dimension mismatch"));
+            }
+        }
+        let searched: Vec<usize> = (0..queries.len())
+            .filter(|&i| queries[i].iter().any(|x| *x != 0.0))
+            .collect();
+        let mut results = vec![Vec::new(); queries.len()];
+        if searched.is_empty() || top_k == 0 {
+            return Ok(results);
+        }
+
+        let vectors = searched.iter().map(|&i| self.to_stored(queries[i].to_vec())).collect();
+        let response = self.client
+            .search(
+                SearchRequest::builder()
+                    .collection_name(self.collection.as_str())
+                    .vector_field(VECTOR_FIELD)
+                    .vectors(SearchVectors::Float(vectors))
+                    .limit(top_k.min(MAX_SEARCH_LIMIT) as i64)
+                    .consistency_level(ConsistencyLevel::Strong)
+                    .build()?,
+            )
+            .await?;
+
+        for (&i, result) in searched.iter().zip(response.results().iter()) {
+            let Ids::VarChar(ids) = result.get_ids() else {
+                return Err(anyhow!("Milvus collection {} does not have string ids", self.collection));
+            };
+            let mut hits = Vec::with_capacity(ids.len());
+            for (id, score) in ids.iter().zip(result.get_scores()) {
+                hits.push((Uuid::parse_str(id)?, self.score_mapping.to_cosine(*score)));
+            }
+            // Same order as the in-memory index, including its tie-break by id
+            hits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(CmpOrdering::Equal).then_with(|| a.0.cmp(&b.0)));
+            results[i] = hits;
+        }
+        Ok(results)
+    }
+
+    async fn add_vector(&mut self, id: Uuid, vector: Vec<f32>) -> Result<()> {
+        self.check_dimension(&vector)?;
+        self.upsert(id, self.to_stored(vector)).await?;
+        self.vector_count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn remove_vector(&mut self, id: Uuid) -> Result<()> {
+        self.client
+            .delete(
+                DeleteRequest::builder()
+                    .collection_name(self.collection.as_str())
+                    .ids(Ids::VarChar(vec![id.to_string()]))
+                    .build()?,
+            )
+            .await?;
+        let _ = self.vector_count.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| count.checked_sub(1));
+        Ok(())
+    }
+
+    async fn update_vector(&mut self, id: Uuid, vector: Vec<f32>) -> Result<()> {
+        self.check_dimension(&vector)?;
+        self.upsert(id, self.to_stored(vector)).await
+    }
+
+    /// Reads the stored vector back, applies the deltas and writes it again. In `IP` and `L2`
+    /// collections the stored vector is the normalized one.
+    async fn update_vector_sparse(&mut self, id: Uuid, deltas: &[(usize, f32)]) -> Result<()> {
+        let mut vector = self
+            .get_stored_vector(id)
+            .await?
+            .ok_or_else(|| anyhow!("Vector not found: {}", id))?;
+        if let Some((index, _)) = deltas.iter().find(|(index, _)| *index >= vector.len()) {
+            return Err(anyhow!("Sparse update index {} out of range for dimension {}", index, vector.len()));
+        }
+        for &(index, delta) in deltas {
+            vector[index] += delta;
+        }
+        self.upsert(id, self.to_stored(vector)).await
+    }
+
+    fn is_shared(&self) -> bool {
+        true
+    }
+
+    fn stats(&self) -> RetrieverStats {
+        // The vectors live in Milvus, not in this process
+        RetrieverStats {
+            estimated_bytes: 0,
+            ..RetrieverStats::flat("milvus", self.vector_count.load(Ordering::Relaxed), self.dimension)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::cosine_similarity;
+
+    #[test]
+    fn test_scores_map_to_cosine_similarity() {
+        let mut a = vec![1.0, 2.0, -0.5, 3.0];
+        let mut b = vec![0.5, -1.0, 2.0, 1.0];
+        let cosine = cosine_similarity(&a, &b);
+        normalize_vector(&mut a);
+        normalize_vector(&mut b);
+        let squared_l2: f32 = a.iter().zip(&b).map(|(x, y)| (x - y).powi(2)).sum();
+        let inner_product: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+
+        assert!((ScoreMapping::SquaredL2.to_cosine(squared_l2) - cosine).abs() < 1e-5);
+        assert!((ScoreMapping::InnerProduct.to_cosine(inner_product) - cosine).abs() < 1e-5);
+        assert_eq!(ScoreMapping::Cosine.to_cosine(cosine), cosine);
+    }
+
+    #[test]
+    fn test_parse_metric_and_index_types() {
+        assert_eq!(ScoreMapping::parse("l2").unwrap().0, ScoreMapping::SquaredL2);
+        assert_eq!(ScoreMapping::parse("COSINE").unwrap().0, ScoreMapping::Cosine);
+        assert!(ScoreMapping::parse("HAMMING").is_err());
+        assert!(matches!(parse_index_type("ivf_flat").unwrap(), IndexType::IvfFlat));
+        assert!(parse_index_type("BTREE").is_err());
+    }
+}
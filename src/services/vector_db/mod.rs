@@ -1,116 +1,1430 @@
-use crate::config::Config;
+use crate::config::{Config, DimensionAdapt};
 use crate::models::*;
-use crate::algorithms::retriever::{InMemoryRetriever, VectorRetriever};
-use anyhow::Result;
-use std::collections::HashMap;
+use crate::services::metadata::{open_metadata_store, MetadataStore};
+use crate::algorithms::clustering::{k_means, nearest_centroid};
+use crate::algorithms::projection::ProjectionMatrix;
+use crate::algorithms::retriever::{DistanceMetric, IVFRetriever, InMemoryRetriever, RetrieverStats, VectorRetriever};
+use self::milvus::MilvusRetriever;
+use serde::Serialize;
+use crate::utils::{euclidean_distance, jaccard_similarity, normalize_vector, wilson_lower_bound};
+use crate::utils::validation::{adapt_embedding_dimension, validate_embedding_dimension};
+use anyhow::{anyhow, Result};
+use rand::seq::{IteratorRandom, SliceRandom};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::info;
+use tracing::{info, warn};
 use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+pub mod milvus;
+
+/// Why an insert was refused under its `InsertMode`.
+#[derive(Debug, thiserror::Error)]
+pub enum InsertConflict {
+    #[error("Item {0} already exists")]
+    Exists(Uuid),
+    #[error("Item {0} does not exist")]
+    Missing(Uuid),
+}
+
+/// An item embedding refused for lying too far from the catalog's embedding distribution.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Item {item_id} embedding is an outlier: norm z-score {norm_z:.2} (max {norm_z_threshold}), \
+     distance-to-mean z-score {distance_z:.2} (max {distance_z_threshold})"
+)]
+pub struct OutlierRejected {
+    pub item_id: Uuid,
+    pub norm_z: f64,
+    pub norm_z_threshold: f32,
+    pub distance_z: f64,
+    pub distance_z_threshold: f32,
+}
+
+/// A write sent to a read-only replica.
+#[derive(Debug, thiserror::Error)]
+#[error("Vector database is a read-only replica; send writes to the writer instance")]
+pub struct ReadOnlyReplica;
 
 pub struct VectorDbService {
-    user_retriever: Arc<RwLock<InMemoryRetriever>>,
-    item_retriever: Arc<RwLock<InMemoryRetriever>>,
+    /// In memory, or Milvus collections when `milvus.host` is set.
+    user_retriever: Arc<RwLock<Box<dyn VectorRetriever>>>,
+    item_retriever: Arc<RwLock<Box<dyn VectorRetriever>>>,
+    /// One retriever per non-current item embedding version, so versions never overwrite each other.
+    versioned_item_retrievers: Arc<RwLock<HashMap<String, InMemoryRetriever>>>,
     user_profiles: Arc<RwLock<HashMap<Uuid, UserProfile>>>,
     item_features: Arc<RwLock<HashMap<Uuid, ItemFeature>>>,
+    item_embedding_stats: Arc<RwLock<EmbeddingStats>>,
+    quarantined_items: Arc<RwLock<HashMap<Uuid, ItemFeature>>>,
+    /// Expiry of every item that has one, so searches can skip expired items without touching metadata.
+    item_expiries: Arc<RwLock<HashMap<Uuid, DateTime<Utc>>>>,
+    item_interactions: Arc<RwLock<HashMap<Uuid, ItemInteractionCounts>>>,
+    /// The same counts sliced by hour of day and region, for context-aware trending.
+    context_interactions: Arc<RwLock<HashMap<TrendingContext, HashMap<Uuid, ItemInteractionCounts>>>>,
+    /// Recent actions per user, capped at `max_action_history`. Unused when a metadata store is set.
+    user_actions: Arc<RwLock<HashMap<Uuid, VecDeque<UserAction>>>>,
+    /// Items each user gave negative feedback on, never recommended to them again.
+    user_exclusions: Arc<RwLock<HashMap<Uuid, HashSet<Uuid>>>>,
+    /// Items each user interacted with, most recent last, capped at `seen_items_cap`.
+    user_seen_items: Arc<RwLock<HashMap<Uuid, VecDeque<Uuid>>>>,
+    /// Accumulated interaction weight per user and item category, never below zero.
+    user_category_affinity: Arc<RwLock<HashMap<Uuid, HashMap<String, f32>>>>,
+    /// The last `cluster_items` result. Items indexed since are in no cluster until the next run.
+    item_clusters: Arc<RwLock<Option<Arc<ItemClusters>>>>,
+    projection: Option<ProjectionMatrix>,
+    /// Durable copy of profiles and features; the maps above are its hot cache.
+    metadata_store: Option<Arc<dyn MetadataStore>>,
+    /// Serve the metadata store's snapshot and reject writes; see `reload_snapshot`.
+    read_only: bool,
     config: Arc<Config>,
 }
 
+/// Snapshot of the vector indexes served at `GET /admin/index-stats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexStats {
+    pub users: RetrieverStats,
+    pub items: RetrieverStats,
+    /// Item indexes for non-current embedding versions, keyed by version.
+    pub item_versions: HashMap<String, RetrieverStats>,
+    pub quarantined_items: usize,
+    pub expiring_items: usize,
+    /// Sum of the retrievers' estimates.
+    pub estimated_bytes: usize,
+}
+
+/// How many stored embeddings a `renormalize_all` sweep rescaled to unit length.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RenormalizationReport {
+    pub users: usize,
+    pub items: usize,
+    /// Non-current item embedding versions, counted per item and version.
+    pub item_versions: usize,
+}
+
+/// k-means clusters of the current item embeddings, from `cluster_items`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemClusters {
+    /// Index into `centroids` of each clustered item.
+    pub assignments: HashMap<Uuid, usize>,
+    pub centroids: Vec<Vec<f32>>,
+    pub computed_at: DateTime<Utc>,
+}
+
+impl ItemClusters {
+    /// Cluster indexes ordered by centroid distance to `embedding`, nearest first.
+    pub fn nearest_clusters(&self, embedding: &[f32]) -> Vec<usize> {
+        let mut clusters: Vec<(usize, f32)> = self.centroids
+            .iter()
+            .map(|centroid| euclidean_distance(embedding, centroid))
+            .enumerate()
+            .collect();
+        clusters.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        clusters.into_iter().map(|(cluster, _)| cluster).collect()
+    }
+
+    /// Members of `cluster`, in id order.
+    pub fn members(&self, cluster: usize) -> Vec<Uuid> {
+        let mut members: Vec<Uuid> = self.assignments
+            .iter()
+            .filter(|(_, assigned)| **assigned == cluster)
+            .map(|(item_id, _)| *item_id)
+            .collect();
+        members.sort();
+        members
+    }
+
+    /// The cluster `embedding` would join.
+    pub fn cluster_of(&self, embedding: &[f32]) -> usize {
+        nearest_centroid(embedding, &self.centroids)
+    }
+}
+
+/// Lloyd iterations per `cluster_items` run; k-means usually settles well before.
+const CLUSTERING_MAX_ITERATIONS: usize = 50;
+/// Seeds k-means++, so reclustering an unchanged catalog reproduces the same clusters.
+const CLUSTERING_SEED: u64 = 0x6b6d65616e73;
+
+/// Nearest users `find_duplicate_users` compares each user against.
+const DUPLICATE_USER_NEIGHBORS: usize = 20;
+
+/// Most extra candidates a search fetches to make up for expired items still waiting on the reaper.
+const MAX_EXPIRED_OVERFETCH: usize = 256;
+
+/// One item's row of the item-item k-NN graph export.
+#[derive(Debug, Clone, Serialize)]
+pub struct KnnGraphEntry {
+    pub item_id: Uuid,
+    /// The `k` most similar other items, most similar first.
+    pub neighbors: Vec<(Uuid, f32)>,
+}
+
+/// k-NN rows for `items`, each queried with its own embedding against `retriever`.
+async fn knn_graph_rows(
+    retriever: &dyn VectorRetriever,
+    items: Vec<(Uuid, Vec<f32>)>,
+    k: usize,
+) -> Result<Vec<KnnGraphEntry>> {
+    let (item_ids, embeddings): (Vec<Uuid>, Vec<Vec<f32>>) = items.into_iter().unzip();
+    let queries: Vec<&[f32]> = embeddings.iter().map(Vec::as_slice).collect();
+
+    // One extra result, since every item is its own nearest neighbor
+    let results = retriever.search_similar_batch(&queries, k + 1).await?;
+    Ok(item_ids
+        .into_iter()
+        .zip(results)
+        .map(|(item_id, mut neighbors)| {
+            neighbors.retain(|(neighbor_id, _)| *neighbor_id != item_id);
+            neighbors.truncate(k);
+            KnnGraphEntry { item_id, neighbors }
+        })
+        .collect())
+}
+
+/// Rescales `embedding` to unit length in place unless it already is, or is zero. Returns whether
+/// it changed.
+fn renormalize(embedding: &mut [f32]) -> bool {
+    let norm = embedding_norm(embedding);
+    if norm == 0.0 || (norm - 1.0).abs() <= RENORMALIZE_TOLERANCE {
+        return false;
+    }
+    normalize_vector(embedding);
+    true
+}
+
+/// Norm deviation from 1 a sweep leaves alone, so re-running it changes nothing.
+const RENORMALIZE_TOLERANCE: f64 = 1e-4;
+
+/// Running mean/variance (Welford) of the item catalog, used for outlier detection at ingest.
+#[derive(Debug, Default)]
+struct EmbeddingStats {
+    count: u64,
+    mean: Vec<f32>,
+    norm_mean: f64,
+    norm_m2: f64,
+    distance_mean: f64,
+    distance_m2: f64,
+}
+
+impl EmbeddingStats {
+    fn distance_to_mean(&self, embedding: &[f32]) -> f64 {
+        if self.mean.len() != embedding.len() {
+            return 0.0;
+        }
+
+        embedding.iter()
+            .zip(self.mean.iter())
+            .map(|(x, m)| ((x - m) as f64).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    fn z_score(value: f64, mean: f64, m2: f64, count: u64) -> f64 {
+        let std_dev = (m2 / count.saturating_sub(1).max(1) as f64).sqrt();
+        (value - mean).abs() / std_dev.max(f64::EPSILON)
+    }
+
+    /// Returns the (norm, distance-to-mean) z-scores of an embedding against the current statistics.
+    fn z_scores(&self, embedding: &[f32]) -> (f64, f64) {
+        let norm = embedding_norm(embedding);
+        let distance = self.distance_to_mean(embedding);
+        (
+            Self::z_score(norm, self.norm_mean, self.norm_m2, self.count),
+            Self::z_score(distance, self.distance_mean, self.distance_m2, self.count),
+        )
+    }
+
+    fn update(&mut self, embedding: &[f32]) {
+        if self.mean.len() != embedding.len() {
+            *self = Self { mean: vec![0.0; embedding.len()], ..Self::default() };
+        }
+
+        let distance = self.distance_to_mean(embedding);
+        let norm = embedding_norm(embedding);
+        self.count += 1;
+        let n = self.count as f64;
+
+        for (m, x) in self.mean.iter_mut().zip(embedding.iter()) {
+            *m += (x - *m) / n as f32;
+        }
+
+        let delta = norm - self.norm_mean;
+        self.norm_mean += delta / n;
+        self.norm_m2 += delta * (norm - self.norm_mean);
+
+        let delta = distance - self.distance_mean;
+        self.distance_mean += delta / n;
+        self.distance_m2 += delta * (distance - self.distance_mean);
+    }
+}
+
+/// Metric of the in-memory indexes. Over Milvus, whose scores come back as cosine similarity, the
+/// in-memory version indexes score by cosine too so their scores stay comparable.
+fn in_memory_metric(config: &Config) -> Result<DistanceMetric> {
+    if config.milvus.host.is_empty() {
+        DistanceMetric::from_metric_type(&config.milvus.metric_type)
+    } else {
+        Ok(DistanceMetric::Cosine)
+    }
+}
+
+/// Counts a `View` or negative feedback as an exposure and any other action as a positive interaction.
+fn count_interaction(counts: &mut ItemInteractionCounts, action_type: &ActionType, at: DateTime<Utc>, half_life: f64) {
+    if matches!(action_type, ActionType::View) || action_type.is_negative() {
+        counts.views += 1;
+    } else {
+        counts.positives += 1;
+    }
+    if !action_type.is_negative() {
+        counts.recent.add(at, half_life);
+    }
+}
+
+fn embedding_norm(embedding: &[f32]) -> f64 {
+    embedding.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt()
+}
+
 impl VectorDbService {
     pub async fn new(config: &Config) -> Result<Self> {
-        let user_retriever = Arc::new(RwLock::new(
-            InMemoryRetriever::new(config.milvus.dimension)
-        ));
-        let item_retriever = Arc::new(RwLock::new(
-            InMemoryRetriever::new(config.milvus.dimension)
-        ));
+        let metadata_store = open_metadata_store(config)?;
+        Self::with_metadata_store(config, metadata_store).await
+    }
+
+    /// Builds the service on top of `metadata_store`, re-indexing every profile and feature it holds.
+    pub async fn with_metadata_store(
+        config: &Config,
+        metadata_store: Option<Arc<dyn MetadataStore>>,
+    ) -> Result<Self> {
+        let (user_retriever, item_retriever): (Box<dyn VectorRetriever>, Box<dyn VectorRetriever>) =
+            if config.milvus.host.is_empty() {
+                let metric = in_memory_metric(config)?;
+                info!("Initialized in-memory vector database with dimension {}", config.milvus.dimension);
+                let users = InMemoryRetriever::new(config.milvus.dimension)
+                    .with_f64_accumulation(config.milvus.f64_accumulation)
+                    .with_cached_norms(config.milvus.cache_vector_norms)
+                    .with_metric(metric);
+                let items = InMemoryRetriever::new(config.milvus.dimension)
+                    .with_f64_accumulation(config.milvus.f64_accumulation)
+                    .with_cached_norms(config.milvus.cache_vector_norms)
+                    .with_normalized_vectors(config.recommendation.auto_normalize_embeddings)
+                    .with_metric(metric);
+                if config.milvus.in_memory_index.eq_ignore_ascii_case("ivf") {
+                    let (nlist, nprobe) = (config.milvus.nlist, config.milvus.nprobe);
+                    (
+                        Box::new(IVFRetriever::from_template(users, nlist, nprobe)),
+                        Box::new(IVFRetriever::from_template(items, nlist, nprobe)),
+                    )
+                } else {
+                    (Box::new(users), Box::new(items))
+                }
+            } else {
+                let collection = &config.milvus.collection_name;
+                (
+                    Box::new(MilvusRetriever::connect(&config.milvus, &format!("{}_users", collection)).await?),
+                    Box::new(MilvusRetriever::connect(&config.milvus, &format!("{}_items", collection)).await?),
+                )
+            };
+        let user_retriever = Arc::new(RwLock::new(user_retriever));
+        let item_retriever = Arc::new(RwLock::new(item_retriever));
 
-        info!("Initialized in-memory vector database with dimension {}", config.milvus.dimension);
+        let projection = match &config.recommendation.projection {
+            Some(projection) => Some(ProjectionMatrix::from_config(projection, config.recommendation.embedding_dim)?),
+            None => None,
+        };
 
-        Ok(Self {
+        if config.milvus.read_only && metadata_store.is_none() {
+            warn!("Read-only mode without a metadata store serves an empty index");
+        }
+
+        let service = Self {
             user_retriever,
             item_retriever,
+            versioned_item_retrievers: Arc::new(RwLock::new(HashMap::new())),
             user_profiles: Arc::new(RwLock::new(HashMap::new())),
             item_features: Arc::new(RwLock::new(HashMap::new())),
+            item_embedding_stats: Arc::new(RwLock::new(EmbeddingStats::default())),
+            quarantined_items: Arc::new(RwLock::new(HashMap::new())),
+            item_expiries: Arc::new(RwLock::new(HashMap::new())),
+            item_interactions: Arc::new(RwLock::new(HashMap::new())),
+            context_interactions: Arc::new(RwLock::new(HashMap::new())),
+            user_actions: Arc::new(RwLock::new(HashMap::new())),
+            user_exclusions: Arc::new(RwLock::new(HashMap::new())),
+            user_seen_items: Arc::new(RwLock::new(HashMap::new())),
+            user_category_affinity: Arc::new(RwLock::new(HashMap::new())),
+            item_clusters: Arc::new(RwLock::new(None)),
+            projection,
+            metadata_store,
+            read_only: config.milvus.read_only,
             config: Arc::new(config.clone()),
+        };
+        service.load_metadata().await?;
+        Ok(service)
+    }
+
+    async fn load_metadata(&self) -> Result<()> {
+        let Some(store) = &self.metadata_store else {
+            return Ok(());
+        };
+
+        let profiles = store.user_profiles().await?;
+        self.index_user_profiles(&profiles)
+            .await
+            .map_err(|(i, e)| e.context(format!("Failed to load user profile {}", profiles[i].user_id)))?;
+
+        let features = store.item_features().await?;
+        if self.config.outlier_detection.enabled {
+            let mut stats = self.item_embedding_stats.write().await;
+            for feature in &features {
+                stats.update(&feature.embedding);
+            }
+        }
+        self.index_item_features(&features)
+            .await
+            .map_err(|(i, e)| e.context(format!("Failed to load item feature {}", features[i].item_id)))?;
+
+        info!("Loaded {} user profiles and {} item features from the metadata store", profiles.len(), features.len());
+        Ok(())
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// A read-only replica over a shared index (Milvus) serves what the writer indexed there, so
+    /// loading the metadata store only fills the local maps.
+    fn serves_shared_index(&self, retriever: &dyn VectorRetriever) -> bool {
+        self.read_only && retriever.is_shared()
+    }
+
+    /// Fails with `ReadOnlyReplica` on a read-only replica.
+    pub fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(ReadOnlyReplica.into());
+        }
+        Ok(())
+    }
+
+    /// Replaces the served indexes with a fresh load of the metadata store, so a read-only replica
+    /// picks up what the writer persisted since. The new indexes are built before any lock is taken.
+    pub async fn reload_snapshot(&self) -> Result<()> {
+        let fresh = Self::with_metadata_store(&self.config, self.metadata_store.clone()).await?;
+
+        // Swapped one index at a time, so a search may briefly see users and items from different loads
+        std::mem::swap(&mut *self.user_retriever.write().await, &mut *fresh.user_retriever.write().await);
+        std::mem::swap(&mut *self.user_profiles.write().await, &mut *fresh.user_profiles.write().await);
+        std::mem::swap(&mut *self.item_retriever.write().await, &mut *fresh.item_retriever.write().await);
+        std::mem::swap(
+            &mut *self.versioned_item_retrievers.write().await,
+            &mut *fresh.versioned_item_retrievers.write().await,
+        );
+        std::mem::swap(&mut *self.item_features.write().await, &mut *fresh.item_features.write().await);
+        std::mem::swap(&mut *self.item_expiries.write().await, &mut *fresh.item_expiries.write().await);
+        std::mem::swap(
+            &mut *self.item_embedding_stats.write().await,
+            &mut *fresh.item_embedding_stats.write().await,
+        );
+
+        info!("Reloaded vector database snapshot");
+        Ok(())
+    }
+
+    /// Periodically reloads the snapshot; a failed reload keeps serving the previous one.
+    pub fn spawn_snapshot_reloader(self: Arc<Self>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick fires immediately, right after the initial load
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.reload_snapshot().await {
+                    warn!("Failed to reload vector database snapshot: {}", e);
+                }
+            }
         })
     }
 
     pub async fn insert_user_profile(&self, profile: &UserProfile) -> Result<()> {
-        // Insert into user retriever
+        self.ensure_writable()?;
+        let mut profile = profile.clone();
+        self.adapt_dimension(&mut profile.embedding, "user", profile.user_id);
+
+        self.index_user_profiles(std::slice::from_ref(&profile)).await.map_err(|(_, e)| e)?;
+        self.persist_user_profile(&profile).await?;
+
+        info!("Inserted user profile: {}", profile.user_id);
+        Ok(())
+    }
+
+    /// Indexes `profiles` under a single acquisition of each lock. On failure the profiles before the
+    /// failing one stay indexed, as if inserted one by one, and its position is returned with the error.
+    async fn index_user_profiles(&self, profiles: &[UserProfile]) -> std::result::Result<(), (usize, anyhow::Error)> {
+        let mut failure = None;
+        let mut indexed = profiles.len();
         {
             let mut retriever = self.user_retriever.write().await;
-            retriever.add_vector(profile.user_id, profile.embedding.clone()).await?;
+            let skip_writes = self.serves_shared_index(retriever.as_ref());
+            for (i, profile) in profiles.iter().enumerate() {
+                if skip_writes {
+                    break;
+                }
+                if let Err(e) = retriever.add_vector(profile.user_id, profile.embedding.clone()).await {
+                    failure = Some((i, e));
+                    indexed = i;
+                    break;
+                }
+            }
         }
 
-        // Store profile metadata
         {
-            let mut profiles = self.user_profiles.write().await;
-            profiles.insert(profile.user_id, profile.clone());
+            let mut metadata = self.user_profiles.write().await;
+            for profile in &profiles[..indexed] {
+                metadata.insert(profile.user_id, profile.clone());
+            }
         }
 
-        info!("Inserted user profile: {}", profile.user_id);
-        Ok(())
+        failure.map_or(Ok(()), Err)
     }
 
     pub async fn insert_item_feature(&self, feature: &ItemFeature) -> Result<()> {
-        // Insert into item retriever
+        self.insert_item_feature_with_mode(feature, InsertMode::Upsert).await?;
+        Ok(())
+    }
+
+    /// Inserts `feature` as `mode` allows, failing with an `InsertConflict` otherwise. Replacing an
+    /// item keeps its `created_at` and refreshes `last_updated`. Returns the feature as stored.
+    pub async fn insert_item_feature_with_mode(&self, feature: &ItemFeature, mode: InsertMode) -> Result<ItemFeature> {
+        self.ensure_writable()?;
+        let mut feature = feature.clone();
+        self.prepare_item_feature(&mut feature);
+
+        let existing_created_at = self.item_features
+            .read()
+            .await
+            .get(&feature.item_id)
+            .map(|existing| existing.created_at);
+        match (mode, existing_created_at) {
+            (InsertMode::InsertOnly, Some(_)) => return Err(InsertConflict::Exists(feature.item_id).into()),
+            (InsertMode::UpdateOnly, None) => return Err(InsertConflict::Missing(feature.item_id).into()),
+            (_, Some(created_at)) => {
+                feature.created_at = created_at;
+                feature.last_updated = Utc::now();
+            }
+            (_, None) => {}
+        }
+
+        self.check_item_outlier(&feature).await?;
+        self.index_item_features(std::slice::from_ref(&feature)).await.map_err(|(_, e)| e)?;
+        self.persist_item_feature(&feature).await?;
+
+        info!("Inserted item feature: {}", feature.item_id);
+        Ok(feature)
+    }
+
+    /// Removes an item from every index and from the metadata store. Returns whether it existed.
+    pub async fn delete_item_feature(&self, item_id: Uuid) -> Result<bool> {
+        self.ensure_writable()?;
+
+        self.item_retriever.write().await.remove_vector(item_id).await?;
+        for version_retriever in self.versioned_item_retrievers.write().await.values_mut() {
+            // Removing from the in-memory retrievers cannot fail
+            let _ = version_retriever.remove_vector(item_id).await;
+        }
+        let existed = self.item_features.write().await.remove(&item_id).is_some();
+        self.item_expiries.write().await.remove(&item_id);
+        self.item_interactions.write().await.remove(&item_id);
+        for bucket in self.context_interactions.write().await.values_mut() {
+            bucket.remove(&item_id);
+        }
+        if let Some(store) = &self.metadata_store {
+            store.delete_item_feature(item_id).await?;
+        }
+
+        if existed {
+            info!("Deleted item feature: {}", item_id);
+        }
+        Ok(existed)
+    }
+
+    /// Item counterpart of `index_user_profiles`, also covering embedding versions and expiries.
+    async fn index_item_features(&self, features: &[ItemFeature]) -> std::result::Result<(), (usize, anyhow::Error)> {
+        let mut failure = None;
+        let mut indexed = features.len();
         {
             let mut retriever = self.item_retriever.write().await;
-            retriever.add_vector(feature.item_id, feature.embedding.clone()).await?;
+            let mut versioned = self.versioned_item_retrievers.write().await;
+            let skip_writes = self.serves_shared_index(retriever.as_ref());
+            for (i, feature) in features.iter().enumerate() {
+                let mut result = if skip_writes {
+                    Ok(())
+                } else {
+                    retriever.add_vector(feature.item_id, feature.embedding.clone()).await
+                };
+
+                // Index the alternative embedding versions in their own namespaces
+                for (version, embedding) in &feature.embedding_versions {
+                    if result.is_err() {
+                        break;
+                    }
+                    let version_retriever = versioned
+                        .entry(version.clone())
+                        .or_insert_with(|| self.new_item_retriever());
+                    result = version_retriever.add_vector(feature.item_id, embedding.clone()).await;
+                }
+
+                if let Err(e) = result {
+                    failure = Some((i, e));
+                    indexed = i;
+                    break;
+                }
+            }
         }
 
         // Store feature metadata
         {
-            let mut features = self.item_features.write().await;
-            features.insert(feature.item_id, feature.clone());
+            let mut metadata = self.item_features.write().await;
+            let mut expiries = self.item_expiries.write().await;
+            for feature in &features[..indexed] {
+                metadata.insert(feature.item_id, feature.clone());
+                match feature.expires_at {
+                    Some(expires_at) => expiries.insert(feature.item_id, expires_at),
+                    None => expiries.remove(&feature.item_id),
+                };
+            }
         }
 
-        info!("Inserted item feature: {}", feature.item_id);
-        Ok(())
+        failure.map_or(Ok(()), Err)
     }
 
     pub async fn search_similar_users(&self, user_embedding: &[f32], top_k: usize) -> Result<Vec<(Uuid, f32)>> {
+        let user_embedding = self.project_query(user_embedding)?;
         let retriever = self.user_retriever.read().await;
-        let results = retriever.search_similar(user_embedding, top_k).await?;
+        let results = retriever.search_similar(&user_embedding, top_k).await?;
         Ok(results)
     }
 
+    /// Groups users whose profile embeddings score at least `threshold` against each other, for
+    /// spotting accounts that behave identically. Each user is matched only against its
+    /// `DUPLICATE_USER_NEIGHBORS` nearest users, so the work stays linear in the user count; groups
+    /// are joined transitively, hold at least two users, and are sorted largest first.
+    pub async fn find_duplicate_users(&self, threshold: f32) -> Result<Vec<Vec<Uuid>>> {
+        let mut users: Vec<(Uuid, Vec<f32>)> = self.user_profiles
+            .read()
+            .await
+            .values()
+            .map(|profile| (profile.user_id, profile.embedding.clone()))
+            .collect();
+        users.sort_by_key(|(user_id, _)| *user_id);
+
+        let index: HashMap<Uuid, usize> = users.iter().enumerate().map(|(i, (user_id, _))| (*user_id, i)).collect();
+        let mut parents: Vec<usize> = (0..users.len()).collect();
+        fn root(parents: &mut [usize], mut i: usize) -> usize {
+            while parents[i] != i {
+                parents[i] = parents[parents[i]];
+                i = parents[i];
+            }
+            i
+        }
+
+        for (i, (user_id, embedding)) in users.iter().enumerate() {
+            // One extra result, since every user is its own nearest neighbor
+            for (neighbor_id, score) in self.search_similar_users(embedding, DUPLICATE_USER_NEIGHBORS + 1).await? {
+                if neighbor_id == *user_id || score < threshold {
+                    continue;
+                }
+                if let Some(&j) = index.get(&neighbor_id) {
+                    let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                    parents[a.max(b)] = a.min(b);
+                }
+            }
+        }
+
+        let mut groups: HashMap<usize, Vec<Uuid>> = HashMap::new();
+        for (i, (user_id, _)) in users.iter().enumerate() {
+            groups.entry(root(&mut parents, i)).or_default().push(*user_id);
+        }
+        let mut groups: Vec<Vec<Uuid>> = groups.into_values().filter(|group| group.len() > 1).collect();
+        groups.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a[0].cmp(&b[0])));
+        Ok(groups)
+    }
+
     pub async fn search_similar_items(&self, item_embedding: &[f32], top_k: usize) -> Result<Vec<(Uuid, f32)>> {
+        let item_embedding = self.project_query(item_embedding)?;
+        let retriever = self.item_retriever.read().await;
+        self.search_unexpired(retriever.as_ref(), &item_embedding, top_k, None).await
+    }
+
+    /// Queries in the projection's input dimension are projected like inserted embeddings are.
+    fn project_query<'a>(&self, embedding: &'a [f32]) -> Result<Cow<'a, [f32]>> {
+        match &self.projection {
+            Some(projection) if embedding.len() == projection.input_dim() => {
+                Ok(Cow::Owned(projection.project(embedding)?))
+            }
+            _ => Ok(Cow::Borrowed(embedding)),
+        }
+    }
+
+    /// Over-fetches by the number of items already expired but not yet reaped, at most
+    /// `MAX_EXPIRED_OVERFETCH`, so dropping them still leaves `top_k`. Items yet to expire cost nothing.
+    async fn search_unexpired(
+        &self,
+        retriever: &dyn VectorRetriever,
+        item_embedding: &[f32],
+        top_k: usize,
+        min_similarity: Option<f32>,
+    ) -> Result<Vec<(Uuid, f32)>> {
+        let search = |top_k| async move {
+            match min_similarity {
+                Some(floor) => retriever.search_similar_filtered(item_embedding, top_k, floor).await,
+                None => retriever.search_similar(item_embedding, top_k).await,
+            }
+        };
+
+        let expiries = self.item_expiries.read().await;
+        let now = Utc::now();
+        let expired = expiries.values().filter(|expires_at| **expires_at <= now).count();
+        if expired == 0 {
+            return search(top_k).await;
+        }
+
+        let mut results = search(top_k + expired.min(MAX_EXPIRED_OVERFETCH)).await?;
+        results.retain(|(item_id, _)| expiries.get(item_id).is_none_or(|expires_at| *expires_at > now));
+        results.truncate(top_k);
+        Ok(results)
+    }
+
+    /// Removes items that expired more than `grace` ago from every index. Returns how many were removed.
+    pub async fn reap_expired_items(&self, grace: chrono::Duration) -> usize {
+        let cutoff = Utc::now() - grace;
+        let expired: Vec<Uuid> = self.item_expiries
+            .read()
+            .await
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= cutoff)
+            .map(|(item_id, _)| *item_id)
+            .collect();
+        if expired.is_empty() {
+            return 0;
+        }
+
+        let mut retriever = self.item_retriever.write().await;
+        let mut versioned = self.versioned_item_retrievers.write().await;
+        let mut features = self.item_features.write().await;
+        let mut expiries = self.item_expiries.write().await;
+        let mut interactions = self.item_interactions.write().await;
+        let mut context_interactions = self.context_interactions.write().await;
+        for item_id in &expired {
+            // A replica over a shared index leaves removal to the writer
+            if !self.serves_shared_index(retriever.as_ref()) {
+                if let Err(e) = retriever.remove_vector(*item_id).await {
+                    warn!("Failed to remove expired item {} from the item index: {}", item_id, e);
+                }
+            }
+            // Removing from the in-memory retrievers cannot fail
+            for version_retriever in versioned.values_mut() {
+                let _ = version_retriever.remove_vector(*item_id).await;
+            }
+            features.remove(item_id);
+            expiries.remove(item_id);
+            interactions.remove(item_id);
+            for bucket in context_interactions.values_mut() {
+                bucket.remove(item_id);
+            }
+            // A replica only drops its own copy; the writer reaps the store
+            if let Some(store) = self.metadata_store.as_ref().filter(|_| !self.read_only) {
+                if let Err(e) = store.delete_item_feature(*item_id).await {
+                    warn!("Failed to delete expired item {} from the metadata store: {}", item_id, e);
+                }
+            }
+        }
+
+        info!("Reaped {} expired items", expired.len());
+        expired.len()
+    }
+
+    /// Periodically reaps items that have been expired for longer than `grace`.
+    pub fn spawn_expired_item_reaper(
+        self: Arc<Self>,
+        interval: std::time::Duration,
+        grace: chrono::Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.reap_expired_items(grace).await;
+            }
+        })
+    }
+
+    /// L2-normalizes every stored user and item embedding, including item embedding versions, in
+    /// the metadata maps, the retrievers and the metadata store. Cosine rankings are unchanged;
+    /// the sweep undoes the magnitude drift of unnormalized updates.
+    pub async fn renormalize_all(&self) -> Result<RenormalizationReport> {
+        self.ensure_writable()?;
+        let mut report = RenormalizationReport::default();
+
+        let mut renormalized_profiles = Vec::new();
+        {
+            let mut retriever = self.user_retriever.write().await;
+            let mut profiles = self.user_profiles.write().await;
+            for profile in profiles.values_mut() {
+                let mut embedding = profile.embedding.clone();
+                if !renormalize(&mut embedding) {
+                    continue;
+                }
+                retriever.update_vector(profile.user_id, embedding.clone()).await?;
+                profile.update_embedding(embedding);
+                renormalized_profiles.push(profile.clone());
+                report.users += 1;
+            }
+        }
+        for profile in &renormalized_profiles {
+            self.persist_user_profile(profile).await?;
+        }
+
+        {
+            let mut retriever = self.item_retriever.write().await;
+            let mut versioned = self.versioned_item_retrievers.write().await;
+            let mut features = self.item_features.write().await;
+            for feature in features.values_mut() {
+                let mut changed = false;
+                if renormalize(&mut feature.embedding) {
+                    retriever.update_vector(feature.item_id, feature.embedding.clone()).await?;
+                    report.items += 1;
+                    changed = true;
+                }
+                for (version, embedding) in feature.embedding_versions.iter_mut() {
+                    if !renormalize(embedding) {
+                        continue;
+                    }
+                    if let Some(version_retriever) = versioned.get_mut(version) {
+                        version_retriever.update_vector(feature.item_id, embedding.clone()).await?;
+                    }
+                    report.item_versions += 1;
+                    changed = true;
+                }
+                if changed {
+                    self.persist_item_feature(feature).await?;
+                }
+            }
+        }
+
+        info!(
+            "Renormalized {} user, {} item and {} versioned item embeddings",
+            report.users, report.items, report.item_versions
+        );
+        Ok(report)
+    }
+
+    /// Periodically runs `renormalize_all`.
+    pub fn spawn_renormalizer(self: Arc<Self>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.renormalize_all().await {
+                    warn!("Failed to renormalize embeddings: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Clusters the current embeddings of unexpired items into at most `k` clusters with k-means,
+    /// and caches the result for `item_clusters`.
+    pub async fn cluster_items(&self, k: usize) -> Result<Arc<ItemClusters>> {
+        if k == 0 {
+            return Err(anyhow!("Clustering needs at least one cluster"));
+        }
+
+        let now = Utc::now();
+        let mut items: Vec<(Uuid, Vec<f32>)> = self.item_features
+            .read()
+            .await
+            .values()
+            .filter(|feature| !feature.is_expired_at(now))
+            .map(|feature| (feature.item_id, feature.embedding.clone()))
+            .collect();
+        // Sorted so the seeded initialisation sees the same order every run
+        items.sort_by_key(|(item_id, _)| *item_id);
+
+        let points: Vec<&[f32]> = items.iter().map(|(_, embedding)| embedding.as_slice()).collect();
+        let clustering = k_means(&points, k, CLUSTERING_MAX_ITERATIONS, CLUSTERING_SEED);
+        let clusters = Arc::new(ItemClusters {
+            assignments: items.iter().map(|(item_id, _)| *item_id).zip(clustering.assignments).collect(),
+            centroids: clustering.centroids,
+            computed_at: now,
+        });
+
+        *self.item_clusters.write().await = Some(clusters.clone());
+        info!("Clustered {} items into {} clusters", items.len(), clusters.centroids.len());
+        Ok(clusters)
+    }
+
+    /// The clusters of the last `cluster_items` run, if any.
+    pub async fn item_clusters(&self) -> Option<Arc<ItemClusters>> {
+        self.item_clusters.read().await.clone()
+    }
+
+    /// Periodically reruns `cluster_items` so clusters follow the catalog.
+    pub fn spawn_item_clusterer(self: Arc<Self>, interval: std::time::Duration, k: usize) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.cluster_items(k).await {
+                    warn!("Failed to cluster items: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Every indexed item's `k` nearest other items (current embeddings, expired items included),
+    /// computed in one pass under the item index read lock.
+    pub async fn export_knn_graph(&self, k: usize) -> Result<HashMap<Uuid, Vec<(Uuid, f32)>>> {
         let retriever = self.item_retriever.read().await;
-        let results = retriever.search_similar(item_embedding, top_k).await?;
+        let item_ids = self.indexed_item_ids().await;
+        let items = self.item_embeddings(&item_ids).await;
+        let rows = knn_graph_rows(retriever.as_ref(), items, k).await?;
+        Ok(rows.into_iter().map(|row| (row.item_id, row.neighbors)).collect())
+    }
+
+    async fn indexed_item_ids(&self) -> Vec<Uuid> {
+        let mut item_ids: Vec<Uuid> = self.item_features.read().await.keys().copied().collect();
+        item_ids.sort();
+        item_ids
+    }
+
+    /// Current embeddings of `item_ids`, skipping ids no longer indexed.
+    async fn item_embeddings(&self, item_ids: &[Uuid]) -> Vec<(Uuid, Vec<f32>)> {
+        let features = self.item_features.read().await;
+        item_ids
+            .iter()
+            .filter_map(|item_id| features.get(item_id).map(|feature| (*item_id, feature.embedding.clone())))
+            .collect()
+    }
+
+    /// Like `export_knn_graph`, but yields `chunk_size` rows at a time and takes the read lock
+    /// once per chunk, so large catalogs are neither materialized nor block writers throughout.
+    /// Items are those indexed when the stream starts; ones deleted since are skipped.
+    pub fn stream_knn_graph(
+        self: Arc<Self>,
+        k: usize,
+        chunk_size: usize,
+    ) -> impl futures::Stream<Item = Result<Vec<KnnGraphEntry>>> {
+        futures::stream::unfold((self, None, 0), move |(service, item_ids, offset)| async move {
+            let item_ids: Vec<Uuid> = match item_ids {
+                Some(item_ids) => item_ids,
+                None => service.indexed_item_ids().await,
+            };
+            if offset >= item_ids.len() {
+                return None;
+            }
+
+            let end = (offset + chunk_size.max(1)).min(item_ids.len());
+            let rows = {
+                let retriever = service.item_retriever.read().await;
+                let items = service.item_embeddings(&item_ids[offset..end]).await;
+                knn_graph_rows(retriever.as_ref(), items, k).await
+            };
+            Some((rows, (service, Some(item_ids), end)))
+        })
+    }
+
+    /// Searches a named item embedding version; `CURRENT_EMBEDDING_VERSION` searches `embedding`.
+    pub async fn search_similar_items_in_version(
+        &self,
+        item_embedding: &[f32],
+        top_k: usize,
+        version: &str,
+    ) -> Result<Vec<(Uuid, f32)>> {
+        self.search_similar_items_above(item_embedding, top_k, version, None).await
+    }
+
+    /// Searches a named item embedding version, leaving out items less similar than `min_similarity`.
+    pub async fn search_similar_items_above(
+        &self,
+        item_embedding: &[f32],
+        top_k: usize,
+        version: &str,
+        min_similarity: Option<f32>,
+    ) -> Result<Vec<(Uuid, f32)>> {
+        let item_embedding = &*self.project_query(item_embedding)?;
+        if version == CURRENT_EMBEDDING_VERSION {
+            let retriever = self.item_retriever.read().await;
+            return self.search_unexpired(retriever.as_ref(), item_embedding, top_k, min_similarity).await;
+        }
+
+        let retrievers = self.versioned_item_retrievers.read().await;
+        match retrievers.get(version) {
+            Some(retriever) => self.search_unexpired(retriever, item_embedding, top_k, min_similarity).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Ranks items by Jaccard overlap of their tags with `query_item_id`'s tags, for catalogs that
+    /// are described by tags rather than dense embeddings. Items sharing no tags are left out.
+    pub async fn search_similar_items_by_tags(&self, query_item_id: Uuid, top_k: usize) -> Result<Vec<(Uuid, f32)>> {
+        let query_tags: HashSet<String> = match self.item_features.read().await.get(&query_item_id) {
+            Some(feature) => feature.tags.iter().cloned().collect(),
+            None => return Err(anyhow!("Item {} not found", query_item_id)),
+        };
+
+        let mut results = self.search_items_by_tag_set(&query_tags, top_k + 1).await;
+        results.retain(|(item_id, _)| *item_id != query_item_id);
+        results.truncate(top_k);
         Ok(results)
     }
 
+    /// Ranks unexpired items by Jaccard overlap of their tags with `tags`.
+    pub async fn search_items_by_tag_set(&self, tags: &HashSet<String>, top_k: usize) -> Vec<(Uuid, f32)> {
+        let features = self.item_features.read().await;
+        let expiries = self.item_expiries.read().await;
+        let now = Utc::now();
+
+        let mut results: Vec<(Uuid, f32)> = features
+            .values()
+            .filter(|feature| expiries.get(&feature.item_id).is_none_or(|expires_at| *expires_at > now))
+            .map(|feature| {
+                let item_tags: HashSet<String> = feature.tags.iter().cloned().collect();
+                (feature.item_id, jaccard_similarity(tags, &item_tags))
+            })
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        results.truncate(top_k);
+        results
+    }
+
+    /// Counts a `View` or negative feedback as an exposure and any other action as a positive interaction.
+    pub async fn record_item_interaction(&self, item_id: Uuid, action_type: &ActionType) {
+        self.record_item_interaction_at(item_id, action_type, Utc::now()).await;
+    }
+
+    /// Like `record_item_interaction`, for an interaction that happened at `at`.
+    pub async fn record_item_interaction_at(&self, item_id: Uuid, action_type: &ActionType, at: DateTime<Utc>) {
+        let half_life = self.config.recommendation.trending_half_life_seconds as f64;
+        let mut interactions = self.item_interactions.write().await;
+        count_interaction(interactions.entry(item_id).or_default(), action_type, at, half_life);
+    }
+
+    /// Counts an interaction toward every bucket of `context`, alongside `record_item_interaction_at`.
+    pub async fn record_contextual_interaction(
+        &self,
+        item_id: Uuid,
+        action_type: &ActionType,
+        context: &TrendingContext,
+        at: DateTime<Utc>,
+    ) {
+        let half_life = self.config.recommendation.trending_half_life_seconds as f64;
+        let mut context_interactions = self.context_interactions.write().await;
+        for bucket in context.buckets() {
+            let counts = context_interactions.entry(bucket).or_default().entry(item_id).or_default();
+            count_interaction(counts, action_type, at, half_life);
+        }
+    }
+
+    /// Recency-weighted interaction count of an item as of now.
+    pub async fn get_item_velocity(&self, item_id: Uuid) -> f64 {
+        let half_life = self.config.recommendation.trending_half_life_seconds as f64;
+        self.item_interactions
+            .read()
+            .await
+            .get(&item_id)
+            .map_or(0.0, |counts| counts.recent.value_at(Utc::now(), half_life))
+    }
+
+    pub async fn exclude_item_for_user(&self, user_id: Uuid, item_id: Uuid) {
+        self.user_exclusions.write().await.entry(user_id).or_default().insert(item_id);
+    }
+
+    pub async fn get_user_exclusions(&self, user_id: Uuid) -> HashSet<Uuid> {
+        self.user_exclusions.read().await.get(&user_id).cloned().unwrap_or_default()
+    }
+
+    /// Remembers that the user interacted with `item_id`, forgetting their oldest item past
+    /// `seen_items_cap`. Does nothing while the cap is 0.
+    pub async fn record_seen_item(&self, user_id: Uuid, item_id: Uuid) {
+        let cap = self.config.recommendation.seen_items_cap;
+        if cap == 0 {
+            return;
+        }
+        let mut seen_items = self.user_seen_items.write().await;
+        let history = seen_items.entry(user_id).or_default();
+        history.retain(|seen| *seen != item_id);
+        history.push_back(item_id);
+        while history.len() > cap {
+            history.pop_front();
+        }
+    }
+
+    pub async fn get_seen_items(&self, user_id: Uuid) -> HashSet<Uuid> {
+        self.user_seen_items
+            .read()
+            .await
+            .get(&user_id)
+            .map(|history| history.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Adds `weight` to the user's affinity for `category`; negative feedback takes it back.
+    pub async fn record_category_affinity(&self, user_id: Uuid, category: &str, weight: f32) {
+        let mut affinities = self.user_category_affinity.write().await;
+        let affinity = affinities.entry(user_id).or_default().entry(category.to_string()).or_default();
+        *affinity = (*affinity + weight).max(0.0);
+    }
+
+    /// Each category's share of the user's total affinity, summing to 1. Empty for a user without
+    /// any positive interactions.
+    pub async fn get_category_affinity(&self, user_id: Uuid) -> HashMap<String, f32> {
+        let affinities = self.user_category_affinity.read().await;
+        let Some(affinity) = affinities.get(&user_id) else {
+            return HashMap::new();
+        };
+        let total: f32 = affinity.values().sum();
+        if total <= 0.0 {
+            return HashMap::new();
+        }
+        affinity.iter().map(|(category, weight)| (category.clone(), weight / total)).collect()
+    }
+
+    /// Mean embedding of the items in `category`. `None` when the category has no items.
+    pub async fn category_centroid(&self, category: &str) -> Option<Vec<f32>> {
+        let features = self.item_features.read().await;
+        let mut centroid = vec![0.0f32; self.config.recommendation.embedding_dim];
+        let mut count = 0;
+        for feature in features.values() {
+            if feature.category == category && feature.embedding.len() == centroid.len() {
+                for (c, x) in centroid.iter_mut().zip(&feature.embedding) {
+                    *c += x;
+                }
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return None;
+        }
+        for c in centroid.iter_mut() {
+            *c /= count as f32;
+        }
+        Some(centroid)
+    }
+
+    /// Appends to the user's action history, from which their profile can be rebuilt.
+    pub async fn record_user_action(&self, action: &UserAction) -> Result<()> {
+        self.ensure_writable()?;
+        if let Some(store) = &self.metadata_store {
+            return store.append_user_action(action, self.config.recommendation.max_action_history).await;
+        }
+
+        let mut histories = self.user_actions.write().await;
+        let history = histories.entry(action.user_id).or_default();
+        history.push_back(action.clone());
+        while history.len() > self.config.recommendation.max_action_history {
+            history.pop_front();
+        }
+        Ok(())
+    }
+
+    /// The user's action history, oldest first.
+    pub async fn get_user_actions(&self, user_id: Uuid) -> Result<Vec<UserAction>> {
+        if let Some(store) = &self.metadata_store {
+            return store.user_actions(user_id).await;
+        }
+
+        Ok(self.user_actions
+            .read()
+            .await
+            .get(&user_id)
+            .map(|history| history.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    pub async fn get_item_interactions(&self, item_id: Uuid) -> ItemInteractionCounts {
+        self.item_interactions.read().await.get(&item_id).copied().unwrap_or_default()
+    }
+
+    /// Ids of items with fewer than `max_interactions` recorded interactions, in id order.
+    pub async fn cold_item_ids(&self, max_interactions: u64) -> Vec<Uuid> {
+        let interactions = self.item_interactions.read().await;
+        let mut ids: Vec<Uuid> = self.item_features
+            .read()
+            .await
+            .keys()
+            .filter(|item_id| {
+                interactions.get(item_id).map_or(0, |counts| counts.views + counts.positives) < max_interactions
+            })
+            .copied()
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Up to `count` distinct items drawn uniformly at random, leaving out `exclude`. Returns every
+    /// remaining item, in random order, when there are no more than `count`.
+    pub async fn sample_item_features(&self, count: usize, exclude: &HashSet<Uuid>) -> Vec<ItemFeature> {
+        let features = self.item_features.read().await;
+        let mut sampled = features
+            .values()
+            .filter(|feature| !exclude.contains(&feature.item_id))
+            .choose_multiple(&mut rand::thread_rng(), count);
+        // choose_multiple does not randomize the order it returns
+        sampled.shuffle(&mut rand::thread_rng());
+        sampled.into_iter().cloned().collect()
+    }
+
+    /// Up to `top_k` items passing `keep`, highest `popularity_score` first, ties by id.
+    pub async fn search_items_by_popularity_score(
+        &self,
+        top_k: usize,
+        keep: impl Fn(&ItemFeature) -> bool,
+    ) -> Vec<ItemFeature> {
+        let features = self.item_features.read().await;
+        let mut ranked: Vec<&ItemFeature> = features.values().filter(|feature| keep(feature)).collect();
+        ranked.sort_by(|a, b| {
+            b.popularity_score.total_cmp(&a.popularity_score).then_with(|| a.item_id.cmp(&b.item_id))
+        });
+        ranked.into_iter().take(top_k).cloned().collect()
+    }
+
+    /// Ranks unexpired items by the Wilson lower bound of their positive rate, so high-volume items
+    /// are not outranked by ones with a perfect rate over a handful of interactions.
+    pub async fn search_popular_items(&self, category: Option<&str>, top_k: usize, z: f64) -> Vec<(Uuid, f32)> {
+        let interactions = self.item_interactions.read().await;
+        let (ranked, _) = self.rank_popular_items(&interactions, category, top_k, z).await;
+        ranked
+    }
+
+    /// Like `search_popular_items`, over interactions in `context` only. `None` when the context
+    /// has fewer than `context_trending_min_interactions` trials among eligible items, too few to
+    /// rank on; a global context always ranks.
+    pub async fn search_popular_items_in_context(
+        &self,
+        category: Option<&str>,
+        context: &TrendingContext,
+        top_k: usize,
+        z: f64,
+    ) -> Option<Vec<(Uuid, f32)>> {
+        if context.is_global() {
+            return Some(self.search_popular_items(category, top_k, z).await);
+        }
+
+        let context_interactions = self.context_interactions.read().await;
+        let interactions = context_interactions.get(context)?;
+        let (ranked, trials) = self.rank_popular_items(interactions, category, top_k, z).await;
+        (trials >= self.config.recommendation.context_trending_min_interactions).then_some(ranked)
+    }
+
+    /// The `top_k` most popular unexpired items in `interactions`, and the total trials over every
+    /// item that qualified.
+    async fn rank_popular_items(
+        &self,
+        interactions: &HashMap<Uuid, ItemInteractionCounts>,
+        category: Option<&str>,
+        top_k: usize,
+        z: f64,
+    ) -> (Vec<(Uuid, f32)>, u64) {
+        let features = self.item_features.read().await;
+        let expiries = self.item_expiries.read().await;
+        let now = Utc::now();
+
+        let eligible: Vec<(&Uuid, &ItemInteractionCounts)> = interactions
+            .iter()
+            .filter(|(item_id, _)| match features.get(item_id) {
+                Some(feature) => category.is_none_or(|category| feature.category == category),
+                None => false,
+            })
+            .filter(|(item_id, _)| expiries.get(item_id).is_none_or(|expires_at| *expires_at > now))
+            .collect();
+        let trials = eligible.iter().map(|(_, counts)| counts.trials()).sum();
+
+        let mut results: Vec<(Uuid, f32)> = eligible
+            .into_iter()
+            .map(|(item_id, counts)| {
+                (*item_id, wilson_lower_bound(counts.positives, counts.trials(), z) as f32)
+            })
+            .collect();
+
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        results.truncate(top_k);
+        (results, trials)
+    }
+
+    /// Maps an incoming embedding into the index space: the configured projection first, then
+    /// `dimension_adapt` for any remaining length mismatch.
+    pub fn adapt_dimension(&self, embedding: &mut Vec<f32>, kind: &str, id: Uuid) {
+        if let Some(projection) = &self.projection {
+            if embedding.len() == projection.input_dim() && embedding.len() != projection.output_dim() {
+                // The length was just checked, so projecting cannot fail
+                if let Ok(projected) = projection.project(embedding) {
+                    *embedding = projected;
+                }
+            }
+        }
+
+        let original_len = embedding.len();
+        let dimension = self.config.recommendation.embedding_dim;
+        if adapt_embedding_dimension(embedding, dimension, self.config.recommendation.dimension_adapt) {
+            warn!("Adapted {} embedding for {} from dimension {} to {}", kind, id, original_len, dimension);
+        }
+    }
+
+    /// Checks that every embedding of an incoming item fits the index once projected and adapted,
+    /// so a mismatched item is refused up front instead of being half-indexed.
+    pub fn validate_item_dimensions(&self, feature: &ItemFeature) -> Result<()> {
+        let dimension = self.config.recommendation.embedding_dim;
+        let accepted = |len: usize| {
+            len == dimension
+                || self.projection.as_ref().is_some_and(|projection| projection.input_dim() == len)
+                || match self.config.recommendation.dimension_adapt {
+                    DimensionAdapt::Reject => false,
+                    DimensionAdapt::PadZero => len < dimension,
+                    DimensionAdapt::Truncate => len > dimension,
+                }
+        };
+
+        if !accepted(feature.embedding.len()) {
+            validate_embedding_dimension(&feature.embedding, dimension)?;
+        }
+        for (version, embedding) in &feature.embedding_versions {
+            if !accepted(embedding.len()) {
+                validate_embedding_dimension(embedding, dimension)
+                    .map_err(|e| e.context(format!("Embedding version {}", version)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps every embedding of an incoming item into the index space and, with
+    /// `auto_normalize_embeddings`, scales it to unit length as the item index expects.
+    pub fn prepare_item_feature(&self, feature: &mut ItemFeature) {
+        self.adapt_dimension(&mut feature.embedding, "item", feature.item_id);
+        for embedding in feature.embedding_versions.values_mut() {
+            self.adapt_dimension(embedding, "item", feature.item_id);
+        }
+
+        if self.config.recommendation.auto_normalize_embeddings {
+            normalize_vector(&mut feature.embedding);
+            for embedding in feature.embedding_versions.values_mut() {
+                normalize_vector(embedding);
+            }
+        }
+    }
+
+    pub async fn index_stats(&self) -> IndexStats {
+        let users = self.user_retriever.read().await.stats();
+        let items = self.item_retriever.read().await.stats();
+        let item_versions: HashMap<String, RetrieverStats> = self.versioned_item_retrievers
+            .read()
+            .await
+            .iter()
+            .map(|(version, retriever)| (version.clone(), retriever.stats()))
+            .collect();
+        let estimated_bytes = users.estimated_bytes
+            + items.estimated_bytes
+            + item_versions.values().map(|stats| stats.estimated_bytes).sum::<usize>();
+
+        IndexStats {
+            users,
+            items,
+            item_versions,
+            quarantined_items: self.quarantined_items.read().await.len(),
+            expiring_items: self.item_expiries.read().await.len(),
+            estimated_bytes,
+        }
+    }
+
+    fn new_item_retriever(&self) -> InMemoryRetriever {
+        InMemoryRetriever::new(self.config.milvus.dimension)
+            .with_f64_accumulation(self.config.milvus.f64_accumulation)
+            .with_cached_norms(self.config.milvus.cache_vector_norms)
+            .with_normalized_vectors(self.config.recommendation.auto_normalize_embeddings)
+            // Already validated at construction
+            .with_metric(in_memory_metric(&self.config).unwrap_or_default())
+    }
+
     pub async fn get_user_profile(&self, user_id: Uuid) -> Result<Option<UserProfile>> {
         let profiles = self.user_profiles.read().await;
         Ok(profiles.get(&user_id).cloned())
     }
 
+    /// The profiles of those of `user_ids` that exist, under one read lock.
+    pub async fn get_user_profiles(&self, user_ids: &[Uuid]) -> HashMap<Uuid, UserProfile> {
+        let profiles = self.user_profiles.read().await;
+        user_ids
+            .iter()
+            .filter_map(|user_id| Some((*user_id, profiles.get(user_id)?.clone())))
+            .collect()
+    }
+
+    /// The features of those of `item_ids` that exist, under one read lock.
+    pub async fn get_item_features(&self, item_ids: &[Uuid]) -> HashMap<Uuid, ItemFeature> {
+        let features = self.item_features.read().await;
+        item_ids
+            .iter()
+            .filter_map(|item_id| Some((*item_id, features.get(item_id)?.clone())))
+            .collect()
+    }
+
     pub async fn get_item_feature(&self, item_id: Uuid) -> Result<Option<ItemFeature>> {
         let features = self.item_features.read().await;
         Ok(features.get(&item_id).cloned())
     }
 
     pub async fn update_user_embedding(&self, user_id: Uuid, new_embedding: Vec<f32>) -> Result<()> {
+        self.ensure_writable()?;
         // Update in retriever
         {
             let mut retriever = self.user_retriever.write().await;
             retriever.update_vector(user_id, new_embedding.clone()).await?;
         }
 
-        // Update profile
-        {
+        // Update profile, persisting it once the lock is released
+        let updated = {
             let mut profiles = self.user_profiles.write().await;
-            if let Some(profile) = profiles.get_mut(&user_id) {
+            profiles.get_mut(&user_id).map(|profile| {
                 profile.update_embedding(new_embedding);
-            }
+                profile.clone()
+            })
+        };
+        if let Some(profile) = updated {
+            self.persist_user_profile(&profile).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies per-dimension deltas to a user embedding without rewriting the whole vector.
+    pub async fn update_user_embedding_sparse(&self, user_id: Uuid, deltas: &[(usize, f32)]) -> Result<()> {
+        self.ensure_writable()?;
+        {
+            let mut retriever = self.user_retriever.write().await;
+            retriever.update_vector_sparse(user_id, deltas).await?;
+        }
+
+        let updated = {
+            let mut profiles = self.user_profiles.write().await;
+            profiles.get_mut(&user_id).map(|profile| {
+                for &(index, delta) in deltas {
+                    profile.embedding[index] += delta;
+                }
+                profile.touch();
+                profile.clone()
+            })
+        };
+        if let Some(profile) = updated {
+            self.persist_user_profile(&profile).await?;
         }
 
         Ok(())
     }
 
-    pub async fn update_item_embedding(&self, item_id: Uuid, new_embedding: Vec<f32>) -> Result<()> {
+    pub async fn update_item_embedding(&self, item_id: Uuid, mut new_embedding: Vec<f32>) -> Result<()> {
+        self.ensure_writable()?;
+        if self.config.recommendation.auto_normalize_embeddings {
+            normalize_vector(&mut new_embedding);
+        }
         // Update in retriever
         {
             let mut retriever = self.item_retriever.write().await;
@@ -122,25 +1436,131 @@ impl VectorDbService {
             let mut features = self.item_features.write().await;
             if let Some(feature) = features.get_mut(&item_id) {
                 feature.embedding = new_embedding;
+                self.persist_item_feature(feature).await?;
             }
         }
 
         Ok(())
     }
 
+    /// Makes every metadata store write so far durable; a no-op without a store.
+    pub async fn flush_metadata(&self) -> Result<()> {
+        match &self.metadata_store {
+            Some(store) => store.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn persist_user_profile(&self, profile: &UserProfile) -> Result<()> {
+        match &self.metadata_store {
+            Some(store) => store.put_user_profile(profile).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn persist_item_feature(&self, feature: &ItemFeature) -> Result<()> {
+        match &self.metadata_store {
+            Some(store) => store.put_item_feature(feature).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Inserts profiles `milvus.batch_insert_chunk_size` at a time, taking each lock once per chunk.
+    /// On failure the profiles before the failing one are kept and the error names the failing id.
     pub async fn batch_insert_profiles(&self, profiles: &[UserProfile]) -> Result<()> {
-        for profile in profiles {
-            self.insert_user_profile(profile).await?;
+        self.ensure_writable()?;
+        for chunk in profiles.chunks(self.config.milvus.batch_insert_chunk_size.max(1)) {
+            let mut chunk = chunk.to_vec();
+            for profile in chunk.iter_mut() {
+                self.adapt_dimension(&mut profile.embedding, "user", profile.user_id);
+            }
+
+            let indexed = self.index_user_profiles(&chunk).await;
+            let persisted = match &indexed {
+                Ok(()) => chunk.len(),
+                Err((i, _)) => *i,
+            };
+            for profile in &chunk[..persisted] {
+                self.persist_user_profile(profile)
+                    .await
+                    .map_err(|e| e.context(format!("Failed to insert user profile {}", profile.user_id)))?;
+            }
+            indexed.map_err(|(i, e)| e.context(format!("Failed to insert user profile {}", chunk[i].user_id)))?;
         }
         info!("Batch inserted {} user profiles", profiles.len());
         Ok(())
     }
 
+    /// Inserts features `milvus.batch_insert_chunk_size` at a time, taking each lock once per chunk.
+    /// Outlier checks still run per item in order; on the first failure the features before it are
+    /// kept and the error names the failing id.
     pub async fn batch_insert_features(&self, features: &[ItemFeature]) -> Result<()> {
-        for feature in features {
-            self.insert_item_feature(feature).await?;
+        self.ensure_writable()?;
+        for chunk in features.chunks(self.config.milvus.batch_insert_chunk_size.max(1)) {
+            let mut prepared = Vec::with_capacity(chunk.len());
+            let mut failure = None;
+            for feature in chunk {
+                let mut feature = feature.clone();
+                self.prepare_item_feature(&mut feature);
+                if let Err(e) = self.check_item_outlier(&feature).await {
+                    failure = Some((feature.item_id, e));
+                    break;
+                }
+                prepared.push(feature);
+            }
+
+            // An indexing failure is earlier in the chunk than any outlier failure
+            if let Err((i, e)) = self.index_item_features(&prepared).await {
+                failure = Some((prepared[i].item_id, e));
+                prepared.truncate(i);
+            }
+            for feature in &prepared {
+                self.persist_item_feature(feature)
+                    .await
+                    .map_err(|e| e.context(format!("Failed to insert item feature {}", feature.item_id)))?;
+            }
+            if let Some((item_id, e)) = failure {
+                return Err(e.context(format!("Failed to insert item feature {}", item_id)));
+            }
         }
         info!("Batch inserted {} item features", features.len());
         Ok(())
     }
+
+    pub async fn get_quarantined_items(&self) -> Vec<ItemFeature> {
+        self.quarantined_items.read().await.values().cloned().collect()
+    }
+
+    /// Rejects embeddings whose norm or distance to the catalog mean is too many standard deviations
+    /// away from what has been ingested so far; accepted embeddings update the running statistics.
+    async fn check_item_outlier(&self, feature: &ItemFeature) -> Result<()> {
+        let outlier_config = &self.config.outlier_detection;
+        if !outlier_config.enabled {
+            return Ok(());
+        }
+
+        let mut stats = self.item_embedding_stats.write().await;
+        if stats.count >= outlier_config.min_samples && stats.mean.len() == feature.embedding.len() {
+            let (norm_z, distance_z) = stats.z_scores(&feature.embedding);
+            if norm_z > outlier_config.norm_z_threshold as f64
+                || distance_z > outlier_config.distance_z_threshold as f64
+            {
+                if outlier_config.quarantine {
+                    self.quarantined_items.write().await.insert(feature.item_id, feature.clone());
+                }
+                warn!("Rejected outlier embedding for item {} (norm z={:.2}, distance z={:.2})", feature.item_id, norm_z, distance_z);
+                return Err(OutlierRejected {
+                    item_id: feature.item_id,
+                    norm_z,
+                    norm_z_threshold: outlier_config.norm_z_threshold,
+                    distance_z,
+                    distance_z_threshold: outlier_config.distance_z_threshold,
+                }
+                .into());
+            }
+        }
+
+        stats.update(&feature.embedding);
+        Ok(())
+    }
 }
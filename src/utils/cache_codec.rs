@@ -0,0 +1,74 @@
+use crate::config::CacheCodec;
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Leading bytes of binary cache payloads. JSON never starts with these, which is how payloads
+/// written before a codec change are still read.
+const BINCODE_MAGIC: &[u8; 4] = b"MRB1";
+const BINCODE_ZSTD_MAGIC: &[u8; 4] = b"MRZ1";
+const ZSTD_LEVEL: i32 = 3;
+
+/// Serializes a value for the cache with `codec`. Binary payloads carry a header naming their format.
+pub fn encode_payload<T: Serialize>(value: &T, codec: CacheCodec) -> Result<Vec<u8>> {
+    match codec {
+        CacheCodec::Json => Ok(serde_json::to_vec(value)?),
+        CacheCodec::Bincode => {
+            let mut bytes = BINCODE_MAGIC.to_vec();
+            bincode::serialize_into(&mut bytes, value)?;
+            Ok(bytes)
+        }
+        CacheCodec::BincodeZstd => {
+            let body = zstd::encode_all(bincode::serialize(value)?.as_slice(), ZSTD_LEVEL)?;
+            let mut bytes = BINCODE_ZSTD_MAGIC.to_vec();
+            bytes.extend_from_slice(&body);
+            Ok(bytes)
+        }
+    }
+}
+
+/// Deserializes a cache payload written by any codec, detecting the format from its header.
+pub fn decode_payload<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    if let Some(body) = bytes.strip_prefix(BINCODE_MAGIC) {
+        return bincode::deserialize(body).map_err(|e| anyhow!("Corrupt bincode cache payload: {}", e));
+    }
+    if let Some(body) = bytes.strip_prefix(BINCODE_ZSTD_MAGIC) {
+        let body = zstd::decode_all(body)?;
+        return bincode::deserialize(&body).map_err(|e| anyhow!("Corrupt compressed cache payload: {}", e));
+    }
+
+    Ok(serde_json::from_slice(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::UserProfile;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_payload_round_trip_for_every_codec() {
+        let mut profile = UserProfile::new(Uuid::new_v4(), 128);
+        profile.embedding = (0..128).map(|i| (i as f32 * 0.37).sin()).collect();
+        profile.preferences = vec!["books".to_string(), "music".to_string()];
+        profile.interaction_count = 42;
+        let expected = serde_json::to_value(&profile).unwrap();
+
+        let json_len = encode_payload(&profile, CacheCodec::Json).unwrap().len();
+        for codec in [CacheCodec::Json, CacheCodec::Bincode, CacheCodec::BincodeZstd] {
+            let bytes = encode_payload(&profile, codec).unwrap();
+            let decoded: UserProfile = decode_payload(&bytes).unwrap();
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), expected, "{:?}", codec);
+            if codec != CacheCodec::Json {
+                assert!(bytes.len() < json_len, "{:?} payload is not smaller than JSON", codec);
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupt_binary_payload_is_an_error() {
+        let mut bytes = BINCODE_MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 2, 3]);
+        assert!(decode_payload::<UserProfile>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,21 @@
+use axum::error_handling::HandleErrorLayer;
+use axum::http::StatusCode;
+use axum::routing::MethodRouter;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+use tower::{BoxError, ServiceBuilder};
+
+/// Bounds `route` to `max_in_flight` concurrent requests. Requests past the limit are answered
+/// with `503` immediately rather than queued, so a burst on one endpoint cannot back up the rest.
+pub fn limit_concurrency<S>(route: MethodRouter<S>, max_in_flight: usize) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    route.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(|_: BoxError| async { StatusCode::SERVICE_UNAVAILABLE }))
+            .layer(LoadShedLayer::new())
+            // Shares one semaphore however often axum instantiates the layered route
+            .layer(GlobalConcurrencyLimitLayer::new(max_in_flight)),
+    )
+}
@@ -1,3 +1,5 @@
+use crate::models::DecayingCount;
+use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -9,6 +11,12 @@ pub struct RecommendationMetrics {
     pub f1_score: f64,
     pub ndcg_at_k: f64,
     pub map_score: f64,
+    /// Reciprocal rank of the first relevant item in the top k, 0 without one.
+    #[serde(default)]
+    pub mrr: f64,
+    /// 1 when any relevant item is in the top k, else 0.
+    #[serde(default)]
+    pub hit_rate: f64,
     pub coverage: f64,
     pub diversity: f64,
     pub novelty: f64,
@@ -54,6 +62,26 @@ impl MetricsCalculator {
         relevant_recommended as f64 / relevant.len() as f64
     }
 
+    /// `1 / rank` of the first relevant item in the top k, or 0 if none made it.
+    pub fn calculate_mrr(&self, recommended: &[Uuid], relevant: &[Uuid]) -> f64 {
+        let relevant_set: std::collections::HashSet<_> = relevant.iter().collect();
+        recommended
+            .iter()
+            .take(self.k)
+            .position(|item| relevant_set.contains(item))
+            .map_or(0.0, |index| 1.0 / (index + 1) as f64)
+    }
+
+    /// 1.0 if any relevant item is in the top k, else 0.0.
+    pub fn calculate_hit_rate(&self, recommended: &[Uuid], relevant: &[Uuid]) -> f64 {
+        let relevant_set: std::collections::HashSet<_> = relevant.iter().collect();
+        if recommended.iter().take(self.k).any(|item| relevant_set.contains(item)) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
     pub fn calculate_f1_score(&self, precision: f64, recall: f64) -> f64 {
         if precision + recall == 0.0 {
             0.0
@@ -81,7 +109,7 @@ impl MetricsCalculator {
             .map(|(i, item_id)| {
                 let relevance = relevant_scores.get(item_id).unwrap_or(&0.0);
                 let position = i + 1;
-                relevance / (position as f64).log2()
+                relevance / ((position + 1) as f64).log2()
             })
             .sum()
     }
@@ -96,7 +124,7 @@ impl MetricsCalculator {
             .enumerate()
             .map(|(i, &score)| {
                 let position = i + 1;
-                score / (position as f64).log2()
+                score / ((position + 1) as f64).log2()
             })
             .sum()
     }
@@ -180,7 +208,43 @@ impl MetricsCalculator {
         }
     }
 
+    /// Mean self-information `-log2(popularity)` of the recommended items, using each item's static
+    /// share of interactions.
     pub fn calculate_novelty(&self, recommended_items: &[Uuid], item_popularity: &HashMap<Uuid, f64>) -> f64 {
+        self.novelty_from(recommended_items, |item_id| item_popularity.get(item_id).copied().unwrap_or(0.0))
+    }
+
+    /// Like `calculate_novelty`, with each item's popularity its share of recency-weighted
+    /// interactions as of `now`: an interaction loses half its weight per `half_life_seconds`, so an
+    /// item that was popular long ago and has gone dormant counts as novel again.
+    pub fn calculate_novelty_at(
+        &self,
+        recommended_items: &[Uuid],
+        item_interactions: &HashMap<Uuid, Vec<DateTime<Utc>>>,
+        now: DateTime<Utc>,
+        half_life_seconds: f64,
+    ) -> f64 {
+        let weights: HashMap<Uuid, f64> = item_interactions
+            .iter()
+            .map(|(item_id, timestamps)| {
+                let mut count = DecayingCount::default();
+                for &at in timestamps {
+                    count.add(at, half_life_seconds);
+                }
+                (*item_id, count.value_at(now, half_life_seconds))
+            })
+            .collect();
+        let total_weight: f64 = weights.values().sum();
+        if total_weight <= 0.0 {
+            return 0.0;
+        }
+
+        self.novelty_from(recommended_items, |item_id| {
+            weights.get(item_id).map_or(0.0, |weight| weight / total_weight)
+        })
+    }
+
+    fn novelty_from(&self, recommended_items: &[Uuid], popularity: impl Fn(&Uuid) -> f64) -> f64 {
         if recommended_items.is_empty() {
             return 0.0;
         }
@@ -188,8 +252,8 @@ impl MetricsCalculator {
         let total_novelty: f64 = recommended_items
             .iter()
             .map(|item_id| {
-                let popularity = item_popularity.get(item_id).unwrap_or(&0.0);
-                if *popularity > 0.0 {
+                let popularity = popularity(item_id);
+                if popularity > 0.0 {
                     -popularity.log2()
                 } else {
                     0.0
@@ -213,6 +277,8 @@ impl MetricsCalculator {
         let recall = self.calculate_recall_at_k(recommended, relevant);
         let f1_score = self.calculate_f1_score(precision, recall);
         let ndcg = self.calculate_ndcg_at_k(recommended, relevant_scores);
+        let mrr = self.calculate_mrr(recommended, relevant);
+        let hit_rate = self.calculate_hit_rate(recommended, relevant);
         let coverage = self.calculate_coverage(recommended, all_items);
         let diversity = self.calculate_diversity(recommended, item_features);
         let novelty = self.calculate_novelty(recommended, item_popularity);
@@ -223,6 +289,8 @@ impl MetricsCalculator {
             f1_score,
             ndcg_at_k: ndcg,
             map_score: 0.0, // Would need multiple queries to calculate MAP
+            mrr,
+            hit_rate,
             coverage,
             diversity,
             novelty,
@@ -230,6 +298,98 @@ impl MetricsCalculator {
     }
 }
 
+/// A candidate item with its model score and whether it was actually relevant to the user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredCandidate {
+    pub item_id: Uuid,
+    pub score: f32,
+    pub relevant: bool,
+}
+
+/// Offline evaluation over a fixed set of scored candidates, for tuning the similarity threshold.
+#[derive(Debug, Clone)]
+pub struct Evaluator {
+    k: usize,
+    candidates: Vec<ScoredCandidate>,
+    total_relevant: usize,
+}
+
+impl Evaluator {
+    pub fn new(k: usize, mut candidates: Vec<ScoredCandidate>) -> Self {
+        candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        let total_relevant = candidates.iter().filter(|c| c.relevant).count();
+        Self { k, candidates, total_relevant }
+    }
+
+    /// Metrics when recommending every candidate scoring at least `threshold`, with the same
+    /// @k semantics as `MetricsCalculator`. Results are returned in the order of `thresholds`.
+    ///
+    /// Thresholds are visited from highest to lowest so one pass over the score-sorted
+    /// candidates accumulates hits and DCG for all of them.
+    pub fn sweep_thresholds(&self, thresholds: &[f32]) -> Vec<(f32, RecommendationMetrics)> {
+        let mut order: Vec<usize> = (0..thresholds.len()).collect();
+        order.sort_by(|&a, &b| thresholds[b].partial_cmp(&thresholds[a]).unwrap_or(std::cmp::Ordering::Equal));
+
+        let ideal_dcg: f64 = (0..self.k.min(self.total_relevant))
+            .map(|i| 1.0 / ((i + 2) as f64).log2())
+            .sum();
+
+        let mut results = vec![None; thresholds.len()];
+        let mut recommended = 0;
+        let mut hits_at_k = 0;
+        let mut first_hit_rank = None;
+        let mut dcg = 0.0;
+
+        for index in order {
+            let threshold = thresholds[index];
+            while recommended < self.candidates.len() && self.candidates[recommended].score >= threshold {
+                if recommended < self.k && self.candidates[recommended].relevant {
+                    hits_at_k += 1;
+                    first_hit_rank.get_or_insert(recommended + 1);
+                    dcg += 1.0 / ((recommended + 2) as f64).log2();
+                }
+                recommended += 1;
+            }
+
+            let precision = if recommended == 0 {
+                0.0
+            } else {
+                hits_at_k as f64 / self.k.min(recommended) as f64
+            };
+            let recall = if self.total_relevant == 0 {
+                0.0
+            } else {
+                hits_at_k as f64 / self.total_relevant as f64
+            };
+            let f1_score = if precision + recall == 0.0 {
+                0.0
+            } else {
+                2.0 * precision * recall / (precision + recall)
+            };
+            let coverage = if self.candidates.is_empty() {
+                0.0
+            } else {
+                recommended as f64 / self.candidates.len() as f64
+            };
+
+            results[index] = Some((threshold, RecommendationMetrics {
+                precision_at_k: precision,
+                recall_at_k: recall,
+                f1_score,
+                ndcg_at_k: if ideal_dcg == 0.0 { 0.0 } else { dcg / ideal_dcg },
+                map_score: 0.0, // Would need multiple queries to calculate MAP
+                mrr: first_hit_rank.map_or(0.0, |rank| 1.0 / rank as f64),
+                hit_rate: if hits_at_k > 0 { 1.0 } else { 0.0 },
+                coverage,
+                diversity: 0.0, // Needs item features, not available from scores alone
+                novelty: 0.0, // Needs item popularity, not available from scores alone
+            }));
+        }
+
+        results.into_iter().flatten().collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct OnlineMetrics {
     pub click_through_rate: f64,
@@ -249,6 +409,12 @@ pub struct OnlineMetricsCalculator {
     total_session_time: f64,
 }
 
+impl Default for OnlineMetricsCalculator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OnlineMetricsCalculator {
     pub fn new() -> Self {
         Self {
@@ -326,3 +492,150 @@ impl OnlineMetricsCalculator {
         self.total_session_time = 0.0;
     }
 }
+
+/// Upper bounds of the default score buckets, covering cosine similarity from -1 to 1.
+pub const DEFAULT_SCORE_BUCKETS: [f64; 13] = [-1.0, -0.5, 0.0, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0];
+
+/// Prometheus-style histogram: counts of observations at or below each bucket's upper bound, plus
+/// their sum and total count.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    /// Per bucket, not cumulative; the last entry counts values above every bound.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    /// Buckets with the given upper bounds, sorted ascending; an implicit `+Inf` bucket follows.
+    pub fn new(bounds: &[f64]) -> Self {
+        let mut bounds = bounds.to_vec();
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        Self {
+            counts: vec![0; bounds.len() + 1],
+            bounds,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// `(upper bound, observations at or below it)` for every bucket, ending with `+Inf`.
+    pub fn cumulative_buckets(&self) -> Vec<(f64, u64)> {
+        let mut total = 0;
+        self.bounds
+            .iter()
+            .copied()
+            .chain(std::iter::once(f64::INFINITY))
+            .zip(&self.counts)
+            .map(|(bound, count)| {
+                total += count;
+                (bound, total)
+            })
+            .collect()
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The histogram in the Prometheus text exposition format, as `name`.
+    pub fn render(&self, name: &str, help: &str) -> String {
+        let mut text = format!("# HELP {name} {help}\n# TYPE {name} histogram\n");
+        for (bound, count) in self.cumulative_buckets() {
+            let le = if bound.is_infinite() { "+Inf".to_string() } else { bound.to_string() };
+            text.push_str(&format!("{name}_bucket{{le=\"{le}\"}} {count}\n"));
+        }
+        text.push_str(&format!("{name}_sum {}\n{name}_count {}\n", self.sum, self.count));
+        text
+    }
+}
+
+/// Registers of a `HyperLogLog` are addressed by this many hash bits; 2^12 registers estimate
+/// within about 1.6%.
+const HYPERLOGLOG_PRECISION: u32 = 12;
+
+/// Approximate count of distinct ids in constant memory (4 KiB), however many are added.
+#[derive(Debug, Clone)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self { registers: vec![0; 1 << HYPERLOGLOG_PRECISION] }
+    }
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: Uuid) {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        id.hash(&mut hasher);
+        let hash = hasher.finish();
+        let register = (hash >> (64 - HYPERLOGLOG_PRECISION)) as usize;
+        // Position of the first set bit among the rest, capped for a remainder of all zeros
+        let rank = ((hash << HYPERLOGLOG_PRECISION).leading_zeros() + 1).min(64 - HYPERLOGLOG_PRECISION + 1) as u8;
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    /// Estimated number of distinct ids inserted, by linear counting while registers are still
+    /// empty (where the raw estimate is biased).
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let harmonic: f64 = self.registers.iter().map(|register| 2f64.powi(-(*register as i32))).sum();
+        let raw = alpha * m * m / harmonic;
+        let empty = self.registers.iter().filter(|register| **register == 0).count();
+        let estimate = if raw <= 2.5 * m && empty > 0 {
+            m * (m / empty as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mrr_and_hit_rate() {
+        let calculator = MetricsCalculator::new(3);
+        let items: Vec<Uuid> = (1..=5u128).map(Uuid::from_u128).collect();
+
+        // No relevant item in the top 3, even though one is ranked fourth
+        let missed = [items[3]];
+        assert_eq!(calculator.calculate_mrr(&items, &missed), 0.0);
+        assert_eq!(calculator.calculate_hit_rate(&items, &missed), 0.0);
+        assert_eq!(calculator.calculate_mrr(&items, &[]), 0.0);
+        assert_eq!(calculator.calculate_hit_rate(&[], &missed), 0.0);
+
+        // First position
+        let first = [items[0], items[2]];
+        assert_eq!(calculator.calculate_mrr(&items, &first), 1.0);
+        assert_eq!(calculator.calculate_hit_rate(&items, &first), 1.0);
+
+        let third = [items[2]];
+        assert!((calculator.calculate_mrr(&items, &third) - 1.0 / 3.0).abs() < 1e-12);
+        assert_eq!(calculator.calculate_hit_rate(&items, &third), 1.0);
+    }
+}
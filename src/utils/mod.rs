@@ -1,8 +1,13 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
+pub mod cache_codec;
+pub mod concurrency;
 pub mod metrics;
+pub mod request_log;
+pub mod shutdown;
+pub mod snapshot;
 pub mod validation;
 
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
@@ -21,6 +26,46 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     }
 }
 
+/// Same as `cosine_similarity` but accumulates the dot product and norms in f64, which keeps
+/// rankings stable for high-dimensional or badly scaled vectors.
+pub fn cosine_similarity_f64(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    
+    let dot_product: f64 = a.iter().zip(b.iter()).map(|(x, y)| *x as f64 * *y as f64).sum();
+    let norm_a: f64 = a.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    let norm_b: f64 = b.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        (dot_product / (norm_a * norm_b)) as f32
+    }
+}
+
+/// |a ∩ b| / |a ∪ b|. Two empty sets share nothing, so they score 0.
+pub fn jaccard_similarity(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let intersection = a.intersection(b).count();
+    let union = a.len() + b.len() - intersection;
+    
+    if union == 0 {
+        0.0
+    } else {
+        intersection as f32 / union as f32
+    }
+}
+
+/// |a ∩ b| / min(|a|, |b|), which unlike Jaccard does not penalize a small set for being contained in a large one.
+pub fn overlap_coefficient(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let smaller = a.len().min(b.len());
+    if smaller == 0 {
+        return 0.0;
+    }
+    
+    a.intersection(b).count() as f32 / smaller as f32
+}
+
 pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() {
         return f32::INFINITY;
@@ -104,6 +149,11 @@ pub fn top_k_indices(scores: &[f32], k: usize) -> Vec<usize> {
         .collect()
 }
 
+/// Derives a user id from an external identifier.
+///
+/// `DefaultHasher` is not guaranteed to hash the same way across Rust releases, so ids may change
+/// on a toolchain upgrade. Migrate this to `fnv1a_64` (as `shard_for` uses) once existing ids can
+/// be remapped.
 pub fn generate_user_id_from_string(user_str: &str) -> Uuid {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
@@ -121,6 +171,29 @@ pub fn generate_user_id_from_string(user_str: &str) -> Uuid {
     Uuid::from_bytes(uuid_bytes)
 }
 
+/// 64-bit FNV-1a, a hash that is stable across platforms and Rust releases.
+pub fn fnv1a_64(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
+
+/// Shard in `0..num_shards` that serves `user_id`, the same on every node and release. Uses jump
+/// consistent hashing, so growing from `n` to `n + 1` shards only moves about `1 / (n + 1)` of users.
+/// `num_shards` of 0 is treated as a single shard.
+pub fn shard_for(user_id: Uuid, num_shards: usize) -> usize {
+    let mut key = fnv1a_64(user_id.as_bytes());
+    let mut shard: i64 = -1;
+    let mut next: i64 = 0;
+    while next < num_shards.max(1) as i64 {
+        shard = next;
+        key = key.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        next = ((shard + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    shard as usize
+}
+
 pub fn calculate_diversity_score(items: &[Uuid], item_categories: &HashMap<Uuid, String>) -> f32 {
     if items.len() <= 1 {
         return 0.0;
@@ -143,6 +216,22 @@ pub fn calculate_diversity_score(items: &[Uuid], item_categories: &HashMap<Uuid,
     }
 }
 
+/// Lower bound of the Wilson score interval for `positives` out of `total` trials. Ranking by it
+/// favors items whose engagement rate is backed by volume over ones with few, lucky interactions.
+pub fn wilson_lower_bound(positives: u64, total: u64, z: f64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    
+    let n = total as f64;
+    let p = (positives.min(total) as f64) / n;
+    let z2 = z * z;
+    let center = p + z2 / (2.0 * n);
+    let margin = z * ((p * (1.0 - p) + z2 / (4.0 * n)) / n).sqrt();
+    
+    (center - margin) / (1.0 + z2 / n)
+}
+
 pub fn exponential_decay_weight(timestamp: chrono::DateTime<chrono::Utc>, decay_rate: f64) -> f32 {
     let now = chrono::Utc::now();
     let time_diff = now.signed_duration_since(timestamp).num_seconds() as f64;
@@ -229,6 +318,32 @@ mod tests {
         assert!((cosine_similarity(&a, &b) - 1.0).abs() < 1e-6);
     }
 
+    #[test]
+    fn test_set_similarity() {
+        let set = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<HashSet<String>>();
+        let empty = set(&[]);
+        let a = set(&["rust", "async", "web"]);
+        let b = set(&["rust", "web", "db", "orm"]);
+        
+        assert_eq!(jaccard_similarity(&empty, &empty), 0.0);
+        assert_eq!(jaccard_similarity(&a, &empty), 0.0);
+        assert!((jaccard_similarity(&a, &a) - 1.0).abs() < 1e-6);
+        assert!((jaccard_similarity(&a, &b) - 2.0 / 5.0).abs() < 1e-6);
+        assert_eq!(jaccard_similarity(&a, &b), jaccard_similarity(&b, &a));
+        
+        assert_eq!(overlap_coefficient(&a, &empty), 0.0);
+        assert!((overlap_coefficient(&a, &b) - 2.0 / 3.0).abs() < 1e-6);
+        assert!((overlap_coefficient(&set(&["rust"]), &a) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_wilson_lower_bound() {
+        assert_eq!(wilson_lower_bound(0, 0, 1.96), 0.0);
+        assert!(wilson_lower_bound(8000, 10000, 1.96) > wilson_lower_bound(5, 5, 1.96));
+        assert!(wilson_lower_bound(5, 5, 1.96) < 1.0);
+        assert!((wilson_lower_bound(500_000, 1_000_000, 1.96) - 0.5).abs() < 1e-2);
+    }
+
     #[test]
     fn test_normalize_vector() {
         let mut v = vec![3.0, 4.0];
@@ -237,6 +352,37 @@ mod tests {
         assert!((norm - 1.0).abs() < 1e-6);
     }
 
+    #[test]
+    fn test_shard_for_is_stable_and_uniform() {
+        // Pinned, so a change to the hash (and so to every user's shard) fails loudly
+        assert_eq!(fnv1a_64(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a_64(b"a"), 0xaf63_dc4c_8601_ec8c);
+
+        let user_id = Uuid::from_u128(42);
+        assert_eq!(shard_for(user_id, 16), shard_for(user_id, 16));
+        assert_eq!(shard_for(user_id, 1), 0);
+        assert_eq!(shard_for(user_id, 0), 0);
+
+        let num_shards = 8;
+        let users = 80_000;
+        let mut counts = vec![0usize; num_shards];
+        let mut moved = 0;
+        for i in 0..users {
+            let user_id = Uuid::from_u128(i as u128 * 7_919 + 1);
+            let shard = shard_for(user_id, num_shards);
+            counts[shard] += 1;
+            if shard_for(user_id, num_shards + 1) != shard {
+                moved += 1;
+            }
+        }
+        let expected = users / num_shards;
+        for count in counts {
+            assert!(count.abs_diff(expected) < expected / 20, "shard holds {} users, expected ~{}", count, expected);
+        }
+        // Adding a ninth shard moves only the users it takes over
+        assert!((moved as f64 / users as f64 - 1.0 / 9.0).abs() < 0.01);
+    }
+
     #[test]
     fn test_top_k_indices() {
         let scores = vec![0.1, 0.5, 0.3, 0.9, 0.2];
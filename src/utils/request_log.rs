@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Per-request log level requested by the caller, e.g. via the `x-log-verbosity` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogVerbosity {
+    /// Successful requests are logged according to the sampling rate.
+    #[default]
+    Sampled,
+    /// Always log this request, including its details.
+    Verbose,
+}
+
+impl LogVerbosity {
+    /// Parses a header value; anything other than `verbose` or `debug` falls back to sampling.
+    pub fn from_header_value(value: Option<&str>) -> Self {
+        match value.map(|value| value.trim().to_ascii_lowercase()).as_deref() {
+            Some("verbose") | Some("debug") => LogVerbosity::Verbose,
+            _ => LogVerbosity::Sampled,
+        }
+    }
+}
+
+/// Decides which handler outcomes get logged: 1 in `sample_every` successful requests, every
+/// failure, and every request that asked for verbose logging. A rate of 0 logs no successes.
+#[derive(Debug)]
+pub struct RequestLogSampler {
+    sample_every: u64,
+    successes: AtomicU64,
+}
+
+impl RequestLogSampler {
+    pub fn new(sample_every: u64) -> Self {
+        Self {
+            sample_every,
+            successes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn should_log(&self, failed: bool, verbosity: LogVerbosity) -> bool {
+        if failed || verbosity == LogVerbosity::Verbose {
+            return true;
+        }
+        if self.sample_every == 0 {
+            return false;
+        }
+
+        self.successes.fetch_add(1, Ordering::Relaxed).is_multiple_of(self.sample_every)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_ratio_and_errors() {
+        let sampler = RequestLogSampler::new(10);
+        let logged = (0..1000)
+            .filter(|_| sampler.should_log(false, LogVerbosity::Sampled))
+            .count();
+        assert_eq!(logged, 100);
+
+        assert!((0..1000).all(|_| sampler.should_log(true, LogVerbosity::Sampled)));
+        assert!((0..1000).all(|_| sampler.should_log(false, LogVerbosity::Verbose)));
+
+        let silent = RequestLogSampler::new(0);
+        assert!(!silent.should_log(false, LogVerbosity::Sampled));
+        assert!(silent.should_log(true, LogVerbosity::Sampled));
+    }
+
+    #[test]
+    fn test_verbosity_header_parsing() {
+        assert_eq!(LogVerbosity::from_header_value(Some("Verbose")), LogVerbosity::Verbose);
+        assert_eq!(LogVerbosity::from_header_value(Some("debug")), LogVerbosity::Verbose);
+        assert_eq!(LogVerbosity::from_header_value(Some("info")), LogVerbosity::Sampled);
+        assert_eq!(LogVerbosity::from_header_value(None), LogVerbosity::Sampled);
+    }
+}
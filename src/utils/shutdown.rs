@@ -0,0 +1,65 @@
+use axum::Router;
+use std::future::Future;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Resolves on ctrl-c, or on SIGTERM where there is one.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Serves `app` until `signal` resolves, then stops accepting connections and lets in-flight
+/// requests finish for up to `drain_timeout`. Requests still running after that are dropped.
+pub async fn serve_with_graceful_shutdown(
+    listener: TcpListener,
+    app: Router,
+    signal: impl Future<Output = ()> + Send + 'static,
+    drain_timeout: Duration,
+) -> std::io::Result<()> {
+    let (draining_tx, mut draining_rx) = watch::channel(false);
+    let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+        let _ = draining_rx.wait_for(|draining| *draining).await;
+    });
+
+    let drain_deadline = async move {
+        signal.await;
+        info!("Shutting down, draining in-flight requests for up to {:?}", drain_timeout);
+        let _ = draining_tx.send(true);
+        tokio::time::sleep(drain_timeout).await;
+    };
+
+    tokio::select! {
+        result = server => result,
+        _ = drain_deadline => {
+            warn!("Drain timeout of {:?} elapsed with requests still in flight", drain_timeout);
+            Ok(())
+        }
+    }
+}
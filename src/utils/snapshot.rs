@@ -0,0 +1,146 @@
+use crate::models::ModelParameters;
+use anyhow::{anyhow, Result};
+
+/// Leading bytes of every model snapshot, so foreign or truncated files fail fast.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"MRRSNAP\0";
+const HEADER_LEN: usize = SNAPSHOT_MAGIC.len() + 4;
+
+/// Format version written by this release. Bump it whenever the `ModelParameters` layout changes,
+/// and upgrade the previous version's body in `SnapshotCodec::decode`.
+pub const MODEL_SNAPSHOT_FORMAT_VERSION: u32 = 3;
+
+/// Version 2 named the embedding maps `user_embedding_weights` and `item_embedding_weights`.
+const RENAMED_EMBEDDINGS_FORMAT_VERSION: u32 = 2;
+
+/// Encodes and decodes model snapshots as a magic + little-endian format version header
+/// followed by the JSON-serialized `ModelParameters`.
+#[derive(Debug, Clone, Copy)]
+pub struct SnapshotCodec {
+    format_version: u32,
+}
+
+impl Default for SnapshotCodec {
+    fn default() -> Self {
+        Self::new(MODEL_SNAPSHOT_FORMAT_VERSION)
+    }
+}
+
+impl SnapshotCodec {
+    pub fn new(format_version: u32) -> Self {
+        Self { format_version }
+    }
+
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    pub fn encode(&self, parameters: &ModelParameters) -> Result<Vec<u8>> {
+        let body = serde_json::to_vec(parameters)?;
+        let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+        bytes.extend_from_slice(SNAPSHOT_MAGIC);
+        bytes.extend_from_slice(&self.format_version.to_le_bytes());
+        bytes.extend_from_slice(&body);
+        Ok(bytes)
+    }
+
+    /// Decodes a snapshot of this codec's format version, or of version 2 when reading version 3.
+    pub fn decode(&self, bytes: &[u8]) -> Result<ModelParameters> {
+        let version = read_format_version(bytes)?;
+        let corrupt = |e: serde_json::Error| anyhow!("Corrupt model snapshot (format version {}): {}", version, e);
+        if version == self.format_version {
+            return serde_json::from_slice(&bytes[HEADER_LEN..]).map_err(corrupt);
+        }
+        if version == RENAMED_EMBEDDINGS_FORMAT_VERSION && self.format_version == MODEL_SNAPSHOT_FORMAT_VERSION {
+            let mut body: serde_json::Value = serde_json::from_slice(&bytes[HEADER_LEN..]).map_err(corrupt)?;
+            if let Some(fields) = body.as_object_mut() {
+                for (old, new) in [("user_embedding_weights", "user_embeddings"), ("item_embedding_weights", "item_embeddings")] {
+                    if let Some(embeddings) = fields.remove(old) {
+                        fields.insert(new.to_string(), embeddings);
+                    }
+                }
+            }
+            return serde_json::from_value(body).map_err(corrupt);
+        }
+
+        Err(anyhow!(
+            "Model snapshot format version {} is incompatible with this reader (expects version {})",
+            version,
+            self.format_version
+        ))
+    }
+}
+
+/// Reads the format version from a snapshot header without decoding the body.
+pub fn read_format_version(bytes: &[u8]) -> Result<u32> {
+    if bytes.len() < HEADER_LEN || &bytes[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+        return Err(anyhow!("Not a model snapshot: missing format header"));
+    }
+
+    let mut version = [0u8; 4];
+    version.copy_from_slice(&bytes[SNAPSHOT_MAGIC.len()..HEADER_LEN]);
+    Ok(u32::from_le_bytes(version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn parameters() -> ModelParameters {
+        ModelParameters {
+            version: "v1".to_string(),
+            user_embeddings: HashMap::from([(Uuid::from_u128(1), vec![0.1, 0.2])]),
+            item_embeddings: HashMap::from([(Uuid::from_u128(2), vec![0.3, 0.4])]),
+            bias_weights: vec![0.0, 0.0],
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let codec = SnapshotCodec::default();
+        let bytes = codec.encode(&parameters()).unwrap();
+        assert_eq!(read_format_version(&bytes).unwrap(), MODEL_SNAPSHOT_FORMAT_VERSION);
+
+        let decoded = codec.decode(&bytes).unwrap();
+        assert_eq!(decoded.item_embeddings[&Uuid::from_u128(2)], vec![0.3, 0.4]);
+    }
+
+    #[test]
+    fn test_snapshot_upgrades_version_2_embedding_names() {
+        let mut body = serde_json::to_value(parameters()).unwrap();
+        let body = body.as_object_mut().unwrap();
+        let users = body.remove("user_embeddings").unwrap();
+        let items = body.remove("item_embeddings").unwrap();
+        body.insert("user_embedding_weights".to_string(), users);
+        body.insert("item_embedding_weights".to_string(), items);
+        let mut bytes = SnapshotCodec::new(RENAMED_EMBEDDINGS_FORMAT_VERSION).encode(&parameters()).unwrap();
+        bytes.truncate(HEADER_LEN);
+        bytes.extend(serde_json::to_vec(&body).unwrap());
+
+        let decoded = SnapshotCodec::default().decode(&bytes).unwrap();
+        assert_eq!(decoded.user_embeddings[&Uuid::from_u128(1)], vec![0.1, 0.2]);
+        assert_eq!(decoded.item_embeddings[&Uuid::from_u128(2)], vec![0.3, 0.4]);
+
+        // The current version does not accept the old names
+        let mut current = SnapshotCodec::default().encode(&parameters()).unwrap();
+        current.truncate(HEADER_LEN);
+        current.extend(serde_json::to_vec(&body).unwrap());
+        assert!(SnapshotCodec::default().decode(&current).unwrap_err().to_string().contains("Corrupt model snapshot"));
+    }
+
+    #[test]
+    fn test_snapshot_version_mismatch() {
+        let bytes = SnapshotCodec::new(1).encode(&parameters()).unwrap();
+
+        let error = SnapshotCodec::new(2).decode(&bytes).unwrap_err().to_string();
+        assert!(error.contains("format version 1"));
+        assert!(error.contains("expects version 2"));
+
+        // Headerless data (e.g. a pre-versioning JSON dump) is rejected rather than misread
+        let legacy = serde_json::to_vec(&parameters()).unwrap();
+        assert!(SnapshotCodec::default().decode(&legacy).is_err());
+    }
+}
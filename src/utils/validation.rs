@@ -1,5 +1,7 @@
+use crate::config::DimensionAdapt;
 use crate::models::*;
 use anyhow::{Result, anyhow};
+use tracing::warn;
 use uuid::Uuid;
 
 pub fn validate_user_action(action: &UserAction) -> Result<()> {
@@ -88,52 +90,166 @@ pub fn validate_item_feature(feature: &ItemFeature) -> Result<()> {
     Ok(())
 }
 
+/// Replaces NaN/Infinity entries with 0.0 in place and returns how many values were repaired.
+pub fn sanitize_embedding(embedding: &mut [f32]) -> usize {
+    let mut repaired = 0;
+    for value in embedding.iter_mut() {
+        if !value.is_finite() {
+            *value = 0.0;
+            repaired += 1;
+        }
+    }
+    repaired
+}
+
+/// Pads or truncates `embedding` to `dimension` as `mode` allows and returns whether it was changed.
+/// Mismatches the mode does not cover are left for the index's dimension check to reject.
+pub fn adapt_embedding_dimension(embedding: &mut Vec<f32>, dimension: usize, mode: DimensionAdapt) -> bool {
+    match mode {
+        DimensionAdapt::PadZero if embedding.len() < dimension => embedding.resize(dimension, 0.0),
+        DimensionAdapt::Truncate if embedding.len() > dimension => embedding.truncate(dimension),
+        _ => return false,
+    }
+    true
+}
+
+/// Validates an item feature, first repairing non-finite embedding values when `sanitize` is set.
+/// With `sanitize` off this is the same strict check as `validate_item_feature`.
+pub fn validate_item_feature_with_sanitization(feature: &mut ItemFeature, sanitize: bool) -> Result<()> {
+    if sanitize {
+        let repaired = sanitize_embedding(&mut feature.embedding);
+        if repaired > 0 {
+            warn!("Replaced {} non-finite embedding values with 0.0 for item {}", repaired, feature.item_id);
+        }
+    }
+
+    validate_item_feature(feature)
+}
+
+/// Validates a feature vector, first repairing non-finite values when `sanitize` is set.
+pub fn validate_feature_vector_with_sanitization(feature: &mut FeatureVector, sanitize: bool) -> Result<()> {
+    if sanitize {
+        let repaired = sanitize_embedding(&mut feature.vector);
+        if repaired > 0 {
+            warn!("Replaced {} non-finite values with 0.0 in feature vector {}", repaired, feature.id);
+        }
+    }
+
+    validate_feature_vector(feature)
+}
+
 pub fn validate_recommendation_request(request: &RecommendationRequest) -> Result<()> {
     if request.user_id.is_nil() {
         return Err(anyhow!("User ID cannot be nil"));
     }
     
-    if request.num_recommendations == 0 {
+    validate_num_recommendations(request.num_recommendations)?;
+    // Every page up to this one is ranked, so the window it reaches is bounded like a single page
+    if request.offset.saturating_add(request.num_recommendations) > 1000 {
+        return Err(anyhow!("Offset plus number of recommendations too large (max 1000)"));
+    }
+    if let Some(ref categories) = request.filter_categories {
+        validate_filter_categories(categories)?;
+    }
+    
+    if let Some(min_categories) = request.min_distinct_categories {
+        if min_categories > request.num_recommendations {
+            return Err(anyhow!(
+                "Cannot guarantee {} distinct categories in {} recommendations",
+                min_categories,
+                request.num_recommendations
+            ));
+        }
+    }
+    
+    if let Some(ref exclude_items) = request.exclude_items {
+        validate_exclude_items(exclude_items)?;
+    }
+    
+    Ok(())
+}
+
+pub fn validate_anonymous_recommendation_request(request: &AnonymousRecommendationRequest) -> Result<()> {
+    validate_num_recommendations(request.num_recommendations)?;
+    // Preferences are a soft signal, so an empty list only means there are none
+    if let Some(categories) = request.preferred_categories.as_ref().filter(|categories| !categories.is_empty()) {
+        validate_filter_categories(categories)?;
+    }
+
+    if request.recent_items.len() > 1000 {
+        return Err(anyhow!("Too many recent items (max 1000)"));
+    }
+    if request.recent_items.iter().any(Uuid::is_nil) {
+        return Err(anyhow!("Recent item ID cannot be nil"));
+    }
+
+    Ok(())
+}
+
+pub fn validate_group_recommendation_request(request: &GroupRecommendationRequest) -> Result<()> {
+    if request.user_ids.is_empty() {
+        return Err(anyhow!("Group recommendations need at least one user"));
+    }
+    if request.user_ids.iter().any(Uuid::is_nil) {
+        return Err(anyhow!("User ID cannot be nil"));
+    }
+
+    validate_num_recommendations(request.num_recommendations)?;
+    if let Some(ref categories) = request.filter_categories {
+        validate_filter_categories(categories)?;
+    }
+    if let Some(ref exclude_items) = request.exclude_items {
+        validate_exclude_items(exclude_items)?;
+    }
+
+    Ok(())
+}
+
+fn validate_num_recommendations(num_recommendations: usize) -> Result<()> {
+    if num_recommendations == 0 {
         return Err(anyhow!("Number of recommendations must be greater than 0"));
     }
     
-    if request.num_recommendations > 1000 {
+    if num_recommendations > 1000 {
         return Err(anyhow!("Number of recommendations too large (max 1000)"));
     }
+
+    Ok(())
+}
+
+fn validate_filter_categories(categories: &[String]) -> Result<()> {
+    if categories.is_empty() {
+        return Err(anyhow!("Filter categories cannot be empty if specified"));
+    }
     
-    // Validate filter categories
-    if let Some(ref categories) = request.filter_categories {
-        if categories.is_empty() {
-            return Err(anyhow!("Filter categories cannot be empty if specified"));
+    for category in categories {
+        if category.is_empty() {
+            return Err(anyhow!("Category name cannot be empty"));
         }
-        
-        for category in categories {
-            if category.is_empty() {
-                return Err(anyhow!("Category name cannot be empty"));
-            }
-            if category.len() > 100 {
-                return Err(anyhow!("Category name too long (max 100 characters)"));
-            }
+        if category.len() > 100 {
+            return Err(anyhow!("Category name too long (max 100 characters)"));
         }
     }
+
+    Ok(())
+}
+
+fn validate_exclude_items(exclude_items: &[Uuid]) -> Result<()> {
+    if exclude_items.len() > 10000 {
+        return Err(anyhow!("Too many items to exclude (max 10000)"));
+    }
     
-    // Validate exclude items
-    if let Some(ref exclude_items) = request.exclude_items {
-        if exclude_items.len() > 10000 {
-            return Err(anyhow!("Too many items to exclude (max 10000)"));
-        }
-        
-        for &item_id in exclude_items {
-            if item_id.is_nil() {
-                return Err(anyhow!("Excluded item ID cannot be nil"));
-            }
+    for &item_id in exclude_items {
+        if item_id.is_nil() {
+            return Err(anyhow!("Excluded item ID cannot be nil"));
         }
     }
-    
+
     Ok(())
 }
 
-pub fn validate_training_example(example: &TrainingExample) -> Result<()> {
+/// Labels must lie in `min_label..=1.0`; a negative `min_label` admits explicit negative feedback.
+pub fn validate_training_example(example: &TrainingExample, min_label: f32) -> Result<()> {
     if example.user_id.is_nil() {
         return Err(anyhow!("User ID cannot be nil"));
     }
@@ -147,8 +263,8 @@ pub fn validate_training_example(example: &TrainingExample) -> Result<()> {
         return Err(anyhow!("Training label contains invalid values (NaN or Infinity)"));
     }
     
-    if example.label < 0.0 || example.label > 1.0 {
-        return Err(anyhow!("Training label must be between 0.0 and 1.0"));
+    if example.label < min_label || example.label > 1.0 {
+        return Err(anyhow!("Training label must be between {} and 1.0", min_label));
     }
     
     // Validate user features
@@ -230,7 +346,7 @@ pub fn validate_model_parameters(params: &ModelParameters) -> Result<()> {
     }
     
     // Validate user embedding weights
-    for embedding in &params.user_embedding_weights {
+    for embedding in params.user_embeddings.values() {
         if embedding.is_empty() {
             return Err(anyhow!("User embedding weights cannot be empty"));
         }
@@ -243,7 +359,7 @@ pub fn validate_model_parameters(params: &ModelParameters) -> Result<()> {
     }
     
     // Validate item embedding weights
-    for embedding in &params.item_embedding_weights {
+    for embedding in params.item_embeddings.values() {
         if embedding.is_empty() {
             return Err(anyhow!("Item embedding weights cannot be empty"));
         }
@@ -318,6 +434,7 @@ mod tests {
             action_type: ActionType::Click,
             timestamp: Utc::now(),
             context: None,
+            recommendation_id: None,
         };
         
         assert!(validate_user_action(&valid_action).is_ok());
@@ -328,6 +445,7 @@ mod tests {
             action_type: ActionType::Click,
             timestamp: Utc::now(),
             context: None,
+            recommendation_id: None,
         };
         
         assert!(validate_user_action(&invalid_action).is_err());
@@ -341,6 +459,7 @@ mod tests {
             preferences: vec!["music".to_string()],
             last_updated: Utc::now(),
             interaction_count: 10,
+            version: 0,
         };
         
         assert!(validate_user_profile(&valid_profile).is_ok());
@@ -351,8 +470,68 @@ mod tests {
             preferences: vec!["music".to_string()],
             last_updated: Utc::now(),
             interaction_count: 10,
+            version: 0,
         };
         
         assert!(validate_user_profile(&invalid_profile).is_err());
     }
+
+    #[test]
+    fn test_item_feature_sanitization_mode() {
+        let mut feature = ItemFeature::new(
+            Uuid::new_v4(),
+            vec![0.5, f32::NAN, f32::INFINITY, f32::NEG_INFINITY],
+            "books".to_string(),
+        );
+
+        let mut strict = feature.clone();
+        assert!(validate_item_feature_with_sanitization(&mut strict, false).is_err());
+        assert!(strict.embedding[1].is_nan());
+
+        assert!(validate_item_feature_with_sanitization(&mut feature, true).is_ok());
+        assert_eq!(feature.embedding, vec![0.5, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_validate_anonymous_and_group_requests() {
+        let anonymous = AnonymousRecommendationRequest {
+            recent_items: vec![Uuid::new_v4()],
+            preferred_categories: Some(Vec::new()),
+            device: None,
+            num_recommendations: 10,
+            tenant_id: None,
+        };
+        assert!(validate_anonymous_recommendation_request(&anonymous).is_ok());
+        assert!(validate_anonymous_recommendation_request(&AnonymousRecommendationRequest {
+            num_recommendations: 0,
+            ..anonymous.clone()
+        })
+        .is_err());
+        assert!(validate_anonymous_recommendation_request(&AnonymousRecommendationRequest {
+            recent_items: vec![Uuid::nil()],
+            ..anonymous
+        })
+        .is_err());
+
+        let group = GroupRecommendationRequest {
+            user_ids: vec![Uuid::new_v4(), Uuid::new_v4()],
+            aggregation: GroupAggregation::default(),
+            num_recommendations: 10,
+            filter_categories: None,
+            exclude_items: None,
+            tenant_id: None,
+        };
+        assert!(validate_group_recommendation_request(&group).is_ok());
+        assert!(validate_group_recommendation_request(&GroupRecommendationRequest { user_ids: Vec::new(), ..group.clone() }).is_err());
+        assert!(validate_group_recommendation_request(&GroupRecommendationRequest {
+            num_recommendations: 5000,
+            ..group.clone()
+        })
+        .is_err());
+        assert!(validate_group_recommendation_request(&GroupRecommendationRequest {
+            exclude_items: Some(vec![Uuid::nil()]),
+            ..group
+        })
+        .is_err());
+    }
 }
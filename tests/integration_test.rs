@@ -2,11 +2,44 @@ use milvuso::*;
 use uuid::Uuid;
 use chrono::Utc;
 use std::collections::HashMap;
+use std::sync::Arc;
+use milvuso::services::cache::InMemoryCache;
+use milvuso::services::recommendation::RecommendationService;
+use milvuso::services::vector_db::VectorDbService;
+
+async fn in_memory_recommendation_service(config: Config) -> (Arc<VectorDbService>, RecommendationService) {
+    in_memory_recommendation_service_with_cache(config, Arc::new(InMemoryCache::new())).await
+}
+
+async fn in_memory_recommendation_service_with_cache(
+    config: Config,
+    cache: Arc<InMemoryCache>,
+) -> (Arc<VectorDbService>, RecommendationService) {
+    let config = Arc::new(config);
+    let vector_db = Arc::new(VectorDbService::new(&config).await.unwrap());
+    let service = RecommendationService::with_cache(vector_db.clone(), cache, config)
+        .await
+        .unwrap();
+    (vector_db, service)
+}
+
+fn small_config(dimension: usize) -> Config {
+    let mut config = Config::default();
+    config.recommendation.embedding_dim = dimension;
+    config.milvus.dimension = dimension;
+    config
+}
+
+fn unit_vector(dimension: usize, axis: usize) -> Vec<f32> {
+    let mut vector = vec![0.0; dimension];
+    vector[axis] = 1.0;
+    vector
+}
 
 #[tokio::test]
 async fn test_recommendation_flow() {
     // Initialize test configuration
-    let config = Config::default();
+    let _config = Config::default();
     
     // Create test state (this would normally connect to real services)
     // For testing, we'll use mock implementations
@@ -36,17 +69,55 @@ async fn test_recommendation_flow() {
     assert_eq!(item_feature.popularity_score, 0.8);
     
     // Test recommendation request
-    let request = RecommendationRequest {
-        user_id,
-        num_recommendations: 10,
-        filter_categories: Some(vec!["electronics".to_string()]),
-        exclude_items: None,
-    };
+    let request = RecommendationRequest::new(user_id, 10)
+        .with_filter_categories(vec!["electronics".to_string()]);
     
     assert_eq!(request.user_id, user_id);
     assert_eq!(request.num_recommendations, 10);
 }
 
+#[tokio::test]
+async fn test_in_memory_retriever_ranks_by_configured_metric() {
+    use milvuso::algorithms::retriever::*;
+
+    let aligned_but_far = Uuid::from_u128(1);
+    let close_but_skewed = Uuid::from_u128(2);
+    let query = [1.0, 0.0];
+    let ranking = |metric: DistanceMetric| async move {
+        let mut retriever = InMemoryRetriever::new(2).with_metric(metric);
+        retriever.add_vector(aligned_but_far, vec![3.0, 0.0]).await.unwrap();
+        retriever.add_vector(close_but_skewed, vec![0.8, 0.5]).await.unwrap();
+        retriever.search_similar(&query, 2).await.unwrap()
+    };
+
+    let cosine = ranking(DistanceMetric::Cosine).await;
+    assert_eq!(cosine[0], (aligned_but_far, 1.0));
+    assert_eq!(cosine[1].0, close_but_skewed);
+
+    // Distances rank nearest first, with the distance as the score
+    let euclidean = ranking(DistanceMetric::Euclidean).await;
+    assert_eq!(euclidean[0].0, close_but_skewed);
+    assert!((euclidean[0].1 - (0.04f32 + 0.25).sqrt()).abs() < 1e-6);
+    assert_eq!(euclidean[1], (aligned_but_far, 2.0));
+
+    let manhattan = ranking(DistanceMetric::Manhattan).await;
+    assert_eq!(manhattan.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![close_but_skewed, aligned_but_far]);
+    assert!((manhattan[0].1 - 0.7).abs() < 1e-6);
+
+    let inner_product = ranking(DistanceMetric::InnerProduct).await;
+    assert_eq!(inner_product[0], (aligned_but_far, 3.0));
+
+    // Under a distance the filter bound is a maximum
+    let mut retriever = InMemoryRetriever::new(2).with_metric(DistanceMetric::Euclidean);
+    retriever.add_vector(aligned_but_far, vec![3.0, 0.0]).await.unwrap();
+    retriever.add_vector(close_but_skewed, vec![0.8, 0.5]).await.unwrap();
+    let nearby = retriever.search_similar_filtered(&query, 2, 1.0).await.unwrap();
+    assert_eq!(nearby.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![close_but_skewed]);
+
+    assert_eq!(DistanceMetric::from_metric_type("l2").unwrap(), DistanceMetric::Euclidean);
+    assert!(DistanceMetric::from_metric_type("HAMMING").is_err());
+}
+
 #[tokio::test]
 async fn test_algorithms() {
     use milvuso::algorithms::*;
@@ -86,6 +157,152 @@ async fn test_algorithms() {
     assert_eq!(user_embedding.unwrap().len(), 64);
 }
 
+#[tokio::test]
+async fn test_adam_backed_collaborative_filtering_converges_faster_than_sgd() {
+    use milvuso::algorithms::optimizer::{Adam, SGD};
+    use milvuso::algorithms::*;
+
+    // Ratings from a rank-2 model: users and items each lean towards one of two tastes
+    let mut examples = Vec::new();
+    for user in 0..8u128 {
+        for item in 0..8u128 {
+            examples.push(TrainingExample {
+                user_id: Uuid::from_u128(user + 1),
+                item_id: Uuid::from_u128(item + 101),
+                label: if user % 2 == item % 2 { 1.0 } else { 0.0 },
+                user_features: Vec::new(),
+                item_features: Vec::new(),
+                context_features: Vec::new(),
+                timestamp: Utc::now(),
+            });
+        }
+    }
+
+    let dimension = 8;
+    let mut sgd = CollaborativeFiltering::with_optimizer(dimension, 0.0, Box::new(SGD::new(0.01)));
+    let mut adam = CollaborativeFiltering::with_optimizer(dimension, 0.0, Box::new(Adam::new(0.01, 0.9, 0.999, 1e-8)));
+    for _ in 0..30 {
+        sgd.train(&examples).await.unwrap();
+        adam.train(&examples).await.unwrap();
+    }
+
+    let (sgd_loss, adam_loss) = (sgd.compute_loss(&examples), adam.compute_loss(&examples));
+    assert!(adam_loss < sgd_loss, "Adam loss {} should be below SGD loss {}", adam_loss, sgd_loss);
+    assert!(adam_loss < 0.05);
+
+    // new() keeps training with plain SGD
+    let mut default = CollaborativeFiltering::new(dimension, 0.01, 0.0);
+    default.train(&examples).await.unwrap();
+    assert!(default.compute_loss(&examples).is_finite());
+}
+
+#[tokio::test]
+async fn test_bpr_ranks_observed_positives_above_negatives() {
+    use milvuso::algorithms::bpr::BPR;
+    use milvuso::algorithms::*;
+
+    // Implicit feedback: even users pick even items, odd users odd ones
+    let mut examples = Vec::new();
+    for user in 0..8u128 {
+        for item in 0..10u128 {
+            examples.push(TrainingExample {
+                user_id: Uuid::from_u128(user + 1),
+                item_id: Uuid::from_u128(item + 101),
+                label: if user % 2 == item % 2 { 1.0 } else { 0.0 },
+                user_features: Vec::new(),
+                item_features: Vec::new(),
+                context_features: Vec::new(),
+                timestamp: Utc::now(),
+            });
+        }
+    }
+
+    let mut bpr = BPR::with_seed(8, 0.05, 0.001, 7);
+    let triples: Vec<_> = (0..8u128)
+        .map(|user| (Uuid::from_u128(user + 1), Uuid::from_u128(user % 2 + 101), Uuid::from_u128((user + 1) % 2 + 101)))
+        .collect();
+    bpr.train(&examples).await.unwrap();
+    let initial_loss = bpr.compute_loss(&triples);
+    for _ in 0..50 {
+        bpr.train(&examples).await.unwrap();
+    }
+    assert!(bpr.compute_loss(&triples) < initial_loss);
+
+    for user in 0..8u128 {
+        let user_embedding = bpr.get_user_embedding(Uuid::from_u128(user + 1)).await.unwrap();
+        for (positive, negative) in (0..10u128).filter(|item| item % 2 == user % 2).zip((0..10u128).filter(|item| item % 2 != user % 2)) {
+            let positive = bpr.get_item_embedding(Uuid::from_u128(positive + 101)).await.unwrap();
+            let negative = bpr.get_item_embedding(Uuid::from_u128(negative + 101)).await.unwrap();
+            let positive_score = bpr.predict(&user_embedding, &positive).await.unwrap();
+            let negative_score = bpr.predict(&user_embedding, &negative).await.unwrap();
+            assert!(positive_score > negative_score, "user {}: {} <= {}", user, positive_score, negative_score);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_two_tower_training_moves_tower_weights() {
+    use milvuso::algorithms::two_tower::TwoTower;
+    use milvuso::algorithms::*;
+
+    let examples: Vec<TrainingExample> = (0..20u128)
+        .map(|i| TrainingExample {
+            user_id: Uuid::from_u128(i % 4 + 1),
+            item_id: Uuid::from_u128(i % 5 + 101),
+            label: if i % 2 == 0 { 1.0 } else { 0.0 },
+            user_features: Vec::new(),
+            item_features: Vec::new(),
+            context_features: Vec::new(),
+            timestamp: Utc::now(),
+        })
+        .collect();
+
+    let mut model = TwoTower::new(8, 16, 0.05, 0.001);
+    let (user_tower, item_tower) = (model.user_tower.clone(), model.item_tower.clone());
+    model.train(&examples).await.unwrap();
+    assert_ne!(model.user_tower.hidden, user_tower.hidden);
+    assert_ne!(model.user_tower.output, user_tower.output);
+    assert_ne!(model.item_tower.hidden, item_tower.hidden);
+    assert_ne!(model.item_tower.output_bias, item_tower.output_bias);
+
+    for example in &examples {
+        let user_embedding = model.get_user_embedding(example.user_id).await.unwrap();
+        let item_embedding = model.get_item_embedding(example.item_id).await.unwrap();
+        assert!(model.predict(&user_embedding, &item_embedding).await.unwrap().is_finite());
+    }
+    assert!(model.predict(&[0.0; 8], &[0.0; 4]).await.is_err());
+}
+
+#[test]
+fn test_predictive_context_feature_ranks_highest_in_importance() {
+    use milvuso::algorithms::feature_importance::context_importance;
+
+    // Only the day of week decides the label; the hour and action weight are noise
+    let examples: Vec<TrainingExample> = (0..200u64)
+        .map(|i| {
+            let noise = |salt: u64| ((i * 37 + salt * 11) % 17) as f32 / 17.0;
+            let day_of_week = (i % 7) as f32 / 7.0;
+            TrainingExample {
+                user_id: Uuid::from_u128(i as u128 % 10 + 1),
+                item_id: Uuid::from_u128(i as u128 % 13 + 101),
+                label: if day_of_week >= 0.5 { 1.0 } else { 0.0 },
+                user_features: Vec::new(),
+                item_features: Vec::new(),
+                context_features: vec![noise(1), day_of_week, noise(2)],
+                timestamp: Utc::now(),
+            }
+        })
+        .collect();
+
+    let importances = context_importance(&examples, |_| 0.0, 42);
+    assert_eq!(importances.len(), 3);
+    assert_eq!(importances[0].index, 1);
+    assert_eq!(importances[0].name.as_deref(), Some("day_of_week"));
+    for other in &importances[1..] {
+        assert!(importances[0].importance > 10.0 * other.importance.max(1e-6));
+    }
+}
+
 #[tokio::test]
 async fn test_optimizers() {
     use milvuso::algorithms::optimizer::*;
@@ -113,6 +330,32 @@ async fn test_optimizers() {
     adagrad.update(&mut params, &gradients);
 }
 
+#[test]
+fn test_adamw_decays_parameters_more_than_adam() {
+    use milvuso::algorithms::optimizer::*;
+    use nalgebra::DVector;
+
+    let initial = DVector::from_vec(vec![1.0, -2.0, 3.0]);
+    let zero_gradients = DVector::zeros(3);
+    let mut adam = Adam::new(0.01, 0.9, 0.999, 1e-8);
+    let mut adamw = AdamW::new(0.01, 0.9, 0.999, 1e-8, 0.1);
+    let mut adam_params = initial.clone();
+    let mut adamw_params = initial.clone();
+    for _ in 0..100 {
+        adam.update(&mut adam_params, &zero_gradients);
+        adamw.update(&mut adamw_params, &zero_gradients);
+    }
+
+    // Adam has nothing to step on without gradients; AdamW still shrinks every parameter
+    assert_eq!(adam_params, initial);
+    for (decayed, original) in adamw_params.iter().zip(initial.iter()) {
+        assert!(decayed.abs() < original.abs());
+        assert_eq!(decayed.signum(), original.signum());
+    }
+    let expected_norm = initial.norm() * (1.0 - 0.01 * 0.1f32).powi(100);
+    assert!((adamw_params.norm() - expected_norm).abs() < 1e-4);
+}
+
 #[tokio::test]
 async fn test_retrievers() {
     use milvuso::algorithms::retriever::*;
@@ -123,7 +366,7 @@ async fn test_retrievers() {
     let id1 = Uuid::new_v4();
     let id2 = Uuid::new_v4();
     let vector1 = vec![1.0; 64];
-    let vector2 = vec![0.5; 64];
+    let vector2: Vec<f32> = (0..64).map(|i| if i % 2 == 0 { 1.0 } else { 0.0 }).collect();
     
     // Add vectors
     retriever.add_vector(id1, vector1.clone()).await.unwrap();
@@ -141,7 +384,112 @@ async fn test_retrievers() {
     hnsw.add_vector(id2, vector2.clone()).await.unwrap();
     
     let results = hnsw.search_similar(&vector1, 2).await.unwrap();
-    assert!(!results.is_empty());
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0, id1);
+}
+
+#[tokio::test]
+async fn test_hnsw_recall_matches_brute_force() {
+    use milvuso::algorithms::retriever::{HNSWRetriever, VectorRetriever};
+    use rand::{Rng, SeedableRng};
+
+    let (dimension, count, top_k) = (16, 10_000, 10);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    let vectors: Vec<(Uuid, Vec<f32>)> = (0..count as u128)
+        .map(|i| (Uuid::from_u128(i + 1), (0..dimension).map(|_| rng.gen_range(-1.0..1.0)).collect()))
+        .collect();
+    let mut hnsw = HNSWRetriever::new(dimension, 16, 40);
+    for (id, vector) in &vectors {
+        hnsw.add_vector(*id, vector.clone()).await.unwrap();
+    }
+
+    let squared_l2 = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f32>();
+    let queries = 50;
+    let mut found = 0;
+    for _ in 0..queries {
+        let query: Vec<f32> = (0..dimension).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let mut exact: Vec<(Uuid, f32)> = vectors.iter().map(|(id, v)| (*id, squared_l2(&query, v))).collect();
+        exact.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let exact: std::collections::HashSet<Uuid> = exact.iter().take(top_k).map(|(id, _)| *id).collect();
+
+        let results = hnsw.search_similar(&query, top_k).await.unwrap();
+        let ids: std::collections::HashSet<Uuid> = results.iter().map(|(id, _)| *id).collect();
+        assert_eq!(results.len(), top_k);
+        assert_eq!(ids.len(), top_k);
+        assert!(results.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        found += ids.intersection(&exact).count();
+    }
+
+    let recall = found as f64 / (queries * top_k) as f64;
+    assert!(recall >= 0.95, "recall {}", recall);
+}
+
+#[tokio::test]
+async fn test_ivf_recall_matches_brute_force() {
+    use milvuso::algorithms::retriever::{DistanceMetric, IVFRetriever, InMemoryRetriever, VectorRetriever};
+    use rand::{Rng, SeedableRng};
+
+    let (dimension, count, top_k) = (16, 5_000, 10);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    let vectors: Vec<(Uuid, Vec<f32>)> = (0..count as u128)
+        .map(|i| (Uuid::from_u128(i + 1), (0..dimension).map(|_| rng.gen_range(-1.0..1.0)).collect()))
+        .collect();
+    let template = InMemoryRetriever::new(dimension).with_metric(DistanceMetric::Euclidean);
+    let mut ivf = IVFRetriever::from_template(template, 32, 12);
+    for (id, vector) in &vectors {
+        ivf.add_vector(*id, vector.clone()).await.unwrap();
+    }
+    ivf.rebuild();
+    assert_eq!(ivf.list_count(), 32);
+    assert_eq!(ivf.stats().vector_count, count);
+
+    let squared_l2 = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f32>();
+    let queries = 50;
+    let mut found = 0;
+    for _ in 0..queries {
+        let query: Vec<f32> = (0..dimension).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let mut exact: Vec<(Uuid, f32)> = vectors.iter().map(|(id, v)| (*id, squared_l2(&query, v))).collect();
+        exact.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let exact: std::collections::HashSet<Uuid> = exact.iter().take(top_k).map(|(id, _)| *id).collect();
+
+        let results = ivf.search_similar(&query, top_k).await.unwrap();
+        let ids: std::collections::HashSet<Uuid> = results.iter().map(|(id, _)| *id).collect();
+        assert_eq!(results.len(), top_k);
+        assert_eq!(ids.len(), top_k);
+        assert!(results.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        found += ids.intersection(&exact).count();
+    }
+
+    let recall = found as f64 / (queries * top_k) as f64;
+    assert!(recall >= 0.9, "recall {}", recall);
+}
+
+#[tokio::test]
+async fn test_ivf_reclusters_in_the_background() {
+    use milvuso::algorithms::retriever::{IVFRetriever, VectorRetriever};
+    use rand::{Rng, SeedableRng};
+
+    // 4 lists start clustering at 4 * 39 vectors
+    let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+    let mut ivf = IVFRetriever::new(8, 4, 4);
+    for i in 0..156u128 {
+        let vector: Vec<f32> = (0..8).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        ivf.add_vector(Uuid::from_u128(i + 1), vector).await.unwrap();
+    }
+    // The write that crossed the threshold only started the recluster
+    assert_eq!(ivf.list_count(), 1);
+
+    // Written while k-means runs: placed by the new centroids once the buckets are swapped in
+    let late = Uuid::from_u128(1_000);
+    let late_vector: Vec<f32> = (0..8).map(|_| rng.gen_range(-1.0..1.0)).collect();
+    ivf.add_vector(late, late_vector.clone()).await.unwrap();
+    ivf.finish_rebuild().await.unwrap();
+    assert_eq!(ivf.list_count(), 4);
+    assert_eq!(ivf.stats().vector_count, 157);
+
+    // Every bucket is probed, so search stays exact
+    let results = ivf.search_similar(&late_vector, 1).await.unwrap();
+    assert_eq!(results[0].0, late);
 }
 
 #[tokio::test]
@@ -248,7 +596,51 @@ async fn test_metrics() {
     relevant_scores.insert(recommended[2], 0.5);
     
     let ndcg = calculator.calculate_ndcg_at_k(&recommended, &relevant_scores);
-    assert!(ndcg >= 0.0 && ndcg <= 1.0);
+    assert!((0.0..=1.0).contains(&ndcg));
+}
+
+#[test]
+fn test_recency_weighted_novelty_favours_dormant_items() {
+    use milvuso::utils::metrics::MetricsCalculator;
+
+    let calculator = MetricsCalculator::new(5);
+    let now = Utc::now();
+    let dormant = Uuid::from_u128(1);
+    let current = Uuid::from_u128(2);
+    let interactions = HashMap::from([
+        (dormant, (0..100).map(|i| now - chrono::Duration::days(365) + chrono::Duration::minutes(i)).collect()),
+        (current, (0..20).map(|i| now - chrono::Duration::hours(i)).collect::<Vec<_>>()),
+    ]);
+
+    // Statically the dormant item is the popular one
+    let popularity = HashMap::from([(dormant, 100.0 / 120.0), (current, 20.0 / 120.0)]);
+    assert!(calculator.calculate_novelty(&[dormant], &popularity) < calculator.calculate_novelty(&[current], &popularity));
+
+    let week = 7.0 * 24.0 * 3600.0;
+    let dormant_novelty = calculator.calculate_novelty_at(&[dormant], &interactions, now, week);
+    let current_novelty = calculator.calculate_novelty_at(&[current], &interactions, now, week);
+    assert!(dormant_novelty > current_novelty);
+    assert!(current_novelty < 0.01);
+    assert_eq!(calculator.calculate_novelty_at(&[], &interactions, now, week), 0.0);
+}
+
+#[tokio::test]
+async fn test_ndcg_discounts_first_rank_by_log2_of_two() {
+    use milvuso::utils::metrics::*;
+
+    let calculator = MetricsCalculator::new(5);
+    let items = vec![Uuid::new_v4(), Uuid::new_v4()];
+
+    // A single relevant item at rank 1 is a perfect ranking, not a division by log2(1) = 0.
+    let mut relevant_scores = HashMap::new();
+    relevant_scores.insert(items[0], 1.0);
+    let ndcg = calculator.calculate_ndcg_at_k(&items, &relevant_scores);
+    assert!((ndcg - 1.0).abs() < 1e-9);
+
+    // Pushed to rank 2 it is discounted by 1 / log2(3), against an ideal DCG of 1 / log2(2).
+    let swapped = vec![items[1], items[0]];
+    let ndcg = calculator.calculate_ndcg_at_k(&swapped, &relevant_scores);
+    assert!((ndcg - 1.0 / 3f64.log2()).abs() < 1e-9);
 }
 
 #[tokio::test]
@@ -262,6 +654,7 @@ async fn test_validation() {
         action_type: ActionType::Click,
         timestamp: Utc::now(),
         context: None,
+        recommendation_id: None,
     };
     assert!(validate_user_action(&valid_action).is_ok());
     
@@ -272,6 +665,7 @@ async fn test_validation() {
         action_type: ActionType::Click,
         timestamp: Utc::now(),
         context: None,
+        recommendation_id: None,
     };
     assert!(validate_user_action(&invalid_action).is_err());
     
@@ -282,6 +676,7 @@ async fn test_validation() {
         preferences: vec!["music".to_string()],
         last_updated: Utc::now(),
         interaction_count: 10,
+        version: 0,
     };
     assert!(validate_user_profile(&valid_profile).is_ok());
     
@@ -292,6 +687,7 @@ async fn test_validation() {
         preferences: vec!["music".to_string()],
         last_updated: Utc::now(),
         interaction_count: 10,
+        version: 0,
     };
     assert!(validate_user_profile(&invalid_profile).is_err());
     
@@ -306,3 +702,3630 @@ async fn test_validation() {
     let invalid_uuid = validate_uuid_string("invalid-uuid");
     assert!(invalid_uuid.is_err());
 }
+
+#[tokio::test]
+async fn test_duplicate_action_suppression() {
+    let mut config = Config::default();
+    config.recommendation.duplicate_action_window_seconds = 60;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let user_id = Uuid::new_v4();
+    let item_id = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(item_id, vec![0.1; 128], "books".to_string())).await.unwrap();
+
+    let action = UserAction::new(user_id, item_id, ActionType::Click);
+    service.process_user_action(&action).await.unwrap();
+    service.process_user_action(&action).await.unwrap();
+
+    // One Click blends 0.1 * 0.3 of the item embedding into a zero profile; a second one would compound it
+    let profile = vector_db.get_user_profile(user_id).await.unwrap().unwrap();
+    assert!((profile.embedding[0] - 0.1 * 0.1 * 0.3).abs() < 1e-6);
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("suppressed_duplicate_actions"), Some(&1));
+
+    // A different action type on the same item is not a duplicate
+    service.process_user_action(&UserAction::new(user_id, item_id, ActionType::Like)).await.unwrap();
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("suppressed_duplicate_actions"), Some(&1));
+
+    // Configs written before the setting existed still load, with suppression off
+    let mut legacy = serde_json::to_value(&Config::default().recommendation).unwrap();
+    legacy.as_object_mut().unwrap().remove("duplicate_action_window_seconds");
+    let legacy: milvuso::config::RecommendationConfig = serde_json::from_value(legacy).unwrap();
+    assert_eq!(legacy.duplicate_action_window_seconds, 0);
+}
+
+#[tokio::test]
+async fn test_per_tenant_recommendation_config() {
+    use milvuso::services::cache::CacheBackend;
+
+    let mut config = small_config(4);
+    let mut strict = config.recommendation.clone();
+    strict.similarity_threshold = 0.9;
+    let mut lenient = config.recommendation.clone();
+    lenient.similarity_threshold = 0.5;
+    config.tenants.insert("strict".to_string(), strict);
+    config.tenants.insert("lenient".to_string(), lenient);
+
+    let cache = Arc::new(InMemoryCache::new());
+    let (_, service) = in_memory_recommendation_service_with_cache(config, cache.clone()).await;
+
+    let user_id = Uuid::new_v4();
+    let liked_item = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(liked_item, unit_vector(4, 0), "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(Uuid::new_v4(), unit_vector(4, 1), "music".to_string())).await.unwrap();
+    service.process_user_action(&UserAction::new(user_id, liked_item, ActionType::Click)).await.unwrap();
+
+    // The liked item scores (1.0 + 0.03) / 2, between the two tenant thresholds
+    let lenient_response = service
+        .get_recommendations(&RecommendationRequest::new(user_id, 10).with_tenant("lenient".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(lenient_response.recommendations.len(), 1);
+    assert_eq!(lenient_response.recommendations[0].item_id, liked_item);
+
+    let strict_response = service
+        .get_recommendations(&RecommendationRequest::new(user_id, 10).with_tenant("strict".to_string()))
+        .await
+        .unwrap();
+    assert!(strict_response.recommendations.is_empty());
+
+    // Unknown tenants use the global threshold (0.7)
+    let fallback_response = service
+        .get_recommendations(&RecommendationRequest::new(user_id, 10).with_tenant("unknown".to_string()))
+        .await
+        .unwrap();
+    assert!(fallback_response.recommendations.is_empty());
+
+    // Tenants can also be configured out-of-band through Redis
+    let mut redis_tenant = Config::default().recommendation;
+    redis_tenant.embedding_dim = 4;
+    redis_tenant.similarity_threshold = 0.1;
+    cache.set_ex("tenant_config:redis", serde_json::to_string(&redis_tenant).unwrap(), 60).await.unwrap();
+    let resolved = service.resolve_recommendation_config(Some("redis")).await.unwrap();
+    assert_eq!(resolved.similarity_threshold, 0.1);
+
+    // Reused in process until tenant_config_cache_ttl_ms runs out
+    redis_tenant.similarity_threshold = 0.2;
+    cache.set_ex("tenant_config:redis", serde_json::to_string(&redis_tenant).unwrap(), 60).await.unwrap();
+    let resolved = service.resolve_recommendation_config(Some("redis")).await.unwrap();
+    assert_eq!(resolved.similarity_threshold, 0.1);
+}
+
+#[tokio::test]
+async fn test_batched_scoring_matches_sequential_scoring() {
+    use milvuso::algorithms::RecommendationAlgorithm;
+
+    let mut config = small_config(8);
+    config.recommendation.similarity_threshold = 0.0;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let categories = ["books", "music", "sports"];
+    let mut item_ids = Vec::new();
+    for i in 0..30 {
+        let item_id = Uuid::new_v4();
+        let embedding: Vec<f32> = (0..8).map(|j| ((i * 7 + j * 3) % 11) as f32 / 11.0).collect();
+        service.add_item_feature(ItemFeature::new(item_id, embedding, categories[i % 3].to_string())).await.unwrap();
+        item_ids.push(item_id);
+    }
+
+    let user_id = Uuid::new_v4();
+    service.process_user_action(&UserAction::new(user_id, item_ids[0], ActionType::Purchase)).await.unwrap();
+    service.process_user_action(&UserAction::new(user_id, item_ids[4], ActionType::Like)).await.unwrap();
+
+    let request = RecommendationRequest::new(user_id, 5)
+        .with_filter_categories(vec!["books".to_string(), "music".to_string()])
+        .with_exclude_items(vec![item_ids[0]]);
+    let response = service.get_recommendations(&request).await.unwrap();
+
+    // Reference: the original one-candidate-at-a-time loop
+    let algorithm = algorithms::CollaborativeFiltering::new(8, 0.01, 0.01);
+    let user_embedding = vector_db.get_user_profile(user_id).await.unwrap().unwrap().embedding;
+    let mut expected = Vec::new();
+    for (item_id, similarity) in vector_db.search_similar_items(&user_embedding, 10).await.unwrap() {
+        if item_id == item_ids[0] {
+            continue;
+        }
+        let feature = vector_db.get_item_feature(item_id).await.unwrap().unwrap();
+        if feature.category == "sports" {
+            continue;
+        }
+        let prediction = algorithm.predict(&user_embedding, &feature.embedding).await.unwrap();
+        expected.push((item_id, (similarity + prediction) / 2.0));
+        if expected.len() >= 5 {
+            break;
+        }
+    }
+    expected.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+    let actual: Vec<(Uuid, f32)> = response.recommendations.iter().map(|r| (r.item_id, r.score)).collect();
+    assert!(!actual.is_empty());
+    assert_eq!(actual, expected);
+}
+
+#[tokio::test]
+async fn test_recommendation_id_round_trips_through_feedback() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    let (_, service) = in_memory_recommendation_service(config).await;
+
+    let user_id = Uuid::new_v4();
+    let item_id = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(item_id, unit_vector(4, 0), "books".to_string())).await.unwrap();
+    service.process_user_action(&UserAction::new(user_id, item_id, ActionType::View)).await.unwrap();
+
+    let first = service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+    let second = service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+    assert!(!first.recommendation_id.is_nil());
+    assert_ne!(first.recommendation_id, second.recommendation_id);
+    assert_eq!(first.recommendations[0].item_id, item_id);
+
+    // The client echoes the id back in the feedback payload
+    let feedback = UserAction::new(user_id, item_id, ActionType::Click).with_recommendation_id(first.recommendation_id);
+    let feedback: UserAction = serde_json::from_str(&serde_json::to_string(&feedback).unwrap()).unwrap();
+    assert_eq!(feedback.recommendation_id, Some(first.recommendation_id));
+    service.process_user_action(&feedback).await.unwrap();
+
+    // An id that was never served is not attributed
+    let stale = UserAction::new(user_id, item_id, ActionType::Like).with_recommendation_id(Uuid::new_v4());
+    service.process_user_action(&stale).await.unwrap();
+
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("attributed_feedback"), Some(&1));
+    assert_eq!(stats.get("unattributed_feedback"), Some(&1));
+
+    // Feedback without the field still deserializes
+    let legacy = serde_json::json!({
+        "user_id": user_id,
+        "item_id": item_id,
+        "action_type": "View",
+        "timestamp": Utc::now(),
+        "context": null
+    });
+    let legacy: UserAction = serde_json::from_value(legacy).unwrap();
+    assert!(legacy.recommendation_id.is_none());
+}
+
+#[tokio::test]
+async fn test_item_embedding_outlier_detection() {
+    let mut config = small_config(8);
+    config.outlier_detection.enabled = true;
+    config.outlier_detection.min_samples = 20;
+    config.outlier_detection.quarantine = true;
+    let vector_db = VectorDbService::new(&config).await.unwrap();
+
+    for i in 0..50 {
+        let embedding: Vec<f32> = (0..8).map(|j| 0.5 + ((i * 3 + j) % 7) as f32 * 0.01).collect();
+        vector_db.insert_item_feature(&ItemFeature::new(Uuid::new_v4(), embedding, "books".to_string())).await.unwrap();
+    }
+
+    let outlier_id = Uuid::new_v4();
+    let outlier = ItemFeature::new(outlier_id, vec![50.0; 8], "books".to_string());
+    let error = vector_db.insert_item_feature(&outlier).await.unwrap_err();
+    assert!(error.to_string().contains("outlier"));
+
+    assert!(vector_db.get_item_feature(outlier_id).await.unwrap().is_none());
+    let quarantined = vector_db.get_quarantined_items().await;
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].item_id, outlier_id);
+
+    // Embeddings in line with the catalog are still accepted
+    let normal = ItemFeature::new(Uuid::new_v4(), vec![0.52; 8], "books".to_string());
+    assert!(vector_db.insert_item_feature(&normal).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_category_taxonomy_filter() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    config.category_taxonomy.insert(
+        "electronics".to_string(),
+        vec!["electronics/phones".to_string(), "electronics/audio".to_string()],
+    );
+    config.category_taxonomy.insert(
+        "electronics/audio".to_string(),
+        vec!["electronics/audio/headphones".to_string()],
+    );
+    let (_, service) = in_memory_recommendation_service(config).await;
+
+    let phone = Uuid::new_v4();
+    let headphones = Uuid::new_v4();
+    let book = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(phone, vec![1.0, 0.1, 0.0, 0.0], "electronics/phones".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(headphones, vec![1.0, 0.0, 0.1, 0.0], "electronics/audio/headphones".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(book, vec![1.0, 0.0, 0.0, 0.1], "books".to_string())).await.unwrap();
+
+    let user_id = Uuid::new_v4();
+    service.process_user_action(&UserAction::new(user_id, phone, ActionType::Purchase)).await.unwrap();
+
+    let request = RecommendationRequest::new(user_id, 10).with_filter_categories(vec!["electronics".to_string()]);
+    let response = service.get_recommendations(&request).await.unwrap();
+    let mut returned: Vec<Uuid> = response.recommendations.iter().map(|r| r.item_id).collect();
+    returned.sort();
+    let mut expected = vec![phone, headphones];
+    expected.sort();
+    assert_eq!(returned, expected);
+
+    // Filtering on a leaf category stays exact
+    let request = RecommendationRequest::new(user_id, 10).with_filter_categories(vec!["electronics/phones".to_string()]);
+    let response = service.get_recommendations(&request).await.unwrap();
+    assert_eq!(response.recommendations.len(), 1);
+    assert_eq!(response.recommendations[0].item_id, phone);
+}
+
+#[tokio::test]
+async fn test_anonymous_recommendations_without_profile() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.3;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let viewed: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+    service.add_item_feature(ItemFeature::new(viewed[0], vec![1.0, 0.1, 0.0, 0.0], "audio".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(viewed[1], vec![0.9, 0.0, 0.1, 0.0], "audio".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(viewed[2], vec![1.0, 0.0, 0.0, 0.1], "audio".to_string())).await.unwrap();
+
+    let nearby = Uuid::new_v4();
+    let far_away = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(nearby, vec![0.95, 0.05, 0.05, 0.0], "audio".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(far_away, vec![0.0, 0.0, 0.0, 1.0], "books".to_string())).await.unwrap();
+
+    let request = AnonymousRecommendationRequest {
+        recent_items: viewed.clone(),
+        preferred_categories: None,
+        device: Some("mobile".to_string()),
+        num_recommendations: 5,
+        tenant_id: None,
+    };
+    let response = service.get_anonymous_recommendations(&request).await.unwrap();
+
+    assert!(response.user_id.is_nil());
+    let returned: Vec<Uuid> = response.recommendations.iter().map(|r| r.item_id).collect();
+    assert_eq!(returned, vec![nearby]);
+
+    // No profile is created for anonymous traffic
+    assert!(vector_db.get_user_profile(Uuid::nil()).await.unwrap().is_none());
+    assert!(vector_db.search_similar_users(&[1.0, 0.0, 0.0, 0.0], 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_f64_similarity_accumulation() {
+    use milvuso::algorithms::retriever::{InMemoryRetriever, VectorRetriever};
+
+    // Exact dot products with the query are 13 (a) and 14 (b) and the norms match, so b ranks higher.
+    // In f32, 1e8 + 13 rounds to 1e8 + 16 before the -1e8 cancels, so a wrongly scores 16.
+    let query = vec![1.0, 1.0, 1.0, 0.0];
+    let a = vec![1e8, 13.0, -1e8, 0.0];
+    let b = vec![0.0, 14.0, 0.0, (2e16_f64 + 169.0 - 196.0).sqrt() as f32];
+    let (a_id, b_id) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let mut f32_retriever = InMemoryRetriever::new(4);
+    let mut f64_retriever = InMemoryRetriever::new(4).with_f64_accumulation(true);
+    for retriever in [&mut f32_retriever, &mut f64_retriever] {
+        retriever.add_vector(a_id, a.clone()).await.unwrap();
+        retriever.add_vector(b_id, b.clone()).await.unwrap();
+    }
+
+    let f32_ranking = f32_retriever.search_similar(&query, 2).await.unwrap();
+    assert_eq!(f32_ranking[0].0, a_id);
+
+    let f64_ranking = f64_retriever.search_similar(&query, 2).await.unwrap();
+    assert_eq!(f64_ranking[0].0, b_id);
+    assert_eq!(f64_ranking[1].0, a_id);
+
+    assert!(utils::cosine_similarity(&query, &a) > utils::cosine_similarity(&query, &b));
+    assert!(utils::cosine_similarity_f64(&query, &a) < utils::cosine_similarity_f64(&query, &b));
+}
+
+#[tokio::test]
+async fn test_sparse_vector_update() {
+    use milvuso::algorithms::retriever::{InMemoryRetriever, VectorRetriever};
+
+    let original = vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6];
+    let deltas = [(1, 0.5), (4, -0.25)];
+    let mut expected = original.clone();
+    for &(index, delta) in &deltas {
+        expected[index] += delta;
+    }
+
+    let id = Uuid::new_v4();
+    let mut sparse = InMemoryRetriever::new(6);
+    let mut full = InMemoryRetriever::new(6);
+    sparse.add_vector(id, original.clone()).await.unwrap();
+    full.add_vector(id, original.clone()).await.unwrap();
+    sparse.update_vector_sparse(id, &deltas).await.unwrap();
+    full.update_vector(id, expected.clone()).await.unwrap();
+
+    for axis in 0..6 {
+        let query = unit_vector(6, axis);
+        assert_eq!(
+            sparse.search_similar(&query, 1).await.unwrap(),
+            full.search_similar(&query, 1).await.unwrap()
+        );
+    }
+
+    // Out-of-range indices are rejected without applying any of the deltas
+    assert!(sparse.update_vector_sparse(id, &[(0, 1.0), (6, 1.0)]).await.is_err());
+    assert!(sparse.update_vector_sparse(Uuid::new_v4(), &[(0, 1.0)]).await.is_err());
+
+    // Through the vector database only the listed dimensions change
+    let vector_db = VectorDbService::new(&small_config(6)).await.unwrap();
+    let user_id = Uuid::new_v4();
+    let mut profile = UserProfile::new(user_id, 6);
+    profile.embedding = original.clone();
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    vector_db.update_user_embedding_sparse(user_id, &deltas).await.unwrap();
+    vector_db.update_user_embedding_sparse(user_id, &[(6, 1.0)]).await.unwrap_err();
+
+    let updated = vector_db.get_user_profile(user_id).await.unwrap().unwrap();
+    assert_eq!(updated.embedding, expected);
+    for (axis, (before, after)) in original.iter().zip(updated.embedding.iter()).enumerate() {
+        if deltas.iter().all(|(index, _)| *index != axis) {
+            assert_eq!(before, after);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_model_warm_up_gate() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    config.recommendation.min_training_examples = 2;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let first = Uuid::new_v4();
+    let second = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(first, vec![1.0, 0.0, 0.0, 0.0], "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(second, vec![0.6, 0.8, 0.0, 0.0], "books".to_string())).await.unwrap();
+
+    let user_id = Uuid::new_v4();
+    service.process_user_action(&UserAction::new(user_id, first, ActionType::Purchase)).await.unwrap();
+
+    // One trained example: below the gate, scores are pure cosine similarity
+    let response = service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+    assert!(response.model_warming_up);
+    let embedding = vector_db.get_user_profile(user_id).await.unwrap().unwrap().embedding;
+    for item in &response.recommendations {
+        let feature = vector_db.get_item_feature(item.item_id).await.unwrap().unwrap();
+        assert!((item.score - utils::cosine_similarity(&embedding, &feature.embedding)).abs() < 1e-6);
+    }
+
+    service.process_user_action(&UserAction::new(user_id, second, ActionType::Purchase)).await.unwrap();
+
+    // Threshold crossed: similarity and prediction are blended
+    let response = service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+    assert!(!response.model_warming_up);
+    let embedding = vector_db.get_user_profile(user_id).await.unwrap().unwrap().embedding;
+    assert!(!response.recommendations.is_empty());
+    for item in &response.recommendations {
+        let feature = vector_db.get_item_feature(item.item_id).await.unwrap().unwrap();
+        let similarity = utils::cosine_similarity(&embedding, &feature.embedding);
+        let prediction: f32 = embedding.iter().zip(feature.embedding.iter()).map(|(a, b)| a * b).sum();
+        assert!((item.score - (similarity + prediction) / 2.0).abs() < 1e-5);
+    }
+}
+
+#[tokio::test]
+async fn test_item_embedding_versions() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    // The retrained "v2" embeddings swap which item is closest to the first axis
+    let x = Uuid::new_v4();
+    let y = Uuid::new_v4();
+    service.add_item_feature(
+        ItemFeature::new(x, unit_vector(4, 0), "books".to_string())
+            .with_embedding_version("v2".to_string(), unit_vector(4, 1)),
+    ).await.unwrap();
+    service.add_item_feature(
+        ItemFeature::new(y, unit_vector(4, 1), "books".to_string())
+            .with_embedding_version("v2".to_string(), unit_vector(4, 0)),
+    ).await.unwrap();
+
+    let query = unit_vector(4, 0);
+    let current = vector_db.search_similar_items_in_version(&query, 1, CURRENT_EMBEDDING_VERSION).await.unwrap();
+    assert_eq!(current[0].0, x);
+    let v2 = vector_db.search_similar_items_in_version(&query, 1, "v2").await.unwrap();
+    assert_eq!(v2[0].0, y);
+    assert!(vector_db.search_similar_items_in_version(&query, 1, "v3").await.unwrap().is_empty());
+
+    // Indexing v2 did not clobber the current embeddings
+    assert_eq!(vector_db.get_item_feature(x).await.unwrap().unwrap().embedding, unit_vector(4, 0));
+
+    let user_id = Uuid::new_v4();
+    service.process_user_action(&UserAction::new(user_id, x, ActionType::Purchase)).await.unwrap();
+
+    let response = service.get_recommendations(&RecommendationRequest::new(user_id, 1)).await.unwrap();
+    assert_eq!(response.recommendations[0].item_id, x);
+
+    let request = RecommendationRequest::new(user_id, 1).with_embedding_version("v2".to_string());
+    let response = service.get_recommendations(&request).await.unwrap();
+    assert_eq!(response.recommendations[0].item_id, y);
+}
+
+#[tokio::test]
+async fn test_predict_lock_timeout_falls_back_to_similarity() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    config.recommendation.predict_lock_timeout_ms = 20;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let item_id = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(item_id, vec![1.0, 0.5, 0.0, 0.0], "books".to_string())).await.unwrap();
+    let user_id = Uuid::new_v4();
+    service.process_user_action(&UserAction::new(user_id, item_id, ActionType::Purchase)).await.unwrap();
+
+    // Simulate a long training step holding the model's write lock
+    let algorithm = service.algorithm();
+    let training_guard = algorithm.write().await;
+
+    let response = tokio::time::timeout(
+        std::time::Duration::from_secs(1),
+        service.get_recommendations(&RecommendationRequest::new(user_id, 10)),
+    )
+    .await
+    .expect("request should not wait for the training lock")
+    .unwrap();
+    drop(training_guard);
+
+    let embedding = vector_db.get_user_profile(user_id).await.unwrap().unwrap().embedding;
+    let feature = vector_db.get_item_feature(item_id).await.unwrap().unwrap();
+    assert_eq!(response.recommendations.len(), 1);
+    assert!((response.recommendations[0].score - utils::cosine_similarity(&embedding, &feature.embedding)).abs() < 1e-6);
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("predict_lock_timeouts"), Some(&1));
+
+    // With the lock free again, scoring is blended
+    service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("predict_lock_timeouts"), Some(&1));
+}
+
+#[tokio::test]
+async fn test_collaborative_blend_applies_to_warm_items_only() {
+    let dimension = 4;
+    let user_id = Uuid::from_u128(1);
+    let (cold, warm) = (Uuid::from_u128(10), Uuid::from_u128(11));
+
+    let scores = |weight: f32| async move {
+        let mut config = small_config(dimension);
+        config.recommendation.similarity_threshold = 0.0;
+        config.recommendation.collaborative_blend_weight = weight;
+        let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+        let mut profile = UserProfile::new(user_id, dimension);
+        profile.embedding = unit_vector(dimension, 0);
+        vector_db.insert_user_profile(&profile).await.unwrap();
+        for item_id in [cold, warm] {
+            service.add_item_feature(ItemFeature::new(item_id, unit_vector(dimension, 0), "books".to_string())).await.unwrap();
+        }
+        // Only the warm item has a learned embedding, orthogonal to its content
+        service.algorithm().write().await.item_embeddings
+            .insert(warm, nalgebra::DVector::from_vec(unit_vector(dimension, 1)));
+
+        let response = service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+        let score_of = |item_id| response.recommendations.iter().find(|r| r.item_id == item_id).unwrap().score;
+        (score_of(cold), score_of(warm))
+    };
+
+    // Content alone: query and both items are identical, so similarity and prediction are 1
+    let (cold_score, warm_score) = scores(0.0).await;
+    assert!((cold_score - 1.0).abs() < 1e-6);
+    assert!((warm_score - 1.0).abs() < 1e-6);
+
+    // Half-way blend of [1, 0] and [0, 1]: cosine 1/sqrt(2), dot product 0.5
+    let (cold_score, warm_score) = scores(0.5).await;
+    assert!((cold_score - 1.0).abs() < 1e-6);
+    assert!((warm_score - (std::f32::consts::FRAC_1_SQRT_2 + 0.5) / 2.0).abs() < 1e-6);
+
+    // Collaborative alone: the warm item is orthogonal to the query
+    let (cold_score, warm_score) = scores(1.0).await;
+    assert!((cold_score - 1.0).abs() < 1e-6);
+    assert!(warm_score.abs() < 1e-6);
+}
+
+#[tokio::test]
+async fn test_item_dimension_validation_reports_expected_and_actual() {
+    use milvuso::config::DimensionAdapt;
+
+    let config = Arc::new(small_config(4));
+    let vector_db = VectorDbService::new(&config).await.unwrap();
+
+    let matching = ItemFeature::new(Uuid::new_v4(), unit_vector(4, 0), "books".to_string());
+    assert!(vector_db.validate_item_dimensions(&matching).is_ok());
+
+    let short = ItemFeature::new(Uuid::new_v4(), vec![1.0, 0.0, 0.0], "books".to_string());
+    let error = vector_db.validate_item_dimensions(&short).unwrap_err();
+    assert_eq!(format!("{:#}", error), "Embedding dimension mismatch: expected 4, got 3");
+
+    let bad_version = matching.clone().with_embedding_version("v2".to_string(), vec![1.0; 5]);
+    let error = vector_db.validate_item_dimensions(&bad_version).unwrap_err();
+    assert_eq!(format!("{:#}", error), "Embedding version v2: Embedding dimension mismatch: expected 4, got 5");
+
+    // Embeddings that dimension_adapt would fix up are let through
+    let mut config = small_config(4);
+    config.recommendation.dimension_adapt = DimensionAdapt::PadZero;
+    let vector_db = VectorDbService::new(&Arc::new(config)).await.unwrap();
+    assert!(vector_db.validate_item_dimensions(&short).is_ok());
+}
+
+#[tokio::test]
+async fn test_cold_item_exploration_fills_reserved_slots() {
+    use milvuso::config::ColdItemExplorationConfig;
+
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = 0.0;
+    config.recommendation.cold_item_exploration = Some(ColdItemExplorationConfig {
+        slot_share: 0.4,
+        max_interactions: 2,
+        seed: Some(7),
+    });
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let user_id = Uuid::from_u128(1);
+    let mut profile = UserProfile::new(user_id, dimension);
+    profile.embedding = unit_vector(dimension, 0);
+    vector_db.insert_user_profile(&profile).await.unwrap();
+
+    // Warm items match the user and have a few interactions; cold ones have none
+    let warm: Vec<Uuid> = (0..10).map(|i| Uuid::from_u128(100 + i)).collect();
+    let cold: Vec<Uuid> = (0..10).map(|i| Uuid::from_u128(200 + i)).collect();
+    for &item_id in &warm {
+        service.add_item_feature(ItemFeature::new(item_id, unit_vector(dimension, 0), "books".to_string())).await.unwrap();
+        for _ in 0..3 {
+            vector_db.record_item_interaction(item_id, &ActionType::Click).await;
+        }
+    }
+    for &item_id in &cold {
+        service.add_item_feature(ItemFeature::new(item_id, unit_vector(dimension, 1), "books".to_string())).await.unwrap();
+    }
+
+    let request = RecommendationRequest::new(user_id, 5);
+    let response = service.get_recommendations(&request).await.unwrap();
+    assert_eq!(response.recommendations.len(), 5);
+    let (ranked, explored) = response.recommendations.split_at(3);
+    assert!(ranked.iter().all(|r| warm.contains(&r.item_id)));
+    assert!(explored.iter().all(|r| cold.contains(&r.item_id) && r.reason.as_deref() == Some("New item exploration")));
+    assert_ne!(explored[0].item_id, explored[1].item_id);
+
+    // The seed makes the sample reproducible
+    let again = service.get_recommendations(&request).await.unwrap();
+    let ids = |response: &RecommendationResponse| response.recommendations.iter().map(|r| r.item_id).collect::<Vec<_>>();
+    assert_eq!(ids(&again), ids(&response));
+    assert_eq!(service.get_recommendation_stats().await.get("cold_item_explorations"), Some(&2));
+}
+
+#[tokio::test]
+async fn test_threshold_sweep() {
+    use milvuso::utils::metrics::{Evaluator, MetricsCalculator, ScoredCandidate};
+
+    // Higher scores are mostly relevant, lower scores mostly not
+    let scores = [0.95, 0.9, 0.85, 0.8, 0.7, 0.6, 0.5, 0.4, 0.3, 0.2];
+    let labels = [true, true, false, true, true, false, false, true, false, false];
+    let candidates: Vec<ScoredCandidate> = scores.iter().zip(labels.iter())
+        .map(|(&score, &relevant)| ScoredCandidate { item_id: Uuid::new_v4(), score, relevant })
+        .collect();
+
+    let k = 5;
+    let thresholds = [0.5, 0.9, 0.0, 0.75, 0.99];
+    let sweep = Evaluator::new(k, candidates.clone()).sweep_thresholds(&thresholds);
+    assert_eq!(sweep.len(), thresholds.len());
+
+    let calculator = MetricsCalculator::new(k);
+    let all_items: Vec<Uuid> = candidates.iter().map(|c| c.item_id).collect();
+    let relevant: Vec<Uuid> = candidates.iter().filter(|c| c.relevant).map(|c| c.item_id).collect();
+    let relevant_scores: HashMap<Uuid, f64> = relevant.iter().map(|id| (*id, 1.0)).collect();
+
+    for (&threshold, (swept_threshold, metrics)) in thresholds.iter().zip(sweep.iter()) {
+        assert_eq!(threshold, *swept_threshold);
+        let recommended: Vec<Uuid> = candidates.iter()
+            .filter(|c| c.score >= threshold)
+            .map(|c| c.item_id)
+            .collect();
+
+        let precision = calculator.calculate_precision_at_k(&recommended, &relevant);
+        let recall = calculator.calculate_recall_at_k(&recommended, &relevant);
+        assert!((metrics.precision_at_k - precision).abs() < 1e-9);
+        assert!((metrics.recall_at_k - recall).abs() < 1e-9);
+        assert!((metrics.f1_score - calculator.calculate_f1_score(precision, recall)).abs() < 1e-9);
+        assert!((metrics.ndcg_at_k - calculator.calculate_ndcg_at_k(&recommended, &relevant_scores)).abs() < 1e-9);
+        assert!((metrics.coverage - calculator.calculate_coverage(&recommended, &all_items)).abs() < 1e-9);
+        assert!((metrics.mrr - calculator.calculate_mrr(&recommended, &relevant)).abs() < 1e-9);
+        assert_eq!(metrics.hit_rate, calculator.calculate_hit_rate(&recommended, &relevant));
+    }
+
+    // Raising the threshold trades recall for precision on this dataset
+    let ascending = Evaluator::new(10, candidates).sweep_thresholds(&[0.0, 0.5, 0.75, 0.9]);
+    for pair in ascending.windows(2) {
+        assert!(pair[1].1.precision_at_k >= pair[0].1.precision_at_k);
+        assert!(pair[1].1.recall_at_k <= pair[0].1.recall_at_k);
+    }
+}
+
+#[tokio::test]
+async fn test_expired_items_are_skipped_and_reaped() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let live = Uuid::new_v4();
+    let expired = Uuid::new_v4();
+    let later = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(live, vec![1.0, 0.2, 0.0, 0.0], "events".to_string())).await.unwrap();
+    service.add_item_feature(
+        ItemFeature::new(expired, vec![1.0, 0.0, 0.0, 0.0], "events".to_string())
+            .with_expiry(Utc::now() - chrono::Duration::minutes(5)),
+    ).await.unwrap();
+    service.add_item_feature(
+        ItemFeature::new(later, vec![1.0, 0.0, 0.2, 0.0], "events".to_string())
+            .with_expiry(Utc::now() + chrono::Duration::days(1)),
+    ).await.unwrap();
+
+    let user_id = Uuid::new_v4();
+    service.process_user_action(&UserAction::new(user_id, live, ActionType::Purchase)).await.unwrap();
+
+    let response = service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+    let returned: Vec<Uuid> = response.recommendations.iter().map(|r| r.item_id).collect();
+    assert!(!returned.contains(&expired));
+    assert!(returned.contains(&live) && returned.contains(&later));
+
+    let search = vector_db.search_similar_items(&[1.0, 0.0, 0.0, 0.0], 2).await.unwrap();
+    assert_eq!(search.len(), 2);
+    assert!(search.iter().all(|(item_id, _)| *item_id != expired));
+
+    // Still inside the grace period: kept in the index but never served
+    assert_eq!(vector_db.reap_expired_items(chrono::Duration::hours(1)).await, 0);
+    assert!(vector_db.get_item_feature(expired).await.unwrap().is_some());
+
+    let reaper = vector_db.clone().spawn_expired_item_reaper(std::time::Duration::from_millis(10), chrono::Duration::zero());
+    for _ in 0..100 {
+        if vector_db.get_item_feature(expired).await.unwrap().is_none() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    reaper.abort();
+    assert!(vector_db.get_item_feature(expired).await.unwrap().is_none());
+    assert!(vector_db.get_item_feature(later).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_hybrid_retriever_merges_top_k() {
+    use milvuso::algorithms::retriever::{HybridRetriever, InMemoryRetriever, VectorRetriever};
+
+    // Route by a bit of the id so the dataset is split across both sub-retrievers
+    let mut hybrid = HybridRetriever::new(Box::new(|id, _| (id.as_bytes()[0] % 2) as usize))
+        .with_retriever(Box::new(InMemoryRetriever::new(8)), 1.0)
+        .with_retriever(Box::new(InMemoryRetriever::new(8)), 1.0);
+    let mut reference = InMemoryRetriever::new(8);
+
+    for i in 0..200 {
+        let id = Uuid::new_v4();
+        let vector: Vec<f32> = (0..8).map(|j| ((i as f32 + 1.0) * (j as f32 + 1.3)).sin()).collect();
+        hybrid.add_vector(id, vector.clone()).await.unwrap();
+        reference.add_vector(id, vector).await.unwrap();
+    }
+
+    let query = vec![0.3, -0.1, 0.5, 0.2, -0.4, 0.1, 0.0, 0.6];
+    let merged = hybrid.search_similar(&query, 10).await.unwrap();
+    let expected = reference.search_similar(&query, 10).await.unwrap();
+    assert_eq!(merged.len(), 10);
+    assert_eq!(merged, expected);
+
+    // Updates move the vector to wherever the policy routes it, without duplicates
+    let (top_id, _) = merged[0];
+    hybrid.update_vector(top_id, query.clone()).await.unwrap();
+    let updated = hybrid.search_similar(&query, 10).await.unwrap();
+    assert_eq!(updated[0].0, top_id);
+    assert_eq!(updated.iter().filter(|(id, _)| *id == top_id).count(), 1);
+}
+
+#[tokio::test]
+async fn test_min_distinct_categories_guarantee() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    let (_, service) = in_memory_recommendation_service(config).await;
+
+    // Skewed catalog: the closest items are all books
+    let mut books = Vec::new();
+    for i in 0..6 {
+        let item_id = Uuid::new_v4();
+        service.add_item_feature(ItemFeature::new(item_id, vec![1.0, 0.01 * i as f32, 0.0, 0.0], "books".to_string())).await.unwrap();
+        books.push(item_id);
+    }
+    service.add_item_feature(ItemFeature::new(Uuid::new_v4(), vec![0.6, 0.0, 0.8, 0.0], "music".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(Uuid::new_v4(), vec![0.5, 0.0, 0.0, 0.86], "games".to_string())).await.unwrap();
+
+    let user_id = Uuid::new_v4();
+    service.process_user_action(&UserAction::new(user_id, books[0], ActionType::Purchase)).await.unwrap();
+
+    let natural = service.get_recommendations(&RecommendationRequest::new(user_id, 4)).await.unwrap();
+    assert!(natural.recommendations.iter().all(|r| r.category == "books"));
+
+    let request = RecommendationRequest::new(user_id, 4).with_min_distinct_categories(3);
+    let diverse = service.get_recommendations(&request).await.unwrap();
+    assert_eq!(diverse.recommendations.len(), 4);
+    let categories: std::collections::HashSet<&str> = diverse.recommendations.iter().map(|r| r.category.as_str()).collect();
+    assert_eq!(categories.len(), 3);
+    assert_eq!(diverse.recommendations[0].item_id, natural.recommendations[0].item_id);
+    assert!(diverse.recommendations.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+    // Only three categories exist: the best effort is returned instead of failing
+    let request = RecommendationRequest::new(user_id, 5).with_min_distinct_categories(5);
+    let best_effort = service.get_recommendations(&request).await.unwrap();
+    let categories: std::collections::HashSet<&str> = best_effort.recommendations.iter().map(|r| r.category.as_str()).collect();
+    assert_eq!(categories.len(), 3);
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("category_guarantee_unmet"), Some(&1));
+
+    // More categories than slots can never be satisfied
+    let request = RecommendationRequest::new(user_id, 2).with_min_distinct_categories(3);
+    assert!(service.get_recommendations(&request).await.is_err());
+    assert!(utils::validation::validate_recommendation_request(&request).is_err());
+}
+
+#[tokio::test]
+async fn test_enrichers_run_in_order_before_indexing() {
+    use milvuso::services::enrichment::FeatureEnricher;
+
+    struct AddTag(&'static str);
+
+    #[async_trait::async_trait]
+    impl FeatureEnricher for AddTag {
+        async fn enrich(&self, mut feature: ItemFeature) -> anyhow::Result<ItemFeature> {
+            feature.tags.push(self.0.to_string());
+            Ok(feature)
+        }
+    }
+
+    struct RejectUncategorized;
+
+    #[async_trait::async_trait]
+    impl FeatureEnricher for RejectUncategorized {
+        async fn enrich(&self, feature: ItemFeature) -> anyhow::Result<ItemFeature> {
+            anyhow::ensure!(!feature.category.is_empty(), "item {} has no category", feature.item_id);
+            Ok(feature)
+        }
+    }
+
+    let (vector_db, service) = in_memory_recommendation_service(small_config(4)).await;
+    let service = service
+        .with_enricher(Arc::new(AddTag("enriched")))
+        .with_enricher(Arc::new(RejectUncategorized))
+        .with_enricher(Arc::new(AddTag("second")));
+
+    let item_id = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(item_id, unit_vector(4, 0), "books".to_string())).await.unwrap();
+    let stored = vector_db.get_item_feature(item_id).await.unwrap().unwrap();
+    assert_eq!(stored.tags, vec!["enriched".to_string(), "second".to_string()]);
+
+    let uncategorized = Uuid::new_v4();
+    assert!(service.add_item_feature(ItemFeature::new(uncategorized, unit_vector(4, 1), String::new())).await.is_err());
+    assert!(vector_db.get_item_feature(uncategorized).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_diversity_lambda_reranks_across_categories() {
+    async fn recommend(diversity_lambda: f32) -> Vec<RecommendationItem> {
+        let mut config = small_config(4);
+        config.recommendation.similarity_threshold = 0.0;
+        config.recommendation.diversity_lambda = diversity_lambda;
+        let (_, service) = in_memory_recommendation_service(config).await;
+
+        // Clustered catalog: six near-identical books closest to the user, then music and games
+        for i in 0..6u128 {
+            let embedding = vec![1.0, 0.01 * i as f32, 0.0, 0.0];
+            service.add_item_feature(ItemFeature::new(Uuid::from_u128(i + 1), embedding, "books".to_string())).await.unwrap();
+        }
+        for i in 0..3u128 {
+            let embedding = vec![0.8, 0.0, 0.6, 0.01 * i as f32];
+            service.add_item_feature(ItemFeature::new(Uuid::from_u128(i + 11), embedding, "music".to_string())).await.unwrap();
+        }
+        service.add_item_feature(ItemFeature::new(Uuid::from_u128(21), vec![0.7, 0.0, 0.0, 0.71], "games".to_string())).await.unwrap();
+
+        let user_id = Uuid::from_u128(100);
+        service.process_user_action(&UserAction::new(user_id, Uuid::from_u128(1), ActionType::Purchase)).await.unwrap();
+        service.get_recommendations(&RecommendationRequest::new(user_id, 4)).await.unwrap().recommendations
+    }
+    let categories = |items: &[RecommendationItem]| {
+        items.iter().map(|item| item.category.clone()).collect::<std::collections::HashSet<_>>().len()
+    };
+
+    let raw = recommend(0.0).await;
+    assert_eq!(raw.len(), 4);
+    assert_eq!(categories(&raw), 1);
+    assert!(raw.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+    let diverse = recommend(0.7).await;
+    assert_eq!(diverse.len(), 4);
+    assert_eq!(categories(&diverse), 3);
+    // The most relevant item still leads
+    assert_eq!(diverse[0].item_id, raw[0].item_id);
+}
+
+#[tokio::test]
+async fn test_session_pages_are_scored_once_per_profile_version() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    let (_, service) = in_memory_recommendation_service(config).await;
+    for i in 0..12u128 {
+        let embedding = vec![1.0, 0.05 * i as f32, 0.0, 0.0];
+        service.add_item_feature(ItemFeature::new(Uuid::from_u128(i + 1), embedding, "books".to_string())).await.unwrap();
+    }
+    let user_id = Uuid::from_u128(100);
+    service.process_user_action(&UserAction::new(user_id, Uuid::from_u128(1), ActionType::Purchase)).await.unwrap();
+
+    let page = |offset: usize| {
+        RecommendationRequest::new(user_id, 3).with_session("session-1".to_string()).with_offset(offset)
+    };
+    let mut paged = Vec::new();
+    for offset in [0, 3, 6] {
+        paged.extend(service.get_recommendations(&page(offset)).await.unwrap().recommendations);
+    }
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("session_pool_misses"), Some(&1));
+    assert_eq!(stats.get("session_pool_hits"), Some(&2));
+
+    // The pages line up with one unpaged ranking
+    let full = service.get_recommendations(&RecommendationRequest::new(user_id, 9)).await.unwrap().recommendations;
+    let ids = |items: &[RecommendationItem]| items.iter().map(|item| item.item_id).collect::<Vec<_>>();
+    assert_eq!(ids(&paged), ids(&full));
+
+    // A changed profile scores the session again
+    service.process_user_action(&UserAction::new(user_id, Uuid::from_u128(12), ActionType::Purchase)).await.unwrap();
+    service.get_recommendations(&page(0)).await.unwrap();
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("session_pool_misses"), Some(&2));
+
+    // The ranked window is the offset plus the page, bounded like a single page and never overflowing
+    assert!(utils::validation::validate_recommendation_request(&page(997)).is_ok());
+    assert!(utils::validation::validate_recommendation_request(&page(998)).is_err());
+    assert!(utils::validation::validate_recommendation_request(&page(usize::MAX)).is_err());
+}
+
+#[tokio::test]
+async fn test_recommendations_without_reasons() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    let (_, service) = in_memory_recommendation_service(config).await;
+    for i in 0..6u128 {
+        let embedding = vec![1.0, 0.1 * i as f32, 0.0, 0.0];
+        service.add_item_feature(ItemFeature::new(Uuid::from_u128(i + 1), embedding, "books".to_string())).await.unwrap();
+    }
+    let user_id = Uuid::from_u128(100);
+    service.process_user_action(&UserAction::new(user_id, Uuid::from_u128(1), ActionType::Purchase)).await.unwrap();
+
+    let explained = service.get_recommendations(&RecommendationRequest::new(user_id, 3)).await.unwrap();
+    assert!(explained.recommendations.iter().all(|item| item.reason.is_some()));
+    let request = RecommendationRequest { include_reasons: false, ..RecommendationRequest::new(user_id, 3) };
+    let unexplained = service.get_recommendations(&request).await.unwrap();
+    assert!(!unexplained.recommendations.is_empty());
+    assert!(unexplained.recommendations.iter().all(|item| item.reason.is_none()));
+
+    // A session's pool keeps its reasons for the pages that want them
+    let session = |include_reasons| RecommendationRequest {
+        include_reasons,
+        ..RecommendationRequest::new(user_id, 3).with_session("session-1".to_string())
+    };
+    let page = service.get_recommendations(&session(false)).await.unwrap();
+    assert!(page.recommendations.iter().all(|item| item.reason.is_none()));
+    let page = service.get_recommendations(&session(true)).await.unwrap();
+    assert!(page.recommendations.iter().all(|item| item.reason.is_some()));
+
+    // Cold-start users get popular items, likewise unexplained
+    let request = RecommendationRequest { include_reasons: false, ..RecommendationRequest::new(Uuid::from_u128(200), 3) };
+    let cold = service.get_recommendations(&request).await.unwrap();
+    assert!(cold.recommendations.iter().all(|item| item.reason.is_none()));
+}
+
+#[tokio::test]
+async fn test_cold_start_users_get_popular_items() {
+    let (_, service) = in_memory_recommendation_service(small_config(4)).await;
+    for (i, popularity) in [0.2, 0.9, 0.5, 0.7].into_iter().enumerate() {
+        let item_id = Uuid::from_u128(i as u128 + 1);
+        let feature = ItemFeature::new(item_id, unit_vector(4, i), "books".to_string()).with_popularity(popularity);
+        service.add_item_feature(feature).await.unwrap();
+    }
+    let music = ItemFeature::new(Uuid::from_u128(5), unit_vector(4, 0), "music".to_string()).with_popularity(1.0);
+    service.add_item_feature(music).await.unwrap();
+
+    let user_id = Uuid::new_v4();
+    let recommendations = service.get_recommendations(&RecommendationRequest::new(user_id, 3)).await.unwrap().recommendations;
+    let ids: Vec<Uuid> = recommendations.iter().map(|item| item.item_id).collect();
+    assert_eq!(ids, vec![Uuid::from_u128(5), Uuid::from_u128(2), Uuid::from_u128(4)]);
+    assert!(recommendations.iter().all(|item| item.reason.as_deref() == Some("popular item (cold start)")));
+    assert!(recommendations.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+    let books = RecommendationRequest::new(user_id, 3).with_filter_categories(vec!["books".to_string()]);
+    let recommendations = service.get_recommendations(&books).await.unwrap().recommendations;
+    let ids: Vec<Uuid> = recommendations.iter().map(|item| item.item_id).collect();
+    assert_eq!(ids, vec![Uuid::from_u128(2), Uuid::from_u128(4), Uuid::from_u128(3)]);
+}
+
+#[tokio::test]
+async fn test_stability_bounds_position_changes_across_model_updates() {
+    // Items fanned out within 40 degrees of the user; `reversed` flips their order of similarity
+    let item_at = |i: u128, reversed: bool| {
+        let step = if reversed { 7 - i } else { i };
+        let angle = (step as f32 * 5.0).to_radians();
+        ItemFeature::new(Uuid::from_u128(i + 1), vec![angle.cos(), angle.sin(), 0.0, 0.0], "books".to_string())
+    };
+
+    let mut max_shifts = Vec::new();
+    for stability_weight in [0.0, 0.75] {
+        let mut config = small_config(4);
+        config.recommendation.stability_weight = stability_weight;
+        let (vector_db, service) = in_memory_recommendation_service(config).await;
+        for i in 0..8 {
+            service.add_item_feature(item_at(i, false)).await.unwrap();
+        }
+        let user_id = Uuid::from_u128(100);
+        let profile = UserProfile { embedding: unit_vector(4, 0), ..UserProfile::new(user_id, 4) };
+        vector_db.insert_user_profile(&profile).await.unwrap();
+
+        let request = RecommendationRequest::new(user_id, 8);
+        let ids = |response: RecommendationResponse| -> Vec<Uuid> {
+            response.recommendations.iter().map(|item| item.item_id).collect()
+        };
+        let before = ids(service.get_recommendations(&request).await.unwrap());
+        assert_eq!(before, (1..=8u128).map(Uuid::from_u128).collect::<Vec<_>>());
+
+        // A model update that turns the ranking upside down
+        for i in 0..8 {
+            service.add_item_feature(item_at(i, true)).await.unwrap();
+        }
+        let after = ids(service.get_recommendations(&request).await.unwrap());
+        assert_eq!(after.len(), 8);
+        let max_shift = before
+            .iter()
+            .enumerate()
+            .map(|(position, item_id)| after.iter().position(|id| id == item_id).unwrap().abs_diff(position))
+            .max()
+            .unwrap();
+        max_shifts.push(max_shift);
+    }
+
+    // Off, the first item drops to the bottom; on, nothing moves more than (1 - 0.75) * 8 places
+    assert_eq!(max_shifts, vec![7, 2]);
+}
+
+#[tokio::test]
+async fn test_cluster_recommendations_span_clusters_near_the_user() {
+    let (vector_db, service) = in_memory_recommendation_service(small_config(4)).await;
+
+    // Three tight groups of four items, around the first three axes
+    let categories = ["books", "music", "games"];
+    for (group, category) in categories.iter().enumerate() {
+        for i in 0..4u128 {
+            let mut embedding = vec![0.05 * i as f32; 4];
+            embedding[group] = 1.0;
+            let item_id = Uuid::from_u128(group as u128 * 10 + i + 1);
+            service.add_item_feature(ItemFeature::new(item_id, embedding, category.to_string())).await.unwrap();
+        }
+    }
+
+    let clusters = vector_db.cluster_items(3).await.unwrap();
+    assert_eq!(clusters.centroids.len(), 3);
+    for group in 0..3u128 {
+        let cluster = clusters.assignments[&Uuid::from_u128(group * 10 + 1)];
+        assert!((1..4).all(|i| clusters.assignments[&Uuid::from_u128(group * 10 + i + 1)] == cluster));
+        assert_eq!(clusters.cluster_of(&unit_vector(4, group as usize)), cluster);
+    }
+    let distinct: std::collections::HashSet<usize> = clusters.assignments.values().copied().collect();
+    assert_eq!(distinct.len(), 3);
+
+    // Mostly books, some music, no games
+    let user_id = Uuid::from_u128(100);
+    let profile = UserProfile { embedding: vec![0.9, 0.4, 0.0, 0.0], ..UserProfile::new(user_id, 4) };
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    let response = service.get_cluster_recommendations(&RecommendationRequest::new(user_id, 4), 2).await.unwrap();
+    let served: Vec<&str> = response.recommendations.iter().map(|item| item.category.as_str()).collect();
+    assert_eq!(served, vec!["books", "music", "books", "music"]);
+}
+
+#[tokio::test]
+async fn test_min_action_weight_filters_training() {
+    let mut config = small_config(4);
+    config.recommendation.min_action_weight = 0.5;
+    config.recommendation.duplicate_action_window_seconds = 0;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+    let trained_examples = service.trained_examples_counter();
+
+    let viewed = Uuid::new_v4();
+    let purchased = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(viewed, unit_vector(4, 1), "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(purchased, unit_vector(4, 0), "books".to_string())).await.unwrap();
+
+    let user_id = Uuid::new_v4();
+    for action_type in [ActionType::View, ActionType::Purchase, ActionType::View, ActionType::Click] {
+        let item_id = if matches!(action_type, ActionType::Purchase) { purchased } else { viewed };
+        service.process_user_action(&UserAction::new(user_id, item_id, action_type)).await.unwrap();
+    }
+
+    // Only the Purchase (1.0) clears the threshold; View (0.1) and Click (0.3) do not
+    assert_eq!(trained_examples.load(std::sync::atomic::Ordering::Relaxed), 1);
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("low_weight_actions_skipped"), Some(&3));
+
+    let profile = vector_db.get_user_profile(user_id).await.unwrap().unwrap();
+    assert_eq!(profile.embedding[1], 0.0);
+    assert!((profile.embedding[0] - 0.1).abs() < 1e-6);
+}
+
+#[tokio::test]
+async fn test_older_actions_move_the_profile_less() {
+    let mut config = small_config(4);
+    config.recommendation.interaction_decay_rate = 0.1;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+    let item_id = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(item_id, unit_vector(4, 0), "books".to_string())).await.unwrap();
+
+    let mut deltas = Vec::new();
+    for age_hours in [0, 24] {
+        let user_id = Uuid::new_v4();
+        let action = UserAction {
+            timestamp: Utc::now() - chrono::Duration::hours(age_hours),
+            ..UserAction::new(user_id, item_id, ActionType::Purchase)
+        };
+        service.process_user_action(&action).await.unwrap();
+        let profile = vector_db.get_user_profile(user_id).await.unwrap().unwrap();
+        deltas.push(profile.embedding.iter().map(|x| x * x).sum::<f32>().sqrt());
+    }
+
+    // A fresh purchase blends in at the full 0.1; a day old one at e^-2.4 of that
+    assert!((deltas[0] - 0.1).abs() < 1e-4, "{:?}", deltas);
+    assert!((deltas[1] - 0.1 * (-2.4f32).exp()).abs() < 1e-4, "{:?}", deltas);
+    assert!(deltas[1] < deltas[0]);
+}
+
+#[tokio::test]
+async fn test_category_affinity_boosts_the_users_category() {
+    let mut config = small_config(4);
+    config.recommendation.category_affinity_weight = 0.2;
+    config.recommendation.similarity_threshold = 0.0;
+    config.recommendation.duplicate_action_window_seconds = 0;
+    let (_, service) = in_memory_recommendation_service(config).await;
+
+    // Two candidates equally similar to the user, one in the category the user buys from
+    let candidate = vec![0.9, 0.3, 0.0, 0.0];
+    let (book, album) = (Uuid::from_u128(2), Uuid::from_u128(3));
+    service.add_item_feature(ItemFeature::new(book, candidate.clone(), "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(album, candidate, "music".to_string())).await.unwrap();
+    let purchased: Vec<Uuid> = (10..13u128).map(Uuid::from_u128).collect();
+    for item_id in &purchased {
+        service.add_item_feature(ItemFeature::new(*item_id, unit_vector(4, 0), "books".to_string())).await.unwrap();
+    }
+
+    let user_id = Uuid::new_v4();
+    for item_id in &purchased {
+        service.process_user_action(&UserAction::new(user_id, *item_id, ActionType::Purchase)).await.unwrap();
+    }
+
+    let request = RecommendationRequest::new(user_id, 5).with_exclude_items(purchased);
+    let recommendations = service.get_recommendations(&request).await.unwrap().recommendations;
+    let score_of = |item_id: Uuid| recommendations.iter().find(|item| item.item_id == item_id).unwrap().score;
+    assert_eq!(recommendations[0].item_id, book);
+    // Every interaction was with books, so books get the full weight
+    assert!((score_of(book) - score_of(album) - 0.2).abs() < 1e-4);
+}
+
+/// Golden-output guard against accidental ranking changes. Regenerate the fixture with
+/// `UPDATE_GOLDEN=1 cargo test --test integration_test test_recommendation_regression_golden`
+/// only when a scoring change is intentional.
+#[tokio::test]
+async fn test_recommendation_regression_golden() {
+    const GOLDEN_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/recommendation_regression.json");
+
+    let mut config = small_config(8);
+    config.recommendation.similarity_threshold = 0.0;
+    config.recommendation.duplicate_action_window_seconds = 0;
+    let (_, service) = in_memory_recommendation_service(config).await;
+
+    // Fixed ids and integer-derived embeddings: no RNG or transcendental functions involved
+    let categories = ["books", "music", "games", "sports"];
+    let item_ids: Vec<Uuid> = (0..40).map(|i| Uuid::from_u128(0x1000 + i as u128)).collect();
+    for (i, item_id) in item_ids.iter().enumerate() {
+        let embedding: Vec<f32> = (0..8).map(|j| ((i * 31 + j * 17 + i * j * 7) % 29) as f32 / 29.0 - 0.5).collect();
+        service.add_item_feature(ItemFeature::new(*item_id, embedding, categories[i % 4].to_string())).await.unwrap();
+    }
+
+    let user_id = Uuid::from_u128(0xabc);
+    let interactions = [
+        (3, ActionType::View),
+        (7, ActionType::Click),
+        (3, ActionType::Like),
+        (12, ActionType::Purchase),
+        (25, ActionType::Share),
+        (7, ActionType::Convert),
+    ];
+    for (index, action_type) in interactions {
+        service.process_user_action(&UserAction::new(user_id, item_ids[index], action_type)).await.unwrap();
+    }
+
+    let request = RecommendationRequest::new(user_id, 10).with_exclude_items(vec![item_ids[12]]);
+    let response = service.get_recommendations(&request).await.unwrap();
+    let actual: Vec<serde_json::Value> = response.recommendations.iter()
+        .map(|r| serde_json::json!({ "item_id": r.item_id, "score": format!("{:.6}", r.score) }))
+        .collect();
+
+    if std::env::var("UPDATE_GOLDEN").is_ok() {
+        std::fs::write(GOLDEN_PATH, serde_json::to_string_pretty(&actual).unwrap() + "\n").unwrap();
+    }
+
+    let golden: Vec<serde_json::Value> = serde_json::from_str(&std::fs::read_to_string(GOLDEN_PATH).unwrap()).unwrap();
+    assert_eq!(actual.len(), golden.len());
+    for (actual, golden) in actual.iter().zip(golden.iter()) {
+        assert_eq!(actual["item_id"], golden["item_id"]);
+        let actual_score: f32 = actual["score"].as_str().unwrap().parse().unwrap();
+        let golden_score: f32 = golden["score"].as_str().unwrap().parse().unwrap();
+        assert!((actual_score - golden_score).abs() < 1e-5, "score drifted: {} vs {}", actual_score, golden_score);
+    }
+}
+
+#[tokio::test]
+async fn test_prediction_failure_falls_back_to_similarity() {
+    use milvuso::config::PredictionFallback;
+    use milvuso::services::cache::CacheBackend;
+
+    let liked = Uuid::new_v4();
+    let stale = Uuid::new_v4();
+    let user_id = Uuid::new_v4();
+    let indexed = ItemFeature::new(stale, vec![0.8, 0.6, 0.0, 0.0], "books".to_string());
+    let similarity = utils::cosine_similarity(&[1.0, 0.0, 0.0, 0.0], &indexed.embedding);
+
+    // The legacy average-with-zero behaviour is still available as an option
+    for (fallback, expected_score) in [
+        (PredictionFallback::SimilarityOnly, similarity),
+        (PredictionFallback::Zero, similarity / 2.0),
+    ] {
+        let mut config = small_config(4);
+        config.recommendation.similarity_threshold = 0.0;
+        config.recommendation.prediction_fallback = fallback;
+        let cache = Arc::new(InMemoryCache::new());
+        let (vector_db, service) = in_memory_recommendation_service_with_cache(config, cache.clone()).await;
+
+        service.add_item_feature(ItemFeature::new(liked, vec![1.0, 0.0, 0.0, 0.0], "books".to_string())).await.unwrap();
+        service.process_user_action(&UserAction::new(user_id, liked, ActionType::Purchase)).await.unwrap();
+
+        // A stale cached feature with the wrong dimension makes the model's prediction fail for this item
+        vector_db.insert_item_feature(&indexed).await.unwrap();
+        let cached = ItemFeature::new(stale, vec![0.8, 0.6, 0.0], "books".to_string());
+        cache.set_ex(&format!("item_feature:{}", stale), serde_json::to_string(&cached).unwrap(), 60).await.unwrap();
+
+        let response = service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+        let stale_item = response.recommendations.iter().find(|r| r.item_id == stale).unwrap();
+        assert!((stale_item.score - expected_score).abs() < 1e-6);
+        assert_eq!(service.get_recommendation_stats().await.get("prediction_failures"), Some(&1));
+    }
+}
+
+#[tokio::test]
+async fn test_checkpoint_rotation_keeps_latest_and_active_versions() {
+    use milvuso::services::kafka::KafkaProducer;
+    use milvuso::services::training::TrainingService;
+
+    let mut config = small_config(4);
+    config.training.checkpoint_retention.keep_latest = 2;
+    config.training.checkpoint_retention.period_seconds = Some(86_400);
+    config.training.checkpoint_retention.keep_periods = 2;
+    let config = Arc::new(config);
+
+    let vector_db = Arc::new(VectorDbService::new(&config).await.unwrap());
+    let kafka_producer = Arc::new(KafkaProducer::new(&config).unwrap());
+    let training = TrainingService::new(vector_db, kafka_producer, config.clone())
+        .await
+        .unwrap();
+
+    // Six checkpoints, two per day over three days
+    let start = chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+    let checkpoint = |n: i64| ModelParameters {
+        version: format!("v{}", n),
+        user_embeddings: HashMap::new(),
+        item_embeddings: HashMap::new(),
+        bias_weights: vec![0.0; 4],
+        updated_at: start + chrono::Duration::hours(12 * n),
+    };
+    training.save_checkpoint(&checkpoint(0)).await.unwrap();
+    training.load_model_parameters("v0").await.unwrap();
+    for n in 1..6 {
+        training.save_checkpoint(&checkpoint(n)).await.unwrap();
+    }
+
+    let mut remaining: Vec<String> = training
+        .model_store()
+        .list()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|checkpoint| checkpoint.version)
+        .collect();
+    remaining.sort();
+    // v5 and v4 are the latest two, v3 is the newest of the previous day, v0 is being served
+    assert_eq!(remaining, vec!["v0", "v3", "v4", "v5"]);
+
+    // Once serving moves on, the old version is rotated out by the next save
+    training.load_model_parameters("v5").await.unwrap();
+    let version = training.save_model_parameters().await.unwrap();
+    let store = training.model_store();
+    assert!(store.load("v0").await.unwrap().is_none());
+    assert!(store.load(&version).await.unwrap().is_some());
+    assert!(store.load("v5").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_model_checkpoint_round_trips_through_model_dir() {
+    use milvuso::services::kafka::KafkaProducer;
+    use milvuso::services::training::TrainingService;
+
+    let dimension = 4;
+    let dir = std::env::temp_dir().join(format!("milvuso-models-{}", Uuid::new_v4()));
+    let mut config = small_config(dimension);
+    config.training.batch_size = 10;
+    config.training.model_dir = Some(dir.to_string_lossy().into_owned());
+    let config = Arc::new(config);
+
+    let vector_db = Arc::new(VectorDbService::new(&config).await.unwrap());
+    let kafka_producer = Arc::new(KafkaProducer::new(&config).unwrap());
+    let training = TrainingService::new(vector_db.clone(), kafka_producer.clone(), config.clone())
+        .await
+        .unwrap();
+
+    let (tx, rx) = tokio::sync::mpsc::channel(100);
+    let worker = training.spawn_batch_training_worker(rx);
+    for i in 0..30 {
+        let example = TrainingExample {
+            user_id: Uuid::from_u128(i as u128 % 3 + 1),
+            item_id: Uuid::from_u128(100 + i as u128 % 5),
+            label: 1.0,
+            user_features: unit_vector(dimension, i % dimension),
+            item_features: unit_vector(dimension, (i + 1) % dimension),
+            context_features: vec![],
+            timestamp: Utc::now(),
+        };
+        tx.send(example).await.unwrap();
+    }
+    drop(tx);
+    worker.await.unwrap();
+    let version = training.save_model_parameters().await.unwrap();
+
+    // A fresh service, as after a restart, reads the checkpoint back from disk
+    let restarted = TrainingService::new(vector_db, kafka_producer, config.clone()).await.unwrap();
+    assert_eq!(restarted.predict(Uuid::from_u128(1), Uuid::from_u128(100)).await, None);
+    restarted.load_model_parameters(&version).await.unwrap();
+    assert_eq!(restarted.active_version().await, Some(version));
+    for user in 1..=3u128 {
+        for item in 100..105u128 {
+            let (user_id, item_id) = (Uuid::from_u128(user), Uuid::from_u128(item));
+            let expected = training.predict(user_id, item_id).await;
+            assert!(expected.is_some());
+            assert_eq!(restarted.predict(user_id, item_id).await, expected);
+        }
+    }
+
+    let error = restarted.load_model_parameters("v0").await.unwrap_err().to_string();
+    assert!(error.contains("v0") && error.contains("not found"), "{}", error);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_training_batches_respect_augmented_cap() {
+    use milvuso::services::kafka::KafkaProducer;
+    use milvuso::services::training::TrainingService;
+
+    let dimension = 8;
+    let mut config = small_config(dimension);
+    config.training.batch_size = 100;
+    config.training.negative_sampling_ratio = 4.0;
+    config.training.max_augmented_batch_size = 64;
+    config.training.max_in_flight_batches = 2;
+    let config = Arc::new(config);
+
+    let vector_db = Arc::new(VectorDbService::new(&config).await.unwrap());
+    // Enough stored items to draw four negatives for every positive
+    for item in 0..8u128 {
+        let feature = ItemFeature::new(Uuid::from_u128(500 + item), unit_vector(dimension, item as usize % dimension), "books".to_string());
+        vector_db.insert_item_feature(&feature).await.unwrap();
+    }
+    let kafka_producer = Arc::new(KafkaProducer::new(&config).unwrap());
+    let training = TrainingService::new(vector_db, kafka_producer, config.clone())
+        .await
+        .unwrap();
+
+    let (tx, rx) = tokio::sync::mpsc::channel(1000);
+    let worker = training.spawn_batch_training_worker(rx);
+
+    let burst = 550;
+    for i in 0..burst {
+        let example = TrainingExample {
+            user_id: Uuid::from_u128(i as u128 % 17 + 1),
+            item_id: Uuid::from_u128(1_000 + i as u128),
+            label: 1.0,
+            user_features: unit_vector(dimension, i % dimension),
+            item_features: unit_vector(dimension, (i + 1) % dimension),
+            context_features: vec![],
+            timestamp: Utc::now(),
+        };
+        tx.send(example).await.unwrap();
+    }
+    drop(tx);
+
+    tokio::time::timeout(std::time::Duration::from_secs(30), worker)
+        .await
+        .expect("training worker should drain the burst")
+        .unwrap();
+
+    let stats = training.get_training_stats().await.unwrap();
+    let largest = stats["largest_augmented_batch"].as_u64().unwrap();
+    assert!(largest > 0 && largest <= 64, "augmented batch of {} exceeds cap", largest);
+    // Every positive example is trained together with its four negatives.
+    assert_eq!(stats["trained_examples"].as_u64().unwrap(), burst as u64 * 5);
+}
+
+#[tokio::test]
+async fn test_negative_samples_reference_stored_items() {
+    use milvuso::services::kafka::KafkaProducer;
+    use milvuso::services::training::TrainingService;
+
+    let dimension = 8;
+    let mut config = small_config(dimension);
+    config.training.batch_size = 5;
+    config.training.negative_sampling_ratio = 4.0;
+    let config = Arc::new(config);
+
+    // Fewer stored items than the four negatives each positive asks for, one of them a positive
+    let vector_db = Arc::new(VectorDbService::new(&config).await.unwrap());
+    let stored: Vec<Uuid> = (0..4u128).map(|item| Uuid::from_u128(500 + item)).collect();
+    for (i, item_id) in stored.iter().enumerate() {
+        let feature = ItemFeature::new(*item_id, unit_vector(dimension, i), "books".to_string());
+        vector_db.insert_item_feature(&feature).await.unwrap();
+    }
+    let kafka_producer = Arc::new(KafkaProducer::new(&config).unwrap());
+    let training = TrainingService::new(vector_db, kafka_producer, config.clone()).await.unwrap();
+
+    let user_id = Uuid::from_u128(1);
+    let positives: Vec<Uuid> = std::iter::once(stored[0]).chain((0..4u128).map(|item| Uuid::from_u128(1_000 + item))).collect();
+    let (tx, rx) = tokio::sync::mpsc::channel(10);
+    let worker = training.spawn_batch_training_worker(rx);
+    for item_id in &positives {
+        tx.send(TrainingExample {
+            user_id,
+            item_id: *item_id,
+            label: 1.0,
+            user_features: unit_vector(dimension, 0),
+            item_features: unit_vector(dimension, 1),
+            context_features: vec![],
+            timestamp: Utc::now(),
+        })
+        .await
+        .unwrap();
+    }
+    drop(tx);
+    tokio::time::timeout(std::time::Duration::from_secs(30), worker).await.unwrap().unwrap();
+
+    // Each positive trains against the three stored items that are not positives, and no others
+    let stats = training.get_training_stats().await.unwrap();
+    assert_eq!(stats["trained_examples"].as_u64().unwrap(), 5 + 5 * 3);
+    assert_eq!(stats["item_embeddings_count"].as_u64().unwrap(), 5 + 3);
+    for item_id in &stored[1..] {
+        assert!(training.predict(user_id, *item_id).await.is_some());
+    }
+}
+
+#[tokio::test]
+async fn test_batch_lookup_returns_only_found_profiles_and_items() {
+    let config = Arc::new(small_config(4));
+    let vector_db = VectorDbService::new(&config).await.unwrap();
+    let stored_users: Vec<Uuid> = (1..=3u128).map(Uuid::from_u128).collect();
+    for user_id in &stored_users {
+        vector_db.insert_user_profile(&UserProfile::new(*user_id, 4)).await.unwrap();
+    }
+    let stored_item = Uuid::from_u128(100);
+    vector_db.insert_item_feature(&ItemFeature::new(stored_item, unit_vector(4, 0), "books".to_string())).await.unwrap();
+
+    let missing = Uuid::from_u128(99);
+    let profiles = vector_db.get_user_profiles(&[stored_users[0], missing, stored_users[2]]).await;
+    assert_eq!(profiles.len(), 2);
+    assert!(!profiles.contains_key(&missing));
+    assert_eq!(profiles[&stored_users[2]].user_id, stored_users[2]);
+
+    let features = vector_db.get_item_features(&[missing, stored_item]).await;
+    assert_eq!(features.keys().collect::<Vec<_>>(), vec![&stored_item]);
+    assert!(vector_db.get_user_profiles(&[]).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_tag_based_item_search() {
+    let dimension = 4;
+    let config = Arc::new(small_config(dimension));
+    let vector_db = VectorDbService::new(&config).await.unwrap();
+
+    let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<String>>();
+    let query = Uuid::from_u128(1);
+    let closest = Uuid::from_u128(2);
+    let partial = Uuid::from_u128(3);
+    let unrelated = Uuid::from_u128(4);
+    let items = [
+        (query, tags(&["rust", "async", "web", "tokio"])),
+        (closest, tags(&["rust", "async", "web"])),
+        (partial, tags(&["rust", "cli"])),
+        (unrelated, tags(&["cooking"])),
+    ];
+    for (i, (item_id, item_tags)) in items.iter().enumerate() {
+        let feature = ItemFeature::new(*item_id, unit_vector(dimension, i), "books".to_string())
+            .with_tags(item_tags.clone());
+        vector_db.insert_item_feature(&feature).await.unwrap();
+    }
+
+    let results = vector_db.search_similar_items_by_tags(query, 10).await.unwrap();
+    let ids: Vec<Uuid> = results.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, vec![closest, partial]);
+    assert!((results[0].1 - 0.75).abs() < 1e-6);
+    assert!((results[1].1 - 0.2).abs() < 1e-6);
+
+    assert!(vector_db.search_similar_items_by_tags(Uuid::from_u128(99), 10).await.is_err());
+}
+
+#[tokio::test]
+async fn test_trending_items_use_smoothed_popularity() {
+    use milvuso::services::serving::ServingService;
+
+    let dimension = 4;
+    let config = small_config(dimension);
+    let (vector_db, service) = in_memory_recommendation_service(config.clone()).await;
+    let service = Arc::new(service);
+
+    let high_volume = Uuid::from_u128(1);
+    let high_rate = Uuid::from_u128(2);
+    for (i, item_id) in [high_volume, high_rate].into_iter().enumerate() {
+        let feature = ItemFeature::new(item_id, unit_vector(dimension, i), "books".to_string());
+        vector_db.insert_item_feature(&feature).await.unwrap();
+    }
+
+    for i in 0..10_000 {
+        vector_db.record_item_interaction(high_volume, &ActionType::View).await;
+        if i < 8_000 {
+            vector_db.record_item_interaction(high_volume, &ActionType::Like).await;
+        }
+    }
+    for _ in 0..4 {
+        vector_db.record_item_interaction(high_rate, &ActionType::View).await;
+        vector_db.record_item_interaction(high_rate, &ActionType::Like).await;
+    }
+    // The last pair goes through the action pipeline, which records interactions too
+    let user_id = Uuid::new_v4();
+    service.process_user_action(&UserAction::new(user_id, high_rate, ActionType::View)).await.unwrap();
+    service.process_user_action(&UserAction::new(user_id, high_rate, ActionType::Like)).await.unwrap();
+    let counts = vector_db.get_item_interactions(high_rate).await;
+    assert_eq!((counts.views, counts.positives), (5, 5));
+
+    let serving = ServingService::new(vector_db.clone(), service, Arc::new(config))
+        .await
+        .unwrap();
+    let trending = serving.get_trending_items(Some("books".to_string()), 10).await.unwrap();
+    let ids: Vec<Uuid> = trending.iter().map(|item| item.item_id).collect();
+    assert_eq!(ids, vec![high_volume, high_rate]);
+    assert!(trending[0].score > trending[1].score);
+
+    assert!(serving.get_trending_items(Some("music".to_string()), 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_serving_stats_count_served_recommendations() {
+    use milvuso::services::serving::ServingService;
+
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    let (vector_db, service) = in_memory_recommendation_service(config.clone()).await;
+    service.add_item_feature(ItemFeature::new(Uuid::from_u128(1), unit_vector(4, 0), "books".to_string())).await.unwrap();
+    let profile = UserProfile { embedding: unit_vector(4, 0), ..UserProfile::new(Uuid::from_u128(100), 4) };
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    let serving = ServingService::new(vector_db, Arc::new(service), Arc::new(config)).await.unwrap();
+    assert!(serving.get_serving_stats().await.is_empty());
+
+    let response = serving.serve_recommendations(&RecommendationRequest::new(profile.user_id, 5)).await.unwrap();
+    assert_eq!(response.recommendations.len(), 1);
+    let stats = serving.get_serving_stats().await;
+    assert_eq!(stats.get("total_requests"), Some(&1));
+    assert_eq!(stats.get("successful_requests"), Some(&1));
+}
+
+#[tokio::test]
+async fn test_catalog_coverage_grows_with_served_recommendations() {
+    use milvuso::services::serving::ServingService;
+
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = 0.0;
+    config.recommendation.cold_start_threshold = 0;
+    let (vector_db, service) = in_memory_recommendation_service(config.clone()).await;
+    // 40 items along each axis; a user on an axis is only ever shown items near it
+    for i in 0..160u128 {
+        let mut embedding = unit_vector(dimension, (i % 4) as usize);
+        embedding[((i + 1) % 4) as usize] = (i / 4) as f32 / 100.0;
+        service.add_item_feature(ItemFeature::new(Uuid::from_u128(i + 1), embedding, "books".to_string())).await.unwrap();
+    }
+    let serving = ServingService::new(vector_db.clone(), Arc::new(service), Arc::new(config)).await.unwrap();
+    let coverage = serving.get_catalog_coverage().await;
+    assert_eq!((coverage.distinct_items_served, coverage.catalog_items), (0, 160));
+
+    let mut previous = 0.0;
+    for axis in 0..2 {
+        for user in 0..5u128 {
+            let user_id = Uuid::from_u128(1000 + axis as u128 * 10 + user);
+            let profile = UserProfile { embedding: unit_vector(dimension, axis), ..UserProfile::new(user_id, dimension) };
+            vector_db.insert_user_profile(&profile).await.unwrap();
+            let response = serving.serve_recommendations(&RecommendationRequest::new(user_id, 40)).await.unwrap();
+            assert_eq!(response.recommendations.len(), 40);
+        }
+        let coverage = serving.get_catalog_coverage().await;
+        assert!(coverage.coverage > previous);
+        previous = coverage.coverage;
+    }
+
+    // Users on two of the four axes see the 80 items nearest them, half the catalog
+    let coverage = serving.get_catalog_coverage().await;
+    assert!((coverage.coverage - 0.5).abs() < 0.05, "coverage {}", coverage.coverage);
+    assert!(serving.render_metrics().await.contains("milvuso_catalog_items 160"));
+}
+
+#[tokio::test]
+async fn test_score_histograms_track_served_score_distribution() {
+    use milvuso::services::serving::ServingService;
+
+    let config = small_config(4);
+    let (vector_db, service) = in_memory_recommendation_service(config.clone()).await;
+    let serving = ServingService::new(vector_db, Arc::new(service), Arc::new(config)).await.unwrap();
+
+    let response = |scores: &[f32]| RecommendationResponse {
+        recommendation_id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        recommendations: scores
+            .iter()
+            .map(|&score| RecommendationItem {
+                item_id: Uuid::new_v4(),
+                score,
+                reason: Some("test".to_string()),
+                category: "books".to_string(),
+                relaxed_filter: None,
+            })
+            .collect(),
+        generated_at: Utc::now(),
+        model_warming_up: false,
+    };
+    // A healthy model, then a deploy that collapses every score
+    for _ in 0..3 {
+        serving.record_score_distribution(&response(&[0.95, 0.85, 0.75])).await;
+    }
+    serving.record_score_distribution(&response(&[0.15, 0.01])).await;
+    serving.record_score_distribution(&response(&[])).await;
+
+    let histograms = serving.get_score_histograms().await;
+    assert_eq!(histograms.top_score.count(), 4);
+    let bucket = |buckets: &[(f64, u64)], le: f64| buckets.iter().find(|(bound, _)| *bound == le).unwrap().1;
+    let top = histograms.top_score.cumulative_buckets();
+    assert_eq!((bucket(&top, 0.1), bucket(&top, 0.2), bucket(&top, 0.9), bucket(&top, 1.0)), (0, 1, 1, 4));
+    let mean = histograms.mean_score.cumulative_buckets();
+    assert_eq!((bucket(&mean, 0.1), bucket(&mean, 0.8), bucket(&mean, 0.9)), (1, 1, 4));
+    assert_eq!(mean.last(), Some(&(f64::INFINITY, 4)));
+    assert!((histograms.mean_score.sum() - (3.0 * 0.85 + 0.08)).abs() < 1e-4);
+
+    let metrics = serving.render_metrics().await;
+    assert!(metrics.contains("# TYPE milvuso_recommendation_top_score histogram"));
+    assert!(metrics.contains("milvuso_recommendation_top_score_bucket{le=\"0.2\"} 1"));
+    assert!(metrics.contains("milvuso_recommendation_mean_score_bucket{le=\"+Inf\"} 4"));
+    assert!(metrics.contains("milvuso_recommendation_mean_score_count 4"));
+}
+
+#[tokio::test]
+async fn test_trending_in_context_falls_back_to_global_without_enough_data() {
+    use chrono::TimeZone;
+    use milvuso::models::TrendingContext;
+    use milvuso::services::serving::ServingService;
+
+    let dimension = 4;
+    let config = small_config(dimension);
+    let (vector_db, service) = in_memory_recommendation_service(config.clone()).await;
+    let service = Arc::new(service);
+
+    let globally_popular = Uuid::from_u128(1);
+    let popular_in_eu_mornings = Uuid::from_u128(2);
+    for (i, item_id) in [globally_popular, popular_in_eu_mornings].into_iter().enumerate() {
+        let feature = ItemFeature::new(item_id, unit_vector(dimension, i), "books".to_string());
+        vector_db.insert_item_feature(&feature).await.unwrap();
+    }
+    for _ in 0..1_000 {
+        vector_db.record_item_interaction(globally_popular, &ActionType::Like).await;
+    }
+
+    let morning = Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap();
+    for _ in 0..15 {
+        for item_id in [globally_popular, popular_in_eu_mornings] {
+            let mut view = UserAction::new(Uuid::new_v4(), item_id, ActionType::View);
+            view.timestamp = morning;
+            view.context = Some(serde_json::json!({ "region": "eu" }));
+            service.process_user_action(&view).await.unwrap();
+        }
+        let mut like = UserAction::new(Uuid::new_v4(), popular_in_eu_mornings, ActionType::Like);
+        like.timestamp = morning;
+        like.context = Some(serde_json::json!({ "region": "eu" }));
+        service.process_user_action(&like).await.unwrap();
+    }
+
+    let serving = ServingService::new(vector_db.clone(), service.clone(), Arc::new(config)).await.unwrap();
+    let ids = |items: &[RecommendationItem]| items.iter().map(|item| item.item_id).collect::<Vec<_>>();
+
+    let global = serving.get_trending_items(None, 10).await.unwrap();
+    assert_eq!(ids(&global), vec![globally_popular, popular_in_eu_mornings]);
+
+    let eu_mornings = TrendingContext { hour_of_day: Some(9), region: Some("eu".to_string()) };
+    let trending = serving.get_trending_items_in_context(None, &eu_mornings, 10).await.unwrap();
+    assert_eq!(ids(&trending), vec![popular_in_eu_mornings, globally_popular]);
+    assert_eq!(trending[0].reason.as_deref(), Some("Trending in your context"));
+
+    // Each dimension is bucketed on its own too
+    let mornings = TrendingContext { hour_of_day: Some(9), region: None };
+    let trending = serving.get_trending_items_in_context(None, &mornings, 10).await.unwrap();
+    assert_eq!(trending[0].item_id, popular_in_eu_mornings);
+
+    // A region with no interactions, and an hour with fewer than the minimum, use global trending
+    let mut late_like = UserAction::new(Uuid::new_v4(), popular_in_eu_mornings, ActionType::Like);
+    late_like.timestamp = morning + chrono::Duration::hours(12);
+    service.process_user_action(&late_like).await.unwrap();
+    for context in [
+        TrendingContext { hour_of_day: Some(9), region: Some("us".to_string()) },
+        TrendingContext { hour_of_day: Some(21), region: None },
+    ] {
+        let trending = serving.get_trending_items_in_context(None, &context, 10).await.unwrap();
+        assert_eq!(ids(&trending), ids(&global));
+        assert_eq!(trending[0].reason.as_deref(), Some("Trending item"));
+    }
+}
+
+#[tokio::test]
+async fn test_batch_serve_reports_per_request_diagnostics() {
+    use milvuso::services::serving::ServingService;
+
+    let dimension = 4;
+    let config = small_config(dimension);
+    let (vector_db, service) = in_memory_recommendation_service(config.clone()).await;
+    for i in 0..3u128 {
+        let feature = ItemFeature::new(Uuid::from_u128(i + 1), vec![1.0, 0.1 * i as f32, 0.0, 0.0], "books".to_string());
+        vector_db.insert_item_feature(&feature).await.unwrap();
+    }
+    let mut profile = UserProfile::new(Uuid::from_u128(100), dimension);
+    profile.embedding = unit_vector(dimension, 0);
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    let serving = ServingService::new(vector_db.clone(), Arc::new(service), Arc::new(config)).await.unwrap();
+
+    let served = RecommendationRequest::new(profile.user_id, 2);
+    // More distinct categories than recommendations can never be satisfied
+    let failing = RecommendationRequest {
+        min_distinct_categories: Some(5),
+        ..RecommendationRequest::new(Uuid::from_u128(101), 2)
+    };
+    let results = serving.batch_serve_recommendations(&[served.clone(), failing.clone()]).await;
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].request.user_id, served.user_id);
+    let response = results[0].result.as_ref().unwrap();
+    assert_eq!(results[0].recommendation_count, response.recommendations.len());
+    assert_eq!(results[0].recommendation_count, 2);
+    assert_eq!(results[0].candidate_count, 3);
+
+    assert_eq!(results[1].request.user_id, failing.user_id);
+    let error = results[1].result.as_ref().unwrap_err();
+    assert!(error.to_string().contains("distinct categories"));
+    assert_eq!((results[1].candidate_count, results[1].recommendation_count), (0, 0));
+    let stats = serving.get_serving_stats().await;
+    assert_eq!(stats.get("failed_requests"), Some(&1));
+    // Batched requests are served and counted like single ones
+    assert_eq!(stats.get("total_requests"), Some(&2));
+    assert_eq!(stats.get("successful_requests"), Some(&1));
+}
+
+#[tokio::test]
+async fn test_embedding_dimension_adaptation() {
+    use milvuso::config::DimensionAdapt;
+
+    let dimension = 4;
+    let item_id = Uuid::from_u128(1);
+
+    let rejecting = VectorDbService::new(&small_config(dimension)).await.unwrap();
+    let short = ItemFeature::new(item_id, vec![1.0, 2.0], "books".to_string());
+    assert!(rejecting.insert_item_feature(&short).await.is_err());
+
+    let mut config = small_config(dimension);
+    config.recommendation.dimension_adapt = DimensionAdapt::PadZero;
+    let padding = VectorDbService::new(&config).await.unwrap();
+    padding.insert_item_feature(&short).await.unwrap();
+    let stored = padding.get_item_feature(item_id).await.unwrap().unwrap();
+    assert_eq!(stored.embedding, vec![1.0, 2.0, 0.0, 0.0]);
+    let long = ItemFeature::new(Uuid::from_u128(2), vec![1.0; 6], "books".to_string());
+    assert!(padding.insert_item_feature(&long).await.is_err());
+
+    config.recommendation.dimension_adapt = DimensionAdapt::Truncate;
+    let truncating = VectorDbService::new(&config).await.unwrap();
+    let long = ItemFeature::new(item_id, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], "books".to_string());
+    truncating.insert_item_feature(&long).await.unwrap();
+    let stored = truncating.get_item_feature(item_id).await.unwrap().unwrap();
+    assert_eq!(stored.embedding, vec![1.0, 2.0, 3.0, 4.0]);
+    let results = truncating.search_similar_items(&[1.0, 2.0, 3.0, 4.0], 1).await.unwrap();
+    assert_eq!(results[0].0, item_id);
+
+    let profile = UserProfile {
+        embedding: vec![1.0; 6],
+        ..UserProfile::new(Uuid::from_u128(3), dimension)
+    };
+    truncating.insert_user_profile(&profile).await.unwrap();
+    let stored = truncating.get_user_profile(profile.user_id).await.unwrap().unwrap();
+    assert_eq!(stored.embedding.len(), dimension);
+}
+
+#[tokio::test]
+async fn test_group_recommendations_least_misery() {
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = -1.0;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let alice = Uuid::from_u128(100);
+    let bob = Uuid::from_u128(101);
+    for (user_id, axis) in [(alice, 0), (bob, 1)] {
+        let profile = UserProfile {
+            embedding: unit_vector(dimension, axis),
+            ..UserProfile::new(user_id, dimension)
+        };
+        vector_db.insert_user_profile(&profile).await.unwrap();
+    }
+
+    let s = std::f32::consts::FRAC_1_SQRT_2;
+    // Alice loves the first item, Bob hates it; both like the compromise
+    let hated_by_bob = Uuid::from_u128(1);
+    let compromise = Uuid::from_u128(2);
+    let alice_pick = Uuid::from_u128(3);
+    let bob_pick = Uuid::from_u128(4);
+    for (item_id, embedding) in [
+        (hated_by_bob, vec![s, -s, 0.0, 0.0]),
+        (compromise, vec![s, s, 0.0, 0.0]),
+        (alice_pick, unit_vector(dimension, 0)),
+        (bob_pick, unit_vector(dimension, 1)),
+    ] {
+        let feature = ItemFeature::new(item_id, embedding, "movies".to_string());
+        service.add_item_feature(feature).await.unwrap();
+    }
+
+    let request = |aggregation| GroupRecommendationRequest {
+        user_ids: vec![alice, bob, alice],
+        aggregation,
+        num_recommendations: 3,
+        filter_categories: None,
+        exclude_items: None,
+        tenant_id: None,
+    };
+
+    let least_misery = service
+        .get_group_recommendations(&request(GroupAggregation::LeastMisery))
+        .await
+        .unwrap();
+    let ids: Vec<Uuid> = least_misery.recommendations.iter().map(|r| r.item_id).collect();
+    assert_eq!(ids[0], compromise);
+    assert!(!ids.contains(&hated_by_bob));
+    assert!(least_misery.recommendations.iter().all(|r| r.score >= 0.0));
+
+    // Most pleasure only looks at the happiest member, so Bob's veto does not count
+    let most_pleasure = service
+        .get_group_recommendations(&request(GroupAggregation::MostPleasure))
+        .await
+        .unwrap();
+    let ids: Vec<Uuid> = most_pleasure.recommendations.iter().map(|r| r.item_id).collect();
+    assert_eq!(ids, vec![alice_pick, bob_pick, hated_by_bob]);
+
+    let average = service
+        .get_group_recommendations(&request(GroupAggregation::AverageEmbedding))
+        .await
+        .unwrap();
+    assert_eq!(average.recommendations[0].item_id, compromise);
+
+    assert!(service
+        .get_group_recommendations(&GroupRecommendationRequest { user_ids: vec![], ..request(GroupAggregation::LeastMisery) })
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_default_config_indexes_in_memory() {
+    let config = Config::default();
+    assert!(config.milvus.host.is_empty());
+
+    let vector_db = VectorDbService::new(&config).await.unwrap();
+    let stats = vector_db.index_stats().await;
+    assert_eq!(stats.users.kind, "in_memory");
+    assert_eq!(stats.items.kind, "in_memory");
+
+    let mut config = Config::default();
+    config.milvus.in_memory_index = "ivf".to_string();
+    let stats = VectorDbService::new(&config).await.unwrap().index_stats().await;
+    assert_eq!(stats.items.kind, "ivf_flat");
+}
+
+#[tokio::test]
+async fn test_renormalize_all_rescales_embeddings_and_keeps_rankings() {
+    let dimension = 4;
+    let vector_db = VectorDbService::new(&small_config(dimension)).await.unwrap();
+    let scales = [0.01, 3.0, 250.0, 1.0];
+    for (i, scale) in scales.iter().enumerate() {
+        let embedding: Vec<f32> = [1.0, i as f32, 0.5, -(i as f32)].iter().map(|x| x * scale).collect();
+        let mut feature = ItemFeature::new(Uuid::from_u128(i as u128 + 1), embedding.clone(), "books".to_string());
+        if i == 0 {
+            feature = feature.with_embedding_version("v2".to_string(), vec![0.0, 0.0, 9.0, 0.0]);
+        }
+        vector_db.insert_item_feature(&feature).await.unwrap();
+
+        let mut profile = UserProfile::new(Uuid::from_u128(100 + i as u128), dimension);
+        profile.embedding = embedding;
+        vector_db.insert_user_profile(&profile).await.unwrap();
+    }
+    // Already unit length, so left alone
+    vector_db.insert_item_feature(&ItemFeature::new(Uuid::from_u128(9), unit_vector(dimension, 1), "books".to_string()))
+        .await
+        .unwrap();
+
+    let query = [0.3, 1.0, 0.2, -0.4];
+    let before = vector_db.search_similar_items(&query, 10).await.unwrap();
+    let users_before = vector_db.search_similar_users(&query, 10).await.unwrap();
+
+    let report = vector_db.renormalize_all().await.unwrap();
+    assert_eq!((report.users, report.items, report.item_versions), (4, 4, 1));
+
+    let norm = |embedding: &[f32]| embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    for i in 0..scales.len() as u128 {
+        let feature = vector_db.get_item_feature(Uuid::from_u128(i + 1)).await.unwrap().unwrap();
+        assert!((norm(&feature.embedding) - 1.0).abs() < 1e-5);
+        for embedding in feature.embedding_versions.values() {
+            assert!((norm(embedding) - 1.0).abs() < 1e-5);
+        }
+        let profile = vector_db.get_user_profile(Uuid::from_u128(100 + i)).await.unwrap().unwrap();
+        assert!((norm(&profile.embedding) - 1.0).abs() < 1e-5);
+    }
+
+    let ids = |results: &[(Uuid, f32)]| results.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    assert_eq!(ids(&vector_db.search_similar_items(&query, 10).await.unwrap()), ids(&before));
+    assert_eq!(ids(&vector_db.search_similar_users(&query, 10).await.unwrap()), ids(&users_before));
+    let version_results = vector_db.search_similar_items_in_version(&[0.0, 0.0, 1.0, 0.0], 1, "v2").await.unwrap();
+    assert!((version_results[0].1 - 1.0).abs() < 1e-5);
+
+    assert_eq!(vector_db.renormalize_all().await.unwrap(), Default::default());
+}
+
+#[tokio::test]
+async fn test_index_stats() {
+    use milvuso::algorithms::retriever::{HNSWRetriever, VectorRetriever};
+
+    let dimension = 16;
+    let vector_db = VectorDbService::new(&small_config(dimension)).await.unwrap();
+    for i in 0..50u128 {
+        let embedding: Vec<f32> = (0..dimension).map(|d| ((i as usize * 7 + d) % 11) as f32 + 1.0).collect();
+        let feature = ItemFeature::new(Uuid::from_u128(i + 1), embedding, "books".to_string())
+            .with_embedding_version("v2".to_string(), unit_vector(dimension, i as usize % dimension));
+        vector_db.insert_item_feature(&feature).await.unwrap();
+    }
+    for i in 0..20u128 {
+        let profile = UserProfile::new(Uuid::from_u128(1_000 + i), dimension);
+        vector_db.insert_user_profile(&profile).await.unwrap();
+    }
+
+    let stats = vector_db.index_stats().await;
+    assert_eq!(stats.items.vector_count, 50);
+    assert_eq!(stats.items.dimension, dimension);
+    assert_eq!(stats.users.vector_count, 20);
+    assert_eq!(stats.item_versions["v2"].vector_count, 50);
+    // At least the raw f32 payload, and not wildly more than it
+    let raw_bytes = 50 * dimension * 4;
+    assert!(stats.items.estimated_bytes >= raw_bytes);
+    assert!(stats.items.estimated_bytes < raw_bytes * 4);
+    assert_eq!(
+        stats.estimated_bytes,
+        stats.users.estimated_bytes + stats.items.estimated_bytes + stats.item_versions["v2"].estimated_bytes
+    );
+
+    let mut hnsw = HNSWRetriever::new(dimension, 16, 200);
+    for i in 0..100u128 {
+        hnsw.add_vector(Uuid::from_u128(i + 1), vec![1.0; dimension]).await.unwrap();
+    }
+    let hnsw_stats = hnsw.stats();
+    assert_eq!(hnsw_stats.vector_count, 100);
+    assert_eq!(hnsw_stats.layer_node_counts[0], 100);
+    assert!(hnsw_stats.layer_node_counts.windows(2).all(|pair| pair[0] >= pair[1]));
+    assert!(hnsw_stats.estimated_bytes >= 100 * dimension * 4);
+}
+
+#[tokio::test]
+async fn test_soft_similarity_threshold() {
+    let dimension = 4;
+    let user_id = Uuid::from_u128(100);
+    let strong = Uuid::from_u128(1);
+    let near_a = Uuid::from_u128(2);
+    let near_b = Uuid::from_u128(3);
+    let at_cosine = |cosine: f32| vec![cosine, (1.0 - cosine * cosine).sqrt(), 0.0, 0.0];
+
+    // Serves the same catalog with near_a/near_b jittering to either side of the 0.7 threshold
+    let serve = |soft_width: f32, near_a_cosine: f32, near_b_cosine: f32| async move {
+        let mut config = small_config(dimension);
+        config.recommendation.similarity_threshold = 0.7;
+        config.recommendation.soft_threshold_width = soft_width;
+        // Keep the model gated so scores are pure similarity
+        config.recommendation.min_training_examples = u64::MAX;
+        let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+        let profile = UserProfile {
+            embedding: unit_vector(dimension, 0),
+            ..UserProfile::new(user_id, dimension)
+        };
+        vector_db.insert_user_profile(&profile).await.unwrap();
+        for (item_id, cosine) in [(strong, 0.95), (near_a, near_a_cosine), (near_b, near_b_cosine)] {
+            let feature = ItemFeature::new(item_id, at_cosine(cosine), "books".to_string());
+            service.add_item_feature(feature).await.unwrap();
+        }
+
+        service
+            .get_recommendations(&RecommendationRequest::new(user_id, 10))
+            .await
+            .unwrap()
+            .recommendations
+    };
+    let item_set = |items: &[RecommendationItem]| {
+        let mut ids: Vec<Uuid> = items.iter().map(|item| item.item_id).collect();
+        ids.sort();
+        ids
+    };
+
+    // A hard cutoff flips membership when scores move by 0.02
+    let hard = serve(0.0, 0.71, 0.69).await;
+    let hard_jittered = serve(0.0, 0.69, 0.71).await;
+    assert_eq!(item_set(&hard), vec![strong, near_a]);
+    assert_eq!(item_set(&hard_jittered), vec![strong, near_b]);
+
+    let soft = serve(0.02, 0.71, 0.69).await;
+    let soft_jittered = serve(0.02, 0.69, 0.71).await;
+    assert_eq!(item_set(&soft), vec![strong, near_a, near_b]);
+    assert_eq!(item_set(&soft), item_set(&soft_jittered));
+
+    let score = |items: &[RecommendationItem], item_id| items.iter().find(|item| item.item_id == item_id).unwrap().score;
+    assert!((score(&soft, strong) - 0.95).abs() < 1e-3);
+    assert!(score(&soft, near_a) < 0.71 && score(&soft, near_a) > score(&soft, near_b));
+    assert!(score(&soft, near_b) > 0.0);
+}
+
+#[tokio::test]
+async fn test_cache_codec_is_detected_on_read() {
+    use milvuso::config::CacheCodec;
+    use milvuso::services::cache::CacheBackend;
+
+    let dimension = 4;
+    let cache = Arc::new(InMemoryCache::new());
+    let mut config = small_config(dimension);
+    config.redis.codec = CacheCodec::BincodeZstd;
+    let (vector_db, service) = in_memory_recommendation_service_with_cache(config, cache.clone()).await;
+
+    // An entry written as JSON before the codec change is still read
+    let legacy_item = Uuid::from_u128(1);
+    let legacy = ItemFeature::new(legacy_item, unit_vector(dimension, 0), "books".to_string());
+    cache
+        .set_ex(&format!("item_feature:{}", legacy_item), serde_json::to_string(&legacy).unwrap(), 60)
+        .await
+        .unwrap();
+    let user_id = Uuid::from_u128(100);
+    service.process_user_action(&UserAction::new(user_id, legacy_item, ActionType::Like)).await.unwrap();
+    // The legacy item only exists in the cache, so the profile moves only if it was decoded
+    let profile = vector_db.get_user_profile(user_id).await.unwrap().unwrap();
+    assert!(profile.embedding[0] > 0.0);
+
+    // New entries are written with the configured codec
+    let item_id = Uuid::from_u128(2);
+    let feature = ItemFeature::new(item_id, unit_vector(dimension, 1), "music".to_string())
+        .with_tags(vec!["jazz".to_string()]);
+    service.add_item_feature(feature.clone()).await.unwrap();
+    let raw = cache.get_bytes(&format!("item_feature:{}", item_id)).await.unwrap().unwrap();
+    assert!(raw.starts_with(b"MRZ1"));
+    let decoded: ItemFeature = milvuso::utils::cache_codec::decode_payload(&raw).unwrap();
+    assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&feature).unwrap());
+}
+
+#[tokio::test]
+async fn test_streamed_recommendations_report_progress() {
+    let dimension = 8;
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = -1.0;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let user_id = Uuid::from_u128(1_000);
+    let profile = UserProfile {
+        embedding: unit_vector(dimension, 0),
+        ..UserProfile::new(user_id, dimension)
+    };
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    for i in 0..200u128 {
+        let embedding: Vec<f32> = (0..dimension).map(|d| ((i as usize * 13 + d * 7) % 17) as f32 - 8.0).collect();
+        let feature = ItemFeature::new(Uuid::from_u128(i + 1), embedding, format!("category_{}", i % 5));
+        service.add_item_feature(feature).await.unwrap();
+    }
+
+    let request = RecommendationRequest::new(user_id, 25);
+    let batch = service.get_recommendations(&request).await.unwrap();
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let streamed = service.get_recommendations_with_progress(&request, &tx).await.unwrap();
+    drop(tx);
+    let mut events = Vec::new();
+    while let Some(event) = rx.recv().await {
+        events.push(event);
+    }
+
+    let progress: Vec<(usize, usize)> = events
+        .iter()
+        .filter_map(|event| match event {
+            RecommendationStreamEvent::Progress { scored, total } => Some((*scored, *total)),
+            _ => None,
+        })
+        .collect();
+    assert!(progress.len() > 2);
+    assert!(progress.windows(2).all(|pair| pair[0].0 <= pair[1].0 && pair[0].1 == pair[1].1));
+    let (last_scored, total) = *progress.last().unwrap();
+    assert_eq!(last_scored, total);
+
+    // Progress comes first, then the ranked items, then a single Done
+    let first_item = events.iter().position(|event| matches!(event, RecommendationStreamEvent::Item { .. })).unwrap();
+    assert!(events[..first_item].iter().all(|event| matches!(event, RecommendationStreamEvent::Progress { .. })));
+    match events.last().unwrap() {
+        RecommendationStreamEvent::Done { recommendation_id, .. } => assert_eq!(*recommendation_id, streamed.recommendation_id),
+        other => panic!("stream ended with {:?}", other),
+    }
+
+    let streamed_items: Vec<(usize, Uuid)> = events
+        .iter()
+        .filter_map(|event| match event {
+            RecommendationStreamEvent::Item { rank, item } => Some((*rank, item.item_id)),
+            _ => None,
+        })
+        .collect();
+    let batch_items: Vec<(usize, Uuid)> = batch.recommendations.iter().map(|item| item.item_id).enumerate().collect();
+    assert_eq!(streamed_items, batch_items);
+}
+
+#[tokio::test]
+async fn test_retriever_min_similarity_floor() {
+    use milvuso::algorithms::retriever::{InMemoryRetriever, VectorRetriever};
+
+    let dimension = 4;
+    let mut retriever = InMemoryRetriever::new(dimension);
+    let close = Uuid::from_u128(1);
+    let related = Uuid::from_u128(2);
+    let orthogonal = Uuid::from_u128(3);
+    let opposite = Uuid::from_u128(4);
+    retriever.add_vector(close, vec![1.0, 0.1, 0.0, 0.0]).await.unwrap();
+    retriever.add_vector(related, vec![1.0, 1.0, 0.0, 0.0]).await.unwrap();
+    retriever.add_vector(orthogonal, unit_vector(dimension, 2)).await.unwrap();
+    retriever.add_vector(opposite, vec![-1.0, 0.0, 0.0, 0.0]).await.unwrap();
+
+    let query = unit_vector(dimension, 0);
+    let unfiltered = retriever.search_similar(&query, 4).await.unwrap();
+    assert_eq!(unfiltered.len(), 4);
+
+    let filtered = retriever.search_similar_filtered(&query, 4, 0.5).await.unwrap();
+    let ids: Vec<Uuid> = filtered.iter().map(|(id, _)| *id).collect();
+    assert_eq!(ids, vec![close, related]);
+    assert!(filtered.iter().all(|(_, similarity)| *similarity >= 0.5));
+
+    let nothing_similar = retriever.search_similar_filtered(&unit_vector(dimension, 3), 4, 0.1).await.unwrap();
+    assert!(nothing_similar.is_empty());
+
+    // The recommendation pipeline applies the configured floor at retrieval
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = -1.0;
+    config.recommendation.retrieval_min_similarity = Some(0.5);
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+    let user_id = Uuid::from_u128(100);
+    let profile = UserProfile {
+        embedding: unit_vector(dimension, 3),
+        ..UserProfile::new(user_id, dimension)
+    };
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    for (i, item_id) in [close, related, orthogonal, opposite].into_iter().enumerate() {
+        let embedding = unit_vector(dimension, i % 3);
+        service.add_item_feature(ItemFeature::new(item_id, embedding, "books".to_string())).await.unwrap();
+    }
+    let response = service.get_recommendations(&RecommendationRequest::new(user_id, 4)).await.unwrap();
+    assert!(response.recommendations.is_empty());
+}
+
+#[tokio::test]
+async fn test_metadata_store_survives_restart() {
+    use milvuso::config::MetadataBackend;
+
+    let dimension = 4;
+    let dir = std::env::temp_dir().join(format!("milvuso-metadata-{}", Uuid::new_v4()));
+    let mut config = small_config(dimension);
+    config.metadata_store.backend = MetadataBackend::Sled;
+    config.metadata_store.path = dir.to_string_lossy().into_owned();
+
+    let user_id = Uuid::from_u128(1);
+    let item_id = Uuid::from_u128(2);
+    let expired_id = Uuid::from_u128(3);
+    {
+        let vector_db = VectorDbService::new(&config).await.unwrap();
+        vector_db.insert_user_profile(&UserProfile::new(user_id, dimension)).await.unwrap();
+        vector_db.update_user_embedding(user_id, unit_vector(dimension, 1)).await.unwrap();
+        vector_db
+            .insert_item_feature(&ItemFeature::new(item_id, unit_vector(dimension, 1), "books".to_string()))
+            .await
+            .unwrap();
+        let mut expired = ItemFeature::new(expired_id, unit_vector(dimension, 2), "books".to_string());
+        expired.expires_at = Some(Utc::now() - chrono::Duration::hours(1));
+        vector_db.insert_item_feature(&expired).await.unwrap();
+        assert_eq!(vector_db.reap_expired_items(chrono::Duration::zero()).await, 1);
+    }
+
+    // A fresh service over the same directory serves what the first one wrote
+    let vector_db = VectorDbService::new(&config).await.unwrap();
+    let profile = vector_db.get_user_profile(user_id).await.unwrap().unwrap();
+    assert_eq!(profile.embedding, unit_vector(dimension, 1));
+    assert!(vector_db.get_item_feature(item_id).await.unwrap().is_some());
+    assert!(vector_db.get_item_feature(expired_id).await.unwrap().is_none());
+
+    let similar = vector_db.search_similar_items(&unit_vector(dimension, 1), 5).await.unwrap();
+    assert_eq!(similar.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![item_id]);
+    let users = vector_db.search_similar_users(&unit_vector(dimension, 1), 5).await.unwrap();
+    assert_eq!(users[0].0, user_id);
+
+    drop(vector_db);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_response_cache_invalidated_by_profile_version() {
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = -1.0;
+    config.redis.response_cache_ttl_seconds = 60;
+    let (_vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let user_id = Uuid::from_u128(1);
+    let item_id = Uuid::from_u128(2);
+    service.add_item_feature(ItemFeature::new(item_id, unit_vector(dimension, 1), "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(Uuid::from_u128(3), unit_vector(dimension, 2), "books".to_string())).await.unwrap();
+
+    let request = RecommendationRequest::new(user_id, 2);
+    let first = service.get_recommendations(&request).await.unwrap();
+    let second = service.get_recommendations(&request).await.unwrap();
+    let item_ids = |response: &RecommendationResponse| response.recommendations.iter().map(|r| r.item_id).collect::<Vec<_>>();
+    assert_eq!(item_ids(&first), item_ids(&second));
+    // A hit is a new serving, so feedback on it can be attributed
+    assert_ne!(first.recommendation_id, second.recommendation_id);
+    service
+        .process_user_action(&UserAction::new(user_id, item_id, ActionType::View).with_recommendation_id(second.recommendation_id))
+        .await
+        .unwrap();
+
+    // A different request is a different key
+    service.get_recommendations(&RecommendationRequest::new(user_id, 1)).await.unwrap();
+
+    service.process_user_action(&UserAction::new(user_id, item_id, ActionType::Click)).await.unwrap();
+    service.get_recommendations(&request).await.unwrap();
+
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("attributed_feedback"), Some(&1));
+    assert_eq!(stats.get("response_cache_hits"), Some(&1));
+    assert_eq!(stats.get("response_cache_misses"), Some(&3));
+}
+
+#[tokio::test]
+async fn test_batch_insert_matches_sequential_insert() {
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.milvus.batch_insert_chunk_size = 3;
+    let mut features: Vec<ItemFeature> = (0..10u128)
+        .map(|i| {
+            let embedding = vec![1.0, i as f32, (i % 3) as f32, 0.5];
+            ItemFeature::new(Uuid::from_u128(i + 1), embedding, format!("category_{}", i % 2))
+        })
+        .collect();
+    features[4].embedding_versions.insert("v2".to_string(), unit_vector(dimension, 3));
+    features[7].expires_at = Some(Utc::now() + chrono::Duration::hours(1));
+    let profiles: Vec<UserProfile> = (0..5u128)
+        .map(|i| UserProfile { embedding: unit_vector(dimension, i as usize % dimension), ..UserProfile::new(Uuid::from_u128(100 + i), dimension) })
+        .collect();
+
+    let sequential = VectorDbService::new(&config).await.unwrap();
+    for feature in &features {
+        sequential.insert_item_feature(feature).await.unwrap();
+    }
+    for profile in &profiles {
+        sequential.insert_user_profile(profile).await.unwrap();
+    }
+    let batched = VectorDbService::new(&config).await.unwrap();
+    batched.batch_insert_features(&features).await.unwrap();
+    batched.batch_insert_profiles(&profiles).await.unwrap();
+
+    let (sequential_stats, batched_stats) = (sequential.index_stats().await, batched.index_stats().await);
+    assert_eq!(sequential_stats.items.vector_count, batched_stats.items.vector_count);
+    assert_eq!(sequential_stats.users.vector_count, batched_stats.users.vector_count);
+    assert_eq!(sequential_stats.item_versions["v2"].vector_count, batched_stats.item_versions["v2"].vector_count);
+    assert_eq!(sequential_stats.expiring_items, batched_stats.expiring_items);
+    for feature in &features {
+        let stored = batched.get_item_feature(feature.item_id).await.unwrap().unwrap();
+        assert_eq!(stored.embedding, feature.embedding);
+    }
+    let query = vec![1.0, 2.0, 1.0, 0.5];
+    assert_eq!(
+        sequential.search_similar_items(&query, 10).await.unwrap(),
+        batched.search_similar_items(&query, 10).await.unwrap()
+    );
+    assert_eq!(
+        sequential.search_similar_items_in_version(&unit_vector(dimension, 3), 5, "v2").await.unwrap(),
+        batched.search_similar_items_in_version(&unit_vector(dimension, 3), 5, "v2").await.unwrap()
+    );
+
+    // A bad record fails the batch with its id; the records before it are kept
+    let bad_id = Uuid::from_u128(50);
+    let batch = vec![
+        ItemFeature::new(Uuid::from_u128(49), unit_vector(dimension, 0), "books".to_string()),
+        ItemFeature::new(bad_id, vec![1.0; dimension + 1], "books".to_string()),
+        ItemFeature::new(Uuid::from_u128(51), unit_vector(dimension, 0), "books".to_string()),
+    ];
+    let error = batched.batch_insert_features(&batch).await.unwrap_err();
+    assert!(error.to_string().contains(&bad_id.to_string()));
+    assert!(batched.get_item_feature(Uuid::from_u128(49)).await.unwrap().is_some());
+    assert!(batched.get_item_feature(bad_id).await.unwrap().is_none());
+    assert!(batched.get_item_feature(Uuid::from_u128(51)).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_stale_model_falls_back_to_similarity() {
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = -1.0;
+    config.recommendation.max_model_age_seconds = Some(3600);
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let user_id = Uuid::from_u128(1);
+    // The dot-product prediction (2.0) differs from the cosine similarity (1.0)
+    let profile = UserProfile { embedding: vec![2.0, 0.0, 0.0, 0.0], ..UserProfile::new(user_id, dimension) };
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    service.add_item_feature(ItemFeature::new(Uuid::from_u128(2), unit_vector(dimension, 0), "books".to_string())).await.unwrap();
+
+    let parameters = |updated_at| ModelParameters {
+        version: "v1".to_string(),
+        user_embeddings: HashMap::new(),
+        item_embeddings: HashMap::new(),
+        bias_weights: vec![0.0; dimension],
+        updated_at,
+    };
+    let request = RecommendationRequest::new(user_id, 1);
+
+    service.load_model_parameters(&parameters(Utc::now() - chrono::Duration::days(2))).await.unwrap();
+    let stale = service.get_recommendations(&request).await.unwrap();
+    assert!((stale.recommendations[0].score - 1.0).abs() < 1e-5);
+    // Every stale request is counted, though only the first is logged
+    service.get_recommendations(&request).await.unwrap();
+    assert_eq!(service.get_recommendation_stats().await.get("stale_model_fallbacks"), Some(&2));
+
+    service.load_model_parameters(&parameters(Utc::now())).await.unwrap();
+    let fresh = service.get_recommendations(&request).await.unwrap();
+    assert!((fresh.recommendations[0].score - 1.5).abs() < 1e-5);
+    assert_eq!(service.get_recommendation_stats().await.get("stale_model_fallbacks"), Some(&2));
+}
+
+#[tokio::test]
+async fn test_rebuild_user_profile_from_history() {
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.profile_rebuild_decay_rate = 0.1;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let user_id = Uuid::from_u128(1);
+    let history = [
+        (Uuid::from_u128(10), ActionType::Purchase, 1.0, 1),
+        (Uuid::from_u128(11), ActionType::Click, 0.3, 10),
+        (Uuid::from_u128(12), ActionType::View, 0.1, 0),
+    ];
+    for (axis, (item_id, action_type, _, hours_ago)) in history.iter().enumerate() {
+        service.add_item_feature(ItemFeature::new(*item_id, unit_vector(dimension, axis), "books".to_string())).await.unwrap();
+        let action = UserAction {
+            timestamp: Utc::now() - chrono::Duration::hours(*hours_ago),
+            ..UserAction::new(user_id, *item_id, action_type.clone())
+        };
+        service.process_user_action(&action).await.unwrap();
+    }
+    assert_eq!(vector_db.get_user_actions(user_id).await.unwrap().len(), 3);
+
+    // Corrupt the stored profile
+    vector_db.update_user_embedding(user_id, vec![100.0; dimension]).await.unwrap();
+
+    let rebuilt = service.rebuild_user_profile(user_id).await.unwrap();
+    let weights: Vec<f32> = history
+        .iter()
+        .map(|(_, _, weight, hours_ago)| weight * (-0.1 * *hours_ago as f32).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+    let mut expected = vec![0.0; dimension];
+    for (axis, weight) in weights.iter().enumerate() {
+        expected[axis] = weight / total;
+    }
+    for (actual, expected) in rebuilt.embedding.iter().zip(expected.iter()) {
+        assert!((actual - expected).abs() < 1e-4, "{:?} vs {:?}", rebuilt.embedding, expected);
+    }
+    assert_eq!(rebuilt.interaction_count, 3);
+    let stored = vector_db.get_user_profile(user_id).await.unwrap().unwrap();
+    assert_eq!(stored.embedding, rebuilt.embedding);
+
+    assert!(service.rebuild_user_profile(Uuid::from_u128(2)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_metadata_store_trims_action_history() {
+    use milvuso::config::MetadataBackend;
+
+    let dir = std::env::temp_dir().join(format!("milvuso-metadata-{}", Uuid::new_v4()));
+    let mut config = small_config(4);
+    config.metadata_store.backend = MetadataBackend::Sled;
+    config.metadata_store.path = dir.to_string_lossy().into_owned();
+    config.recommendation.max_action_history = 3;
+    let vector_db = VectorDbService::new(&config).await.unwrap();
+
+    let user_id = Uuid::from_u128(1);
+    let item_ids: Vec<Uuid> = (10..15u128).map(Uuid::from_u128).collect();
+    for (age, item_id) in item_ids.iter().rev().enumerate() {
+        let action = UserAction {
+            timestamp: Utc::now() - chrono::Duration::minutes(age as i64),
+            ..UserAction::new(user_id, *item_id, ActionType::Click)
+        };
+        vector_db.record_user_action(&action).await.unwrap();
+    }
+    vector_db.record_user_action(&UserAction::new(Uuid::from_u128(2), item_ids[0], ActionType::Click)).await.unwrap();
+
+    let kept: Vec<Uuid> = vector_db.get_user_actions(user_id).await.unwrap().iter().map(|a| a.item_id).collect();
+    assert_eq!(kept, item_ids[2..].to_vec());
+    assert_eq!(vector_db.get_user_actions(Uuid::from_u128(2)).await.unwrap().len(), 1);
+
+    drop(vector_db);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_cached_norms_match_recomputed_norms() {
+    use milvuso::algorithms::retriever::{InMemoryRetriever, VectorRetriever};
+
+    let dimension = 16;
+    let mut plain = InMemoryRetriever::new(dimension);
+    let mut cached = InMemoryRetriever::new(dimension).with_cached_norms(true);
+    for i in 0..200u128 {
+        let vector: Vec<f32> = (0..dimension).map(|j| (((i as usize) * 31 + j * 7) % 23) as f32 - 11.0).collect();
+        plain.add_vector(Uuid::from_u128(i), vector.clone()).await.unwrap();
+        cached.add_vector(Uuid::from_u128(i), vector).await.unwrap();
+    }
+    // Norms must follow every kind of write
+    plain.add_vector(Uuid::from_u128(500), vec![0.0; dimension]).await.unwrap();
+    cached.add_vector(Uuid::from_u128(500), vec![0.0; dimension]).await.unwrap();
+    plain.update_vector(Uuid::from_u128(3), unit_vector(dimension, 2)).await.unwrap();
+    cached.update_vector(Uuid::from_u128(3), unit_vector(dimension, 2)).await.unwrap();
+    plain.update_vector_sparse(Uuid::from_u128(4), &[(0, 40.0), (5, -3.0)]).await.unwrap();
+    cached.update_vector_sparse(Uuid::from_u128(4), &[(0, 40.0), (5, -3.0)]).await.unwrap();
+    plain.remove_vector(Uuid::from_u128(5)).await.unwrap();
+    cached.remove_vector(Uuid::from_u128(5)).await.unwrap();
+
+    for query in [unit_vector(dimension, 0), unit_vector(dimension, 2), (0..dimension).map(|j| j as f32 - 8.0).collect()] {
+        let expected = plain.search_similar(&query, 20).await.unwrap();
+        let actual = cached.search_similar(&query, 20).await.unwrap();
+        assert_eq!(expected.len(), actual.len());
+        for ((expected_id, expected_score), (actual_id, actual_score)) in expected.iter().zip(actual.iter()) {
+            assert!((expected_score - actual_score).abs() < 1e-5);
+            if expected_id != actual_id {
+                // Only a near-tie may swap order
+                let swapped = expected.iter().find(|(id, _)| id == actual_id).unwrap();
+                assert!((swapped.1 - actual_score).abs() < 1e-5);
+            }
+        }
+    }
+    let zero_query = cached.search_similar(&vec![0.0; dimension], 5).await.unwrap();
+    assert!(zero_query.iter().all(|(_, score)| *score == 0.0));
+}
+
+#[tokio::test]
+async fn test_dislike_pushes_profile_away_and_excludes_item() {
+    use milvuso::utils::{cosine_similarity, validation::validate_training_example};
+
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = -1.0;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let user_id = Uuid::from_u128(1);
+    let disliked = Uuid::from_u128(2);
+    let profile = UserProfile { embedding: vec![1.0, 0.5, 0.0, 0.0], ..UserProfile::new(user_id, dimension) };
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    service.add_item_feature(ItemFeature::new(disliked, unit_vector(dimension, 0), "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(Uuid::from_u128(3), unit_vector(dimension, 1), "books".to_string())).await.unwrap();
+
+    let before = service.get_recommendations(&RecommendationRequest::new(user_id, 2)).await.unwrap();
+    assert_eq!(before.recommendations[0].item_id, disliked);
+
+    service.process_user_action(&UserAction::new(user_id, disliked, ActionType::Dislike)).await.unwrap();
+
+    let updated = vector_db.get_user_profile(user_id).await.unwrap().unwrap();
+    let item = unit_vector(dimension, 0);
+    assert!(cosine_similarity(&updated.embedding, &item) < cosine_similarity(&profile.embedding, &item));
+
+    let after = service.get_recommendations(&RecommendationRequest::new(user_id, 2)).await.unwrap();
+    assert!(after.recommendations.iter().all(|item| item.item_id != disliked));
+    assert_eq!(after.recommendations.len(), 1);
+
+    // Negative labels pass validation only within the configured range
+    let example = TrainingExample {
+        user_id,
+        item_id: disliked,
+        label: -1.0,
+        user_features: updated.embedding.clone(),
+        item_features: item.clone(),
+        context_features: vec![0.0; 10],
+        timestamp: Utc::now(),
+    };
+    assert!(validate_training_example(&example, -1.0).is_ok());
+    assert!(validate_training_example(&example, 0.0).is_err());
+}
+
+#[tokio::test]
+async fn test_deny_list_from_cache_key() {
+    use milvuso::services::cache::CacheBackend;
+
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = -1.0;
+    config.recommendation.deny_list_key = Some("merchandising:deny".to_string());
+    config.recommendation.allow_list_key = Some("merchandising:allow".to_string());
+    config.recommendation.item_list_cache_ttl_ms = 50;
+    let cache = Arc::new(InMemoryCache::new());
+    let (_vector_db, service) = in_memory_recommendation_service_with_cache(config, cache.clone()).await;
+
+    let item_ids: Vec<Uuid> = (1..=3).map(Uuid::from_u128).collect();
+    for (axis, item_id) in item_ids.iter().enumerate() {
+        service.add_item_feature(ItemFeature::new(*item_id, unit_vector(dimension, axis), "books".to_string())).await.unwrap();
+    }
+    let denied = item_ids[0];
+    cache.set_ex("merchandising:deny", serde_json::to_string(&[denied]).unwrap(), 3600).await.unwrap();
+    // Deny wins over allow
+    cache.set_ex("merchandising:allow", serde_json::to_string(&[denied, item_ids[1]]).unwrap(), 3600).await.unwrap();
+
+    let served = |response: &RecommendationResponse| -> Vec<Uuid> {
+        response.recommendations.iter().map(|item| item.item_id).collect()
+    };
+    let request = RecommendationRequest::new(Uuid::from_u128(100), 3);
+    let response = service.get_recommendations(&request).await.unwrap();
+    assert_eq!(served(&response), vec![item_ids[1]]);
+
+    // Lifting the lists takes effect once the local copy expires
+    cache.set_ex("merchandising:deny", "[]".to_string(), 3600).await.unwrap();
+    cache.delete("merchandising:allow").await.unwrap();
+    let response = service.get_recommendations(&request).await.unwrap();
+    assert_eq!(served(&response), vec![item_ids[1]]);
+
+    tokio::time::sleep(std::time::Duration::from_millis(80)).await;
+    let response = service.get_recommendations(&request).await.unwrap();
+    let mut now_served = served(&response);
+    now_served.sort();
+    assert_eq!(now_served, item_ids);
+}
+
+#[tokio::test]
+async fn test_personalized_trending_favours_surging_items() {
+    use milvuso::services::serving::ServingService;
+
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.trending_half_life_seconds = 3600;
+    let (vector_db, service) = in_memory_recommendation_service(config.clone()).await;
+    let service = Arc::new(service);
+
+    let user_id = Uuid::from_u128(1);
+    let profile = UserProfile { embedding: unit_vector(dimension, 0), ..UserProfile::new(user_id, dimension) };
+    vector_db.insert_user_profile(&profile).await.unwrap();
+
+    // The stale item sorts first on a tie, so only velocity can put the surging one ahead
+    let stale = Uuid::from_u128(10);
+    let surging = Uuid::from_u128(11);
+    let irrelevant = Uuid::from_u128(12);
+    for (item_id, embedding) in [(stale, unit_vector(dimension, 0)), (surging, unit_vector(dimension, 0)), (irrelevant, unit_vector(dimension, 1))] {
+        vector_db.insert_item_feature(&ItemFeature::new(item_id, embedding, "books".to_string())).await.unwrap();
+    }
+
+    let two_days_ago = Utc::now() - chrono::Duration::days(2);
+    for i in 0..50u128 {
+        let action = UserAction { timestamp: two_days_ago, ..UserAction::new(Uuid::from_u128(1000 + i), stale, ActionType::Click) };
+        service.process_user_action(&action).await.unwrap();
+    }
+    for i in 0..3u128 {
+        service.process_user_action(&UserAction::new(Uuid::from_u128(2000 + i), surging, ActionType::Click)).await.unwrap();
+    }
+    for i in 0..20u128 {
+        service.process_user_action(&UserAction::new(Uuid::from_u128(3000 + i), irrelevant, ActionType::Click)).await.unwrap();
+    }
+    assert!(vector_db.get_item_velocity(surging).await > 2.9);
+    assert!(vector_db.get_item_velocity(stale).await < 1e-6);
+
+    let serving = ServingService::new(vector_db.clone(), service, Arc::new(config)).await.unwrap();
+    let trending = serving.get_personalized_trending(user_id, 3).await.unwrap();
+    let ids: Vec<Uuid> = trending.iter().map(|item| item.item_id).collect();
+    assert_eq!(ids[0], surging);
+    assert!(trending[0].score > trending[1].score);
+}
+
+#[tokio::test]
+async fn test_projection_preserves_relative_distances() {
+    use milvuso::config::ProjectionConfig;
+    use rand::{Rng, SeedableRng};
+
+    let (input_dim, dimension) = (512, 64);
+    let mut config = small_config(dimension);
+    config.recommendation.projection = Some(ProjectionConfig { input_dim, seed: 7, weights: None });
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+    let originals: Vec<Vec<f32>> = (0..12)
+        .map(|_| (0..input_dim).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect();
+    for (i, embedding) in originals.iter().enumerate() {
+        let feature = ItemFeature::new(Uuid::from_u128(i as u128 + 1), embedding.clone(), "books".to_string());
+        service.add_item_feature(feature).await.unwrap();
+    }
+
+    let mut projected = Vec::new();
+    for i in 0..originals.len() {
+        let feature = vector_db.get_item_feature(Uuid::from_u128(i as u128 + 1)).await.unwrap().unwrap();
+        assert_eq!(feature.embedding.len(), dimension);
+        projected.push(feature.embedding);
+    }
+
+    let distance = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f32>().sqrt();
+    for i in 0..originals.len() {
+        for j in (i + 1)..originals.len() {
+            let ratio = distance(&projected[i], &projected[j]) / distance(&originals[i], &originals[j]);
+            assert!((0.6..1.4).contains(&ratio), "distance ratio {} for pair ({}, {})", ratio, i, j);
+        }
+    }
+
+    // Queries in the input dimension go through the same projection
+    let results = vector_db.search_similar_items(&originals[3], 1).await.unwrap();
+    assert_eq!(results[0].0, Uuid::from_u128(4));
+    assert!(results[0].1 > 0.999);
+}
+
+#[tokio::test]
+async fn test_actions_beyond_rate_limit_are_dropped() {
+    use milvuso::config::{ActionRateLimitConfig, RateLimitPolicy};
+
+    let mut config = small_config(4);
+    config.recommendation.action_rate_limit = Some(ActionRateLimitConfig {
+        actions_per_second: 0.001,
+        burst: 3,
+        policy: RateLimitPolicy::Drop,
+        down_weight: 0.1,
+        abuse_threshold: 5,
+    });
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let bot = Uuid::new_v4();
+    for _ in 0..10 {
+        service.process_user_action(&UserAction::new(bot, Uuid::new_v4(), ActionType::Click)).await.unwrap();
+    }
+    let person = Uuid::new_v4();
+    service.process_user_action(&UserAction::new(person, Uuid::new_v4(), ActionType::Click)).await.unwrap();
+
+    assert_eq!(vector_db.get_user_actions(bot).await.unwrap().len(), 3);
+    assert_eq!(vector_db.get_user_actions(person).await.unwrap().len(), 1);
+
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("rate_limited_actions"), Some(&7));
+    assert_eq!(stats.get("flagged_abusive_users"), Some(&1));
+    assert!(service.is_user_flagged(bot));
+    assert!(!service.is_user_flagged(person));
+}
+
+#[tokio::test]
+async fn test_read_only_replica_serves_snapshot_and_rejects_writes() {
+    use milvuso::algorithms::retriever::{InMemoryRetriever, ReadOnlyRetriever, VectorRetriever};
+    use milvuso::services::metadata::{MetadataStore, SledMetadataStore};
+
+    let dimension = 4;
+    let dir = std::env::temp_dir().join(format!("milvuso-replica-{}", Uuid::new_v4()));
+    let store: Arc<dyn MetadataStore> = Arc::new(SledMetadataStore::open(&dir.to_string_lossy()).unwrap());
+
+    let writer_config = small_config(dimension);
+    let mut replica_config = small_config(dimension);
+    replica_config.milvus.read_only = true;
+
+    let writer = VectorDbService::with_metadata_store(&writer_config, Some(store.clone())).await.unwrap();
+    let first = Uuid::from_u128(1);
+    writer
+        .insert_item_feature(&ItemFeature::new(first, unit_vector(dimension, 0), "books".to_string()))
+        .await
+        .unwrap();
+
+    let replica = VectorDbService::with_metadata_store(&replica_config, Some(store.clone())).await.unwrap();
+    assert!(replica.is_read_only());
+    let similar = replica.search_similar_items(&unit_vector(dimension, 0), 5).await.unwrap();
+    assert_eq!(similar[0].0, first);
+
+    let rejected = replica
+        .insert_item_feature(&ItemFeature::new(Uuid::new_v4(), unit_vector(dimension, 1), "books".to_string()))
+        .await
+        .unwrap_err();
+    assert!(rejected.to_string().contains("read-only"));
+    assert!(replica.insert_user_profile(&UserProfile::new(Uuid::new_v4(), dimension)).await.is_err());
+    assert!(replica.update_item_embedding(first, unit_vector(dimension, 1)).await.is_err());
+    assert_eq!(replica.get_item_feature(first).await.unwrap().unwrap().embedding, unit_vector(dimension, 0));
+
+    // Writes made by the writer show up only once the replica reloads its snapshot
+    let second = Uuid::from_u128(2);
+    writer
+        .insert_item_feature(&ItemFeature::new(second, unit_vector(dimension, 1), "books".to_string()))
+        .await
+        .unwrap();
+    assert!(replica.get_item_feature(second).await.unwrap().is_none());
+    replica.reload_snapshot().await.unwrap();
+    let similar = replica.search_similar_items(&unit_vector(dimension, 1), 1).await.unwrap();
+    assert_eq!(similar[0].0, second);
+
+    let mut snapshot = InMemoryRetriever::new(dimension);
+    snapshot.add_vector(first, unit_vector(dimension, 0)).await.unwrap();
+    let mut retriever = ReadOnlyRetriever::from_snapshot(Box::new(snapshot));
+    assert_eq!(retriever.search_similar(&unit_vector(dimension, 0), 1).await.unwrap()[0].0, first);
+    assert!(retriever.add_vector(second, unit_vector(dimension, 1)).await.is_err());
+    assert!(retriever.remove_vector(first).await.is_err());
+    assert_eq!(retriever.stats().vector_count, 1);
+
+    drop((writer, replica, store));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_relax_filters_fills_short_response() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    config.recommendation.min_training_examples = u64::MAX;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let item = |i: u128, category: &str, tags: &[&str]| {
+        ItemFeature::new(Uuid::from_u128(i), vec![1.0, 0.1 * i as f32, 0.0, 0.0], category.to_string())
+            .with_tags(tags.iter().map(|tag| tag.to_string()).collect())
+    };
+    for feature in [
+        item(1, "books", &["sci-fi"]),
+        item(2, "books", &["romance"]),
+        item(3, "books", &[]),
+        item(4, "music", &["sci-fi"]),
+        item(5, "music", &[]),
+    ] {
+        service.add_item_feature(feature).await.unwrap();
+    }
+
+    let user_id = Uuid::new_v4();
+    let mut profile = UserProfile::new(user_id, 4);
+    profile.embedding = unit_vector(4, 0);
+    vector_db.insert_user_profile(&profile).await.unwrap();
+
+    let strict = RecommendationRequest::new(user_id, 4)
+        .with_filter_categories(vec!["books".to_string()])
+        .with_filter_tags(vec!["sci-fi".to_string()]);
+    let response = service.get_recommendations(&strict).await.unwrap();
+    let ids: Vec<Uuid> = response.recommendations.iter().map(|r| r.item_id).collect();
+    assert_eq!(ids, vec![Uuid::from_u128(1)]);
+
+    let response = service.get_recommendations(&strict.clone().with_relaxed_filters()).await.unwrap();
+    let served: Vec<(Uuid, Option<RelaxedFilter>)> = response.recommendations
+        .iter()
+        .map(|r| (r.item_id, r.relaxed_filter))
+        .collect();
+    assert_eq!(served, vec![
+        (Uuid::from_u128(1), None),
+        (Uuid::from_u128(2), Some(RelaxedFilter::Tags)),
+        (Uuid::from_u128(3), Some(RelaxedFilter::Tags)),
+        (Uuid::from_u128(4), Some(RelaxedFilter::Category)),
+    ]);
+
+    // Items the user excluded are never brought back by relaxation
+    let excluded = strict.with_exclude_items(vec![Uuid::from_u128(2)]).with_relaxed_filters();
+    let response = service.get_recommendations(&excluded).await.unwrap();
+    assert!(response.recommendations.iter().all(|r| r.item_id != Uuid::from_u128(2)));
+    assert_eq!(response.recommendations.len(), 4);
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("relaxed_filter_responses"), Some(&2));
+}
+
+#[tokio::test]
+async fn test_write_requests_leave_audit_records() {
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use axum::routing::{get, post};
+    use milvuso::services::audit::{audit_middleware, AuditLog, InMemoryAuditSink};
+    use tower::ServiceExt;
+
+    let sink = Arc::new(InMemoryAuditSink::new());
+    let audit_log = AuditLog::new(sink.clone(), Some("secret".to_string()));
+    let app = axum::Router::new()
+        .route("/items", post(|| async { StatusCode::OK }))
+        .route("/items/:item_id", get(|| async { StatusCode::OK }).delete(|| async { StatusCode::OK }))
+        .layer(axum::middleware::from_fn_with_state(audit_log, audit_middleware));
+
+    let item_id = Uuid::new_v4();
+    let feature = ItemFeature::new(item_id, vec![1.0, 0.0], "books".to_string());
+    let insert = Request::builder()
+        .method(Method::POST)
+        .uri("/items")
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(&feature).unwrap()))
+        .unwrap();
+    assert_eq!(app.clone().oneshot(insert).await.unwrap().status(), StatusCode::OK);
+
+    // Reads are not audited
+    let read = Request::builder().uri(format!("/items/{}", item_id)).body(Body::empty()).unwrap();
+    assert_eq!(app.clone().oneshot(read).await.unwrap().status(), StatusCode::OK);
+
+    let delete = Request::builder()
+        .method(Method::DELETE)
+        .uri(format!("/items/{}", item_id))
+        .header("x-admin-token", "secret")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(app.oneshot(delete).await.unwrap().status(), StatusCode::OK);
+
+    let records = sink.records();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].operation, "POST /items");
+    assert_eq!(records[0].principal, "anonymous");
+    assert_eq!(records[0].target_ids, vec![item_id]);
+    assert_eq!(records[0].status, 200);
+    assert_eq!(records[1].operation, "DELETE /items/:item_id");
+    assert_eq!(records[1].principal, "admin");
+    assert_eq!(records[1].target_ids, vec![item_id]);
+    assert!(records[0].timestamp <= records[1].timestamp);
+}
+
+#[tokio::test]
+async fn test_upsert_replaces_item_and_keeps_created_at() {
+    let (vector_db, _) = in_memory_recommendation_service(small_config(4)).await;
+    let item_id = Uuid::new_v4();
+    let mut original = ItemFeature::new(item_id, unit_vector(4, 0), "books".to_string());
+    original.created_at = Utc::now() - chrono::Duration::days(3);
+    vector_db.insert_item_feature_with_mode(&original, InsertMode::Upsert).await.unwrap();
+    let missing = ItemFeature::new(Uuid::new_v4(), unit_vector(4, 1), "books".to_string());
+    vector_db.insert_item_feature_with_mode(&missing, InsertMode::Upsert).await.unwrap();
+    assert!(vector_db.get_item_feature(missing.item_id).await.unwrap().is_some());
+
+    let replacement = ItemFeature::new(item_id, unit_vector(4, 2), "music".to_string());
+    vector_db.insert_item_feature_with_mode(&replacement, InsertMode::Upsert).await.unwrap();
+    let stored = vector_db.get_item_feature(item_id).await.unwrap().unwrap();
+    assert_eq!(stored.category, "music");
+    assert_eq!(stored.created_at, original.created_at);
+    assert!(stored.last_updated > original.created_at);
+}
+
+#[tokio::test]
+async fn test_insert_only_refuses_existing_items() {
+    use milvuso::services::vector_db::InsertConflict;
+
+    let (vector_db, service) = in_memory_recommendation_service(small_config(4)).await;
+    let item_id = Uuid::new_v4();
+    let original = ItemFeature::new(item_id, unit_vector(4, 0), "books".to_string());
+    service.add_item_feature_with_mode(original, InsertMode::InsertOnly).await.unwrap();
+
+    let reused_id = ItemFeature::new(item_id, unit_vector(4, 1), "music".to_string());
+    let error = service.add_item_feature_with_mode(reused_id, InsertMode::InsertOnly).await.unwrap_err();
+    assert!(matches!(error.downcast_ref::<InsertConflict>(), Some(InsertConflict::Exists(id)) if *id == item_id));
+    assert_eq!(vector_db.get_item_feature(item_id).await.unwrap().unwrap().category, "books");
+}
+
+#[tokio::test]
+async fn test_update_only_refuses_missing_items() {
+    use milvuso::services::vector_db::InsertConflict;
+
+    let (vector_db, service) = in_memory_recommendation_service(small_config(4)).await;
+    let item_id = Uuid::new_v4();
+    let missing = ItemFeature::new(item_id, unit_vector(4, 0), "books".to_string());
+    let error = service.add_item_feature_with_mode(missing.clone(), InsertMode::UpdateOnly).await.unwrap_err();
+    assert!(matches!(error.downcast_ref::<InsertConflict>(), Some(InsertConflict::Missing(id)) if *id == item_id));
+    assert!(vector_db.get_item_feature(item_id).await.unwrap().is_none());
+
+    service.add_item_feature(missing).await.unwrap();
+    let update = ItemFeature::new(item_id, unit_vector(4, 1), "music".to_string());
+    service.add_item_feature_with_mode(update, InsertMode::UpdateOnly).await.unwrap();
+    assert_eq!(vector_db.get_item_feature(item_id).await.unwrap().unwrap().category, "music");
+}
+
+#[tokio::test]
+async fn test_delete_item_stops_serving_it() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let item_id = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(item_id, unit_vector(4, 0), "books".to_string())).await.unwrap();
+    assert!(service.delete_item_feature(item_id).await.unwrap());
+    assert!(!service.delete_item_feature(item_id).await.unwrap());
+
+    assert!(vector_db.get_item_feature(item_id).await.unwrap().is_none());
+    assert!(vector_db.search_similar_items(&unit_vector(4, 0), 5).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_deleted_item_stays_gone_after_cache_miss() {
+    use milvuso::services::cache::CacheBackend;
+
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = -1.0;
+    config.recommendation.cold_start_threshold = 0;
+    config.redis.response_cache_ttl_seconds = 60;
+    let cache = Arc::new(InMemoryCache::new());
+    let (vector_db, service) = in_memory_recommendation_service_with_cache(config, cache.clone()).await;
+
+    let user_id = Uuid::new_v4();
+    let deleted_id = Uuid::new_v4();
+    let clicked_id = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(deleted_id, unit_vector(dimension, 0), "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(Uuid::new_v4(), unit_vector(dimension, 1), "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(clicked_id, vec![1.0, 1.0, 0.0, 0.0], "books".to_string())).await.unwrap();
+    service.process_user_action(&UserAction::new(user_id, clicked_id, ActionType::Click)).await.unwrap();
+
+    let request = RecommendationRequest::new(user_id, 3);
+    let session_request = RecommendationRequest::new(user_id, 3).with_session("session-1".to_string());
+    let serves_deleted = |response: &RecommendationResponse| response.recommendations.iter().any(|r| r.item_id == deleted_id);
+    assert!(serves_deleted(&service.get_recommendations(&request).await.unwrap()));
+    assert!(serves_deleted(&service.get_recommendations(&session_request).await.unwrap()));
+
+    assert!(service.delete_item_feature(deleted_id).await.unwrap());
+    assert!(cache.get_bytes(&format!("item_feature:{}", deleted_id)).await.unwrap().is_none());
+    assert!(!serves_deleted(&service.get_recommendations(&request).await.unwrap()));
+    assert!(!serves_deleted(&service.get_recommendations(&session_request).await.unwrap()));
+
+    // Missing the in-process copy must not bring the item back from Redis
+    let before = vector_db.get_user_profile(user_id).await.unwrap().unwrap().embedding;
+    service.process_user_action(&UserAction::new(user_id, deleted_id, ActionType::Click)).await.unwrap();
+    let after = vector_db.get_user_profile(user_id).await.unwrap().unwrap().embedding;
+    assert_eq!(before, after);
+}
+
+#[tokio::test]
+async fn test_normalized_dot_product_matches_cosine() {
+    use milvuso::algorithms::retriever::{InMemoryRetriever, VectorRetriever};
+    use rand::{Rng, SeedableRng};
+
+    let dimension = 32;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+    let mut full = InMemoryRetriever::new(dimension);
+    let mut fast = InMemoryRetriever::new(dimension).with_normalized_vectors(true);
+    for _ in 0..200 {
+        let id = Uuid::new_v4();
+        let vector: Vec<f32> = (0..dimension).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let vector = utils::normalize_vector_copy(&vector);
+        full.add_vector(id, vector.clone()).await.unwrap();
+        fast.add_vector(id, vector).await.unwrap();
+    }
+
+    // The query is normalized by the fast path itself, so any scale gives the same scores
+    let query: Vec<f32> = (0..dimension).map(|_| rng.gen_range(-5.0..5.0)).collect();
+    let expected = full.search_similar(&query, 20).await.unwrap();
+    let actual = fast.search_similar(&query, 20).await.unwrap();
+    assert_eq!(expected.len(), actual.len());
+    for ((expected_id, expected_score), (actual_id, actual_score)) in expected.iter().zip(&actual) {
+        assert_eq!(expected_id, actual_id);
+        assert!((expected_score - actual_score).abs() < 1e-5);
+    }
+    assert!(fast.search_similar(&vec![0.0; dimension], 5).await.unwrap().iter().all(|(_, score)| *score == 0.0));
+
+    // With auto-normalization the service stores unit-length item embeddings
+    let mut config = small_config(4);
+    config.recommendation.auto_normalize_embeddings = true;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+    let item_id = Uuid::new_v4();
+    service.add_item_feature(ItemFeature::new(item_id, vec![3.0, 4.0, 0.0, 0.0], "books".to_string())).await.unwrap();
+    let stored = vector_db.get_item_feature(item_id).await.unwrap().unwrap();
+    assert_eq!(stored.embedding, vec![0.6, 0.8, 0.0, 0.0]);
+    let results = vector_db.search_similar_items(&[6.0, 8.0, 0.0, 0.0], 1).await.unwrap();
+    assert!((results[0].1 - 1.0).abs() < 1e-6);
+}
+
+#[tokio::test]
+async fn test_max_item_age_limits_recommendations_to_recent_items() {
+    let mut config = small_config(4);
+    config.recommendation.similarity_threshold = 0.0;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let mut ages = HashMap::new();
+    for (i, age_hours) in [1i64, 5, 30, 200].into_iter().enumerate() {
+        let item_id = Uuid::from_u128(i as u128 + 1);
+        let mut feature = ItemFeature::new(item_id, vec![1.0, 0.1 * i as f32, 0.0, 0.0], "news".to_string());
+        feature.created_at = Utc::now() - chrono::Duration::hours(age_hours);
+        service.add_item_feature(feature).await.unwrap();
+        ages.insert(item_id, age_hours);
+    }
+
+    let user_id = Uuid::new_v4();
+    let mut profile = UserProfile::new(user_id, 4);
+    profile.embedding = unit_vector(4, 0);
+    vector_db.insert_user_profile(&profile).await.unwrap();
+
+    let unbounded = service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+    assert_eq!(unbounded.recommendations.len(), 4);
+
+    let request = RecommendationRequest::new(user_id, 10).with_max_item_age(std::time::Duration::from_secs(24 * 3600));
+    let recent = service.get_recommendations(&request).await.unwrap();
+    let mut served: Vec<i64> = recent.recommendations.iter().map(|r| ages[&r.item_id]).collect();
+    served.sort();
+    assert_eq!(served, vec![1, 5]);
+
+    // The age bound is a hard filter, unaffected by relaxing the other filters
+    let recent = service.get_recommendations(&request.with_relaxed_filters()).await.unwrap();
+    assert_eq!(recent.recommendations.len(), 2);
+}
+
+#[tokio::test]
+async fn test_corrupt_cache_entries_are_repaired() {
+    use milvuso::services::cache::CacheBackend;
+
+    let cache = Arc::new(InMemoryCache::new());
+    let (vector_db, service) = in_memory_recommendation_service_with_cache(small_config(4), cache.clone()).await;
+
+    let user_id = Uuid::new_v4();
+    let mut profile = UserProfile::new(user_id, 4);
+    profile.embedding = unit_vector(4, 0);
+    vector_db.insert_user_profile(&profile).await.unwrap();
+
+    let profile_key = format!("user_profile:{}", user_id);
+    cache.set_ex_bytes(&profile_key, b"{not a profile".to_vec(), 60).await.unwrap();
+    service.get_recommendations(&RecommendationRequest::new(user_id, 5)).await.unwrap();
+
+    // The bad entry was replaced with a fresh copy of the profile
+    let repaired = cache.get(&profile_key).await.unwrap().unwrap();
+    let repaired: UserProfile = serde_json::from_str(&repaired).unwrap();
+    assert_eq!(repaired.embedding, unit_vector(4, 0));
+
+    // Entries with nothing to rewrite them are deleted
+    let recommendation_id = Uuid::new_v4();
+    let served_key = format!("served_recommendation:{}", recommendation_id);
+    cache.set_ex(&served_key, "garbage".to_string(), 60).await.unwrap();
+    let action = UserAction::new(user_id, Uuid::new_v4(), ActionType::Click).with_recommendation_id(recommendation_id);
+    service.process_user_action(&action).await.unwrap();
+    assert!(cache.get(&served_key).await.unwrap().is_none());
+
+    let stats = service.get_recommendation_stats().await;
+    assert_eq!(stats.get("cache_corruptions"), Some(&2));
+}
+
+#[tokio::test]
+async fn test_manual_scores_override_ranking() {
+    use milvuso::services::cache::CacheBackend;
+
+    let (best, close, curated) = (Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3));
+    let mut config = small_config(4);
+    config.recommendation.min_training_examples = u64::MAX;
+    config.recommendation.manual_scores.insert(curated, 0.95);
+    config.recommendation.manual_scores_key = Some("merchandising:manual_scores".to_string());
+    config.recommendation.item_list_cache_ttl_ms = 0;
+    let cache = Arc::new(InMemoryCache::new());
+    let (vector_db, service) = in_memory_recommendation_service_with_cache(config, cache.clone()).await;
+
+    service.add_item_feature(ItemFeature::new(best, vec![1.0, 0.0, 0.0, 0.0], "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(close, vec![1.0, 0.5, 0.0, 0.0], "books".to_string())).await.unwrap();
+    // Far below the similarity threshold, so only the override can place it
+    service.add_item_feature(ItemFeature::new(curated, vec![0.0, 1.0, 0.0, 0.0], "books".to_string())).await.unwrap();
+
+    let user_id = Uuid::new_v4();
+    let mut profile = UserProfile::new(user_id, 4);
+    profile.embedding = unit_vector(4, 0);
+    vector_db.insert_user_profile(&profile).await.unwrap();
+
+    let response = service.get_recommendations(&RecommendationRequest::new(user_id, 3)).await.unwrap();
+    let ids: Vec<Uuid> = response.recommendations.iter().map(|r| r.item_id).collect();
+    assert_eq!(ids, vec![best, curated, close]);
+    assert_eq!(response.recommendations[1].score, 0.95);
+    assert!(response.recommendations[1].reason.as_deref().unwrap().contains("curated"));
+    assert!(!response.recommendations[0].reason.as_deref().unwrap().contains("curated"));
+
+    // Scores under the Redis key are overlaid on the configured ones
+    let user_id = Uuid::new_v4();
+    let mut profile = UserProfile::new(user_id, 4);
+    profile.embedding = unit_vector(4, 0);
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    let overrides = serde_json::to_string(&HashMap::from([(close, 2.0f32)])).unwrap();
+    cache.set_ex("merchandising:manual_scores", overrides, 60).await.unwrap();
+    let response = service.get_recommendations(&RecommendationRequest::new(user_id, 3)).await.unwrap();
+    let ids: Vec<Uuid> = response.recommendations.iter().map(|r| r.item_id).collect();
+    assert_eq!(ids, vec![close, best, curated]);
+    assert_eq!(response.recommendations[0].score, 2.0);
+}
+
+#[tokio::test]
+async fn test_knn_graph_export_matches_direct_search() {
+    use futures::StreamExt;
+    use rand::{Rng, SeedableRng};
+
+    let dimension = 8;
+    let vector_db = Arc::new(VectorDbService::new(&small_config(dimension)).await.unwrap());
+    let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+    let mut embeddings = HashMap::new();
+    for i in 0..30u128 {
+        let item_id = Uuid::from_u128(i + 1);
+        let embedding: Vec<f32> = (0..dimension).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        vector_db.insert_item_feature(&ItemFeature::new(item_id, embedding.clone(), "books".to_string())).await.unwrap();
+        embeddings.insert(item_id, embedding);
+    }
+
+    let k = 5;
+    let graph = vector_db.export_knn_graph(k).await.unwrap();
+    assert_eq!(graph.len(), embeddings.len());
+    for (item_id, embedding) in &embeddings {
+        let mut expected = vector_db.search_similar_items(embedding, k + 1).await.unwrap();
+        expected.retain(|(neighbor_id, _)| neighbor_id != item_id);
+        expected.truncate(k);
+
+        let neighbors = &graph[item_id];
+        assert_eq!(neighbors.len(), k);
+        assert!(neighbors.iter().all(|(neighbor_id, _)| neighbor_id != item_id));
+        assert_eq!(
+            neighbors.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            expected.iter().map(|(id, _)| *id).collect::<Vec<_>>()
+        );
+        for ((_, score), (_, expected_score)) in neighbors.iter().zip(&expected) {
+            assert!((score - expected_score).abs() < 1e-6);
+        }
+    }
+
+    // The chunked stream yields the same rows, at most `chunk_size` at a time
+    let chunks: Vec<_> = vector_db.clone().stream_knn_graph(k, 7).collect().await;
+    assert_eq!(chunks.len(), 5);
+    let mut streamed = HashMap::new();
+    for chunk in chunks {
+        let chunk = chunk.unwrap();
+        assert!(chunk.len() <= 7);
+        streamed.extend(chunk.into_iter().map(|row| (row.item_id, row.neighbors)));
+    }
+    assert_eq!(streamed, graph);
+}
+
+#[tokio::test]
+async fn test_concurrency_limit_sheds_excess_requests_per_endpoint() {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use milvuso::utils::concurrency::limit_concurrency;
+    use tokio::sync::{Notify, Semaphore};
+    use tower::ServiceExt;
+
+    // The expensive handler holds its slot until the test releases it
+    let entered = Arc::new(Notify::new());
+    let release = Arc::new(Semaphore::new(0));
+    let expensive = {
+        let (entered, release) = (entered.clone(), release.clone());
+        get(move || async move {
+            entered.notify_one();
+            release.acquire().await.unwrap().forget();
+            StatusCode::OK
+        })
+    };
+    let app = axum::Router::new()
+        .route("/expensive", limit_concurrency(expensive, 2))
+        .route("/cheap", get(|| async { StatusCode::OK }));
+    let request = |path: &str| Request::builder().uri(path).body(Body::empty()).unwrap();
+
+    let mut in_flight = Vec::new();
+    for _ in 0..2 {
+        in_flight.push(tokio::spawn(app.clone().oneshot(request("/expensive"))));
+        entered.notified().await;
+    }
+
+    for _ in 0..3 {
+        let shed = app.clone().oneshot(request("/expensive")).await.unwrap();
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+    assert_eq!(app.clone().oneshot(request("/cheap")).await.unwrap().status(), StatusCode::OK);
+
+    release.add_permits(2);
+    for request in in_flight {
+        assert_eq!(request.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    // Finished requests give their slots back
+    release.add_permits(1);
+    assert_eq!(app.oneshot(request("/expensive")).await.unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown_drains_in_flight_requests() {
+    use axum::routing::get;
+    use milvuso::utils::shutdown::serve_with_graceful_shutdown;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::{oneshot, Notify, Semaphore};
+
+    let entered = Arc::new(Notify::new());
+    let release = Arc::new(Semaphore::new(0));
+    let slow = {
+        let (entered, release) = (entered.clone(), release.clone());
+        get(move || async move {
+            entered.notify_one();
+            release.acquire().await.unwrap().forget();
+            "done"
+        })
+    };
+    let app = axum::Router::new().route("/slow", slow);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve_with_graceful_shutdown(
+        listener,
+        app,
+        async move {
+            let _ = shutdown_rx.await;
+        },
+        std::time::Duration::from_secs(5),
+    ));
+
+    let mut in_flight = tokio::net::TcpStream::connect(addr).await.unwrap();
+    in_flight
+        .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    entered.notified().await;
+
+    // Once draining, the listener is closed to new connections
+    shutdown_tx.send(()).unwrap();
+    let mut refused = false;
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(addr).await.is_err() {
+            refused = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(refused);
+    assert!(!server.is_finished());
+
+    release.add_permits(1);
+    let mut response = String::new();
+    in_flight.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.ends_with("done"));
+    server.await.unwrap().unwrap();
+}
+
+/// Speaks just enough RESP for `RedisCache`: GET misses, every other command replies OK.
+/// Counts the connections it accepts.
+async fn spawn_fake_redis() -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("redis://{}/", listener.local_addr().unwrap());
+    let accepted = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut reader = BufReader::new(reader);
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let arg_count: usize = line.trim_start_matches('*').trim().parse().unwrap();
+                    let mut args = Vec::new();
+                    for _ in 0..arg_count {
+                        line.clear();
+                        reader.read_line(&mut line).await.unwrap();
+                        let len: usize = line.trim_start_matches('$').trim().parse().unwrap();
+                        let mut arg = vec![0u8; len + 2];
+                        reader.read_exact(&mut arg).await.unwrap();
+                        arg.truncate(len);
+                        args.push(String::from_utf8(arg).unwrap());
+                    }
+                    let reply: &[u8] = if args[0].eq_ignore_ascii_case("GET") { b"$-1\r\n" } else { b"+OK\r\n" };
+                    if writer.write_all(reply).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    (url, accepted)
+}
+
+#[tokio::test]
+async fn test_redis_cache_reuses_pooled_connections() {
+    use milvuso::services::cache::{CacheBackend, RedisCache};
+
+    let (url, accepted) = spawn_fake_redis().await;
+    let client = Arc::new(redis::Client::open(url.as_str()).unwrap());
+    let cache = RedisCache::with_pool_size(client, 2);
+
+    // Nothing connects until the first call
+    assert_eq!(cache.connections_opened(), 0);
+
+    for i in 0..20 {
+        let key = format!("user_profile:{}", i);
+        assert_eq!(cache.get(&key).await.unwrap(), None);
+        cache.set_ex(&key, "{}".to_string(), 60).await.unwrap();
+    }
+
+    assert_eq!(cache.connections_opened(), 2);
+    assert_eq!(accepted.load(std::sync::atomic::Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_undeserializable_messages_are_dead_lettered() {
+    use milvuso::services::kafka::{InMemoryDeadLetterSink, KafkaConsumer};
+
+    let mut config = Config::default();
+    config.kafka.dead_letter_topic = Some("dead_letters".to_string());
+    let sink = Arc::new(InMemoryDeadLetterSink::new());
+    let consumer = KafkaConsumer::new(&config).unwrap().with_dead_letter_sink(sink.clone());
+
+    let decoded = consumer.decode_message::<UserAction>("user_actions", b"{not json", "user action").await;
+    assert!(decoded.is_none());
+
+    let letters = sink.letters();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].topic, "dead_letters");
+    assert_eq!(letters[0].source_topic, "user_actions");
+    assert_eq!(letters[0].payload, b"{not json".to_vec());
+    assert!(letters[0].error.contains("user action"));
+
+    // Without a dead-letter topic the message is only dropped
+    config.kafka.dead_letter_topic = None;
+    let sink = Arc::new(InMemoryDeadLetterSink::new());
+    let consumer = KafkaConsumer::new(&config).unwrap().with_dead_letter_sink(sink.clone());
+    assert!(consumer.decode_message::<UserAction>("user_actions", b"{not json", "user action").await.is_none());
+    assert!(sink.letters().is_empty());
+}
+
+#[tokio::test]
+async fn test_find_duplicate_users_groups_near_identical_embeddings() {
+    let dimension = 4;
+    let (vector_db, _service) = in_memory_recommendation_service(small_config(dimension)).await;
+
+    let embeddings = [
+        (Uuid::from_u128(1), vec![1.0, 0.0, 0.0, 0.0]),
+        (Uuid::from_u128(2), vec![0.999, 0.01, 0.0, 0.0]),
+        (Uuid::from_u128(3), vec![0.998, 0.0, 0.02, 0.0]),
+        (Uuid::from_u128(4), vec![0.0, 1.0, 0.0, 0.0]),
+        (Uuid::from_u128(5), vec![0.0, 0.01, 0.999, 0.0]),
+        (Uuid::from_u128(6), vec![0.0, 0.0, 1.0, 0.0]),
+        (Uuid::from_u128(7), vec![0.0, 0.0, 0.0, 1.0]),
+    ];
+    for (user_id, embedding) in &embeddings {
+        let mut profile = UserProfile::new(*user_id, dimension);
+        profile.embedding = utils::normalize_vector_copy(embedding);
+        vector_db.insert_user_profile(&profile).await.unwrap();
+    }
+
+    let groups = vector_db.find_duplicate_users(0.99).await.unwrap();
+    assert_eq!(groups, vec![
+        vec![Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3)],
+        vec![Uuid::from_u128(5), Uuid::from_u128(6)],
+    ]);
+}
+
+#[test]
+fn test_offset_tracker_holds_back_unprocessed_offsets() {
+    use milvuso::services::kafka::{MessageOffset, OffsetTracker};
+
+    let at = |offset| MessageOffset { partition: 0, offset };
+    let mut tracker = OffsetTracker::new();
+    for offset in 0..4 {
+        tracker.track(at(offset));
+    }
+    tracker.track(MessageOffset { partition: 1, offset: 7 });
+
+    // Offset 1's batch failed, so later successes can't move the commit past it
+    tracker.complete(at(0));
+    tracker.complete(at(2));
+    tracker.complete(at(3));
+    assert_eq!(tracker.take_commit_positions(), vec![(0, 1)]);
+    assert!(tracker.take_commit_positions().is_empty());
+
+    tracker.complete(at(1));
+    tracker.complete(MessageOffset { partition: 1, offset: 7 });
+    assert_eq!(tracker.take_commit_positions(), vec![(0, 4), (1, 8)]);
+}
+
+#[tokio::test]
+async fn test_acked_training_worker_acks_trained_offsets() {
+    use milvuso::services::kafka::{KafkaProducer, MessageOffset};
+    use milvuso::services::training::TrainingService;
+
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.training.batch_size = 4;
+    let config = Arc::new(config);
+
+    let vector_db = Arc::new(VectorDbService::new(&config).await.unwrap());
+    let kafka_producer = Arc::new(KafkaProducer::new(&config).unwrap());
+    let training = TrainingService::new(vector_db, kafka_producer, config.clone()).await.unwrap();
+
+    let (tx, rx) = tokio::sync::mpsc::channel(100);
+    let (ack_tx, mut ack_rx) = tokio::sync::mpsc::unbounded_channel();
+    let worker = training.spawn_acked_batch_training_worker(rx, ack_tx);
+    for i in 0..10i64 {
+        let example = TrainingExample {
+            user_id: Uuid::from_u128(i as u128 % 3 + 1),
+            item_id: Uuid::from_u128(100 + i as u128),
+            label: 1.0,
+            user_features: unit_vector(dimension, i as usize % dimension),
+            item_features: unit_vector(dimension, (i as usize + 1) % dimension),
+            context_features: vec![],
+            timestamp: Utc::now(),
+        };
+        tx.send((example, MessageOffset { partition: 0, offset: i })).await.unwrap();
+    }
+    drop(tx);
+    tokio::time::timeout(std::time::Duration::from_secs(30), worker).await.unwrap().unwrap();
+
+    let mut acked = Vec::new();
+    while let Ok(offsets) = ack_rx.try_recv() {
+        acked.extend(offsets.into_iter().map(|offset| offset.offset));
+    }
+    acked.sort();
+    assert_eq!(acked, (0..10).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn test_request_scores_with_named_model_version() {
+    let dimension = 4;
+    let user_id = Uuid::from_u128(1);
+    let item_id = Uuid::from_u128(10);
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = 0.0;
+    config.recommendation.collaborative_blend_weight = 0.5;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let mut profile = UserProfile::new(user_id, dimension);
+    profile.embedding = unit_vector(dimension, 0);
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    service.add_item_feature(ItemFeature::new(item_id, unit_vector(dimension, 0), "books".to_string())).await.unwrap();
+
+    // The challenger has learned an embedding for the item orthogonal to its content
+    let challenger = ModelParameters {
+        version: "challenger".to_string(),
+        user_embeddings: HashMap::new(),
+        item_embeddings: HashMap::from([(item_id, unit_vector(dimension, 1))]),
+        bias_weights: vec![],
+        updated_at: Utc::now(),
+    };
+    service.load_named_model("challenger", &challenger).await.unwrap();
+
+    let default = service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+    let request = RecommendationRequest {
+        model_version: Some("challenger".to_string()),
+        ..RecommendationRequest::new(user_id, 10)
+    };
+    let selected = service.get_recommendations(&request).await.unwrap();
+    assert_eq!(default.recommendations[0].item_id, item_id);
+    assert_eq!(selected.recommendations[0].item_id, item_id);
+    assert!(selected.recommendations[0].score < default.recommendations[0].score);
+
+    let unknown = RecommendationRequest {
+        model_version: Some("missing".to_string()),
+        ..RecommendationRequest::new(user_id, 10)
+    };
+    let error = service.get_recommendations(&unknown).await.unwrap_err();
+    assert!(error.is::<milvuso::services::recommendation::UnknownModelVersion>());
+    assert_eq!(error.to_string(), "Unknown model version missing");
+}
+
+#[tokio::test]
+async fn test_ignored_impressions_train_as_hard_negatives() {
+    use milvuso::config::HardNegativeConfig;
+    use nalgebra::DVector;
+
+    let dimension = 4;
+    let user_id = Uuid::from_u128(1);
+    let (clicked, ignored) = (Uuid::from_u128(10), [Uuid::from_u128(11), Uuid::from_u128(12)]);
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = 0.0;
+    config.recommendation.cold_start_threshold = 0;
+    config.recommendation.hard_negatives = Some(HardNegativeConfig { per_impression: 3, delay_seconds: 0, interval_seconds: 60 });
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let mut profile = UserProfile::new(user_id, dimension);
+    profile.embedding = unit_vector(dimension, 0);
+    vector_db.insert_user_profile(&profile).await.unwrap();
+    for (i, item_id) in std::iter::once(clicked).chain(ignored).enumerate() {
+        service.add_item_feature(ItemFeature::new(item_id, unit_vector(dimension, i), "books".to_string())).await.unwrap();
+    }
+
+    let response = service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap();
+    assert_eq!(response.recommendations.len(), 3);
+    // Nothing has had feedback yet
+    assert!(service.train_hard_negatives().await.unwrap().is_empty());
+
+    let click = UserAction::new(user_id, clicked, ActionType::Click).with_recommendation_id(response.recommendation_id);
+    service.process_user_action(&click).await.unwrap();
+
+    // Fix the learned embeddings so the ignored items start out ranked above the clicked one
+    let algorithm = service.algorithm();
+    {
+        let mut algorithm = algorithm.write().await;
+        algorithm.user_embeddings.insert(user_id, DVector::from_vec(unit_vector(dimension, 0)));
+        algorithm.item_embeddings.insert(clicked, DVector::from_vec(vec![0.0, 0.5, 0.0, 0.0]));
+        for item_id in ignored {
+            algorithm.item_embeddings.insert(item_id, DVector::from_vec(vec![0.8, 0.0, 0.0, 0.0]));
+        }
+    }
+    let margin = |algorithm: &milvuso::algorithms::CollaborativeFiltering| {
+        let user = &algorithm.user_embeddings[&user_id];
+        user.dot(&algorithm.item_embeddings[&clicked]) - user.dot(&algorithm.item_embeddings[&ignored[0]])
+    };
+    let before = margin(&*algorithm.read().await);
+
+    let examples = service.train_hard_negatives().await.unwrap();
+    let mut negatives: Vec<Uuid> = examples.iter().map(|example| example.item_id).collect();
+    negatives.sort();
+    assert_eq!(negatives, ignored.to_vec());
+    assert!(examples.iter().all(|example| example.user_id == user_id && example.label == 0.0));
+    assert!(margin(&*algorithm.read().await) > before);
+
+    // Each impression yields its negatives once
+    assert!(service.train_hard_negatives().await.unwrap().is_empty());
+    assert_eq!(service.get_recommendation_stats().await.get("hard_negatives_trained"), Some(&2));
+}
+
+#[tokio::test]
+async fn test_action_weights_config_sets_training_label() {
+    use milvuso::config::ActionWeightsConfig;
+    use nalgebra::DVector;
+
+    let dimension = 4;
+    let (user_id, item_id) = (Uuid::from_u128(1), Uuid::from_u128(10));
+
+    // Trained from a zero item embedding, one click moves it by `learning_rate * label` along the user's
+    let learned_after_click = |action_weights: ActionWeightsConfig| async move {
+        let mut config = small_config(dimension);
+        config.action_weights = action_weights;
+        let learning_rate = config.training.learning_rate as f32;
+        let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+        let mut profile = UserProfile::new(user_id, dimension);
+        profile.embedding = unit_vector(dimension, 0);
+        vector_db.insert_user_profile(&profile).await.unwrap();
+        service.add_item_feature(ItemFeature::new(item_id, unit_vector(dimension, 0), "books".to_string())).await.unwrap();
+        {
+            let algorithm = service.algorithm();
+            let mut algorithm = algorithm.write().await;
+            algorithm.user_embeddings.insert(user_id, DVector::from_vec(unit_vector(dimension, 0)));
+            algorithm.item_embeddings.insert(item_id, DVector::zeros(dimension));
+        }
+
+        service.process_user_action(&UserAction::new(user_id, item_id, ActionType::Click)).await.unwrap();
+        let learned = service.algorithm().read().await.item_embeddings[&item_id][0];
+        learned / learning_rate
+    };
+
+    let default_label = learned_after_click(ActionWeightsConfig::default()).await;
+    assert!((default_label - 0.3).abs() < 1e-4, "{}", default_label);
+
+    let custom = ActionWeightsConfig { click: 0.9, ..ActionWeightsConfig::default() };
+    let custom_label = learned_after_click(custom).await;
+    assert!((custom_label - 0.9).abs() < 1e-4, "{}", custom_label);
+}
+
+#[tokio::test]
+async fn test_embedding_smoothing_pulls_sparse_users_toward_category_centroid() {
+    use milvuso::config::EmbeddingSmoothingConfig;
+
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.embedding_smoothing = Some(EmbeddingSmoothingConfig { prior_strength: 5.0 });
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+    service.add_item_feature(ItemFeature::new(Uuid::from_u128(10), vec![1.0, 1.0, 0.0, 0.0], "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(Uuid::from_u128(11), vec![1.0, -1.0, 0.0, 0.0], "books".to_string())).await.unwrap();
+    service.add_item_feature(ItemFeature::new(Uuid::from_u128(12), unit_vector(dimension, 3), "music".to_string())).await.unwrap();
+    let centroid = vector_db.category_centroid("books").await.unwrap();
+    assert_eq!(centroid, vec![1.0, 0.0, 0.0, 0.0]);
+
+    let distance_to_centroid = |embedding: &[f32]| milvuso::utils::euclidean_distance(embedding, &centroid);
+    let mut effective_distances = Vec::new();
+    for (user, interactions) in [(1u128, 1u64), (2, 1000)] {
+        let user_id = Uuid::from_u128(user);
+        vector_db.record_category_affinity(user_id, "books", 3.0).await;
+        vector_db.record_category_affinity(user_id, "music", 1.0).await;
+        let mut profile = UserProfile::new(user_id, dimension);
+        profile.embedding = unit_vector(dimension, 2);
+        profile.interaction_count = interactions;
+
+        let effective = service.effective_user_embedding(&profile).await;
+        assert!(distance_to_centroid(&effective) < distance_to_centroid(&profile.embedding));
+        effective_distances.push(milvuso::utils::euclidean_distance(&effective, &profile.embedding));
+    }
+    // One interaction against a prior of five: shrunk five sixths of the way
+    assert!((effective_distances[0] - 5.0 / 6.0 * std::f32::consts::SQRT_2).abs() < 1e-5);
+    assert!(effective_distances[1] < 0.01);
+
+    // Without any category affinity there is nothing to shrink toward
+    let mut stranger = UserProfile::new(Uuid::from_u128(3), dimension);
+    stranger.embedding = unit_vector(dimension, 2);
+    assert_eq!(service.effective_user_embedding(&stranger).await, stranger.embedding);
+}
+
+#[tokio::test]
+async fn test_seen_items_are_excluded_unless_requested() {
+    let dimension = 4;
+    let mut config = small_config(dimension);
+    config.recommendation.similarity_threshold = 0.0;
+    config.recommendation.cold_start_threshold = 0;
+    config.recommendation.seen_items_cap = 2;
+    let (vector_db, service) = in_memory_recommendation_service(config).await;
+
+    let user_id = Uuid::from_u128(1);
+    let items: Vec<Uuid> = (10..14u128).map(Uuid::from_u128).collect();
+    for item_id in &items {
+        service.add_item_feature(ItemFeature::new(*item_id, vec![1.0, 0.5, 0.0, 0.0], "books".to_string())).await.unwrap();
+    }
+    let mut profile = UserProfile::new(user_id, dimension);
+    profile.embedding = unit_vector(dimension, 0);
+    vector_db.insert_user_profile(&profile).await.unwrap();
+
+    let purchased = items[0];
+    service.process_user_action(&UserAction::new(user_id, purchased, ActionType::Purchase)).await.unwrap();
+    // Views do not count as seen
+    service.process_user_action(&UserAction::new(user_id, items[1], ActionType::View)).await.unwrap();
+
+    let served = |response: RecommendationResponse| response.recommendations.into_iter().map(|item| item.item_id).collect::<Vec<_>>();
+    let default = served(service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap());
+    assert!(!default.contains(&purchased));
+    assert!(default.contains(&items[1]));
+
+    let request = RecommendationRequest { include_seen: true, ..RecommendationRequest::new(user_id, 10) };
+    assert!(served(service.get_recommendations(&request).await.unwrap()).contains(&purchased));
+
+    // Past the cap the oldest seen item is forgotten and served again
+    service.process_user_action(&UserAction::new(user_id, items[2], ActionType::Click)).await.unwrap();
+    service.process_user_action(&UserAction::new(user_id, items[3], ActionType::Like)).await.unwrap();
+    assert_eq!(vector_db.get_seen_items(user_id).await, [items[2], items[3]].into_iter().collect());
+    assert!(served(service.get_recommendations(&RecommendationRequest::new(user_id, 10)).await.unwrap()).contains(&purchased));
+}
+
+#[tokio::test]
+async fn test_embedding_momentum_smooths_oscillating_interests() {
+    let dimension = 4;
+    let user_id = Uuid::from_u128(1);
+    let items = [Uuid::from_u128(10), Uuid::from_u128(11)];
+
+    // Path length of the profile over the second half of 40 clicks alternating between two items
+    let trajectory_length = |momentum: f32| async move {
+        let mut config = small_config(dimension);
+        config.recommendation.embedding_momentum = momentum;
+        config.recommendation.duplicate_action_window_seconds = 0;
+        let (vector_db, service) = in_memory_recommendation_service(config).await;
+        for (axis, item_id) in items.iter().enumerate() {
+            service.add_item_feature(ItemFeature::new(*item_id, unit_vector(dimension, axis), "books".to_string())).await.unwrap();
+        }
+
+        let mut trajectory = Vec::new();
+        for step in 0..40 {
+            service.process_user_action(&UserAction::new(user_id, items[step % 2], ActionType::Click)).await.unwrap();
+            trajectory.push(vector_db.get_user_profile(user_id).await.unwrap().unwrap().embedding);
+        }
+        let length: f32 = trajectory[20..]
+            .windows(2)
+            .map(|pair| milvuso::utils::euclidean_distance(&pair[0], &pair[1]))
+            .sum();
+        (length, trajectory.pop().unwrap())
+    };
+
+    let (plain_length, plain_embedding) = trajectory_length(0.0).await;
+    let (momentum_length, momentum_embedding) = trajectory_length(0.8).await;
+    assert!(plain_length > 0.0);
+    assert!(momentum_length < plain_length / 2.0, "{} vs {}", momentum_length, plain_length);
+    // Both settle around the same blend of the two items
+    assert!(milvuso::utils::euclidean_distance(&plain_embedding, &momentum_embedding) < 0.05);
+}